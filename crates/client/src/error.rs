@@ -0,0 +1,93 @@
+use shard_common::{
+    error::{ExecutionError, TxError},
+    webserver::ApiError,
+};
+use std::fmt;
+
+/// Why a [`Client`](crate::Client) request failed.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The node answered with an error response.
+    Api {
+        status: u16,
+        error: ApiError,
+        message: String,
+    },
+    /// The request couldn't be sent or its response couldn't be read.
+    Http(reqwest::Error),
+    InvalidUrl(String),
+    /// The transaction couldn't be signed.
+    Signing(String),
+    /// The transaction was included on Celestia, but its execution failed.
+    ExecutionFailed {
+        da_height: u64,
+        error: ExecutionError,
+    },
+    /// The transaction wasn't executed before the timeout.
+    Timeout,
+}
+
+impl ClientError {
+    /// Returns the reason the node rejected the transaction, if it did.
+    pub fn rejection(&self) -> Option<&TxError> {
+        match self {
+            ClientError::Api {
+                error: ApiError::Rejected(e),
+                ..
+            } => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Whether the request may succeed if it is sent again: connection
+    /// failures, rate limiting, a full or busy mempool, internal node errors and
+    /// retryable execution failures (see [`ExecutionError::is_retryable`]).
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::Http(e) => e.is_connect() || e.is_timeout(),
+            ClientError::Api { error, .. } => matches!(
+                error,
+                ApiError::RateLimited
+                    | ApiError::Internal(_)
+                    | ApiError::Rejected(TxError::MempoolFull)
+                    | ApiError::Rejected(TxError::MempoolBusy)
+            ),
+            ClientError::ExecutionFailed { error, .. } => error.is_retryable(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Api {
+                status, message, ..
+            } => write!(f, "Node returned {}: {}", status, message),
+            ClientError::Http(e) => write!(f, "Request failed: {}", e),
+            ClientError::InvalidUrl(url) => write!(f, "Invalid node URL {}", url),
+            ClientError::Signing(reason) => write!(f, "Failed to sign transaction: {}", reason),
+            ClientError::ExecutionFailed { da_height, error } => write!(
+                f,
+                "Transaction failed at celestia height {}: {}",
+                da_height, error
+            ),
+            ClientError::Timeout => write!(f, "Timed out waiting for the transaction"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
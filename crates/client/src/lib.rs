@@ -0,0 +1,228 @@
+//! A typed client for a shard node's HTTP API, so applications can submit
+//! transactions and query state without shelling out to the CLI.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use prism_common::keys::{SigningKey, VerifyingKey};
+use reqwest::{Method, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use shard_common::{
+    da::RetryPolicy,
+    fees::FeeEstimate,
+    keys::verifying_key,
+    receipt::Receipt,
+    state::Account,
+    status::TxStatus,
+    tx::{Transaction, TransactionBuilder},
+    webserver::{ApiError, ErrorResponse, SubmitTxResponse},
+};
+use std::time::Duration;
+
+mod error;
+
+pub use error::ClientError;
+
+#[macro_use]
+extern crate tracing;
+
+/// How often [`Client::wait_for_inclusion`] polls the transaction's status
+/// unless configured otherwise.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A client of a node's HTTP API. Requests that fail for retryable reasons
+/// (see [`ClientError::is_retryable`]) are retried according to its
+/// [`RetryPolicy`].
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base: Url,
+    retry_policy: RetryPolicy,
+    poll_interval: Duration,
+}
+
+impl Client {
+    /// Creates a client of the node at `url`, e.g. `http://localhost:3000`.
+    pub fn new(url: &str) -> Result<Self, ClientError> {
+        let base = Url::parse(url).map_err(|_| ClientError::InvalidUrl(url.to_string()))?;
+        if base.cannot_be_a_base() {
+            return Err(ClientError::InvalidUrl(url.to_string()));
+        }
+        Ok(Client {
+            http: reqwest::Client::new(),
+            base,
+            retry_policy: RetryPolicy::default(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        })
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sends a signed transaction to the sequencer, returning its hex encoded
+    /// hash.
+    pub async fn submit_tx(&self, tx: &Transaction) -> Result<String, ClientError> {
+        let response: SubmitTxResponse =
+            self.request(Method::POST, &["submit_tx"], Some(tx)).await?;
+        Ok(response.tx_hash)
+    }
+
+    /// Signs the transaction built by `builder` as the account of `key` at
+    /// the account's next nonce and submits it, returning its hex encoded
+    /// hash. Transactions of the account still waiting in the mempool
+    /// aren't accounted for, set the nonce on the builder and use
+    /// [`Client::submit_tx`] to submit several at once.
+    pub async fn sign_and_submit(
+        &self,
+        builder: TransactionBuilder,
+        key: &SigningKey,
+    ) -> Result<String, ClientError> {
+        let nonce = self
+            .get_account(&verifying_key(key))
+            .await?
+            .map_or(0, |account| account.nonce());
+        let tx = builder
+            .nonce(nonce)
+            .sign(key)
+            .map_err(|e| ClientError::Signing(e.to_string()))?;
+        self.submit_tx(&tx).await
+    }
+
+    /// Estimates the fees of a transaction before it is signed and submitted.
+    pub async fn estimate_fee(&self, tx: &Transaction) -> Result<FeeEstimate, ClientError> {
+        self.request(Method::POST, &["estimate_fee"], Some(tx))
+            .await
+    }
+
+    /// Returns the account of `vk`, or `None` if it doesn't exist.
+    pub async fn get_account(&self, vk: &VerifyingKey) -> Result<Option<Account>, ClientError> {
+        let vk = BASE64.encode(vk.as_bytes());
+        not_found_as_none(self.get(&["account", &vk]).await)
+    }
+
+    /// Returns the status of a transaction, or `None` if the node hasn't seen
+    /// it.
+    pub async fn get_tx_status(&self, tx_hash: &str) -> Result<Option<TxStatus>, ClientError> {
+        not_found_as_none(self.get(&["tx", tx_hash]).await)
+    }
+
+    /// Returns the receipt of an executed transaction, or `None` if it
+    /// hasn't been executed.
+    pub async fn get_receipt(&self, tx_hash: &str) -> Result<Option<Receipt>, ClientError> {
+        not_found_as_none(self.get(&["receipt", tx_hash]).await)
+    }
+
+    /// Polls the status of a submitted transaction until it is executed,
+    /// returning its receipt. Fails with [`ClientError::ExecutionFailed`] if
+    /// its execution failed and with [`ClientError::Timeout`] if it isn't
+    /// executed within `timeout`.
+    pub async fn wait_for_inclusion(
+        &self,
+        tx_hash: &str,
+        timeout: Duration,
+    ) -> Result<Receipt, ClientError> {
+        tokio::time::timeout(timeout, self.poll_inclusion(tx_hash))
+            .await
+            .map_err(|_| ClientError::Timeout)?
+    }
+
+    async fn poll_inclusion(&self, tx_hash: &str) -> Result<Receipt, ClientError> {
+        loop {
+            match self.get_tx_status(tx_hash).await? {
+                Some(TxStatus::Executed { .. }) => {
+                    if let Some(receipt) = self.get_receipt(tx_hash).await? {
+                        return Ok(receipt);
+                    }
+                }
+                Some(TxStatus::Failed { da_height, error }) => {
+                    return Err(ClientError::ExecutionFailed { da_height, error });
+                }
+                _ => {}
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, segments: &[&str]) -> Result<T, ClientError> {
+        self.request::<T, ()>(Method::GET, segments, None).await
+    }
+
+    /// Sends a request to the endpoint at `segments`, retrying it with
+    /// exponential backoff while it fails for retryable reasons.
+    async fn request<T: DeserializeOwned, B: serde::Serialize>(
+        &self,
+        method: Method,
+        segments: &[&str],
+        body: Option<&B>,
+    ) -> Result<T, ClientError> {
+        let mut url = self.base.clone();
+        // pushed as segments, so base64 keys containing `/` are escaped
+        url.path_segments_mut()
+            .map_err(|_| ClientError::InvalidUrl(self.base.to_string()))?
+            .pop_if_empty()
+            .extend(segments);
+
+        let mut backoff = self.retry_policy.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            let mut request = self.http.request(method.clone(), url.clone());
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+            let result = match request.send().await {
+                Ok(response) => parse_response(response).await,
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Err(e) if e.is_retryable() && attempt < self.retry_policy.max_attempts => {
+                    warn!(
+                        "request to {} failed ({}/{}), retrying in {:?}: {}",
+                        url, attempt, self.retry_policy.max_attempts, backoff, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.retry_policy.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+async fn parse_response<T: DeserializeOwned>(
+    response: reqwest::Response,
+) -> Result<T, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response.json().await?);
+    }
+    let body = response.text().await?;
+    let (error, message) = match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(response) => (response.error, response.message),
+        // e.g. an error of a proxy in front of the node
+        Err(_) if status == StatusCode::TOO_MANY_REQUESTS => (ApiError::RateLimited, body),
+        Err(_) if status.is_server_error() => (ApiError::Internal(body.clone()), body),
+        Err(_) => (ApiError::BadRequest(body.clone()), body),
+    };
+    Err(ClientError::Api {
+        status: status.as_u16(),
+        error,
+        message,
+    })
+}
+
+fn not_found_as_none<T>(result: Result<T, ClientError>) -> Result<Option<T>, ClientError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(ClientError::Api {
+            error: ApiError::NotFound(_),
+            ..
+        }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
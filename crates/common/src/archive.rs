@@ -0,0 +1,38 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{storage::NodeStore, tx::Transaction};
+
+/// The input a Celestia height was processed with, archived so the state can
+/// be rebuilt from the local store alone, see [`Node::replay`].
+///
+/// [`Node::replay`]: crate::node::Node::replay
+#[derive(Serialize, Deserialize, Default)]
+pub struct ArchivedHeight {
+    /// The data of the blobs that decoded into batches, in execution order
+    pub batches: Vec<Vec<u8>>,
+    /// The forced transactions that were due at the height
+    pub forced_txs: Vec<Transaction>,
+}
+
+fn archive_key(da_height: u64) -> String {
+    format!("archive:{}", da_height)
+}
+
+pub fn get_archived_height<S: NodeStore + ?Sized>(
+    store: &S,
+    da_height: u64,
+) -> Result<Option<ArchivedHeight>> {
+    match store.get_metadata(&archive_key(da_height))? {
+        Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn put_archived_height<S: NodeStore + ?Sized>(
+    store: &S,
+    da_height: u64,
+    archived: &ArchivedHeight,
+) -> Result<()> {
+    store.put_metadata(&archive_key(da_height), &bincode::serialize(archived)?)
+}
@@ -0,0 +1,406 @@
+//! A load generator for tuning `batch_interval` and mempool limits: submits
+//! transfers between generated accounts at a fixed rate and measures how
+//! long they take to be executed.
+//!
+//! Each account submits from its own task, so its nonces stay in order
+//! while accounts submit concurrently. Inclusion is detected by polling
+//! `/tx/<hash>`, so latencies are only accurate to the poll interval.
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use prism_common::keys::{SigningKey, VerifyingKey};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    keys,
+    status::TxStatus,
+    tx::{Transaction, TransactionBuilder, TransactionType},
+    webserver::SubmitTxResponse,
+};
+
+/// How often the status of unresolved transactions is polled.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// The amount moved by every transfer.
+const TRANSFER_AMOUNT: u64 = 1;
+
+pub struct BenchOptions {
+    /// The number of accounts transfers are sent between
+    pub accounts: usize,
+    /// Transactions submitted per second, across all accounts
+    pub tps: u32,
+    /// How long transactions are submitted for
+    pub duration: Duration,
+    /// The fee every transaction pays
+    pub fee: u64,
+    pub chain_id: u64,
+    /// How long to wait for submitted transactions to be executed once the
+    /// load ends
+    pub drain_timeout: Duration,
+}
+
+/// The outcome of a benchmark run.
+#[derive(Default)]
+pub struct BenchReport {
+    pub submitted: usize,
+    /// Rejected submissions by error code
+    pub rejected: BTreeMap<String, usize>,
+    /// Accepted transactions whose execution failed
+    pub failed: usize,
+    /// Accepted transactions still unresolved after the drain timeout
+    pub pending: usize,
+    /// Time from submission to execution, sorted
+    pub latencies: Vec<Duration>,
+    /// The time it took to submit every transaction
+    pub elapsed: Duration,
+}
+
+impl BenchReport {
+    /// Returns the latency below which `percentile` percent of the executed
+    /// transactions were included.
+    pub fn latency(&self, percentile: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let index = (percentile / 100.0 * (self.latencies.len() - 1) as f64).round() as usize;
+        Some(self.latencies[index])
+    }
+
+    pub fn print(&self) {
+        let rejected: usize = self.rejected.values().sum();
+        println!(
+            "submitted {} transactions in {:.1}s ({:.1} tx/s)",
+            self.submitted,
+            self.elapsed.as_secs_f64(),
+            self.submitted as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+        );
+        println!(
+            "accepted {}, rejected {}",
+            self.submitted - rejected,
+            rejected
+        );
+        for (code, count) in &self.rejected {
+            println!("  {}: {}", code, count);
+        }
+        println!(
+            "executed {}, failed {}, pending {}",
+            self.latencies.len(),
+            self.failed,
+            self.pending
+        );
+        for percentile in [50.0, 90.0, 99.0, 100.0] {
+            if let Some(latency) = self.latency(percentile) {
+                println!("  p{}: {:.2}s", percentile, latency.as_secs_f64());
+            }
+        }
+    }
+}
+
+/// The transactions submitted so far that haven't been executed or failed
+/// yet, with their submission time.
+type Pending = Arc<Mutex<HashMap<String, Instant>>>;
+
+/// The webserver of the node under load.
+#[derive(Clone)]
+struct NodeApi {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl NodeApi {
+    /// Submits `tx`, returning its hash or the error code it was rejected
+    /// with.
+    async fn submit(&self, tx: &Transaction) -> Result<Result<String, String>> {
+        let response = self
+            .client
+            .post(format!("{}/submit_tx", self.url))
+            .json(tx)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let response: SubmitTxResponse = response.json().await?;
+            return Ok(Ok(response.tx_hash));
+        }
+        let status = response.status();
+        let body: serde_json::Value = match response.json().await {
+            Ok(body) => body,
+            Err(_) => return Ok(Err(format!("http_{}", status.as_u16()))),
+        };
+        // rejections are grouped by their tx error code, other errors by kind
+        let code = match body["error"]["kind"].as_str() {
+            Some("rejected") => body["error"]["details"]["code"].as_str(),
+            kind => kind,
+        };
+        Ok(Err(code.unwrap_or("unknown").to_string()))
+    }
+
+    /// Returns the next nonce of the account `vk`, zero if it doesn't exist
+    /// yet.
+    async fn nonce(&self, vk: &VerifyingKey) -> Result<u64> {
+        let mut url = reqwest::Url::parse(&self.url)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid node url {}", self.url))?
+            .extend(["account", &BASE64.encode(vk.as_bytes())]);
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .context("Failed to query account")?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(0);
+        }
+        let account: serde_json::Value = response.error_for_status()?.json().await?;
+        account["nonce"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("Account response without a nonce"))
+    }
+
+    async fn tx_status(&self, tx_hash: &str) -> Result<Option<TxStatus>> {
+        let response = self
+            .client
+            .get(format!("{}/tx/{}", self.url, tx_hash))
+            .send()
+            .await
+            .context("Failed to query transaction status")?;
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        Ok(Some(response.json().await?))
+    }
+}
+
+struct BenchAccount {
+    key: SigningKey,
+    vk: VerifyingKey,
+    nonce: u64,
+}
+
+impl BenchAccount {
+    fn new(key: SigningKey) -> Self {
+        BenchAccount {
+            vk: keys::verifying_key(&key),
+            key,
+            nonce: 0,
+        }
+    }
+
+    fn generate() -> Self {
+        Self::new(SigningKey::Ed25519(Box::new(
+            keystore_rs::create_signing_key(),
+        )))
+    }
+
+    fn next_tx(&self, tx_type: TransactionType, options: &BenchOptions) -> Result<Transaction> {
+        TransactionBuilder::new(tx_type)
+            .nonce(self.nonce)
+            .fee(options.fee)
+            .chain_id(options.chain_id)
+            .sign(&self.key)
+    }
+}
+
+/// Runs the benchmark against the node at `url`: funds `accounts`
+/// generated accounts with tokens minted by `mint_key`, the node's mint
+/// authority, then submits transfers between them. The mint authority pays
+/// the fee of its mint from its existing balance.
+pub async fn run(url: &str, mint_key: SigningKey, options: BenchOptions) -> Result<BenchReport> {
+    if options.accounts == 0 || options.tps == 0 {
+        return Err(anyhow!("Accounts and tps must be greater than zero"));
+    }
+    let api = NodeApi {
+        client: reqwest::Client::new(),
+        url: url.to_string(),
+    };
+    let accounts: Vec<BenchAccount> = (0..options.accounts)
+        .map(|_| BenchAccount::generate())
+        .collect();
+
+    let total_txs = options.tps as u64 * options.duration.as_secs().max(1);
+    let txs_per_account = total_txs.div_ceil(options.accounts as u64);
+    let funding = (TRANSFER_AMOUNT + options.fee)
+        .saturating_mul(txs_per_account)
+        .saturating_add(options.fee);
+
+    info!("funding {} accounts", accounts.len());
+    let mut minter = BenchAccount::new(mint_key);
+    minter.nonce = api.nonce(&minter.vk).await?;
+    let amount = (funding + options.fee).saturating_mul(accounts.len() as u64);
+    let mint = minter.next_tx(TransactionType::Mint { amount }, &options)?;
+    let pending = Pending::default();
+    let tx_hash = api
+        .submit(&mint)
+        .await?
+        .map_err(|code| anyhow!("Funding mint was rejected: {}", code))?;
+    pending.lock().unwrap().insert(tx_hash, Instant::now());
+    minter.nonce += 1;
+    // transfers are checked against the minted balance when submitted
+    await_funding(&api, &pending, options.drain_timeout).await?;
+    for account in &accounts {
+        let transfer = TransactionType::Transfer {
+            to: account.vk.clone(),
+            amount: funding,
+        };
+        let tx = minter.next_tx(transfer, &options)?;
+        let tx_hash = api
+            .submit(&tx)
+            .await?
+            .map_err(|code| anyhow!("Funding transfer was rejected: {}", code))?;
+        pending.lock().unwrap().insert(tx_hash, Instant::now());
+        minter.nonce += 1;
+    }
+    await_funding(&api, &pending, options.drain_timeout).await?;
+
+    info!(
+        "submitting {} tx/s for {}s",
+        options.tps,
+        options.duration.as_secs()
+    );
+    let options = Arc::new(options);
+    let recipients: Vec<VerifyingKey> = accounts.iter().map(|account| account.vk.clone()).collect();
+    let period = Duration::from_secs_f64(options.accounts as f64 / options.tps as f64);
+    let start = Instant::now();
+    let deadline = start + options.duration;
+    let mut tasks = Vec::new();
+    for (i, account) in accounts.into_iter().enumerate() {
+        let api = api.clone();
+        let pending = pending.clone();
+        let options = options.clone();
+        let to = recipients[(i + 1) % recipients.len()].clone();
+        // staggered, so the accounts' submissions spread over the period
+        let offset = period.mul_f64(i as f64 / recipients.len() as f64);
+        tasks.push(tokio::spawn(async move {
+            submit_transfers(account, to, &api, &pending, &options, offset, deadline).await
+        }));
+    }
+
+    let mut report = BenchReport::default();
+    let mut poller = Box::pin(poll_until_resolved(
+        &api,
+        &pending,
+        &mut report,
+        options.duration + options.drain_timeout,
+    ));
+    let mut results = Vec::new();
+    for task in tasks {
+        tokio::select! {
+            result = task => results.push(result??),
+            result = &mut poller => {
+                result?;
+                return Err(anyhow!("Stopped polling before every transaction was submitted"));
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+    poller.await?;
+
+    report.elapsed = elapsed;
+    for (submitted, rejected) in results {
+        report.submitted += submitted;
+        for (code, count) in rejected {
+            *report.rejected.entry(code).or_default() += count;
+        }
+    }
+    report.latencies.sort();
+    Ok(report)
+}
+
+/// Waits until the pending funding transactions are executed, failing if
+/// any of them failed or took longer than `timeout`.
+async fn await_funding(api: &NodeApi, pending: &Pending, timeout: Duration) -> Result<()> {
+    let mut report = BenchReport::default();
+    poll_until_resolved(api, pending, &mut report, timeout).await?;
+    if report.failed > 0 || report.pending > 0 {
+        return Err(anyhow!(
+            "{} funding transactions failed and {} weren't executed in time",
+            report.failed,
+            report.pending
+        ));
+    }
+    Ok(())
+}
+
+/// Submits transfers from `account` to `to` every period until `deadline`.
+/// Returns the number of submissions and the rejections by error code.
+async fn submit_transfers(
+    mut account: BenchAccount,
+    to: VerifyingKey,
+    api: &NodeApi,
+    pending: &Pending,
+    options: &BenchOptions,
+    offset: Duration,
+    deadline: Instant,
+) -> Result<(usize, BTreeMap<String, usize>)> {
+    let period = Duration::from_secs_f64(options.accounts as f64 / options.tps as f64);
+    let mut interval = tokio::time::interval_at((Instant::now() + offset).into(), period);
+    // a slow node lowers the achieved rate instead of causing bursts
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut submitted = 0;
+    let mut rejected = BTreeMap::new();
+    loop {
+        interval.tick().await;
+        if Instant::now() >= deadline {
+            break;
+        }
+        let transfer = TransactionType::Transfer {
+            to: to.clone(),
+            amount: TRANSFER_AMOUNT,
+        };
+        let tx = account.next_tx(transfer, options)?;
+        submitted += 1;
+        match api.submit(&tx).await {
+            Ok(Ok(tx_hash)) => {
+                pending.lock().unwrap().insert(tx_hash, Instant::now());
+                account.nonce += 1;
+            }
+            // the nonce wasn't used, so the next transaction reuses it
+            Ok(Err(code)) => *rejected.entry(code).or_default() += 1,
+            Err(e) => {
+                debug!("submission failed: {}", e);
+                *rejected.entry("request_failed".to_string()).or_default() += 1;
+            }
+        }
+    }
+    Ok((submitted, rejected))
+}
+
+/// Polls the status of the pending transactions, recording them in `report`
+/// once they are executed or failed, until none are left or `timeout` has
+/// passed. Transactions still pending then are counted as such.
+async fn poll_until_resolved(
+    api: &NodeApi,
+    pending: &Pending,
+    report: &mut BenchReport,
+    timeout: Duration,
+) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let unresolved: Vec<(String, Instant)> = pending
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(tx_hash, submitted_at)| (tx_hash.clone(), *submitted_at))
+            .collect();
+        for (tx_hash, submitted_at) in unresolved {
+            match api.tx_status(&tx_hash).await? {
+                Some(TxStatus::Executed { .. }) => report.latencies.push(submitted_at.elapsed()),
+                Some(TxStatus::Failed { .. }) => report.failed += 1,
+                _ => continue,
+            }
+            pending.lock().unwrap().remove(&tx_hash);
+        }
+        let remaining = pending.lock().unwrap().len();
+        if Instant::now() >= deadline {
+            report.pending = remaining;
+            return Ok(());
+        }
+        // the load tasks keep adding transactions until they are done
+        if remaining == 0 && Arc::strong_count(pending) == 1 {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use anyhow::{anyhow, Result};
+use celestia_types::Commitment;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    encoding::{Decode, Decoder, Encode, Encoder},
+    storage::NodeStore,
+    tree::Digest,
+    tx::Transaction,
+};
+
+/// Metadata key under which the height of the latest executed block is
+/// stored.
+const LATEST_BLOCK_KEY: &str = "latest_block";
+
+/// The header height of batches users post directly to Celestia for forced
+/// inclusion. Sequencer blocks start at height 1.
+pub const DIRECT_BATCH_HEIGHT: u64 = 0;
+
+/// Metadata key prefix of the state roots sequencers claimed for blocks.
+const CLAIMED_ROOT_PREFIX: &str = "claimed_root:";
+
+/// Metadata key prefix of where the batches of blocks were posted.
+const DA_INCLUSION_PREFIX: &str = "block_da:";
+
+/// The part of a block the sequencer commits to when posting a batch. The
+/// DA height is only known once the batch has been included, and the state
+/// roots once it has been executed.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct BatchHeader {
+    pub height: u64,
+    /// Merkle root over the hashes of the batch's transactions, see
+    /// [`tx_root`].
+    pub tx_root: Digest,
+    /// Unix timestamp (in seconds) at which the sequencer sealed the batch.
+    pub timestamp: u64,
+    /// The state root the sequencer expects after executing the batch, so
+    /// nodes re-executing it notice if their state diverges from the
+    /// sequencer's. Only a claim: the root nodes compute is authoritative.
+    pub state_root: Option<Digest>,
+    /// The protocol version the batch was executed under, see
+    /// [`crate::protocol`]. 0 for batches from before protocol versions.
+    #[serde(default)]
+    pub protocol_version: u32,
+}
+
+impl BatchHeader {
+    /// The message a sequencer signs to commit to the header, prefixed with
+    /// `domain` so signatures over batches and state diffs can't be swapped.
+    pub(crate) fn signature_msg(&self, domain: &[u8]) -> Vec<u8> {
+        let mut enc = Encoder::default();
+        enc.put_raw(domain);
+        enc.put_u64(self.height);
+        self.tx_root.encode(&mut enc);
+        enc.put_u64(self.timestamp);
+        // headers without a state root sign the same message as before it
+        // was added, so earlier signatures stay valid
+        if let Some(state_root) = &self.state_root {
+            state_root.encode(&mut enc);
+        }
+        // likewise for version 0, the longer state root keeps the two
+        // distinguishable
+        if self.protocol_version != 0 {
+            enc.put_u32(self.protocol_version);
+        }
+        enc.finish()
+    }
+}
+
+impl Encode for BatchHeader {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_u64(self.height);
+        self.tx_root.encode(enc);
+        enc.put_u64(self.timestamp);
+        match &self.state_root {
+            Some(state_root) => {
+                enc.put_u8(1);
+                state_root.encode(enc);
+            }
+            None => enc.put_u8(0),
+        }
+        enc.put_u32(self.protocol_version);
+    }
+}
+
+impl Decode for BatchHeader {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        Ok(BatchHeader {
+            height: dec.u64()?,
+            tx_root: Digest::decode(dec)?,
+            timestamp: dec.u64()?,
+            // blobs before version 7 carry no state root
+            state_root: if dec.version() >= 7 {
+                match dec.u8()? {
+                    0 => None,
+                    1 => Some(Digest::decode(dec)?),
+                    tag => return Err(anyhow!("Invalid state root tag {}", tag)),
+                }
+            } else {
+                None
+            },
+            // blobs before version 11 carry no protocol version
+            protocol_version: if dec.version() >= 11 { dec.u32()? } else { 0 },
+        })
+    }
+}
+
+/// A rollup block: the header of an executed batch, anchoring its
+/// transactions to the state roots before and after execution and to the
+/// Celestia height it was included at.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub height: u64,
+    pub prev_root: Digest,
+    pub new_root: Digest,
+    pub tx_root: Digest,
+    pub da_height: u64,
+    pub timestamp: u64,
+}
+
+/// Where the sequencer posted the batch of a block, as reported by the DA
+/// layer when it was submitted. Only recorded by the sequencer that posted
+/// it, since the PayForBlobs transaction isn't part of the blob.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct DaInclusion {
+    pub da_height: u64,
+    /// The commitment of the batch blob, identifying it at `da_height`
+    pub commitment: Commitment,
+    /// The hash of the PayForBlobs transaction, if the DA layer has one
+    pub tx_hash: Option<String>,
+}
+
+/// Computes the binary Merkle root over the hashes of `txs`. An odd node at
+/// the end of a level is promoted unchanged; no transactions give the zero
+/// digest.
+pub fn tx_root(txs: &[Transaction]) -> Digest {
+    tx_root_of_hashes(txs.iter().map(Transaction::hash).collect())
+}
+
+/// Computes the [`tx_root`] of transactions given by their hashes.
+pub(crate) fn tx_root_of_hashes(mut level: Vec<Digest>) -> Digest {
+    if level.is_empty() {
+        return Digest::zero();
+    }
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => Digest::hash_items(&[left.0, right.0]),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+fn block_key(height: u64) -> String {
+    format!("block:{}", height)
+}
+
+pub fn get_block<S: NodeStore + ?Sized>(store: &S, height: u64) -> Result<Option<Block>> {
+    match store.get_metadata(&block_key(height))? {
+        Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Stores `block` and makes it the latest block.
+pub fn put_block<S: NodeStore + ?Sized>(store: &S, block: &Block) -> Result<()> {
+    store.put_metadata(&block_key(block.height), &bincode::serialize(block)?)?;
+    store.put_metadata(LATEST_BLOCK_KEY, &bincode::serialize(&block.height)?)
+}
+
+/// Stores a block synced from an archive without touching the latest block,
+/// used to backfill history from before a trusted snapshot.
+pub fn put_historical_block<S: NodeStore + ?Sized>(store: &S, block: &Block) -> Result<()> {
+    store.put_metadata(&block_key(block.height), &bincode::serialize(block)?)
+}
+
+/// Makes the latest block included at or before `da_height` the latest
+/// block again, e.g. after a DA reorg. Later blocks are overwritten once
+/// their heights are executed again.
+pub fn rollback_blocks<S: NodeStore + ?Sized>(store: &S, da_height: u64) -> Result<()> {
+    let Some(latest) = get_latest_block(store)? else {
+        return Ok(());
+    };
+    let mut height = latest.height;
+    while height > 0 {
+        if let Some(block) = get_block(store, height)? {
+            if block.da_height <= da_height {
+                break;
+            }
+        }
+        height -= 1;
+    }
+    // there is no block 0, so height 0 reads as no latest block
+    store.put_metadata(LATEST_BLOCK_KEY, &bincode::serialize(&height)?)
+}
+
+fn claimed_root_key(height: u64) -> String {
+    format!("{}{}", CLAIMED_ROOT_PREFIX, height)
+}
+
+/// Returns the state root the sequencer claimed in the header of the block
+/// at `height`, if it claimed one.
+pub fn get_claimed_root<S: NodeStore + ?Sized>(store: &S, height: u64) -> Result<Option<Digest>> {
+    match store.get_metadata(&claimed_root_key(height))? {
+        Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Stores the state root claimed for the block at `height`, replacing or,
+/// if `None`, removing the claim of a block previously executed at it.
+pub fn put_claimed_root<S: NodeStore + ?Sized>(
+    store: &S,
+    height: u64,
+    claimed_root: Option<&Digest>,
+) -> Result<()> {
+    match claimed_root {
+        Some(root) => store.put_metadata(&claimed_root_key(height), &bincode::serialize(root)?),
+        None => store.delete_metadata(&claimed_root_key(height)),
+    }
+}
+
+fn da_inclusion_key(height: u64) -> String {
+    format!("{}{}", DA_INCLUSION_PREFIX, height)
+}
+
+/// Returns where the batch of the block at `height` was posted, if this
+/// node posted it.
+pub fn get_da_inclusion<S: NodeStore + ?Sized>(
+    store: &S,
+    height: u64,
+) -> Result<Option<DaInclusion>> {
+    match store.get_metadata(&da_inclusion_key(height))? {
+        Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Records where the batch of the block at `height` was posted, replacing
+/// the record of a batch that was posted under the same height before.
+pub fn put_da_inclusion<S: NodeStore + ?Sized>(
+    store: &S,
+    height: u64,
+    inclusion: &DaInclusion,
+) -> Result<()> {
+    store.put_metadata(&da_inclusion_key(height), &bincode::serialize(inclusion)?)
+}
+
+pub fn get_latest_block<S: NodeStore + ?Sized>(store: &S) -> Result<Option<Block>> {
+    match store.get_metadata(LATEST_BLOCK_KEY)? {
+        Some(bytes) => get_block(store, bincode::deserialize(&bytes)?),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,42 @@
+//! Deposits from Celestia or an external chain into shard accounts.
+//!
+//! A relayer watches the bridged chain (e.g. the deposit events of an EVM
+//! contract) and posts a [`TransactionType::Deposit`] per deposit, signed
+//! with the bridge key, to [`crate::node::Config::deposit_namespace`]. The
+//! node executes batches of only such transactions right away, even under
+//! signed batch auth, as system transactions crediting the referenced
+//! accounts. Each deposit id is recorded in the state tree, so relaying a
+//! deposit twice can't mint it twice.
+
+use anyhow::Result;
+use celestia_types::{nmt::Namespace, Blob};
+use prism_common::keys::VerifyingKey;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    block::DIRECT_BATCH_HEIGHT,
+    da::{DataAvailability, Submission},
+    encoding::encode_blob,
+    tx::{Batch, Transaction, TransactionType},
+};
+
+/// Returns whether `batch` only contains deposits sent by `bridge_vk`.
+pub fn is_bridge_batch(batch: &Batch, bridge_vk: &VerifyingKey) -> bool {
+    let txs = batch.get_transactions();
+    !txs.is_empty()
+        && txs
+            .iter()
+            .all(|tx| tx.vk == *bridge_vk && matches!(tx.tx_type, TransactionType::Deposit { .. }))
+}
+
+/// Posts `deposits` as a single batch to `namespace`, the deposit namespace.
+pub async fn post_deposits(
+    da: &dyn DataAvailability,
+    namespace: Namespace,
+    deposits: Vec<Transaction>,
+) -> Result<Submission> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let batch = Batch::with_header(DIRECT_BATCH_HEIGHT, timestamp, deposits);
+    let blob = Blob::new(namespace, encode_blob(&batch))?;
+    da.submit(&[blob]).await
+}
@@ -0,0 +1,295 @@
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use std::{fs, path::Path};
+
+/// The config file written by `init-config`. Every value is commented out, so
+/// the file documents the defaults without pinning them.
+pub const DEFAULT_CONFIG: &str = r#"# Configuration for a zk-shard node.
+# Values passed as CLI flags take precedence over values in this file.
+
+# Which tasks this node runs: "sequencer", "full" or "light"
+# role = "sequencer"
+
+# The URL of the sequencer's webserver, required for full and light nodes
+# unless transactions are gossiped (see p2p_listen_addr)
+# sequencer_url = "http://127.0.0.1:3000"
+
+# The namespace used by this rollup (hex encoded)
+# namespace = "2a2a2a2a"
+
+# Namespaces (hex encoded) of additional transaction lanes, e.g. one per
+# application. Their batches are executed alongside the rollup namespace's,
+# ordered by namespace, so all nodes must configure the same lanes
+# lane_namespaces = ["2a2a2a2c"]
+
+# The chain id transactions are signed for. Transactions for other chains are
+# rejected. Rollups with transactions from before chain ids must keep 0
+# chain_id = 0
+
+# Whether any batch is executed ("permissionless") or only those signed by
+# the sequencer ("signed")
+# batch_auth = "permissionless"
+
+# The number of Celestia blocks after which unsigned batches are
+# force-included under signed batch auth
+# forced_inclusion_delay = 30
+
+# The base64 encoded key batches must be signed with. Sequencers derive it
+# from their signing key
+# sequencer_vk = ""
+
+# The base64 encoded key of the bridge relayer, the only sender allowed to
+# credit deposits. Deposits are rejected if unset
+# bridge_vk = ""
+
+# The base64 encoded key of the governance account, the only sender allowed to
+# change the live chain parameters. Parameters can't be changed if unset
+# governance_vk = ""
+
+# Protocol upgrades of the chain, each activating a protocol version from a
+# celestia height on. Batches of any other version are skipped, so all nodes
+# must configure the same upgrades
+# protocol_upgrades = ["1@1000000"]
+
+# The namespace (hex encoded) the bridge relayer posts deposits to. Batches of
+# only deposits from the bridge key are executed right away, even under
+# signed batch auth
+# deposit_namespace = "2a2a2a2e"
+
+# The base64 encoded key of the mint authority, the only sender allowed to mint
+# new tokens. Mints are rejected if unset
+# mint_vk = ""
+
+# Whether transactions may be scheduled for a later celestia height. Epochs
+# including or executing them can't be proven yet, so sequencers proving their
+# epochs refuse to start with it. All nodes of a rollup must agree
+# scheduled_txs = false
+
+# The name of the key the sequencer signs batches with, a key file in
+# keys_dir or a key in the OS keychain
+# sequencer_key_name = "sequencer"
+
+# The directory of key files. Encrypted key files are decrypted with the
+# password in SHARD_KEY_PASSWORD
+# keys_dir = "keys"
+
+# The namespace epoch proofs are posted to (hex encoded)
+# proof_namespace = "2a2a2a2b"
+
+# How often processed batches are sealed into an epoch and proven, as a number
+# of batches ("10") or seconds ("30s")
+# epoch_interval = "1"
+
+# Whether epochs are proven with a zkVM ("validity") or only claimed and
+# challenged by watchtowers ("optimistic")
+# proof_mode = "validity"
+
+# Run a full node that checks the root claims of an optimistic sequencer and
+# posts fraud proofs of wrong ones to challenge_namespace. Requires an epoch
+# interval in batches, the same as the sequencer's
+# watchtower = false
+
+# The namespace fraud proofs are posted to (hex encoded)
+# challenge_namespace = "2a2a2a2d"
+
+# The height from which to start syncing
+# start_height = 1
+
+# Whether to roll the state back and process every height from the start
+# height again on boot, instead of resuming after the last processed one.
+# Better passed once as --resync than set here
+# resync = false
+
+# How many Celestia heights historical sync fetches concurrently. They are
+# still executed one after another, in order
+# sync_parallelism = 8
+
+# The URL of the Celestia node to connect to, or a list of URLs to fail over
+# between if the current one keeps failing
+# celestia_url = "ws://0.0.0.0:26658"
+# celestia_url = ["ws://0.0.0.0:26658", "ws://backup:26658"]
+
+# The address to listen on for the node's webserver
+# listen_addr = "0.0.0.0:3000"
+
+# The address to serve the gRPC API on (requires the grpc feature)
+# grpc_addr = "0.0.0.0:50051"
+
+# The multiaddr to listen on for p2p transaction gossip (requires the p2p
+# feature). Full and light nodes gossip submitted transactions to the
+# sequencer instead of forwarding them to sequencer_url
+# p2p_listen_addr = "/ip4/0.0.0.0/tcp/4001"
+
+# Multiaddrs of peers to connect to on startup, e.g. the sequencer
+# bootnodes = ["/ip4/127.0.0.1/tcp/4001"]
+
+# An OpenTelemetry collector to export spans to (requires the otlp feature)
+# otlp_endpoint = "http://localhost:4317"
+
+# The Ethereum RPC endpoint to settle epoch proofs through (requires the
+# settlement feature). The relayer key is read from SETTLEMENT_PRIVATE_KEY
+# settlement_rpc_url = "http://localhost:8545"
+
+# The address of the deployed settlement contract (see export-verifier)
+# settlement_contract = "0x0000000000000000000000000000000000000000"
+
+# The maximum number of /submit_tx requests per client IP and minute,
+# unlimited if unset
+# submit_rate_limit = 60
+
+# A bearer token required for admin endpoints (/snapshot, /admin/log_level),
+# which aren't served if unset
+# admin_token = "change-me"
+
+# Origins allowed to make cross-origin requests, "*" for any. CORS is
+# disabled if empty
+# cors_origins = ["https://example.com"]
+
+# The data availability layer to use: "celestia", "mock" (in-process, for
+# local development) or "file" (a directory shared between the nodes, for
+# private deployments)
+# da = "celestia"
+
+# Whether Celestia is synced via "rpc" or an embedded "lumina" light node.
+# With lumina, celestia_url is only used by sequencers to post blobs
+# da_mode = "rpc"
+
+# The network the embedded Lumina node joins: "mainnet", "arabica" or "mocha"
+# (requires the lumina feature)
+# lumina_network = "mainnet"
+
+# The interval at which the mock DA layer, or a sequencer on the file DA
+# layer, produces blocks (in seconds)
+# mock_block_time = 2
+
+# The directory the file DA layer stores blocks in. Only the sequencer writes
+# to it, other nodes poll it for new blocks
+# da_dir = "da"
+
+# The auth token to use when connecting to Celestia
+# auth_token = ""
+
+# The gas price (utia per unit of gas) to post blobs with. Estimated by the
+# celestia-node if unset, and raised automatically after insufficient fee
+# errors. Also used by /estimate_fee, which assumes 0.002 if unset
+# celestia_gas_price = 0.002
+
+# The upper bound for automatically raised gas prices, unbounded if unset
+# celestia_max_gas_price = 0.1
+
+# The key of the celestia-node's keyring to sign blob submissions with
+# celestia_key_name = "my_celes_key"
+
+# The address to sign blob submissions with
+# celestia_signer = "celestia1..."
+
+# An address granting the signer a fee allowance to pay blob submissions from
+# celestia_fee_granter = "celestia1..."
+
+# The interval at which to post batches of transactions (in seconds)
+# batch_interval = 3
+
+# Post a batch before the interval elapses once the queued transactions reach
+# this many bytes. Only posted at the interval if unset
+# batch_max_bytes = 500000
+
+# Post a batch right away when a transaction paying at least this gas price is
+# queued
+# priority_gas_price = 100
+
+# How many times a batch or proof submission is attempted before giving up
+# submit_max_attempts = 5
+
+# The delay before retrying a failed submission, doubled on every further
+# failure (in milliseconds)
+# submit_initial_backoff = 1000
+
+# The maximum number of transactions held in the mempool
+# mempool_size = 10000
+
+# The order the sequencer posts queued transactions in: "fifo" (arrival
+# order), "fee-priority" (highest gas price first) or "round-robin" (one
+# transaction per sender in turn). A sender's transactions always stay in
+# nonce order
+# batch_ordering = "fifo"
+
+# What the sequencer posts: "transactions", which every node executes, or
+# "state-diffs", the signed tree writes of each batch, which nodes apply
+# without executing them and the sequencer proves by re-execution. Requires a
+# sequencer key; only the sequencer's diffs change the state, so direct
+# transactions and bridge deposits aren't executed. All nodes of a rollup must
+# use the same format
+# da_format = "transactions"
+
+# Whether the sequencer executes queued transactions right away on a soft
+# state, serving soft-confirmed receipts and accounts (/account/<vk>?soft=true)
+# before they are read back from Celestia
+# soft_confirmations = false
+
+# Whether nonces must be sequential ("strict") or may contain gaps
+# ("allow-gaps")
+# nonce_policy = "strict"
+
+# Accept transactions without checking their signatures. For development
+# only: anyone can spend from any account
+# insecure_signatures = false
+
+# The minimum fee per unit of gas for queued transactions
+# min_gas_price = 0
+
+# The directory to persist state in (RocksDB). State is kept in memory if
+# unset
+# db_path = "./data"
+
+# How many tree nodes to cache in front of the database, least recently used
+# ones are evicted first. 0 disables the cache
+# node_cache_size = 100000
+
+# Run a read-only full node that keeps the complete history and indexes
+# executed transactions by height and account, serving /account/<vk>/history
+# and /events
+# archive = false
+
+# How many of the latest Celestia heights to keep the tree versions of. Older
+# versions are pruned and can't be read or rolled back to, so at least 100
+# heights must be kept to cover reorgs. Every version is kept if unset
+# retain_heights = 1000
+
+# A snapshot file or URL to start from if the store is empty. Its root is
+# verified against the proof namespace
+# trusted_snapshot = "http://127.0.0.1:3000/snapshot"
+
+# The root the trusted snapshot must have, as <hex root>@<celestia height>.
+# Replaces checking the root against the proof namespace
+# trusted_root = "<hex root>@<celestia height>"
+
+# A node with the full block history to backfill blocks from before the
+# trusted snapshot from
+# archive_url = "http://127.0.0.1:3000"
+
+# A genesis file (JSON or TOML) with initial accounts and chain parameters,
+# applied if the store is empty. Its parameters take precedence over this
+# file
+# genesis = "genesis.json"
+"#;
+
+/// Reads and parses a TOML config file.
+pub fn load<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse config file {}", path.display()))
+}
+
+/// Writes [`DEFAULT_CONFIG`] to `path`, refusing to overwrite an existing
+/// file unless `force` is set.
+pub fn write_default(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        return Err(anyhow!(
+            "{} already exists, use --force to overwrite it",
+            path.display()
+        ));
+    }
+    fs::write(path, DEFAULT_CONFIG)
+        .with_context(|| format!("Failed to write config file {}", path.display()))
+}
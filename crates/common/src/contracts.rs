@@ -0,0 +1,180 @@
+//! Execution environment for [`TransactionType::Deploy`] and
+//! [`TransactionType::Call`], backed by wasmtime.
+//!
+//! A contract is a WASM module exporting its `memory` and a `call` function
+//! taking no arguments and returning an `i32`, where any value but 0 reverts
+//! the call. It can import the following host functions from `env`:
+//!
+//! - `input_len() -> i32` and `read_input(ptr: i32)` to copy the call input
+//!   into memory.
+//! - `caller(ptr: i32)` to write the 32 byte hash of the caller's verifying
+//!   key to memory.
+//! - `da_height() -> i64` to get the Celestia height the call is executed
+//!   at.
+//! - `storage_get(key_ptr, key_len, value_ptr, value_cap: i32) -> i32` to
+//!   read a value of the contract's storage. Returns the length of the value,
+//!   or -1 if the key is unset, and copies at most `value_cap` bytes.
+//! - `storage_set(key_ptr, key_len, value_ptr, value_len: i32)` to write a
+//!   value.
+//!
+//! Execution is metered with wasmtime fuel and runs with the non-deterministic
+//! WASM features disabled, so every node computes the same result.
+//!
+//! [`TransactionType::Deploy`]: crate::tx::TransactionType::Deploy
+//! [`TransactionType::Call`]: crate::tx::TransactionType::Call
+
+use anyhow::{anyhow, Context, Result};
+use std::sync::OnceLock;
+use wasmtime::{Caller, Config, Engine, Extern, Linker, Memory, Module, Store};
+
+use crate::tree::Digest;
+
+/// The fuel available to a single contract call.
+pub const MAX_CALL_FUEL: u64 = 10_000_000;
+
+/// Read and write access to the storage of the called contract.
+pub trait ContractStorage: Send {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+    fn set(&mut self, key: Vec<u8>, value: Vec<u8>);
+}
+
+struct HostState<St> {
+    caller: Digest,
+    da_height: u64,
+    input: Vec<u8>,
+    storage: St,
+}
+
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::new();
+        config
+            .consume_fuel(true)
+            .cranelift_nan_canonicalization(true)
+            .wasm_threads(false)
+            .wasm_relaxed_simd(false);
+        Engine::new(&config).expect("contract engine config is valid")
+    })
+}
+
+/// Checks that `code` is a valid WASM module, so invalid contracts are
+/// rejected at deployment instead of on every call.
+pub fn validate_code(code: &[u8]) -> Result<()> {
+    Module::validate(engine(), code).context("Invalid contract code")
+}
+
+fn memory<St>(caller: &mut Caller<'_, HostState<St>>) -> Result<Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(anyhow!("Contract does not export its memory")),
+    }
+}
+
+fn read_memory<St>(caller: &mut Caller<'_, HostState<St>>, ptr: i32, len: i32) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; len as u32 as usize];
+    memory(caller)?.read(&mut *caller, ptr as u32 as usize, &mut buf)?;
+    Ok(buf)
+}
+
+fn write_memory<St>(caller: &mut Caller<'_, HostState<St>>, ptr: i32, data: &[u8]) -> Result<()> {
+    memory(caller)?.write(&mut *caller, ptr as u32 as usize, data)?;
+    Ok(())
+}
+
+fn linker<St: ContractStorage + 'static>() -> Result<Linker<HostState<St>>> {
+    let mut linker = Linker::new(engine());
+    linker.func_wrap("env", "input_len", |caller: Caller<'_, HostState<St>>| {
+        caller.data().input.len() as i32
+    })?;
+    linker.func_wrap(
+        "env",
+        "read_input",
+        |mut caller: Caller<'_, HostState<St>>, ptr: i32| {
+            let input = caller.data().input.clone();
+            write_memory(&mut caller, ptr, &input)
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "caller",
+        |mut caller: Caller<'_, HostState<St>>, ptr: i32| {
+            let id = caller.data().caller;
+            write_memory(&mut caller, ptr, &id.0)
+        },
+    )?;
+    linker.func_wrap("env", "da_height", |caller: Caller<'_, HostState<St>>| {
+        caller.data().da_height as i64
+    })?;
+    linker.func_wrap(
+        "env",
+        "storage_get",
+        |mut caller: Caller<'_, HostState<St>>,
+         key_ptr: i32,
+         key_len: i32,
+         value_ptr: i32,
+         value_cap: i32|
+         -> Result<i32> {
+            let key = read_memory(&mut caller, key_ptr, key_len)?;
+            let Some(value) = caller.data().storage.get(&key)? else {
+                return Ok(-1);
+            };
+            let copied = value.len().min(value_cap as u32 as usize);
+            write_memory(&mut caller, value_ptr, &value[..copied])?;
+            Ok(value.len() as i32)
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "storage_set",
+        |mut caller: Caller<'_, HostState<St>>,
+         key_ptr: i32,
+         key_len: i32,
+         value_ptr: i32,
+         value_len: i32|
+         -> Result<()> {
+            let key = read_memory(&mut caller, key_ptr, key_len)?;
+            let value = read_memory(&mut caller, value_ptr, value_len)?;
+            caller.data_mut().storage.set(key, value);
+            Ok(())
+        },
+    )?;
+    Ok(linker)
+}
+
+/// Runs the `call` export of `code` with `input` as of Celestia height
+/// `da_height`, returning `storage` with the contract's writes applied. Fails if the contract traps, runs out of
+/// fuel or returns a non-zero status.
+pub fn call<St: ContractStorage + 'static>(
+    code: &[u8],
+    caller: Digest,
+    da_height: u64,
+    input: Vec<u8>,
+    storage: St,
+) -> Result<St> {
+    let module = Module::new(engine(), code).context("Invalid contract code")?;
+    let mut store = Store::new(
+        engine(),
+        HostState {
+            caller,
+            da_height,
+            input,
+            storage,
+        },
+    );
+    store.set_fuel(MAX_CALL_FUEL)?;
+
+    let instance = linker()?
+        .instantiate(&mut store, &module)
+        .context("Failed to instantiate contract")?;
+    let entrypoint = instance
+        .get_typed_func::<(), i32>(&mut store, "call")
+        .context("Contract does not export `call`")?;
+    let status = entrypoint
+        .call(&mut store, ())
+        .context("Contract execution failed")?;
+    if status != 0 {
+        return Err(anyhow!("Contract reverted with status {}", status));
+    }
+    Ok(store.into_data().storage)
+}
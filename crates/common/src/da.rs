@@ -0,0 +1,638 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use celestia_rpc::{BlobClient, HeaderClient, StateClient};
+use celestia_types::{
+    hash::Hash,
+    nmt::{Namespace, NamespaceProof},
+    state::AccAddress,
+    Blob, Commitment, DataAvailabilityHeader, ExtendedHeader, TxConfig,
+};
+use clap::ValueEnum;
+use futures::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+use tokio::sync::broadcast;
+
+use crate::{fees::DEFAULT_CELESTIA_GAS_PRICE, tree::Digest};
+
+pub mod file;
+#[cfg(feature = "lumina")]
+pub mod lumina;
+
+/// A stream of (height, blobs) pairs for every new DA block.
+pub type BlobStream = Pin<Box<dyn Stream<Item = Result<(u64, Vec<Blob>)>> + Send>>;
+
+/// Identifies a DA block and its parent, used to detect reorgs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DaBlockId {
+    pub hash: Digest,
+    pub parent_hash: Digest,
+}
+
+fn digest(hash: Hash) -> Digest {
+    match hash {
+        Hash::Sha256(bytes) => Digest::new(bytes),
+        Hash::None => Digest::zero(),
+    }
+}
+
+impl From<&ExtendedHeader> for DaBlockId {
+    fn from(header: &ExtendedHeader) -> Self {
+        DaBlockId {
+            hash: digest(header.hash()),
+            parent_hash: header
+                .header
+                .last_block_id
+                .map_or(Digest::zero(), |id| digest(id.hash)),
+        }
+    }
+}
+
+/// Everything needed to verify that a blob was published to Celestia
+/// without running a Celestia node: the NMT proofs of the blob's shares,
+/// the data availability header they verify against and the data root of
+/// the block's header, which commits to that data availability header.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BlobProof {
+    pub height: u64,
+    pub namespace: Namespace,
+    pub commitment: Commitment,
+    pub data_root: Digest,
+    pub dah: DataAvailabilityHeader,
+    /// One namespace proof per row the blob's shares span, in row order
+    pub nmt_proofs: Vec<NamespaceProof>,
+}
+
+/// Where a blob submission was included.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Submission {
+    pub height: u64,
+    /// The hash of the PayForBlobs transaction, if the layer has one
+    pub tx_hash: Option<String>,
+}
+
+/// The blob operations the node needs from its data availability layer.
+#[async_trait]
+pub trait DataAvailability: Send + Sync {
+    /// Submits blobs and returns where they were included.
+    async fn submit(&self, blobs: &[Blob]) -> Result<Submission>;
+
+    /// Returns all blobs of `namespace` at `height`.
+    async fn get_blobs(&self, height: u64, namespace: Namespace) -> Result<Vec<Blob>>;
+
+    /// Returns the height of the latest block.
+    async fn network_height(&self) -> Result<u64>;
+
+    /// Returns the hashes of the block at `height` and of its parent.
+    async fn block_id(&self, height: u64) -> Result<DaBlockId>;
+
+    /// Streams the blobs of `namespace` for every new block.
+    async fn subscribe(&self, namespace: Namespace) -> Result<BlobStream>;
+
+    /// Returns the inclusion proof of the blob with `commitment` at
+    /// `height`. Only supported by layers backed by a celestia-node.
+    async fn blob_proof(
+        &self,
+        _height: u64,
+        _namespace: Namespace,
+        _commitment: Commitment,
+    ) -> Result<BlobProof> {
+        Err(anyhow!("Blob inclusion proofs require a celestia-node"))
+    }
+}
+
+/// How often and how patiently blob submissions are retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Total number of submission attempts, including the first one.
+    pub max_attempts: u32,
+    /// The delay before the first retry, doubled after every failed attempt.
+    pub initial_backoff: Duration,
+    /// The upper bound for the delay between attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Submits `blobs`, retrying with exponential backoff according to `policy`.
+/// Returns the error of the last attempt if all attempts fail.
+#[tracing::instrument(skip_all, fields(blobs = blobs.len()))]
+pub async fn submit_with_retry(
+    da: &dyn DataAvailability,
+    blobs: &[Blob],
+    policy: &RetryPolicy,
+) -> Result<Submission> {
+    let mut backoff = policy.initial_backoff;
+    let mut attempt = 1;
+    loop {
+        match da.submit(blobs).await {
+            Ok(submission) => return Ok(submission),
+            Err(e) if attempt >= policy.max_attempts => {
+                return Err(e.context(format!("Blob submission failed {} times", attempt)));
+            }
+            Err(e) => {
+                warn!(
+                    "blob submission attempt {}/{} failed, retrying in {:?}: {}",
+                    attempt, policy.max_attempts, backoff, e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(policy.max_backoff);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Selects the data availability layer a node uses.
+#[derive(ValueEnum, Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DaKind {
+    /// A celestia-node reached via RPC.
+    #[default]
+    Celestia,
+    /// An in-process chain for local development, see [`MockDA`].
+    Mock,
+    /// Blocks stored in a directory shared between the nodes of a private
+    /// deployment, see [`file::FileDA`].
+    File,
+}
+
+/// How a node syncs from Celestia.
+#[derive(ValueEnum, Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DaMode {
+    /// Sync and submit through a celestia-node's RPC.
+    #[default]
+    Rpc,
+    /// Sync through an embedded Lumina light node, only using the RPC for
+    /// submitting blobs. Requires the `lumina` feature.
+    Lumina,
+}
+
+/// The factor the gas price is raised by after a submission failed for an
+/// insufficient fee.
+const GAS_PRICE_BUMP: f64 = 1.5;
+
+/// How blob submissions to Celestia are paid for and signed. Unset values
+/// are left to the celestia-node.
+#[derive(Clone, Debug, Default)]
+pub struct CelestiaTxOptions {
+    /// The gas price (utia per unit of gas) to pay. Estimated by the
+    /// celestia-node if unset.
+    pub gas_price: Option<f64>,
+    /// The upper bound for the gas price when it is raised after an
+    /// insufficient fee error. Unbounded if unset.
+    pub max_gas_price: Option<f64>,
+    /// The key of the celestia-node's keyring to sign with
+    pub key_name: Option<String>,
+    /// The bech32 address to sign with
+    pub signer_address: Option<String>,
+    /// The bech32 address of an account granting the signer a fee allowance
+    pub fee_granter_address: Option<String>,
+}
+
+/// The number of consecutive failed RPC calls after which [`CelestiaDA`]
+/// reconnects to the next endpoint.
+const FAILOVER_AFTER_ERRORS: u32 = 3;
+
+/// The endpoint [`CelestiaDA`] is currently connected to.
+struct Connection {
+    index: usize,
+    client: Arc<celestia_rpc::Client>,
+}
+
+/// Celestia, accessed via the RPC of a celestia-node.
+///
+/// Multiple endpoints can be given for failover: after
+/// [`FAILOVER_AFTER_ERRORS`] consecutive failed calls, the next reachable
+/// endpoint is connected to, in the order given. Subscriptions of the
+/// replaced connection end, so subscribers have to resubscribe.
+pub struct CelestiaDA {
+    endpoints: Vec<String>,
+    auth_token: Option<String>,
+    connection: tokio::sync::RwLock<Connection>,
+    consecutive_errors: AtomicU32,
+    key_name: Option<String>,
+    signer_address: Option<AccAddress>,
+    fee_granter_address: Option<AccAddress>,
+    max_gas_price: Option<f64>,
+    /// The gas price submissions pay, raised after insufficient fee errors
+    /// and kept for later submissions.
+    gas_price: Mutex<Option<f64>>,
+}
+
+impl CelestiaDA {
+    pub async fn new(
+        endpoints: &[String],
+        auth_token: Option<&str>,
+        options: &CelestiaTxOptions,
+    ) -> Result<Self> {
+        if endpoints.is_empty() {
+            return Err(anyhow!("At least one Celestia endpoint is required"));
+        }
+        let parse_address = |address: &Option<String>| {
+            address
+                .as_deref()
+                .map(|address| {
+                    address
+                        .parse::<AccAddress>()
+                        .map_err(|e| anyhow!("Invalid Celestia address {}: {}", address, e))
+                })
+                .transpose()
+        };
+        let signer_address = parse_address(&options.signer_address)?;
+        let fee_granter_address = parse_address(&options.fee_granter_address)?;
+
+        let mut connection = None;
+        for (index, url) in endpoints.iter().enumerate() {
+            match connect(url, auth_token).await {
+                Ok(client) => {
+                    connection = Some(Connection {
+                        index,
+                        client: Arc::new(client),
+                    });
+                    break;
+                }
+                Err(e) if index + 1 < endpoints.len() => warn!("{:#}, trying the next one", e),
+                Err(e) => return Err(e),
+            }
+        }
+        let connection = connection.ok_or_else(|| anyhow!("No Celestia endpoint reachable"))?;
+
+        Ok(CelestiaDA {
+            endpoints: endpoints.to_vec(),
+            auth_token: auth_token.map(str::to_string),
+            connection: tokio::sync::RwLock::new(connection),
+            consecutive_errors: AtomicU32::new(0),
+            key_name: options.key_name.clone(),
+            signer_address,
+            fee_granter_address,
+            max_gas_price: options.max_gas_price,
+            gas_price: Mutex::new(options.gas_price),
+        })
+    }
+
+    /// Runs an RPC call against the current endpoint, failing over to the
+    /// next one once calls kept failing.
+    async fn call<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(Arc<celestia_rpc::Client>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let (index, client) = {
+            let connection = self.connection.read().await;
+            (connection.index, connection.client.clone())
+        };
+        let result = f(client).await;
+        if result.is_ok() {
+            self.consecutive_errors.store(0, Ordering::Relaxed);
+        } else if self.consecutive_errors.fetch_add(1, Ordering::Relaxed) + 1
+            >= FAILOVER_AFTER_ERRORS
+        {
+            self.failover(index).await;
+        }
+        result
+    }
+
+    /// Connects to the next reachable endpoint after the one at `failed`,
+    /// wrapping around to reconnect to it if no other one is reachable.
+    async fn failover(&self, failed: usize) {
+        let mut connection = self.connection.write().await;
+        // a concurrent call already failed over
+        if connection.index != failed {
+            return;
+        }
+        for offset in 1..=self.endpoints.len() {
+            let index = (failed + offset) % self.endpoints.len();
+            let url = &self.endpoints[index];
+            match connect(url, self.auth_token.as_deref()).await {
+                Ok(client) => {
+                    warn!(
+                        "Celestia endpoint {} kept failing, switched to {}",
+                        self.endpoints[failed], url
+                    );
+                    *connection = Connection {
+                        index,
+                        client: Arc::new(client),
+                    };
+                    self.consecutive_errors.store(0, Ordering::Relaxed);
+                    return;
+                }
+                Err(e) => warn!("{:#}", e),
+            }
+        }
+        error!("no Celestia endpoint reachable");
+    }
+
+    fn tx_config(&self) -> TxConfig {
+        TxConfig {
+            signer_address: self.signer_address.clone(),
+            key_name: self.key_name.clone(),
+            gas_price: *self.gas_price.lock().unwrap(),
+            fee_granter_address: self.fee_granter_address.clone(),
+            ..TxConfig::default()
+        }
+    }
+
+    /// Raises the gas price by [`GAS_PRICE_BUMP`], up to the maximum gas
+    /// price. Starts from the default gas price if none was set.
+    fn bump_gas_price(&self) {
+        let mut gas_price = self.gas_price.lock().unwrap();
+        let mut bumped = gas_price.unwrap_or(DEFAULT_CELESTIA_GAS_PRICE) * GAS_PRICE_BUMP;
+        if let Some(max_gas_price) = self.max_gas_price {
+            if *gas_price == Some(max_gas_price) {
+                warn!(
+                    "insufficient fee at the maximum gas price {}",
+                    max_gas_price
+                );
+                return;
+            }
+            bumped = bumped.min(max_gas_price);
+        }
+        warn!("insufficient fee, raising the gas price to {} utia", bumped);
+        *gas_price = Some(bumped);
+    }
+}
+
+#[async_trait]
+impl DataAvailability for CelestiaDA {
+    async fn submit(&self, blobs: &[Blob]) -> Result<Submission> {
+        let tx_config = self.tx_config();
+        // submitted as a PayForBlobs transaction rather than via
+        // blob_submit, which only returns the height
+        let result = self
+            .call(|client| async move {
+                let response =
+                    StateClient::state_submit_pay_for_blob(&*client, blobs, tx_config).await?;
+                Ok(Submission {
+                    height: response.height.value(),
+                    tx_hash: Some(response.txhash),
+                })
+            })
+            .await;
+        // the next attempt pays the raised price
+        if result
+            .as_ref()
+            .is_err_and(|e| e.to_string().contains("insufficient fee"))
+        {
+            self.bump_gas_price();
+        }
+        result
+    }
+
+    async fn get_blobs(&self, height: u64, namespace: Namespace) -> Result<Vec<Blob>> {
+        self.call(|client| async move {
+            let blobs = BlobClient::blob_get_all(&*client, height, &[namespace]).await?;
+            Ok(blobs.unwrap_or_default())
+        })
+        .await
+    }
+
+    async fn network_height(&self) -> Result<u64> {
+        self.call(|client| async move {
+            let network_head = HeaderClient::header_network_head(&*client).await?;
+            Ok(network_head.height().value())
+        })
+        .await
+    }
+
+    async fn block_id(&self, height: u64) -> Result<DaBlockId> {
+        self.call(|client| async move {
+            let header = HeaderClient::header_get_by_height(&*client, height).await?;
+            Ok(DaBlockId::from(&header))
+        })
+        .await
+    }
+
+    async fn subscribe(&self, namespace: Namespace) -> Result<BlobStream> {
+        let subscription = self
+            .call(|client| async move {
+                BlobClient::blob_subscribe(&*client, namespace)
+                    .await
+                    .context("Failed to subscribe to namespace")
+            })
+            .await?;
+        Ok(Box::pin(subscription.map(|result| {
+            result
+                .map(|response| (response.height, response.blobs.unwrap_or_default()))
+                .map_err(|e| anyhow!(e))
+        })))
+    }
+
+    async fn blob_proof(
+        &self,
+        height: u64,
+        namespace: Namespace,
+        commitment: Commitment,
+    ) -> Result<BlobProof> {
+        self.call(|client| async move {
+            let header = HeaderClient::header_get_by_height(&*client, height).await?;
+            let nmt_proofs =
+                BlobClient::blob_get_proof(&*client, height, namespace, commitment).await?;
+            Ok(BlobProof {
+                height,
+                namespace,
+                commitment,
+                data_root: header.header.data_hash.map_or(Digest::zero(), digest),
+                dah: header.dah,
+                nmt_proofs,
+            })
+        })
+        .await
+    }
+}
+
+async fn connect(url: &str, auth_token: Option<&str>) -> Result<celestia_rpc::Client> {
+    celestia_rpc::Client::new(url, auth_token)
+        .await
+        .with_context(|| format!("Couldn't start RPC connection to celestia-node at {}", url))
+}
+
+/// An in-process DA layer for local development and tests. Submitted blobs
+/// are included in the next block, which is produced every `block_time` or
+/// manually via [`MockDA::produce_block`].
+pub struct MockDA {
+    /// Blobs of every produced block, where index 0 is height 1
+    blocks: Arc<RwLock<Vec<Vec<Blob>>>>,
+    /// Blobs submitted since the last block
+    pending: RwLock<Vec<Blob>>,
+    /// The fork every block was produced on, where index 0 is height 1.
+    /// Part of the block hashes, so reorged blocks hash differently
+    forks: RwLock<Vec<u64>>,
+    reorgs: AtomicU64,
+    new_blocks: broadcast::Sender<u64>,
+}
+
+impl Default for MockDA {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockDA {
+    pub fn new() -> Self {
+        let (new_blocks, _) = broadcast::channel(1024);
+        MockDA {
+            blocks: Arc::new(RwLock::new(Vec::new())),
+            pending: RwLock::new(Vec::new()),
+            forks: RwLock::new(Vec::new()),
+            reorgs: AtomicU64::new(0),
+            new_blocks,
+        }
+    }
+
+    /// Produces a block every `block_time` in the background.
+    pub fn spawn_block_production(self: &Arc<Self>, block_time: Duration) {
+        let da = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(block_time);
+            loop {
+                interval.tick().await;
+                if let Err(e) = da.produce_block() {
+                    error!("producing mock block: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Includes all pending blobs in a new block and returns its height.
+    pub fn produce_block(&self) -> Result<u64> {
+        let blobs = std::mem::take(&mut *self.pending.write().map_err(|e| anyhow!("{}", e))?);
+        let height = {
+            let mut blocks = self.blocks.write().map_err(|e| anyhow!("{}", e))?;
+            let mut forks = self.forks.write().map_err(|e| anyhow!("{}", e))?;
+            blocks.push(blobs);
+            forks.push(self.reorgs.load(Ordering::Relaxed));
+            blocks.len() as u64
+        };
+        // an error only means there are no subscribers
+        let _ = self.new_blocks.send(height);
+        Ok(height)
+    }
+
+    /// Replaces the last `depth` blocks with empty ones on a new fork, as
+    /// if Celestia reorged. Subscribers only notice once the next block is
+    /// produced on top of them.
+    pub fn reorg(&self, depth: u64) -> Result<()> {
+        let mut blocks = self.blocks.write().map_err(|e| anyhow!("{}", e))?;
+        let mut forks = self.forks.write().map_err(|e| anyhow!("{}", e))?;
+        if depth > blocks.len() as u64 {
+            return Err(anyhow!(
+                "Can't reorg {} blocks, only {} were produced",
+                depth,
+                blocks.len()
+            ));
+        }
+        let fork = self.reorgs.fetch_add(1, Ordering::Relaxed) + 1;
+        let start = blocks.len() - depth as usize;
+        for (blobs, block_fork) in blocks[start..].iter_mut().zip(&mut forks[start..]) {
+            blobs.clear();
+            *block_fork = fork;
+        }
+        Ok(())
+    }
+
+    /// Returns the fork the block at `height` was produced on, 0 for
+    /// heights before the first block.
+    fn fork_at(&self, height: u64) -> Result<u64> {
+        let forks = self.forks.read().map_err(|e| anyhow!("{}", e))?;
+        Ok(height
+            .checked_sub(1)
+            .and_then(|i| forks.get(i as usize))
+            .copied()
+            .unwrap_or(0))
+    }
+}
+
+fn blobs_at(
+    blocks: &RwLock<Vec<Vec<Blob>>>,
+    height: u64,
+    namespace: Namespace,
+) -> Result<Vec<Blob>> {
+    let blocks = blocks.read().map_err(|e| anyhow!("{}", e))?;
+    let block = match height.checked_sub(1).and_then(|i| blocks.get(i as usize)) {
+        Some(block) => block,
+        None => return Err(anyhow!("Height {} has not been produced yet", height)),
+    };
+    Ok(block
+        .iter()
+        .filter(|blob| blob.namespace == namespace)
+        .cloned()
+        .collect())
+}
+
+#[async_trait]
+impl DataAvailability for MockDA {
+    async fn submit(&self, blobs: &[Blob]) -> Result<Submission> {
+        let mut pending = self.pending.write().map_err(|e| anyhow!("{}", e))?;
+        pending.extend_from_slice(blobs);
+        let blocks = self.blocks.read().map_err(|e| anyhow!("{}", e))?;
+        Ok(Submission {
+            height: blocks.len() as u64 + 1,
+            tx_hash: None,
+        })
+    }
+
+    async fn get_blobs(&self, height: u64, namespace: Namespace) -> Result<Vec<Blob>> {
+        blobs_at(&self.blocks, height, namespace)
+    }
+
+    async fn network_height(&self) -> Result<u64> {
+        let blocks = self.blocks.read().map_err(|e| anyhow!("{}", e))?;
+        Ok(blocks.len() as u64)
+    }
+
+    async fn block_id(&self, height: u64) -> Result<DaBlockId> {
+        // hashes only need to be unique, per fork after a `reorg`
+        let hash = |height: u64| -> Result<Digest> {
+            let fork = self.fork_at(height)?;
+            Ok(Digest::hash_items(&[
+                height.to_be_bytes(),
+                fork.to_be_bytes(),
+            ]))
+        };
+        Ok(DaBlockId {
+            hash: hash(height)?,
+            parent_hash: hash(height.saturating_sub(1))?,
+        })
+    }
+
+    async fn subscribe(&self, namespace: Namespace) -> Result<BlobStream> {
+        let receiver = self.new_blocks.subscribe();
+        let blocks = self.blocks.clone();
+        Ok(Box::pin(stream::unfold(receiver, move |mut receiver| {
+            let blocks = blocks.clone();
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(height) => {
+                            let result =
+                                blobs_at(&blocks, height, namespace).map(|blobs| (height, blobs));
+                            return Some((result, receiver));
+                        }
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("mock DA subscriber lagged, skipped {} blocks", skipped);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            }
+        })))
+    }
+}
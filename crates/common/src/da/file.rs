@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use celestia_types::{nmt::Namespace, Blob};
+use futures::stream;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::{BlobStream, DaBlockId, DataAvailability, Submission};
+use crate::tree::Digest;
+
+/// How often readers check the directory for new blocks.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// The file holding the height of the latest block, written after the
+/// block itself.
+const HEAD_FILE: &str = "head";
+
+/// A block as stored in the DA directory.
+#[derive(Serialize, Deserialize)]
+struct FileBlock {
+    parent_hash: Digest,
+    blobs: Vec<Blob>,
+}
+
+/// A DA layer for private deployments that don't need a public chain: blocks
+/// are JSON files in a directory shared between the nodes, e.g. a network
+/// file system or a mounted object store bucket.
+///
+/// Only the sequencer writes. It includes submitted blobs in the next block,
+/// produced every `block_time` by [`FileDA::spawn_block_production`], and
+/// the other nodes poll the directory for new blocks. A block's hash is the
+/// hash of its file, so replacing written blocks shows up as a reorg.
+pub struct FileDA {
+    dir: PathBuf,
+    /// Blobs submitted since the last block
+    pending: Mutex<Vec<Blob>>,
+}
+
+impl FileDA {
+    /// Opens the DA directory `dir`, creating it if it doesn't exist.
+    pub fn new(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create DA directory {}", dir.display()))?;
+        Ok(FileDA {
+            dir: dir.to_path_buf(),
+            pending: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Produces a block every `block_time` in the background. Must only be
+    /// called on the node writing the directory.
+    pub fn spawn_block_production(self: &Arc<Self>, block_time: Duration) {
+        let da = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(block_time);
+            loop {
+                interval.tick().await;
+                if let Err(e) = da.produce_block().await {
+                    error!("producing file DA block: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Writes all pending blobs to a new block and returns its height.
+    pub async fn produce_block(&self) -> Result<u64> {
+        let blobs = std::mem::take(&mut *self.pending.lock().unwrap());
+        let height = head(&self.dir).await? + 1;
+        let parent_hash = match height {
+            1 => Digest::zero(),
+            _ => Digest::hash(read_block_file(&self.dir, height - 1).await?),
+        };
+        let block = serde_json::to_vec(&FileBlock { parent_hash, blobs })?;
+        // written to temporary files and renamed, so readers never see
+        // partial blocks
+        write_atomic(&block_path(&self.dir, height), &block).await?;
+        write_atomic(&self.dir.join(HEAD_FILE), height.to_string().as_bytes()).await?;
+        Ok(height)
+    }
+}
+
+fn block_path(dir: &Path, height: u64) -> PathBuf {
+    dir.join(format!("{:020}.json", height))
+}
+
+async fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, contents)
+        .await
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    tokio::fs::rename(&tmp, path)
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Returns the height of the latest block, 0 if none was produced yet.
+async fn head(dir: &Path) -> Result<u64> {
+    match tokio::fs::read_to_string(dir.join(HEAD_FILE)).await {
+        Ok(head) => head.trim().parse().context("Invalid DA head file"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn read_block_file(dir: &Path, height: u64) -> Result<Vec<u8>> {
+    tokio::fs::read(block_path(dir, height))
+        .await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                anyhow!("Height {} has not been produced yet", height)
+            }
+            _ => anyhow!("Failed to read block {}: {}", height, e),
+        })
+}
+
+async fn blobs_at(dir: &Path, height: u64, namespace: Namespace) -> Result<Vec<Blob>> {
+    let block: FileBlock = serde_json::from_slice(&read_block_file(dir, height).await?)
+        .with_context(|| format!("Invalid block file at height {}", height))?;
+    Ok(block
+        .blobs
+        .into_iter()
+        .filter(|blob| blob.namespace == namespace)
+        .collect())
+}
+
+#[async_trait]
+impl DataAvailability for FileDA {
+    async fn submit(&self, blobs: &[Blob]) -> Result<Submission> {
+        self.pending.lock().unwrap().extend_from_slice(blobs);
+        Ok(Submission {
+            height: head(&self.dir).await? + 1,
+            tx_hash: None,
+        })
+    }
+
+    async fn get_blobs(&self, height: u64, namespace: Namespace) -> Result<Vec<Blob>> {
+        blobs_at(&self.dir, height, namespace).await
+    }
+
+    async fn network_height(&self) -> Result<u64> {
+        head(&self.dir).await
+    }
+
+    async fn block_id(&self, height: u64) -> Result<DaBlockId> {
+        let bytes = read_block_file(&self.dir, height).await?;
+        let block: FileBlock = serde_json::from_slice(&bytes)
+            .with_context(|| format!("Invalid block file at height {}", height))?;
+        Ok(DaBlockId {
+            hash: Digest::hash(&bytes),
+            parent_hash: block.parent_hash,
+        })
+    }
+
+    async fn subscribe(&self, namespace: Namespace) -> Result<BlobStream> {
+        let dir = self.dir.clone();
+        let next_height = head(&dir).await? + 1;
+        Ok(Box::pin(stream::unfold(next_height, move |height| {
+            let dir = dir.clone();
+            async move {
+                loop {
+                    match head(&dir).await {
+                        Ok(head) if head >= height => break,
+                        Ok(_) => tokio::time::sleep(POLL_INTERVAL).await,
+                        Err(e) => return Some((Err(e), height)),
+                    }
+                }
+                let result = blobs_at(&dir, height, namespace)
+                    .await
+                    .map(|blobs| (height, blobs));
+                Some((result, height + 1))
+            }
+        })))
+    }
+}
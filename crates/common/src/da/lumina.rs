@@ -0,0 +1,167 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use celestia_types::{nmt::Namespace, Blob, Commitment};
+use clap::ValueEnum;
+use futures::stream;
+use libp2p_identity::Keypair;
+use lumina_node::{
+    blockstore::InMemoryBlockstore,
+    network::{canonical_network_bootnodes, network_genesis, network_id, Network},
+    store::InMemoryStore,
+    Node, NodeConfig,
+};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+
+use super::{BlobProof, BlobStream, CelestiaDA, DaBlockId, DataAvailability, Submission};
+
+/// How long to wait for the shares of a namespace to be retrieved via p2p.
+const BLOB_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often the local head is checked for new headers.
+const HEAD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const SYNC_BATCH_SIZE: u64 = 512;
+
+/// The Celestia network the embedded Lumina node joins.
+#[derive(ValueEnum, Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LuminaNetwork {
+    #[default]
+    Mainnet,
+    Arabica,
+    Mocha,
+}
+
+impl From<LuminaNetwork> for Network {
+    fn from(network: LuminaNetwork) -> Self {
+        match network {
+            LuminaNetwork::Mainnet => Network::Mainnet,
+            LuminaNetwork::Arabica => Network::Arabica,
+            LuminaNetwork::Mocha => Network::Mocha,
+        }
+    }
+}
+
+/// Syncs headers and blobs through an embedded Lumina light node, which
+/// verifies data availability by sampling instead of trusting an RPC
+/// endpoint. Blob submission still goes through a celestia-node, since
+/// Lumina can't submit transactions yet.
+pub struct LuminaDA {
+    node: Arc<Node<InMemoryBlockstore, InMemoryStore>>,
+    submitter: Option<CelestiaDA>,
+}
+
+impl LuminaDA {
+    /// Starts the light node and waits until it is connected to peers.
+    /// Without a `submitter`, [`DataAvailability::submit`] fails, which is
+    /// fine for nodes that don't post blobs.
+    pub async fn new(network: LuminaNetwork, submitter: Option<CelestiaDA>) -> Result<Self> {
+        let network = Network::from(network);
+        let config = NodeConfig {
+            network_id: network_id(network).to_owned(),
+            genesis_hash: network_genesis(network),
+            p2p_local_keypair: Keypair::generate_ed25519(),
+            p2p_bootnodes: canonical_network_bootnodes(network).collect(),
+            p2p_listen_on: vec![],
+            sync_batch_size: SYNC_BATCH_SIZE,
+            custom_syncing_window: None,
+            blockstore: InMemoryBlockstore::new(),
+            store: InMemoryStore::new(),
+        };
+
+        let node = Node::new(config)
+            .await
+            .context("Failed to start Lumina node")?;
+        node.wait_connected()
+            .await
+            .context("Lumina node couldn't connect to peers")?;
+        info!("lumina node connected to {:?}", network);
+
+        Ok(LuminaDA {
+            node: Arc::new(node),
+            submitter,
+        })
+    }
+}
+
+async fn blobs_at(
+    node: &Node<InMemoryBlockstore, InMemoryStore>,
+    height: u64,
+    namespace: Namespace,
+) -> Result<Vec<Blob>> {
+    let header = node.get_header_by_height(height).await?;
+    Ok(node
+        .request_all_blobs(&header, namespace, Some(BLOB_REQUEST_TIMEOUT))
+        .await?)
+}
+
+#[async_trait]
+impl DataAvailability for LuminaDA {
+    async fn submit(&self, blobs: &[Blob]) -> Result<Submission> {
+        match &self.submitter {
+            Some(submitter) => submitter.submit(blobs).await,
+            None => Err(anyhow!(
+                "Blob submission requires a celestia-node RPC endpoint"
+            )),
+        }
+    }
+
+    async fn get_blobs(&self, height: u64, namespace: Namespace) -> Result<Vec<Blob>> {
+        blobs_at(&self.node, height, namespace).await
+    }
+
+    async fn network_height(&self) -> Result<u64> {
+        let network_head = self.node.get_network_head_header().await?;
+        Ok(network_head.height().value())
+    }
+
+    async fn block_id(&self, height: u64) -> Result<DaBlockId> {
+        let header = self.node.get_header_by_height(height).await?;
+        Ok(DaBlockId::from(&header))
+    }
+
+    async fn subscribe(&self, namespace: Namespace) -> Result<BlobStream> {
+        let local_head = self.node.get_local_head_header().await?;
+        let next_height = local_head.height().value() + 1;
+        let node = self.node.clone();
+
+        // Lumina has no blob subscriptions, so the synced head is polled and
+        // the blobs of every new header are requested
+        Ok(Box::pin(stream::unfold(
+            (node, next_height),
+            move |(node, height)| async move {
+                loop {
+                    match node.get_local_head_header().await {
+                        Ok(head) if head.height().value() >= height => break,
+                        Ok(_) => {}
+                        Err(e) => {
+                            return Some((Err(anyhow!(e)), (node, height)));
+                        }
+                    }
+                    tokio::time::sleep(HEAD_POLL_INTERVAL).await;
+                }
+                match blobs_at(&node, height, namespace).await {
+                    Ok(blobs) => Some((Ok((height, blobs)), (node, height + 1))),
+                    Err(e) => {
+                        // the height is retried on the next poll
+                        tokio::time::sleep(HEAD_POLL_INTERVAL).await;
+                        Some((Err(e), (node, height)))
+                    }
+                }
+            },
+        )))
+    }
+
+    async fn blob_proof(
+        &self,
+        height: u64,
+        namespace: Namespace,
+        commitment: Commitment,
+    ) -> Result<BlobProof> {
+        match &self.submitter {
+            Some(submitter) => submitter.blob_proof(height, namespace, commitment).await,
+            None => Err(anyhow!(
+                "Blob inclusion proofs require a celestia-node RPC endpoint"
+            )),
+        }
+    }
+}
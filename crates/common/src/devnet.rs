@@ -0,0 +1,235 @@
+//! A local multi-node network for development: a sequencer and a number of
+//! full nodes, each running as a child process of `devnet up` with a
+//! generated config, sharing a generated sequencer key.
+//!
+//! By default the nodes share a [`crate::da::file::FileDA`] directory, whose
+//! blocks the sequencer produces. The in-process mock DA layer can't be
+//! shared between processes. With Celestia, a celestia-node can be launched
+//! alongside the nodes from a given command.
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::Serialize;
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+use tokio::process::{Child, Command};
+
+use crate::{
+    da::DaKind,
+    keys::{self, KeyFile, KeyScheme},
+    node::{shutdown_signal, NodeRole},
+};
+
+/// The name of the generated sequencer key in the devnet's keys directory.
+const SEQUENCER_KEY_NAME: &str = "sequencer";
+/// How long to wait for a child to exit after it was asked to.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct DevnetOptions {
+    /// The directory configs, keys, logs and the file DA blocks are kept in
+    pub dir: PathBuf,
+    /// The number of full nodes to run besides the sequencer
+    pub full_nodes: usize,
+    /// The webserver port of the sequencer, full nodes use the following
+    /// ones
+    pub base_port: u16,
+    /// Either [`DaKind::File`] or [`DaKind::Celestia`]
+    pub da: DaKind,
+    /// The interval at which the sequencer produces file DA blocks
+    pub block_time: u64,
+    pub celestia_url: String,
+    pub auth_token: Option<String>,
+    /// A command launching a local celestia-node, run through the shell
+    pub celestia_node_cmd: Option<String>,
+}
+
+/// The subset of the node config `devnet up` sets, written as each node's
+/// TOML config file.
+#[derive(Serialize)]
+struct NodeConfig {
+    role: NodeRole,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequencer_url: Option<String>,
+    sequencer_vk: String,
+    sequencer_key_name: String,
+    keys_dir: PathBuf,
+    listen_addr: String,
+    da: DaKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    da_dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mock_block_time: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    celestia_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth_token: Option<String>,
+    db_path: PathBuf,
+}
+
+/// A child process of the devnet.
+struct Process {
+    name: String,
+    child: Child,
+}
+
+/// Starts the devnet and runs it until ctrl-c or until one of its processes
+/// exits, then stops every process.
+pub async fn up(options: DevnetOptions) -> Result<()> {
+    if !matches!(options.da, DaKind::File | DaKind::Celestia) {
+        return Err(anyhow!("The devnet runs on the file or celestia DA layer"));
+    }
+    fs::create_dir_all(&options.dir)
+        .with_context(|| format!("Failed to create {}", options.dir.display()))?;
+    let dir = options.dir.canonicalize()?;
+    let keys_dir = dir.join("keys");
+    let sequencer_vk = sequencer_vk(&keys_dir)?;
+    let sequencer_url = format!("http://127.0.0.1:{}", options.base_port);
+
+    let mut processes = Vec::new();
+    let result = async {
+        if let Some(cmd) = &options.celestia_node_cmd {
+            let log = File::create(dir.join("celestia-node.log"))?;
+            let child = Command::new("sh")
+                .args(["-c", cmd])
+                .stdout(log.try_clone()?)
+                .stderr(log)
+                .kill_on_drop(true)
+                .spawn()
+                .context("Failed to launch the celestia-node")?;
+            processes.push(Process {
+                name: "celestia-node".to_string(),
+                child,
+            });
+        }
+
+        for i in 0..=options.full_nodes {
+            let (name, role) = match i {
+                0 => ("sequencer".to_string(), NodeRole::Sequencer),
+                i => (format!("full-{}", i), NodeRole::Full),
+            };
+            let node_dir = dir.join(&name);
+            fs::create_dir_all(&node_dir)?;
+            let port = options.base_port + i as u16;
+            let config = NodeConfig {
+                role,
+                sequencer_url: (role == NodeRole::Full).then(|| sequencer_url.clone()),
+                sequencer_vk: sequencer_vk.clone(),
+                sequencer_key_name: SEQUENCER_KEY_NAME.to_string(),
+                keys_dir: keys_dir.clone(),
+                listen_addr: format!("127.0.0.1:{}", port),
+                da: options.da,
+                da_dir: (options.da == DaKind::File).then(|| dir.join("da")),
+                mock_block_time: (options.da == DaKind::File).then_some(options.block_time),
+                celestia_url: (options.da == DaKind::Celestia)
+                    .then(|| options.celestia_url.clone()),
+                auth_token: options.auth_token.clone(),
+                db_path: node_dir.join("data"),
+            };
+            let config_path = node_dir.join("config.toml");
+            fs::write(&config_path, toml::to_string(&config)?)
+                .with_context(|| format!("Failed to write {}", config_path.display()))?;
+            processes.push(spawn_node(&name, &config_path, &node_dir)?);
+            println!(
+                "{:<12} http://127.0.0.1:{}  (log: {})",
+                name,
+                port,
+                node_dir.join("node.log").display()
+            );
+        }
+        println!("sequencer key {}: {}", SEQUENCER_KEY_NAME, sequencer_vk);
+        println!("keys in {}, press ctrl-c to stop", keys_dir.display());
+
+        tokio::select! {
+            _ = shutdown_signal() => Ok(()),
+            name = first_exit(&mut processes) => {
+                Err(anyhow!("{} exited, stopping the devnet", name))
+            }
+        }
+    }
+    .await;
+
+    for process in &mut processes {
+        stop(process).await;
+    }
+    result
+}
+
+/// Returns the base64 encoded key of the devnet's sequencer key, generating
+/// the key if it doesn't exist yet.
+fn sequencer_vk(keys_dir: &Path) -> Result<String> {
+    let key_file = match KeyFile::load(keys_dir, SEQUENCER_KEY_NAME)? {
+        Some(key_file) => key_file,
+        None => {
+            let signer = keystore_rs::create_signing_key();
+            let key_file = KeyFile::new(KeyScheme::Ed25519, &signer.to_bytes(), None)?;
+            key_file.save(keys_dir, SEQUENCER_KEY_NAME)?;
+            key_file
+        }
+    };
+    let vk = keys::verifying_key(&key_file.signing_key()?);
+    Ok(BASE64.encode(vk.as_bytes()))
+}
+
+/// Runs `serve` of the current executable with the config at `config_path`,
+/// logging to `node.log` in `node_dir`.
+fn spawn_node(name: &str, config_path: &Path, node_dir: &Path) -> Result<Process> {
+    let log = File::create(node_dir.join("node.log"))?;
+    let child = Command::new(std::env::current_exe()?)
+        .arg("serve")
+        .arg("--config")
+        .arg(config_path)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to launch {}", name))?;
+    Ok(Process {
+        name: name.to_string(),
+        child,
+    })
+}
+
+/// Resolves with the name of the first process to exit.
+async fn first_exit(processes: &mut [Process]) -> String {
+    if processes.is_empty() {
+        return std::future::pending().await;
+    }
+    let exits = processes.iter_mut().map(|process| {
+        Box::pin(async move {
+            let status = process.child.wait().await;
+            (process.name.clone(), status)
+        })
+    });
+    let ((name, status), _, _) = futures::future::select_all(exits).await;
+    match status {
+        Ok(status) => warn!("{} exited with {}", name, status),
+        Err(e) => warn!("waiting for {}: {}", name, e),
+    }
+    name
+}
+
+/// Asks `process` to shut down gracefully, killing it if it doesn't exit in
+/// time.
+async fn stop(process: &mut Process) {
+    #[cfg(unix)]
+    if let Some(pid) = process.child.id() {
+        // SIGTERM, handled by the node's shutdown
+        let _ = std::process::Command::new("kill")
+            .arg(pid.to_string())
+            .status();
+    }
+    match tokio::time::timeout(SHUTDOWN_TIMEOUT, process.child.wait()).await {
+        Ok(_) => info!("stopped {}", process.name),
+        Err(_) => {
+            warn!("{} didn't stop in time, killing it", process.name);
+            if let Err(e) = process.child.kill().await {
+                error!("killing {}: {}", process.name, e);
+            }
+        }
+    }
+}
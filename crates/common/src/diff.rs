@@ -0,0 +1,385 @@
+//! State diffs as an alternative DA format, see [`DaFormat::StateDiffs`].
+//!
+//! Instead of a batch's transactions the sequencer posts the tree writes
+//! they result in, signed together with the batch header. Full nodes apply
+//! the writes without executing anything, after checking that they lead
+//! from their current root to the signed new root. The transactions stay
+//! with the sequencer, which proves each diff by re-execution: the
+//! [`crate::stf::StfPublicValues`] of the proof commit to the diff's roots
+//! and to its header's tx root, attesting that valid transactions produced
+//! it.
+
+use anyhow::{anyhow, Result};
+use celestia_types::Blob;
+use clap::ValueEnum;
+use jmt::KeyHash;
+use prism_common::keys::{Signature, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block::{tx_root, BatchHeader},
+    encoding::{decode_blob, Decode, Decoder, Encode, Encoder},
+    error::{ExecutionError, TxError},
+    receipt::Receipt,
+    state::{State, StateReader},
+    status::TxStatus,
+    storage::NodeStore,
+    tree::Digest,
+    tx::{BatchSignature, Transaction},
+};
+
+/// Prepended to the payload of sequencer signatures over diff headers, so
+/// they can't be passed off as signatures over a batch.
+const DIFF_SIGNING_DOMAIN: &[u8] = b"zk-shard/state-diff/v1";
+
+/// The blob version state diffs were introduced in.
+const DIFF_BLOB_VERSION: u8 = 8;
+
+/// Starts the canonical encoding of a diff. A batch would have to be at a
+/// block height above 2^62 to start the same.
+const DIFF_MARKER: &[u8; 4] = b"DIFF";
+
+/// What the sequencer posts to [`crate::node::Config::namespace`].
+#[derive(ValueEnum, Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DaFormat {
+    /// Batches of transactions, which every node executes.
+    #[default]
+    Transactions,
+    /// Signed [`StateDiff`]s, which nodes apply without executing them.
+    /// Only the sequencer's diffs change the state: transactions posted
+    /// directly and bridge deposits aren't executed, full nodes keep no
+    /// receipts, the state doesn't record the last processed height, and
+    /// transactions can't be scheduled for a later height.
+    StateDiffs,
+}
+
+/// The tree writes of a batch, where `None` removes the key.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct StateDiff {
+    /// Commits to the executed transactions through its tx root and to the
+    /// root after the diff through its state root, which is always set
+    header: BatchHeader,
+    prev_root: Digest,
+    writes: Vec<(Digest, Option<Vec<u8>>)>,
+    signature: Option<BatchSignature>,
+}
+
+impl StateDiff {
+    /// Creates the diff of the block at `height` executing `txs` under
+    /// `protocol_version`, which takes the state from `prev_root` to
+    /// `new_root` by `writes`.
+    pub fn new(
+        height: u64,
+        timestamp: u64,
+        protocol_version: u32,
+        txs: &[Transaction],
+        prev_root: Digest,
+        new_root: Digest,
+        writes: Vec<(KeyHash, Option<Vec<u8>>)>,
+    ) -> Self {
+        StateDiff {
+            header: BatchHeader {
+                height,
+                tx_root: tx_root(txs),
+                timestamp,
+                state_root: Some(new_root),
+                protocol_version,
+            },
+            prev_root,
+            writes: writes
+                .into_iter()
+                .map(|(key, value)| (Digest::new(key.0), value))
+                .collect(),
+            signature: None,
+        }
+    }
+
+    /// Signs the diff header as the sequencer owning `key`.
+    pub fn sign(&mut self, key: &SigningKey, vk: VerifyingKey) {
+        let signature = key.sign(&self.header.signature_msg(DIFF_SIGNING_DOMAIN));
+        self.signature = Some(BatchSignature { vk, signature });
+    }
+
+    /// Checks that the diff was signed by the sequencer with key `vk`. The
+    /// writes aren't signed, but only the signed writes lead to the signed
+    /// new root.
+    pub fn verify_signature(&self, vk: &VerifyingKey) -> Result<()> {
+        let batch_signature = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| anyhow!("State diff is not signed"))?;
+        if batch_signature.vk != *vk {
+            return Err(anyhow!("State diff is signed by an unknown sequencer"));
+        }
+        vk.verify_signature(
+            &self.header.signature_msg(DIFF_SIGNING_DOMAIN),
+            &batch_signature.signature,
+        )
+        .map_err(|e| anyhow!("Invalid state diff signature: {}", e))
+    }
+
+    pub fn header(&self) -> &BatchHeader {
+        &self.header
+    }
+
+    pub fn prev_root(&self) -> Digest {
+        self.prev_root
+    }
+
+    pub fn new_root(&self) -> Digest {
+        self.header
+            .state_root
+            .expect("state diffs always carry a state root")
+    }
+
+    /// Returns the writes as tree keys and values.
+    pub fn writes(&self) -> Vec<(KeyHash, Option<Vec<u8>>)> {
+        self.writes
+            .iter()
+            .map(|(key, value)| (KeyHash(key.0), value.clone()))
+            .collect()
+    }
+}
+
+impl Encode for StateDiff {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_raw(DIFF_MARKER);
+        self.header.encode(enc);
+        self.prev_root.encode(enc);
+        enc.put_u32(self.writes.len() as u32);
+        for (key, value) in &self.writes {
+            key.encode(enc);
+            match value {
+                Some(value) => {
+                    enc.put_u8(1);
+                    enc.put_bytes(value);
+                }
+                None => enc.put_u8(0),
+            }
+        }
+        match &self.signature {
+            Some(batch_signature) => {
+                enc.put_u8(1);
+                batch_signature.vk.encode(enc);
+                batch_signature.signature.encode(enc);
+            }
+            None => enc.put_u8(0),
+        }
+    }
+}
+
+impl Decode for StateDiff {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        if dec.raw(DIFF_MARKER.len())? != DIFF_MARKER {
+            return Err(anyhow!("Not a state diff"));
+        }
+        let header = BatchHeader::decode(dec)?;
+        if header.state_root.is_none() {
+            return Err(anyhow!("State diff without a state root"));
+        }
+        let prev_root = Digest::decode(dec)?;
+        let len = dec.u32()?;
+        // don't trust the length for preallocation, it comes from the blob
+        let mut writes = Vec::new();
+        for _ in 0..len {
+            let key = Digest::decode(dec)?;
+            let value = match dec.u8()? {
+                0 => None,
+                1 => Some(dec.bytes()?.to_vec()),
+                tag => return Err(anyhow!("Invalid state diff value tag {}", tag)),
+            };
+            writes.push((key, value));
+        }
+        let signature = match dec.u8()? {
+            0 => None,
+            1 => Some(BatchSignature {
+                vk: VerifyingKey::decode(dec)?,
+                signature: Signature::decode(dec)?,
+            }),
+            tag => return Err(anyhow!("Invalid state diff signature tag {}", tag)),
+        };
+        Ok(StateDiff {
+            header,
+            prev_root,
+            writes,
+            signature,
+        })
+    }
+}
+
+impl TryFrom<&Blob> for StateDiff {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &Blob) -> Result<Self, Self::Error> {
+        decode_blob(&value.data, DIFF_BLOB_VERSION)?.ok_or_else(|| anyhow!("Not a versioned blob"))
+    }
+}
+
+/// A diff the sequencer posted that hasn't been applied yet, with the
+/// transactions only the sequencer knows.
+pub(crate) struct PendingDiff {
+    pub diff: StateDiff,
+    /// The executed transactions, in order
+    pub txs: Vec<Transaction>,
+    /// Their receipts as of the height they were executed at
+    pub receipts: Vec<Receipt>,
+}
+
+/// The outcome of executing a batch for a diff, see [`execute_for_diff`].
+pub(crate) struct DiffExecution {
+    pub prev_root: Digest,
+    pub new_root: Digest,
+    pub writes: Vec<(KeyHash, Option<Vec<u8>>)>,
+    pub txs: Vec<Transaction>,
+    pub receipts: Vec<Receipt>,
+    /// The transactions that failed and are left out of the diff
+    pub failed: Vec<(Transaction, ExecutionError)>,
+}
+
+/// Executes `txs` as of `da_height` on `fork`, a fork of the canonical
+/// state, after applying the `pending` diffs to it, so the diff continues
+/// the ones that aren't included yet. Transactions for other chains than
+/// `chain_id` fail.
+pub(crate) fn execute_for_diff<S: NodeStore>(
+    fork: &mut State<S>,
+    pending: &[PendingDiff],
+    txs: Vec<Transaction>,
+    chain_id: u64,
+    da_height: u64,
+) -> Result<DiffExecution> {
+    for pending in pending {
+        fork.apply_diff(&pending.diff)?;
+    }
+    let prev_root = fork.get_commitment()?;
+    fork.record_writes();
+
+    let mut execution = DiffExecution {
+        prev_root,
+        new_root: prev_root,
+        writes: Vec::new(),
+        txs: Vec::new(),
+        receipts: Vec::new(),
+        failed: Vec::new(),
+    };
+    for tx in txs {
+        let tx_hash = tx.hash();
+        let gas_used = tx.tx_type.gas();
+        let result = if tx.chain_id != chain_id {
+            Err(TxError::WrongChainId {
+                chain_id: tx.chain_id,
+                expected: chain_id,
+            }
+            .into())
+        } else if tx
+            .execute_at_da_height
+            .is_some_and(|height| height > da_height)
+        {
+            // nothing would execute them at their height
+            Err(anyhow!(
+                "Scheduled transactions aren't supported with state diffs"
+            ))
+        } else {
+            fork.process_tx(tx.clone(), da_height)
+        };
+        match result {
+            Ok(events) => {
+                execution.receipts.push(Receipt {
+                    tx_hash: hex::encode(tx_hash.0),
+                    status: TxStatus::Executed { da_height },
+                    gas_used,
+                    events,
+                });
+                execution.txs.push(tx);
+            }
+            Err(e) => execution.failed.push((tx, ExecutionError::from(&e))),
+        }
+    }
+    execution.new_root = fork.get_commitment()?;
+    execution.writes = fork.take_writes();
+    Ok(execution)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        keys,
+        state::NoncePolicy,
+        storage::InMemoryStore,
+        testkit::generate_key,
+        tx::{TransactionBuilder, TransactionType, LEGACY_CHAIN_ID},
+    };
+
+    fn new_state() -> State<InMemoryStore> {
+        State::new(Arc::new(InMemoryStore::default()), NoncePolicy::default()).unwrap()
+    }
+
+    #[test]
+    fn diff_applies_only_at_its_prev_root() {
+        let key = generate_key();
+        let txs = vec![
+            TransactionBuilder::new(TransactionType::Noop)
+                .sign(&key)
+                .unwrap(),
+            TransactionBuilder::new(TransactionType::Noop)
+                .nonce(1)
+                .chain_id(LEGACY_CHAIN_ID + 1)
+                .sign(&key)
+                .unwrap(),
+        ];
+        let mut fork = new_state();
+        let execution = execute_for_diff(&mut fork, &[], txs, LEGACY_CHAIN_ID, 1).unwrap();
+        assert_eq!(execution.txs.len(), 1);
+        assert_eq!(execution.receipts.len(), 1);
+        assert_eq!(execution.failed.len(), 1);
+        assert_ne!(execution.prev_root, execution.new_root);
+
+        let diff = StateDiff::new(
+            1,
+            0,
+            0,
+            &execution.txs,
+            execution.prev_root,
+            execution.new_root,
+            execution.writes,
+        );
+        let mut replica = new_state();
+        replica.apply_diff(&diff).unwrap();
+        assert_eq!(replica.get_commitment().unwrap(), diff.new_root());
+        assert!(replica.apply_diff(&diff).is_err());
+        assert_eq!(replica.get_commitment().unwrap(), diff.new_root());
+    }
+
+    #[test]
+    fn signed_diff_round_trips_through_its_encoding() {
+        let key = generate_key();
+        let vk = keys::verifying_key(&key);
+        let mut diff = StateDiff::new(
+            3,
+            0,
+            0,
+            &[],
+            Digest::new([1; 32]),
+            Digest::new([2; 32]),
+            vec![
+                (KeyHash([3; 32]), Some(b"value".to_vec())),
+                (KeyHash([4; 32]), None),
+            ],
+        );
+        assert!(diff.verify_signature(&vk).is_err());
+        diff.sign(&key, vk.clone());
+
+        let decoded = StateDiff::from_canonical_bytes(&diff.to_canonical_bytes()).unwrap();
+        assert_eq!(decoded.prev_root(), diff.prev_root());
+        assert_eq!(decoded.new_root(), diff.new_root());
+        assert_eq!(decoded.writes(), diff.writes());
+        decoded.verify_signature(&vk).unwrap();
+        let other = keys::verifying_key(&generate_key());
+        assert!(decoded.verify_signature(&other).is_err());
+
+        assert!(StateDiff::from_canonical_bytes(b"not a diff").is_err());
+    }
+}
@@ -0,0 +1,447 @@
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use prism_common::keys::{Signature, VerifyingKey};
+use std::borrow::Cow;
+
+use crate::tree::Digest;
+
+/// Prefix of every blob posted in the canonical format. Blobs without it
+/// are decoded as legacy bincode.
+pub const BLOB_MAGIC: &[u8; 4] = b"SHRD";
+
+/// The current version of the blob format. Bump it when the encoding of an
+/// existing type changes; new transaction types only need a new tag.
+///
+/// Version 1 batches carry no [`BatchHeader`](crate::block::BatchHeader),
+/// version 2 batches no sequencer signature. Version 4 added a flags byte
+/// after the version, see [`FLAG_ZSTD`], version 5 the transaction chain id,
+/// version 6 the transaction expiry, version 7 the state root in the batch
+/// header, version 8 the transaction cosignatures and state diffs, version 9
+/// length prefixes for the transactions of a batch, version 10 the
+/// transaction execution height, version 11 the protocol version in the
+/// batch header.
+pub const BLOB_VERSION: u8 = 11;
+
+/// Set if the blob body is zstd compressed.
+pub const FLAG_ZSTD: u8 = 1;
+const KNOWN_FLAGS: u8 = FLAG_ZSTD;
+
+const COMPRESSION_LEVEL: i32 = 3;
+/// Compressed bodies expanding beyond this are rejected, so a small blob
+/// can't make nodes allocate unbounded memory.
+const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+/// Blob data beyond this is rejected before decoding. Well above the blob
+/// size limit of Celestia, so it only bounds what other DA layers deliver.
+pub const MAX_BLOB_SIZE: usize = 16 * 1024 * 1024;
+
+/// A deterministic, language-independent binary encoding.
+///
+/// Integers are big-endian and fixed width, variable-length fields are
+/// prefixed with their length as a big-endian `u32`, and enums are encoded
+/// as a one byte tag followed by their fields in declaration order.
+pub trait Encode {
+    fn encode(&self, enc: &mut Encoder);
+
+    fn to_canonical_bytes(&self) -> Vec<u8> {
+        let mut enc = Encoder::default();
+        self.encode(&mut enc);
+        enc.finish()
+    }
+}
+
+pub trait Decode: Sized {
+    fn decode(dec: &mut Decoder) -> Result<Self>;
+
+    /// Decodes a value that spans all of `bytes`.
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut dec = Decoder::new(bytes);
+        let value = Self::decode(&mut dec)?;
+        dec.finish()?;
+        Ok(value)
+    }
+}
+
+#[derive(Default)]
+pub struct Encoder(Vec<u8>);
+
+impl Encoder {
+    pub fn put_u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub fn put_u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_bytes(&mut self, value: &[u8]) {
+        self.put_u32(value.len() as u32);
+        self.0.extend_from_slice(value);
+    }
+
+    /// Writes bytes without a length prefix, for fixed-size fields.
+    pub fn put_raw(&mut self, value: &[u8]) {
+        self.0.extend_from_slice(value);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    version: u8,
+}
+
+impl<'a> Decoder<'a> {
+    /// Creates a decoder for data in the current [`BLOB_VERSION`].
+    pub fn new(data: &'a [u8]) -> Self {
+        Self::with_version(data, BLOB_VERSION)
+    }
+
+    /// Creates a decoder for data in an earlier blob format `version`, for
+    /// types whose encoding changed between versions.
+    pub fn with_version(data: &'a [u8], version: u8) -> Self {
+        Decoder { data, version }
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.raw(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.raw(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.raw(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.raw(len)
+    }
+
+    /// Reads `len` bytes without a length prefix.
+    pub fn raw(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(anyhow!(
+                "Unexpected end of input: needed {} bytes, {} left",
+                len,
+                self.data.len()
+            ));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    /// Fails if there is unread input left.
+    pub fn finish(self) -> Result<()> {
+        if !self.data.is_empty() {
+            return Err(anyhow!("{} trailing bytes after value", self.data.len()));
+        }
+        Ok(())
+    }
+}
+
+impl Encode for Digest {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_raw(&self.0);
+    }
+}
+
+impl Decode for Digest {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(dec.raw(32)?);
+        Ok(Digest::new(bytes))
+    }
+}
+
+impl Encode for VerifyingKey {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_bytes(&self.as_bytes());
+    }
+}
+
+impl Decode for VerifyingKey {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        // the key type is determined by the length of the key bytes
+        let bytes = dec.bytes()?;
+        VerifyingKey::try_from(BASE64.encode(bytes)).context("Invalid verifying key")
+    }
+}
+
+/// Ed25519 signatures and compact secp256k1 and secp256r1 signatures are 64
+/// bytes.
+const SIGNATURE_LEN: usize = 64;
+
+impl Encode for Signature {
+    fn encode(&self, enc: &mut Encoder) {
+        match self {
+            Signature::Placeholder => enc.put_u8(0),
+            Signature::Ed25519(signature) => {
+                enc.put_u8(1);
+                enc.put_raw(&signature.to_bytes());
+            }
+            Signature::Secp256k1(signature) => {
+                enc.put_u8(2);
+                enc.put_raw(&signature.serialize_compact());
+            }
+            Signature::Secp256r1(signature) => {
+                enc.put_u8(3);
+                enc.put_raw(&signature.to_bytes());
+            }
+        }
+    }
+}
+
+impl Decode for Signature {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        match dec.u8()? {
+            0 => Ok(Signature::Placeholder),
+            1 => {
+                let mut bytes = [0u8; SIGNATURE_LEN];
+                bytes.copy_from_slice(dec.raw(SIGNATURE_LEN)?);
+                Ok(Signature::Ed25519(ed25519_consensus::Signature::from(
+                    bytes,
+                )))
+            }
+            2 => Ok(Signature::Secp256k1(
+                secp256k1::ecdsa::Signature::from_compact(dec.raw(SIGNATURE_LEN)?)
+                    .context("Invalid secp256k1 signature")?,
+            )),
+            3 => Ok(Signature::Secp256r1(
+                p256::ecdsa::Signature::from_slice(dec.raw(SIGNATURE_LEN)?)
+                    .context("Invalid secp256r1 signature")?,
+            )),
+            tag => Err(anyhow!("Invalid signature tag {}", tag)),
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_u32(self.len() as u32);
+        for item in self {
+            item.encode(enc);
+        }
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        let len = dec.u32()?;
+        // don't trust the length for preallocation, it comes from the blob
+        let mut items = Vec::new();
+        for _ in 0..len {
+            items.push(T::decode(dec)?);
+        }
+        Ok(items)
+    }
+}
+
+/// Encodes `value` as blob data: [`BLOB_MAGIC`], [`BLOB_VERSION`], the
+/// flags, then the canonical encoding of `value`. The encoding is zstd
+/// compressed if that makes it smaller.
+pub fn encode_blob<T: Encode>(value: &T) -> Vec<u8> {
+    let body = value.to_canonical_bytes();
+    let (flags, body) = match zstd::bulk::compress(&body, COMPRESSION_LEVEL) {
+        Ok(compressed) if compressed.len() < body.len() => (FLAG_ZSTD, compressed),
+        _ => (0, body),
+    };
+
+    let mut enc = Encoder::default();
+    enc.put_raw(BLOB_MAGIC);
+    enc.put_u8(BLOB_VERSION);
+    enc.put_u8(flags);
+    enc.put_raw(&body);
+    enc.finish()
+}
+
+/// Reads the header of blob data written by [`encode_blob`], returning the
+/// format version and the decompressed body. Returns `Ok(None)` if the data
+/// doesn't start with [`BLOB_MAGIC`], i.e. predates the versioned format.
+pub fn open_blob(data: &[u8]) -> Result<Option<(u8, Cow<'_, [u8]>)>> {
+    check_blob_size(data)?;
+    let Some(body) = data.strip_prefix(BLOB_MAGIC.as_slice()) else {
+        return Ok(None);
+    };
+    let mut dec = Decoder::new(body);
+    let version = dec.u8()?;
+    if version == 0 || version > BLOB_VERSION {
+        return Err(anyhow!("Unsupported blob version {}", version));
+    }
+    // blobs before version 4 have no flags
+    let flags = if version >= 4 { dec.u8()? } else { 0 };
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(anyhow!("Unknown blob flags {:#04x}", flags));
+    }
+
+    let body = dec.data;
+    if flags & FLAG_ZSTD != 0 {
+        let body = zstd::bulk::decompress(body, MAX_DECOMPRESSED_SIZE)
+            .context("Failed to decompress blob")?;
+        return Ok(Some((version, Cow::Owned(body))));
+    }
+    Ok(Some((version, Cow::Borrowed(body))))
+}
+
+/// Decodes blob data of a type introduced in blob version `since`, in the
+/// version of the blob. Types that were also posted before `since` should
+/// use [`open_blob`] instead.
+pub fn decode_blob<T: Decode>(data: &[u8], since: u8) -> Result<Option<T>> {
+    match open_blob(data)? {
+        Some((version, body)) if version >= since => {
+            let mut dec = Decoder::with_version(&body, version);
+            let value = T::decode(&mut dec)?;
+            dec.finish()?;
+            Ok(Some(value))
+        }
+        Some((version, _)) => Err(anyhow!("Unsupported blob version {}", version)),
+        None => Ok(None),
+    }
+}
+
+/// Fails if `data` exceeds [`MAX_BLOB_SIZE`].
+pub fn check_blob_size(data: &[u8]) -> Result<()> {
+    if data.len() > MAX_BLOB_SIZE {
+        return Err(anyhow!(
+            "Blob of {} bytes exceeds the maximum of {}",
+            data.len(),
+            MAX_BLOB_SIZE
+        ));
+    }
+    Ok(())
+}
+
+/// Deserializes legacy bincode blob data, bounding the allocations it
+/// makes by [`MAX_BLOB_SIZE`] instead of trusting its length prefixes.
+pub fn decode_legacy_blob<T: serde::de::DeserializeOwned>(data: &[u8]) -> Result<T> {
+    use bincode::Options;
+    check_blob_size(data)?;
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(MAX_BLOB_SIZE as u64)
+        .deserialize(data)
+        .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        keys::{signing_key_from_bytes, KeyScheme},
+        testkit::generate_key,
+    };
+
+    fn signatures() -> Vec<Signature> {
+        let ed25519 = generate_key();
+        let secp256k1 = signing_key_from_bytes(KeyScheme::Secp256k1, &[7; 32]).unwrap();
+        let secp256r1 = signing_key_from_bytes(KeyScheme::Secp256r1, &[7; 32]).unwrap();
+        vec![
+            ed25519.sign(b"message"),
+            secp256k1.sign(b"message"),
+            secp256r1.sign(b"message"),
+            Signature::Placeholder,
+        ]
+    }
+
+    #[test]
+    fn signatures_round_trip() {
+        for signature in signatures() {
+            let bytes = signature.to_canonical_bytes();
+            let decoded = Signature::from_canonical_bytes(&bytes).unwrap();
+            assert_eq!(decoded.to_canonical_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn signatures_are_tagged_raw_bytes() {
+        let signatures = signatures();
+        let [ed25519, secp256k1, secp256r1, placeholder] = &signatures[..] else {
+            unreachable!()
+        };
+        assert_eq!(ed25519.to_canonical_bytes()[0], 1);
+        assert_eq!(ed25519.to_canonical_bytes().len(), 1 + SIGNATURE_LEN);
+        assert_eq!(secp256k1.to_canonical_bytes()[0], 2);
+        assert_eq!(secp256k1.to_canonical_bytes().len(), 1 + SIGNATURE_LEN);
+        assert_eq!(secp256r1.to_canonical_bytes()[0], 3);
+        assert_eq!(secp256r1.to_canonical_bytes().len(), 1 + SIGNATURE_LEN);
+        assert_eq!(placeholder.to_canonical_bytes(), vec![0]);
+        assert!(Signature::from_canonical_bytes(&[4]).is_err());
+    }
+
+    fn blob(version: u8, flags: Option<u8>, body: &[u8]) -> Vec<u8> {
+        let mut data = BLOB_MAGIC.to_vec();
+        data.push(version);
+        data.extend(flags);
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn compressible_blobs_are_compressed() {
+        let value = vec![Digest::new([0; 32]); 64];
+        let data = encode_blob(&value);
+        assert_eq!(data[BLOB_MAGIC.len() + 1], FLAG_ZSTD);
+        assert!(data.len() < value.to_canonical_bytes().len());
+
+        let (version, body) = open_blob(&data).unwrap().unwrap();
+        assert_eq!(version, BLOB_VERSION);
+        assert_eq!(body.as_ref(), value.to_canonical_bytes());
+    }
+
+    #[test]
+    fn incompressible_blobs_are_stored() {
+        let value = vec![Digest::new([1; 32])];
+        let data = encode_blob(&value);
+        assert_eq!(
+            data,
+            blob(BLOB_VERSION, Some(0), &value.to_canonical_bytes())
+        );
+
+        let (_, body) = open_blob(&data).unwrap().unwrap();
+        assert_eq!(body.as_ref(), value.to_canonical_bytes());
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        assert!(open_blob(&blob(BLOB_VERSION, Some(0x80), b"body")).is_err());
+        assert!(open_blob(&blob(BLOB_VERSION, Some(FLAG_ZSTD | 0x02), b"body")).is_err());
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        assert!(open_blob(&blob(0, Some(0), b"body")).is_err());
+        assert!(open_blob(&blob(BLOB_VERSION + 1, Some(0), b"body")).is_err());
+    }
+
+    #[test]
+    fn blobs_before_version_4_have_no_flags() {
+        // the first body byte would be an unknown flag in later versions
+        let (version, body) = open_blob(&blob(3, None, b"\xffbody")).unwrap().unwrap();
+        assert_eq!(version, 3);
+        assert_eq!(body.as_ref(), b"\xffbody");
+        assert!(open_blob(&blob(4, None, b"\xffbody")).is_err());
+    }
+
+    #[test]
+    fn unversioned_data_is_not_opened() {
+        assert!(open_blob(b"legacy bincode").unwrap().is_none());
+    }
+}
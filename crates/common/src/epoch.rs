@@ -0,0 +1,222 @@
+use anyhow::{anyhow, Context, Result};
+use celestia_types::Commitment;
+use serde::{Deserialize, Serialize};
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    proofs::{Batch, Proof},
+    storage::NodeStore,
+    tree::Digest,
+};
+
+/// How often the proof streams of processed batches are sealed into an
+/// epoch and proven. Written as a number of batches, e.g. `10`, or as
+/// seconds, e.g. `30s`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EpochInterval {
+    Batches(u64),
+    Duration(Duration),
+}
+
+impl Default for EpochInterval {
+    fn default() -> Self {
+        EpochInterval::Batches(1)
+    }
+}
+
+impl FromStr for EpochInterval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let interval = match s.strip_suffix('s') {
+            Some(secs) => EpochInterval::Duration(Duration::from_secs(
+                secs.parse().context("Invalid number of seconds")?,
+            )),
+            None => EpochInterval::Batches(s.parse().context("Invalid number of batches")?),
+        };
+        if matches!(
+            interval,
+            EpochInterval::Batches(0) | EpochInterval::Duration(Duration::ZERO)
+        ) {
+            return Err(anyhow!("Epoch interval must be greater than zero"));
+        }
+        Ok(interval)
+    }
+}
+
+/// A sealed epoch, stored when it is handed to the prover so its roots are
+/// known even if the proof never arrives.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct EpochCommitment {
+    pub epoch: u64,
+    pub prev_root: Digest,
+    pub new_root: Digest,
+    /// The Celestia height the epoch was sealed at
+    pub da_height: u64,
+}
+
+fn epoch_commitment_key(epoch: u64) -> String {
+    format!("epoch_commitment:{}", epoch)
+}
+
+pub fn get_epoch_commitment<S: NodeStore + ?Sized>(
+    store: &S,
+    epoch: u64,
+) -> Result<Option<EpochCommitment>> {
+    match store.get_metadata(&epoch_commitment_key(epoch))? {
+        Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn put_epoch_commitment<S: NodeStore + ?Sized>(
+    store: &S,
+    commitment: &EpochCommitment,
+) -> Result<()> {
+    store.put_metadata(
+        &epoch_commitment_key(commitment.epoch),
+        &bincode::serialize(commitment)?,
+    )
+}
+
+/// Metadata key under which the latest epoch with a recorded
+/// [`EpochProofPointer`] is stored.
+const LATEST_PROVEN_EPOCH_KEY: &str = "latest_proven_epoch";
+
+/// Where the validity proof of an epoch was posted, so clients can fetch
+/// and verify it themselves. Recorded by the sequencer when posting the
+/// proof, and by full nodes when they find a proof of a root they executed.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct EpochProofPointer {
+    pub epoch: u64,
+    /// The root the proof attests to
+    pub root: Digest,
+    /// The Celestia height the proof was posted at
+    pub da_height: u64,
+    /// The commitment of the proof blob, identifying it at `da_height`
+    pub commitment: Commitment,
+}
+
+fn epoch_proof_pointer_key(epoch: u64) -> String {
+    format!("epoch_proof:{}", epoch)
+}
+
+pub fn get_epoch_proof_pointer<S: NodeStore + ?Sized>(
+    store: &S,
+    epoch: u64,
+) -> Result<Option<EpochProofPointer>> {
+    match store.get_metadata(&epoch_proof_pointer_key(epoch))? {
+        Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the pointer of the latest proven epoch.
+pub fn get_latest_epoch_proof_pointer<S: NodeStore + ?Sized>(
+    store: &S,
+) -> Result<Option<EpochProofPointer>> {
+    match store.get_metadata(LATEST_PROVEN_EPOCH_KEY)? {
+        Some(bytes) => get_epoch_proof_pointer(store, bincode::deserialize(&bytes)?),
+        None => Ok(None),
+    }
+}
+
+/// Stores `pointer`, advancing the latest proven epoch if it is newer.
+pub fn put_epoch_proof_pointer<S: NodeStore + ?Sized>(
+    store: &S,
+    pointer: &EpochProofPointer,
+) -> Result<()> {
+    store.put_metadata(
+        &epoch_proof_pointer_key(pointer.epoch),
+        &bincode::serialize(pointer)?,
+    )?;
+    let latest = get_latest_epoch_proof_pointer(store)?;
+    if latest.map_or(true, |latest| latest.epoch < pointer.epoch) {
+        store.put_metadata(
+            LATEST_PROVEN_EPOCH_KEY,
+            &bincode::serialize(&pointer.epoch)?,
+        )?;
+    }
+    Ok(())
+}
+
+struct PendingEpoch {
+    prev_root: Digest,
+    proofs: Vec<Proof>,
+    first_da_height: u64,
+    batch_commitments: Vec<Digest>,
+    batches: u64,
+    started: Instant,
+}
+
+/// Accumulates the proof streams of processed batches until the
+/// [`EpochInterval`] has passed, then seals them into a single
+/// [`Batch`] spanning the whole epoch.
+///
+/// Unsealed proof streams only live in memory, so the transitions of an
+/// epoch interrupted by a restart or a reorg stay unproven.
+pub struct EpochScheduler {
+    interval: EpochInterval,
+    pending: Option<PendingEpoch>,
+}
+
+impl EpochScheduler {
+    pub fn new(interval: EpochInterval) -> Self {
+        EpochScheduler {
+            interval,
+            pending: None,
+        }
+    }
+
+    /// Adds the proofs of the batches read at `da_height`, with blob
+    /// commitments `batch_commitments`, that took the state from
+    /// `prev_root` to `new_root`. Returns the epoch's [`Batch`] if the epoch
+    /// is due.
+    pub fn push(
+        &mut self,
+        prev_root: Digest,
+        new_root: Digest,
+        proofs: Vec<Proof>,
+        da_height: u64,
+        batch_commitments: Vec<Digest>,
+    ) -> Option<Batch> {
+        if !proofs.is_empty() {
+            let pending = self.pending.get_or_insert_with(|| PendingEpoch {
+                prev_root,
+                proofs: Vec::new(),
+                first_da_height: da_height,
+                batch_commitments: Vec::new(),
+                batches: 0,
+                started: Instant::now(),
+            });
+            pending.proofs.extend(proofs);
+            pending.batch_commitments.extend(batch_commitments);
+            pending.batches += 1;
+        }
+
+        let pending = self.pending.as_ref()?;
+        let due = match self.interval {
+            EpochInterval::Batches(batches) => pending.batches >= batches,
+            EpochInterval::Duration(duration) => pending.started.elapsed() >= duration,
+        };
+        if !due {
+            return None;
+        }
+        let pending = self.pending.take()?;
+        Some(Batch {
+            prev_root: pending.prev_root,
+            new_root,
+            proofs: pending.proofs,
+            da_height_range: (pending.first_da_height, da_height),
+            batch_commitments: pending.batch_commitments,
+        })
+    }
+
+    /// Drops the unsealed epoch, e.g. after the state was rolled back.
+    pub fn reset(&mut self) {
+        self.pending = None;
+    }
+}
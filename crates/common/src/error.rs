@@ -0,0 +1,296 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+
+/// Why a transaction was rejected, either when it was queued or when it was
+/// executed.
+///
+/// Functions return these wrapped in an [`anyhow::Error`], so callers that
+/// need to tell rejections apart from internal failures can `downcast_ref`.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq, Eq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum TxError {
+    /// The signature doesn't verify against the sender's key.
+    InvalidSignature {
+        reason: String,
+    },
+    /// Transfers, mints and burns must move a non-zero amount.
+    ZeroAmount,
+    /// The nonce has already been used by the account.
+    NonceTooLow {
+        nonce: u64,
+        account_nonce: u64,
+    },
+    /// The nonce skips ahead of the account's next nonce, which the strict
+    /// nonce policy doesn't allow.
+    NonceGap {
+        nonce: u64,
+        expected: u64,
+    },
+    /// The balance can't cover the transaction's amount and fee.
+    InsufficientBalance,
+    BalanceOverflow,
+    /// The nonce is the last one, the account can't send any more
+    /// transactions.
+    NonceOverflow,
+    /// Contract code or call input exceeds its size limit.
+    PayloadTooLarge {
+        size: usize,
+        max: usize,
+    },
+    GasPriceTooLow {
+        gas_price: u64,
+        min_gas_price: u64,
+    },
+    /// The same transaction is already in the mempool.
+    AlreadyQueued,
+    /// The sender already has a different transaction with this nonce in the
+    /// mempool.
+    NonceAlreadyQueued {
+        nonce: u64,
+    },
+    MempoolFull,
+    /// The sequencer is busy with earlier submissions, retry later.
+    MempoolBusy,
+    /// The key is already authorized on the account.
+    KeyAlreadyAuthorized,
+    /// The key to revoke isn't authorized on the account.
+    KeyNotAuthorized,
+    /// Revoking the key would leave the account without authorized keys.
+    LastKey,
+    TooManyKeys {
+        max: usize,
+    },
+    /// Multisig thresholds must be at least one and at most the number of
+    /// authorized keys.
+    InvalidThreshold {
+        threshold: u32,
+        keys: usize,
+    },
+    /// Fewer authorized keys signed than the multisig account requires.
+    ThresholdNotMet {
+        signers: usize,
+        threshold: usize,
+    },
+    /// The transaction carries more signatures than an account has keys.
+    TooManySignatures {
+        max: usize,
+    },
+    /// The transaction was signed for another chain.
+    WrongChainId {
+        chain_id: u64,
+        expected: u64,
+    },
+    /// The account to close doesn't exist.
+    AccountNotFound,
+    /// The account to close still has a balance after paying the fee.
+    AccountNotEmpty {
+        balance: u64,
+    },
+    /// The transaction's `valid_until_da_height` has passed.
+    Expired {
+        valid_until_da_height: u64,
+        da_height: u64,
+    },
+    /// Only the configured mint authority can mint.
+    UnauthorizedMint,
+    /// Scheduled transactions are disabled on this chain, since epochs
+    /// including them can't be proven yet.
+    SchedulingUnsupported,
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::InvalidSignature { reason } => write!(f, "Invalid signature: {}", reason),
+            TxError::ZeroAmount => write!(f, "Amount must be greater than zero"),
+            TxError::NonceTooLow {
+                nonce,
+                account_nonce,
+            } => write!(
+                f,
+                "Nonce {} has already been used, account nonce is {}",
+                nonce, account_nonce
+            ),
+            TxError::NonceGap { nonce, expected } => {
+                write!(f, "Invalid nonce: expected {}, got {}", expected, nonce)
+            }
+            TxError::InsufficientBalance => write!(f, "Insufficient balance"),
+            TxError::BalanceOverflow => write!(f, "Balance overflow"),
+            TxError::NonceOverflow => write!(f, "Nonce overflow"),
+            TxError::PayloadTooLarge { size, max } => {
+                write!(
+                    f,
+                    "Payload of {} bytes exceeds the maximum of {}",
+                    size, max
+                )
+            }
+            TxError::GasPriceTooLow {
+                gas_price,
+                min_gas_price,
+            } => write!(
+                f,
+                "Gas price {} is below the minimum of {}",
+                gas_price, min_gas_price
+            ),
+            TxError::AlreadyQueued => write!(f, "Transaction already queued"),
+            TxError::NonceAlreadyQueued { nonce } => {
+                write!(f, "Transaction with nonce {} already queued", nonce)
+            }
+            TxError::MempoolFull => write!(f, "Mempool is full"),
+            TxError::MempoolBusy => write!(f, "Mempool is busy, retry later"),
+            TxError::KeyAlreadyAuthorized => write!(f, "Key is already authorized"),
+            TxError::KeyNotAuthorized => write!(f, "Key is not authorized on the account"),
+            TxError::LastKey => write!(f, "Cannot revoke the last key of an account"),
+            TxError::TooManyKeys { max } => {
+                write!(f, "Accounts can have at most {} keys", max)
+            }
+            TxError::InvalidThreshold { threshold, keys } => write!(
+                f,
+                "Threshold {} is invalid for an account with {} keys",
+                threshold, keys
+            ),
+            TxError::ThresholdNotMet { signers, threshold } => write!(
+                f,
+                "Signed by {} authorized keys, {} required",
+                signers, threshold
+            ),
+            TxError::TooManySignatures { max } => {
+                write!(f, "Transactions can carry at most {} signatures", max)
+            }
+            TxError::WrongChainId { chain_id, expected } => write!(
+                f,
+                "Transaction is for chain {}, expected chain {}",
+                chain_id, expected
+            ),
+            TxError::AccountNotFound => write!(f, "Account does not exist"),
+            TxError::AccountNotEmpty { balance } => write!(
+                f,
+                "Account still holds a balance of {}, burn or transfer it first",
+                balance
+            ),
+            TxError::Expired {
+                valid_until_da_height,
+                da_height,
+            } => write!(
+                f,
+                "Transaction expired at celestia height {}, current height is {}",
+                valid_until_da_height, da_height
+            ),
+            TxError::UnauthorizedMint => write!(f, "Mints must be sent by the mint authority"),
+            TxError::SchedulingUnsupported => {
+                write!(f, "Scheduled transactions are disabled on this chain")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TxError {}
+
+/// Why a transaction included on Celestia failed to execute, recorded in its
+/// [`TxStatus::Failed`](crate::status::TxStatus::Failed) status and receipt.
+///
+/// The common failures get their own variants so clients can tell whether
+/// resubmitting may succeed, see [`ExecutionError::is_retryable`].
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq, Eq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum ExecutionError {
+    /// The signature doesn't verify against the sender's key.
+    InvalidSignature { reason: String },
+    /// The nonce was already used (`nonce < expected`) or skips ahead of the
+    /// account's next nonce under the strict nonce policy.
+    InvalidNonce { nonce: u64, expected: u64 },
+    /// The balance can't cover the transaction's amount and fee.
+    InsufficientBalance,
+    /// The transaction was signed for another chain.
+    WrongChainId { chain_id: u64, expected: u64 },
+    /// The transaction's `valid_until_da_height` had passed.
+    Expired {
+        valid_until_da_height: u64,
+        da_height: u64,
+    },
+    /// The transaction broke another rule of the state transition.
+    Rejected { error: TxError },
+    /// Execution failed without a structured error, e.g. a contract call
+    /// trapped.
+    Failed { message: String },
+}
+
+impl ExecutionError {
+    /// Whether the transaction may succeed if it is submitted again once the
+    /// account's state changed: nonce gaps can be filled and balances topped
+    /// up. Every other failure is fatal and needs a different transaction.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ExecutionError::InvalidNonce { nonce, expected } => nonce > expected,
+            ExecutionError::InsufficientBalance => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<&anyhow::Error> for ExecutionError {
+    fn from(e: &anyhow::Error) -> Self {
+        let Some(error) = e.downcast_ref::<TxError>() else {
+            return ExecutionError::Failed {
+                message: e.to_string(),
+            };
+        };
+        match error.clone() {
+            TxError::InvalidSignature { reason } => ExecutionError::InvalidSignature { reason },
+            TxError::NonceTooLow {
+                nonce,
+                account_nonce,
+            } => ExecutionError::InvalidNonce {
+                nonce,
+                expected: account_nonce,
+            },
+            TxError::NonceGap { nonce, expected } => {
+                ExecutionError::InvalidNonce { nonce, expected }
+            }
+            TxError::InsufficientBalance => ExecutionError::InsufficientBalance,
+            TxError::WrongChainId { chain_id, expected } => {
+                ExecutionError::WrongChainId { chain_id, expected }
+            }
+            TxError::Expired {
+                valid_until_da_height,
+                da_height,
+            } => ExecutionError::Expired {
+                valid_until_da_height,
+                da_height,
+            },
+            error => ExecutionError::Rejected { error },
+        }
+    }
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::InvalidSignature { reason } => {
+                write!(f, "Invalid signature: {}", reason)
+            }
+            ExecutionError::InvalidNonce { nonce, expected } => {
+                write!(f, "Invalid nonce: expected {}, got {}", expected, nonce)
+            }
+            ExecutionError::InsufficientBalance => write!(f, "Insufficient balance"),
+            ExecutionError::WrongChainId { chain_id, expected } => write!(
+                f,
+                "Transaction is for chain {}, expected chain {}",
+                chain_id, expected
+            ),
+            ExecutionError::Expired {
+                valid_until_da_height,
+                da_height,
+            } => write!(
+                f,
+                "Transaction expired at celestia height {}, included at {}",
+                valid_until_da_height, da_height
+            ),
+            ExecutionError::Rejected { error } => write!(f, "{}", error),
+            ExecutionError::Failed { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ExecutionError {}
@@ -0,0 +1,162 @@
+use anyhow::{anyhow, Result};
+use prism_common::keys::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::tx::parse_verifying_key;
+
+/// The number of events buffered per subscriber before it starts lagging.
+pub const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Node activity streamed to subscribers of the `/ws` endpoint.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A batch of transactions was posted to Celestia.
+    BatchPosted { tx_count: usize, da_height: u64 },
+    /// A transaction from a Celestia block was executed.
+    TxIncluded {
+        vk: VerifyingKey,
+        nonce: u64,
+        success: bool,
+        /// The accounts the transaction reads or writes, the sender's too
+        #[serde(default)]
+        accounts: Vec<VerifyingKey>,
+    },
+    /// A batch was executed, producing a rollup block.
+    BlockProduced {
+        height: u64,
+        root: String,
+        da_height: u64,
+    },
+    /// The state root changed after processing a Celestia block.
+    StateRoot { root: String, epoch: u64 },
+    /// All blobs of a Celestia height have been processed.
+    DaHeightProcessed { height: u64 },
+}
+
+/// The `type` of each [`Event`].
+const EVENT_KINDS: [&str; 5] = [
+    "batch_posted",
+    "tx_included",
+    "block_produced",
+    "state_root",
+    "da_height_processed",
+];
+
+impl Event {
+    /// Returns the event's `type`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::BatchPosted { .. } => EVENT_KINDS[0],
+            Event::TxIncluded { .. } => EVENT_KINDS[1],
+            Event::BlockProduced { .. } => EVENT_KINDS[2],
+            Event::StateRoot { .. } => EVENT_KINDS[3],
+            Event::DaHeightProcessed { .. } => EVENT_KINDS[4],
+        }
+    }
+
+    /// Returns the accounts the event concerns.
+    pub fn accounts(&self) -> &[VerifyingKey] {
+        match self {
+            Event::TxIncluded { accounts, .. } => accounts,
+            _ => &[],
+        }
+    }
+}
+
+/// A message of a `/ws` client changing which events it receives, e.g.
+/// `{"subscribe": {"account": "<vk>"}}`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionRequest {
+    Subscribe(Subscription),
+    Unsubscribe(Subscription),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Subscription {
+    /// Events concerning the account with this verifying key
+    Account(String),
+    /// Events of this `type`, e.g. `tx_included`
+    Event(String),
+}
+
+/// The events a `/ws` client subscribed to. A client that subscribed to
+/// accounts only receives events concerning one of them, and one that
+/// subscribed to event types only events of one of them. Clients that
+/// never subscribed receive every event.
+#[derive(Default, Debug)]
+pub struct EventFilter {
+    accounts: Vec<VerifyingKey>,
+    kinds: Vec<&'static str>,
+}
+
+impl EventFilter {
+    pub fn apply(&mut self, request: SubscriptionRequest) -> Result<()> {
+        match request {
+            SubscriptionRequest::Subscribe(Subscription::Account(vk)) => {
+                let vk = parse_verifying_key(&vk)?;
+                if !self.accounts.contains(&vk) {
+                    self.accounts.push(vk);
+                }
+            }
+            SubscriptionRequest::Subscribe(Subscription::Event(kind)) => {
+                let kind = parse_kind(&kind)?;
+                if !self.kinds.contains(&kind) {
+                    self.kinds.push(kind);
+                }
+            }
+            SubscriptionRequest::Unsubscribe(Subscription::Account(vk)) => {
+                let vk = parse_verifying_key(&vk)?;
+                self.accounts.retain(|account| *account != vk);
+            }
+            SubscriptionRequest::Unsubscribe(Subscription::Event(kind)) => {
+                let kind = parse_kind(&kind)?;
+                self.kinds.retain(|subscribed| *subscribed != kind);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&event.kind()))
+            && (self.accounts.is_empty()
+                || event.accounts().iter().any(|vk| self.accounts.contains(vk)))
+    }
+}
+
+fn parse_kind(kind: &str) -> Result<&'static str> {
+    EVENT_KINDS
+        .into_iter()
+        .find(|known| *known == kind)
+        .ok_or_else(|| anyhow!("Unknown event type {}", kind))
+}
+
+/// Fans out [`Event`]s to all current subscribers.
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        EventBus { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, event: Event) {
+        // an error only means there are no subscribers
+        let _ = self.sender.send(event);
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{encoding::Encode, tx::Transaction};
+
+/// The gas price (utia per unit of gas) blob costs are estimated with if none
+/// is configured. Celestia's default minimum gas price.
+pub const DEFAULT_CELESTIA_GAS_PRICE: f64 = 0.002;
+
+/// The size of a Celestia share.
+const SHARE_SIZE: u64 = 512;
+/// The blob data held by the first share of a blob, after the namespace,
+/// info byte and sequence length.
+const FIRST_SHARE_CAPACITY: u64 = 478;
+/// The blob data held by every further share of a blob.
+const CONTINUATION_SHARE_CAPACITY: u64 = 482;
+/// The gas charged per byte of the shares a blob occupies.
+const GAS_PER_BLOB_BYTE: u64 = 8;
+/// The gas of a PayForBlobs transaction independent of its blobs' size:
+/// signature verification, transaction bytes and state access.
+const PFB_FIXED_GAS: u64 = 75_000;
+
+/// The expected cost of a transaction, shown to users before they submit it.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq)]
+pub struct FeeEstimate {
+    /// The gas executing the transaction uses
+    pub gas: u64,
+    /// The minimum fee per unit of gas the node queues transactions with
+    pub min_gas_price: u64,
+    /// The minimum fee the transaction must pay, `gas * min_gas_price`
+    pub min_fee: u64,
+    /// The size of the transaction's encoding in a batch blob
+    pub tx_bytes: u64,
+    /// The Celestia gas price (utia per unit of gas) the blob cost is
+    /// estimated with
+    pub celestia_gas_price: f64,
+    /// The estimated share of the batch blob's Celestia fee taken up by the
+    /// transaction (in utia)
+    pub blob_cost_share: u64,
+}
+
+/// Returns the number of shares a blob of `len` bytes occupies.
+pub fn blob_shares(len: u64) -> u64 {
+    if len <= FIRST_SHARE_CAPACITY {
+        return 1;
+    }
+    1 + (len - FIRST_SHARE_CAPACITY).div_ceil(CONTINUATION_SHARE_CAPACITY)
+}
+
+/// Returns the gas of a PayForBlobs transaction posting a blob of `len`
+/// bytes.
+pub fn blob_gas(len: u64) -> u64 {
+    PFB_FIXED_GAS + blob_shares(len) * SHARE_SIZE * GAS_PER_BLOB_BYTE
+}
+
+/// Estimates the fees of `tx`. Its blob cost share assumes a batch of
+/// `batch_size` transactions of the same size, whose blob's gas is split
+/// evenly among them.
+pub fn estimate_fee(
+    tx: &Transaction,
+    min_gas_price: u64,
+    celestia_gas_price: f64,
+    batch_size: usize,
+) -> FeeEstimate {
+    let gas = tx.tx_type.gas();
+    let tx_bytes = tx.to_canonical_bytes().len() as u64;
+    let batch_size = batch_size.max(1) as u64;
+    let blob_gas = blob_gas(tx_bytes * batch_size).div_ceil(batch_size);
+    FeeEstimate {
+        gas,
+        min_gas_price,
+        min_fee: gas.saturating_mul(min_gas_price),
+        tx_bytes,
+        celestia_gas_price,
+        blob_cost_share: (blob_gas as f64 * celestia_gas_price).ceil() as u64,
+    }
+}
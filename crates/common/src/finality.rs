@@ -0,0 +1,32 @@
+//! How final the data served by a node is. Effects move from the
+//! sequencer's soft state, see [`crate::soft::SoftState`], to the state
+//! executed from Celestia, and finally under a validity proof. Query
+//! responses carry the [`Finality`] of their data, and clients can ask the
+//! node to withhold data below a minimum finality.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+
+/// The finality levels, from least to most final.
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Finality {
+    /// Executed on the sequencer's soft state ahead of Celestia. The
+    /// sequencer may still post something else
+    SoftConfirmed,
+    /// Executed from a Celestia block. Final unless Celestia reorgs
+    DaIncluded,
+    /// Covered by the latest epoch with a recorded validity proof
+    ProofFinalized,
+}
+
+impl fmt::Display for Finality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Finality::SoftConfirmed => "soft confirmed",
+            Finality::DaIncluded => "DA included",
+            Finality::ProofFinalized => "proof finalized",
+        })
+    }
+}
@@ -0,0 +1,332 @@
+//! The optimistic alternative to validity proofs: sequencers post a root
+//! claim per epoch instead of proving it, and watchtowers re-executing the
+//! rollup challenge wrong claims with a [`FraudProof`].
+//!
+//! Claims are posted as [`EpochProof`]s without proof bytes, so the
+//! optimistic mode plugs into the epoch pipeline through
+//! [`OptimisticProver`] like any zkVM backend. Fraud proofs cover the same
+//! state as validity proofs: the sender account of every transaction, and
+//! the recipient's of transfers.
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::{
+    proofs::{Batch, EpochProof, Proof, ProverBackend},
+    storage::NodeStore,
+    tree::Digest,
+};
+
+/// How sequencers attest to the state roots of epochs.
+#[derive(ValueEnum, Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProofMode {
+    /// Epochs are proven with a zkVM backend.
+    #[default]
+    Validity,
+    /// Epochs are claimed without a proof and challenged by watchtowers.
+    Optimistic,
+}
+
+/// A state transition as claimed by the sequencer: the transaction, or the
+/// DA height for the watermark (see [`Proof::id`]), and the root after it.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ClaimedTransition {
+    pub tx_hash: Digest,
+    pub new_root: Digest,
+}
+
+/// The public values of an optimistic [`EpochProof`]: every transition of
+/// the epoch, so a fraud proof only has to show the first wrong one.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct RootClaim {
+    pub transitions: Vec<ClaimedTransition>,
+}
+
+impl RootClaim {
+    /// Decodes the claim of an optimistic `proof`.
+    pub fn from_epoch_proof(proof: &EpochProof) -> Result<Self> {
+        if !proof.is_optimistic() {
+            return Err(anyhow!("Epoch {} has a validity proof", proof.epoch));
+        }
+        bincode::deserialize(&proof.public_values).context("Invalid root claim")
+    }
+
+    /// Returns the root the transition at `index` starts at: the epoch's
+    /// previous root or the root claimed by the transition before.
+    fn pre_root(&self, claim: &EpochProof, index: usize) -> Digest {
+        match index {
+            0 => claim.prev_root,
+            _ => self.transitions[index - 1].new_root,
+        }
+    }
+}
+
+/// A [`ProverBackend`] that claims roots instead of proving them. Claims
+/// can't be verified, only refuted by a [`FraudProof`].
+pub struct OptimisticProver;
+
+impl ProverBackend for OptimisticProver {
+    fn prove(&self, epoch: u64, batch: &Batch) -> Result<EpochProof> {
+        let transitions = batch
+            .proofs
+            .iter()
+            .map(|proof| ClaimedTransition {
+                tx_hash: proof.id(),
+                new_root: proof.roots().1,
+            })
+            .collect();
+        Ok(EpochProof {
+            epoch,
+            prev_root: batch.prev_root,
+            new_root: batch.new_root,
+            proof: Vec::new(),
+            public_values: bincode::serialize(&RootClaim { transitions })?,
+        })
+    }
+
+    fn prove_recursive(
+        &self,
+        _epoch: u64,
+        _batch: &Batch,
+        _prev: Option<&EpochProof>,
+    ) -> Result<EpochProof> {
+        Err(anyhow!("Recursive proofs require a zkVM backend"))
+    }
+
+    /// Only checks that the claim is well-formed: its transitions end at the
+    /// claimed root.
+    fn verify(&self, proof: &EpochProof) -> Result<bool> {
+        let claim = RootClaim::from_epoch_proof(proof)?;
+        let last_root = claim
+            .transitions
+            .last()
+            .map_or(proof.prev_root, |transition| transition.new_root);
+        Ok(last_root == proof.new_root)
+    }
+}
+
+/// Proves that an optimistic epoch claim is wrong: the transition at
+/// `index` starts at the root claimed before it, but the transaction takes
+/// that state to a different root than claimed.
+#[derive(Serialize, Deserialize)]
+pub struct FraudProof {
+    pub epoch: u64,
+    pub index: usize,
+    /// The honest transition, proven against the claimed pre-state root
+    pub proof: Proof,
+}
+
+impl FraudProof {
+    /// Checks the fraud proof against the `claim` it challenges. Succeeds if
+    /// the claim is proven wrong.
+    pub fn verify(&self, claim: &EpochProof) -> Result<()> {
+        if claim.epoch != self.epoch {
+            return Err(anyhow!(
+                "Fraud proof is for epoch {}, not {}",
+                self.epoch,
+                claim.epoch
+            ));
+        }
+        let root_claim = RootClaim::from_epoch_proof(claim)?;
+        let transition = root_claim
+            .transitions
+            .get(self.index)
+            .ok_or_else(|| anyhow!("Claim has no transition {}", self.index))?;
+        if self.proof.id() != transition.tx_hash {
+            return Err(anyhow!("Fraud proof is for a different transaction"));
+        }
+        let (old_root, new_root) = self.proof.roots();
+        if old_root != root_claim.pre_root(claim, self.index) {
+            return Err(anyhow!("Fraud proof doesn't start at the claimed root"));
+        }
+        self.proof.verify().context("Invalid transition proof")?;
+        if new_root == transition.new_root {
+            return Err(anyhow!("Claimed root matches the proven transition"));
+        }
+        Ok(())
+    }
+}
+
+/// Why a claim couldn't be checked transition by transition.
+#[derive(Debug)]
+pub enum Unprovable {
+    /// The claim starts at a different root, so the divergence happened in
+    /// an earlier epoch.
+    DifferentPrevRoot,
+    /// The claim executes a different transaction at `index`.
+    DifferentTx { index: usize },
+    /// The claim has a different number of transitions.
+    DifferentLength { claimed: usize, executed: usize },
+    /// The claim's transitions don't end at its new root, which anyone can
+    /// check without a fraud proof.
+    Inconsistent,
+}
+
+impl fmt::Display for Unprovable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unprovable::DifferentPrevRoot => write!(f, "claim starts at a different root"),
+            Unprovable::DifferentTx { index } => {
+                write!(f, "claim executes a different transaction at {}", index)
+            }
+            Unprovable::DifferentLength { claimed, executed } => write!(
+                f,
+                "claim has {} transitions, {} were executed",
+                claimed, executed
+            ),
+            Unprovable::Inconsistent => {
+                write!(f, "claim's transitions don't end at its new root")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Unprovable {}
+
+/// Compares `claim` with the epoch as executed by this node. Returns a
+/// fraud proof of the first wrong transition, or `None` if the claim is
+/// right.
+pub fn find_fraud(claim: &EpochProof, executed: Batch) -> Result<Option<FraudProof>> {
+    let root_claim = RootClaim::from_epoch_proof(claim)?;
+    if claim.prev_root != executed.prev_root {
+        return Err(Unprovable::DifferentPrevRoot.into());
+    }
+    let executed_len = executed.proofs.len();
+    for (index, (transition, proof)) in root_claim
+        .transitions
+        .iter()
+        .zip(executed.proofs)
+        .enumerate()
+    {
+        if proof.id() != transition.tx_hash {
+            return Err(Unprovable::DifferentTx { index }.into());
+        }
+        if proof.roots().1 != transition.new_root {
+            return Ok(Some(FraudProof {
+                epoch: claim.epoch,
+                index,
+                proof,
+            }));
+        }
+    }
+    if root_claim.transitions.len() != executed_len {
+        return Err(Unprovable::DifferentLength {
+            claimed: root_claim.transitions.len(),
+            executed: executed_len,
+        }
+        .into());
+    }
+    if claim.new_root != executed.new_root {
+        return Err(Unprovable::Inconsistent.into());
+    }
+    Ok(None)
+}
+
+fn watched_epoch_key(epoch: u64) -> String {
+    format!("watched_epoch:{}", epoch)
+}
+
+/// Returns the epoch a watchtower executed, kept until the sequencer's
+/// claim for it was checked.
+pub fn take_watched_epoch<S: NodeStore + ?Sized>(store: &S, epoch: u64) -> Result<Option<Batch>> {
+    let key = watched_epoch_key(epoch);
+    let Some(bytes) = store.get_metadata(&key)? else {
+        return Ok(None);
+    };
+    store.delete_metadata(&key)?;
+    Ok(Some(bincode::deserialize(&bytes)?))
+}
+
+pub fn put_watched_epoch<S: NodeStore + ?Sized>(
+    store: &S,
+    epoch: u64,
+    batch: &Batch,
+) -> Result<()> {
+    store.put_metadata(&watched_epoch_key(epoch), &bincode::serialize(batch)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        state::{NoncePolicy, State, StateReader},
+        storage::InMemoryStore,
+        testkit::generate_key,
+        tx::{Transaction, TransactionBuilder, TransactionType},
+    };
+
+    fn noops(count: u64) -> Vec<Transaction> {
+        let key = generate_key();
+        (0..count)
+            .map(|nonce| {
+                TransactionBuilder::new(TransactionType::Noop)
+                    .nonce(nonce)
+                    .sign(&key)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    /// Executes `txs` on a fresh state, returning the epoch as proven.
+    fn execute(txs: &[Transaction]) -> Batch {
+        let mut state: State<InMemoryStore> =
+            State::new(Arc::new(InMemoryStore::default()), NoncePolicy::default()).unwrap();
+        state.record_proofs();
+        let prev_root = state.get_commitment().unwrap();
+        for tx in txs {
+            state.process_tx(tx.clone(), 1).unwrap();
+        }
+        Batch {
+            prev_root,
+            new_root: state.get_commitment().unwrap(),
+            proofs: state.take_proofs(),
+            da_height_range: (1, 1),
+            batch_commitments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn honest_claim_has_no_fraud() {
+        let txs = noops(2);
+        let claim = OptimisticProver.prove(1, &execute(&txs)).unwrap();
+        assert!(claim.is_optimistic());
+        assert!(OptimisticProver.verify(&claim).unwrap());
+        assert!(find_fraud(&claim, execute(&txs)).unwrap().is_none());
+    }
+
+    #[test]
+    fn wrong_transition_is_proven_fraudulent() {
+        let txs = noops(3);
+        let honest = OptimisticProver.prove(1, &execute(&txs)).unwrap();
+        let mut root_claim = RootClaim::from_epoch_proof(&honest).unwrap();
+        root_claim.transitions[1].new_root = Digest::new([7; 32]);
+        let claim = EpochProof {
+            public_values: bincode::serialize(&root_claim).unwrap(),
+            ..honest
+        };
+        // the transitions still end at the claimed root
+        assert!(OptimisticProver.verify(&claim).unwrap());
+
+        let fraud = find_fraud(&claim, execute(&txs)).unwrap().unwrap();
+        assert_eq!(fraud.index, 1);
+        fraud.verify(&claim).unwrap();
+
+        let honest = OptimisticProver.prove(1, &execute(&txs)).unwrap();
+        assert!(fraud.verify(&honest).is_err());
+    }
+
+    #[test]
+    fn claim_of_other_txs_is_unprovable() {
+        let claim = OptimisticProver.prove(1, &execute(&noops(1))).unwrap();
+        let err = find_fraud(&claim, execute(&noops(1))).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(Unprovable::DifferentTx { index: 0 })
+        ));
+    }
+}
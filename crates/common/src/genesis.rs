@@ -0,0 +1,158 @@
+use anyhow::{anyhow, Context, Result};
+use prism_common::keys::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use crate::{
+    node::BatchAuth,
+    state::{NoncePolicy, State, StateReader},
+    storage::NodeStore,
+    tree::Digest,
+};
+
+/// Metadata key under which the hash of the genesis a store was initialized
+/// with is stored.
+const GENESIS_HASH_KEY: &str = "genesis_hash";
+
+/// The initial state and chain parameters of a rollup. Every node of a
+/// rollup must start from the same genesis, which is checked via the
+/// resulting state root.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Genesis {
+    /// Pre-funded accounts.
+    #[serde(default)]
+    pub accounts: Vec<GenesisAccount>,
+
+    /// Parameters that all nodes must agree on. They take precedence over
+    /// the node's own configuration.
+    #[serde(default)]
+    pub params: ChainParams,
+
+    /// The hex encoded state root after applying the genesis. If set, nodes
+    /// refuse to start if they compute a different root.
+    pub state_root: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisAccount {
+    /// The base64 encoded verifying key of the account.
+    pub vk: String,
+    pub balance: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ChainParams {
+    pub nonce_policy: Option<NoncePolicy>,
+    pub batch_auth: Option<BatchAuth>,
+    /// The base64 encoded key sequencer batches must be signed with.
+    pub sequencer_vk: Option<String>,
+    /// The base64 encoded key of the mint authority allowed to mint new
+    /// tokens.
+    pub mint_vk: Option<String>,
+    pub forced_inclusion_delay: Option<u64>,
+    /// The base64 encoded key of the bridge relayer allowed to credit
+    /// deposits.
+    pub bridge_vk: Option<String>,
+    /// The base64 encoded key of the governance account allowed to change
+    /// the live chain parameters.
+    pub governance_vk: Option<String>,
+    /// Whether transactions may be scheduled for a later Celestia height.
+    pub scheduled_txs: Option<bool>,
+    /// The protocol upgrades of the chain, each written as
+    /// `<version>@<celestia height>`.
+    pub protocol_upgrades: Option<Vec<String>>,
+}
+
+impl Genesis {
+    /// Reads a genesis file, parsed as TOML if it has a `.toml` extension and
+    /// as JSON otherwise.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read genesis file {}", path.display()))?;
+        let genesis = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&contents)?,
+            _ => serde_json::from_str(&contents)?,
+        };
+        Ok(genesis)
+    }
+
+    /// Identifies the genesis, so a store can't be reused with a different
+    /// one.
+    pub fn hash(&self) -> Result<Digest> {
+        Ok(Digest::hash(bincode::serialize(self)?))
+    }
+
+    pub fn sequencer_vk(&self) -> Result<Option<VerifyingKey>> {
+        self.params
+            .sequencer_vk
+            .clone()
+            .map(|vk| VerifyingKey::try_from(vk).context("Invalid genesis sequencer key"))
+            .transpose()
+    }
+
+    pub fn mint_vk(&self) -> Result<Option<VerifyingKey>> {
+        self.params
+            .mint_vk
+            .clone()
+            .map(|vk| VerifyingKey::try_from(vk).context("Invalid genesis mint key"))
+            .transpose()
+    }
+
+    pub fn bridge_vk(&self) -> Result<Option<VerifyingKey>> {
+        self.params
+            .bridge_vk
+            .clone()
+            .map(|vk| VerifyingKey::try_from(vk).context("Invalid genesis bridge key"))
+            .transpose()
+    }
+
+    pub fn governance_vk(&self) -> Result<Option<VerifyingKey>> {
+        self.params
+            .governance_vk
+            .clone()
+            .map(|vk| VerifyingKey::try_from(vk).context("Invalid genesis governance key"))
+            .transpose()
+    }
+
+    /// Writes the genesis accounts to an empty state and checks the
+    /// resulting root against [`Genesis::state_root`].
+    pub fn apply<S: NodeStore>(&self, state: &mut State<S>, store: &S) -> Result<Digest> {
+        let balances = self
+            .accounts
+            .iter()
+            .map(|account| {
+                let vk = VerifyingKey::try_from(account.vk.clone())
+                    .with_context(|| format!("Invalid genesis account key {}", account.vk))?;
+                Ok((vk, account.balance))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        state.init_accounts(balances)?;
+
+        let root = state.get_commitment()?;
+        if let Some(expected) = &self.state_root {
+            if hex::encode(root.0) != expected.to_lowercase() {
+                return Err(anyhow!(
+                    "Genesis state root {} does not match expected root {}",
+                    hex::encode(root.0),
+                    expected
+                ));
+            }
+        }
+        store.put_metadata(GENESIS_HASH_KEY, &self.hash()?.0)?;
+        Ok(root)
+    }
+
+    /// Checks that `store` was initialized with this genesis. Stores
+    /// initialized without a genesis are accepted.
+    pub fn check_store<S: NodeStore + ?Sized>(&self, store: &S) -> Result<()> {
+        match store.get_metadata(GENESIS_HASH_KEY)? {
+            Some(hash) if hash != self.hash()?.0 => {
+                Err(anyhow!("Store was initialized with a different genesis"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::{stream, Stream};
+use prism_common::keys::VerifyingKey;
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+use tokio::sync::broadcast::error::RecvError;
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::{
+    encoding::Decode,
+    error::TxError,
+    events::Event,
+    finality::Finality,
+    node::{Node, NodeRole},
+    tx::Transaction,
+};
+
+pub mod proto {
+    tonic::include_proto!("shard.v1");
+}
+
+use proto::shard_server::{Shard, ShardServer};
+
+/// Serves the [`Node`] API over gRPC, backed by the same node methods as the
+/// HTTP webserver.
+pub struct ShardService {
+    node: Arc<Node>,
+}
+
+impl ShardService {
+    pub fn new(node: Arc<Node>) -> Self {
+        ShardService { node }
+    }
+
+    /// Light nodes don't execute transactions, so they have no state to
+    /// query.
+    fn require_state(&self) -> Result<(), Status> {
+        if self.node.role() == NodeRole::Light {
+            return Err(Status::unimplemented("Light nodes have no state"));
+        }
+        Ok(())
+    }
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Shard for ShardService {
+    async fn submit_tx(
+        &self,
+        request: Request<proto::SubmitTxRequest>,
+    ) -> Result<Response<proto::SubmitTxResponse>, Status> {
+        let tx = Transaction::from_canonical_bytes(&request.into_inner().tx)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let submitted = self.node.queue_transaction(tx).await.map_err(|e| {
+            match e.downcast_ref::<TxError>() {
+                Some(TxError::MempoolFull | TxError::MempoolBusy) => {
+                    Status::resource_exhausted(e.to_string())
+                }
+                Some(_) => Status::invalid_argument(e.to_string()),
+                None => Status::internal(e.to_string()),
+            }
+        })?;
+        Ok(Response::new(proto::SubmitTxResponse {
+            tx_hash: submitted.tx_hash.0.to_vec(),
+        }))
+    }
+
+    async fn get_account(
+        &self,
+        request: Request<proto::GetAccountRequest>,
+    ) -> Result<Response<proto::Account>, Status> {
+        self.require_state()?;
+        let request = request.into_inner();
+        let min_finality = from_proto_finality(request.min_finality());
+        let vk = VerifyingKey::try_from(request.vk)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        match self
+            .node
+            .get_account_with_finality(&vk, min_finality.unwrap_or(Finality::DaIncluded))
+            .await
+        {
+            Ok(Some((Some(account), finality))) => Ok(Response::new(proto::Account {
+                nonce: account.nonce(),
+                balance: account.balance(),
+                keys: account
+                    .authorized_keys(&vk)
+                    .iter()
+                    .map(|key| BASE64.encode(key.as_bytes()))
+                    .collect(),
+                threshold: account.threshold() as u32,
+                finality: proto::Finality::from(finality) as i32,
+            })),
+            Ok(Some((None, _))) => Err(Status::not_found("Account not found")),
+            Ok(None) => Err(Status::failed_precondition(
+                "No state of the requested finality",
+            )),
+            Err(e) => Err(Status::internal(e.to_string())),
+        }
+    }
+
+    async fn get_block(
+        &self,
+        request: Request<proto::GetBlockRequest>,
+    ) -> Result<Response<proto::Block>, Status> {
+        self.require_state()?;
+        let request = request.into_inner();
+        let block = match self.node.get_block(request.height) {
+            Ok(Some(block)) => block,
+            Ok(None) => return Err(Status::not_found("Block not found")),
+            Err(e) => return Err(Status::internal(e.to_string())),
+        };
+        let finality = self
+            .node
+            .da_height_finality(block.da_height)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        if let Some(min_finality) =
+            from_proto_finality(request.min_finality()).filter(|min| finality < *min)
+        {
+            return Err(Status::not_found(format!(
+                "Block is not {} yet",
+                min_finality
+            )));
+        }
+        Ok(Response::new(proto::Block {
+            height: block.height,
+            prev_root: block.prev_root.0.to_vec(),
+            new_root: block.new_root.0.to_vec(),
+            tx_root: block.tx_root.0.to_vec(),
+            da_height: block.da_height,
+            timestamp: block.timestamp,
+            finality: proto::Finality::from(finality) as i32,
+        }))
+    }
+
+    type SubscribeStream = EventStream;
+
+    async fn subscribe(
+        &self,
+        _request: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let events = self.node.subscribe_events();
+        let stream = stream::unfold(events, |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => return Some((Ok(event.into()), events)),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("grpc subscriber lagged, skipped {} events", skipped);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+impl From<Finality> for proto::Finality {
+    fn from(finality: Finality) -> Self {
+        match finality {
+            Finality::SoftConfirmed => proto::Finality::SoftConfirmed,
+            Finality::DaIncluded => proto::Finality::DaIncluded,
+            Finality::ProofFinalized => proto::Finality::ProofFinalized,
+        }
+    }
+}
+
+fn from_proto_finality(finality: proto::Finality) -> Option<Finality> {
+    match finality {
+        proto::Finality::Unspecified => None,
+        proto::Finality::SoftConfirmed => Some(Finality::SoftConfirmed),
+        proto::Finality::DaIncluded => Some(Finality::DaIncluded),
+        proto::Finality::ProofFinalized => Some(Finality::ProofFinalized),
+    }
+}
+
+impl From<Event> for proto::Event {
+    fn from(event: Event) -> Self {
+        use proto::event::Event as Kind;
+        let kind = match event {
+            Event::BatchPosted {
+                tx_count,
+                da_height,
+            } => Kind::BatchPosted(proto::BatchPosted {
+                tx_count: tx_count as u64,
+                da_height,
+            }),
+            Event::TxIncluded {
+                vk, nonce, success, ..
+            } => Kind::TxIncluded(proto::TxIncluded {
+                vk: BASE64.encode(vk.as_bytes()),
+                nonce,
+                success,
+            }),
+            Event::BlockProduced {
+                height,
+                root,
+                da_height,
+            } => Kind::BlockProduced(proto::BlockProduced {
+                height,
+                root,
+                da_height,
+            }),
+            Event::StateRoot { root, epoch } => Kind::StateRoot(proto::StateRoot { root, epoch }),
+            Event::DaHeightProcessed { height } => {
+                Kind::DaHeightProcessed(proto::DaHeightProcessed { height })
+            }
+        };
+        proto::Event { event: Some(kind) }
+    }
+}
+
+/// Runs the gRPC server until `shutdown` resolves.
+pub async fn serve(
+    node: Arc<Node>,
+    addr: SocketAddr,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> Result<()> {
+    info!("grpc server listening on {}", addr);
+    Server::builder()
+        .add_service(ShardServer::new(ShardService::new(node)))
+        .serve_with_shutdown(addr, shutdown)
+        .await
+        .context("Failed to start gRPC server")
+}
@@ -0,0 +1,223 @@
+use anyhow::{anyhow, Result};
+use prism_common::keys::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use utoipa::ToSchema;
+
+use crate::{
+    receipt::{get_receipt, Receipt, TxEvent},
+    storage::NodeStore,
+    tree::Digest,
+};
+
+/// The number of entries a history query returns unless it asks for fewer.
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+/// The maximum number of entries a history query returns.
+pub const MAX_HISTORY_LIMIT: usize = 1_000;
+
+/// Metadata key prefix of the index of executed transactions by Celestia
+/// height.
+const HEIGHT_PREFIX: &str = "history:height:";
+/// Metadata key prefix of the index of executed transactions by the
+/// accounts they touched.
+const ACCOUNT_PREFIX: &str = "history:account:";
+
+/// An executed transaction in the history index.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// The Celestia height the transaction was executed at
+    pub da_height: u64,
+    pub receipt: Receipt,
+}
+
+/// A page of the transactions that touched an account, oldest first.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq, Eq)]
+pub struct AccountTxsPage {
+    pub entries: Vec<HistoryEntry>,
+    pub limit: usize,
+    /// The cursor to pass as `after` for the next page, none on the last
+    /// page
+    pub next: Option<String>,
+}
+
+/// Keys end in the zero padded height and the position of the transaction
+/// within it, so sorting keys sorts entries by execution order.
+fn entry_suffix(da_height: u64, position: usize) -> String {
+    format!("{:020}:{:06}", da_height, position)
+}
+
+fn height_prefix(da_height: u64) -> String {
+    format!("{}{:020}:", HEIGHT_PREFIX, da_height)
+}
+
+fn account_prefix(vk: &[u8]) -> String {
+    format!("{}{}:", ACCOUNT_PREFIX, hex::encode(vk))
+}
+
+/// The accounts a transaction touched: its sender and every account named
+/// in its events.
+fn touched_accounts(sender: &VerifyingKey, receipt: &Receipt) -> BTreeSet<Vec<u8>> {
+    let mut accounts = BTreeSet::from([sender.as_bytes()]);
+    for event in &receipt.events {
+        match event {
+            TxEvent::AccountCreated { vk } | TxEvent::AccountClosed { vk } => {
+                accounts.insert(vk.as_bytes());
+            }
+            TxEvent::Transfer { from, to, .. } => {
+                accounts.insert(from.as_bytes());
+                accounts.insert(to.as_bytes());
+            }
+            TxEvent::Mint { to: vk, .. }
+            | TxEvent::Deposit { to: vk, .. }
+            | TxEvent::Burn { from: vk, .. }
+            | TxEvent::KeyAdded { account: vk, .. }
+            | TxEvent::KeyRevoked { account: vk, .. }
+            | TxEvent::MultisigSet { account: vk, .. }
+            | TxEvent::FeePaid { payer: vk, .. } => {
+                accounts.insert(vk.as_bytes());
+            }
+            TxEvent::ContractDeployed { .. }
+            | TxEvent::ContractCalled { .. }
+            | TxEvent::Scheduled { .. }
+            | TxEvent::ParamsScheduled { .. } => {}
+        }
+    }
+    accounts
+}
+
+/// Indexes the receipts of transactions executed at `da_height`, in
+/// execution order after those already indexed for the height. Each entry
+/// is the sender, the transaction hash and its receipt.
+pub fn index_receipts<S: NodeStore + ?Sized>(
+    store: &S,
+    da_height: u64,
+    receipts: &[(VerifyingKey, Digest, Receipt)],
+) -> Result<()> {
+    let offset = store.iter_metadata(&height_prefix(da_height))?.len();
+    for (i, (sender, tx_hash, receipt)) in receipts.iter().enumerate() {
+        let suffix = entry_suffix(da_height, offset + i);
+        let value = bincode::serialize(tx_hash)?;
+        store.put_metadata(&format!("{}{}", HEIGHT_PREFIX, suffix), &value)?;
+        for account in touched_accounts(sender, receipt) {
+            store.put_metadata(&format!("{}{}", account_prefix(&account), suffix), &value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns up to `limit` transactions that touched the account of `vk`
+/// between the Celestia heights `from` and `to` (inclusive), oldest first.
+pub fn get_account_history<S: NodeStore + ?Sized>(
+    store: &S,
+    vk: &VerifyingKey,
+    from: u64,
+    to: u64,
+    limit: usize,
+) -> Result<Vec<HistoryEntry>> {
+    query(store, &account_prefix(&vk.as_bytes()), from, to, limit)
+}
+
+/// Returns up to `limit` transactions that touched the account of `vk`,
+/// oldest first, from the one after the cursor `after` on. Seeks to the
+/// cursor instead of reading the account's whole index.
+pub fn get_account_txs<S: NodeStore + ?Sized>(
+    store: &S,
+    vk: &VerifyingKey,
+    after: Option<&str>,
+    limit: usize,
+) -> Result<AccountTxsPage> {
+    let prefix = account_prefix(&vk.as_bytes());
+    let index = store.iter_metadata_range(&prefix, after, limit)?;
+    let next = match index.last() {
+        Some((key, _)) if index.len() == limit => Some(key[prefix.len()..].to_string()),
+        _ => None,
+    };
+
+    let mut entries = Vec::new();
+    for (key, value) in index {
+        if let Some(entry) = read_entry(store, &key[prefix.len()..], &value)? {
+            entries.push(entry);
+        }
+    }
+    Ok(AccountTxsPage {
+        entries,
+        limit,
+        next,
+    })
+}
+
+/// Returns up to `limit` transactions executed between the Celestia heights
+/// `from` and `to` (inclusive) with their events, oldest first.
+pub fn get_events<S: NodeStore + ?Sized>(
+    store: &S,
+    from: u64,
+    to: u64,
+    limit: usize,
+) -> Result<Vec<HistoryEntry>> {
+    query(store, HEIGHT_PREFIX, from, to, limit)
+}
+
+fn query<S: NodeStore + ?Sized>(
+    store: &S,
+    prefix: &str,
+    from: u64,
+    to: u64,
+    limit: usize,
+) -> Result<Vec<HistoryEntry>> {
+    let mut index = store.iter_metadata(prefix)?;
+    index.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut entries = Vec::new();
+    for (key, value) in index {
+        let da_height = entry_height(&key[prefix.len()..])?;
+        if da_height < from {
+            continue;
+        }
+        if da_height > to || entries.len() >= limit {
+            break;
+        }
+        if let Some(entry) = read_entry(store, &key[prefix.len()..], &value)? {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Reads the entry of an index key with `suffix`, whose value is the hash
+/// of the transaction. `None` if its receipt is missing.
+fn read_entry<S: NodeStore + ?Sized>(
+    store: &S,
+    suffix: &str,
+    value: &[u8],
+) -> Result<Option<HistoryEntry>> {
+    let da_height = entry_height(suffix)?;
+    let tx_hash: Digest = bincode::deserialize(value)?;
+    Ok(get_receipt(store, &tx_hash)?.map(|receipt| HistoryEntry { da_height, receipt }))
+}
+
+/// Removes the index entries of heights after `da_height`, e.g. after their
+/// blocks were reorged out.
+pub fn remove_history_after<S: NodeStore + ?Sized>(store: &S, da_height: u64) -> Result<()> {
+    for (key, _) in store.iter_metadata("history:")? {
+        let suffix = match key.strip_prefix(HEIGHT_PREFIX) {
+            Some(suffix) => suffix,
+            // account keys: the hex encoded key, then the entry suffix
+            None => key
+                .strip_prefix(ACCOUNT_PREFIX)
+                .and_then(|rest| rest.split_once(':'))
+                .map_or("", |(_, suffix)| suffix),
+        };
+        if entry_height(suffix)? > da_height {
+            store.delete_metadata(&key)?;
+        }
+    }
+    Ok(())
+}
+
+fn entry_height(suffix: &str) -> Result<u64> {
+    suffix
+        .split(':')
+        .next()
+        .and_then(|height| height.parse().ok())
+        .ok_or_else(|| anyhow!("Invalid history index key suffix {}", suffix))
+}
@@ -0,0 +1,242 @@
+//! The public values committed by the guest programs, in a versioned
+//! canonical encoding so verifiers can tell journals of different program
+//! versions and proof kinds apart.
+//!
+//! Version 0 covers the unversioned journals of earlier programs: the two
+//! roots of plain proofs (64 bytes), the 96 bytes of recursive proofs and
+//! the tagged 129 bytes of STF proofs, which are still accepted. Version 1
+//! journals are plain proofs only. Version 2 journals tag the kind of proof
+//! after the version, see [`Journal`]; versioned journals are never 64, 96
+//! or 129 bytes long, so they can't be mistaken for unversioned ones.
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    encoding::{Decode, Decoder, Encode, Encoder},
+    proofs::{Batch, RecursivePublicValues},
+    stf::StfPublicValues,
+    tree::Digest,
+};
+
+/// The version of the journal committed by the current guest programs.
+/// Bump it when fields are added or their encoding changes, and keep
+/// decoding the previous versions.
+pub const JOURNAL_VERSION: u8 = 2;
+
+const LEGACY_LEN: usize = 64;
+const LEGACY_RECURSIVE_LEN: usize = 96;
+const LEGACY_STF_LEN: usize = 129;
+/// Prefixed the unversioned STF values, so they couldn't be mistaken for
+/// the 96 bytes of recursive ones.
+const LEGACY_STF_TAG: u8 = 0x53;
+
+/// The public values of a plain epoch proof.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PublicValues {
+    /// The journal version the values were decoded from
+    pub version: u8,
+    pub prev_root: Digest,
+    pub new_root: Digest,
+    /// The first and last Celestia height the epoch's batches were read
+    /// from. `(0, 0)` for legacy journals.
+    pub da_height_range: (u64, u64),
+    /// The blob commitments of the epoch's batches, in execution order.
+    /// Empty for legacy journals.
+    pub batch_commitments: Vec<Digest>,
+}
+
+impl PublicValues {
+    /// Returns the values the guest commits for `batch`, in the current
+    /// [`JOURNAL_VERSION`].
+    pub fn from_batch(batch: &Batch) -> Self {
+        PublicValues {
+            version: JOURNAL_VERSION,
+            prev_root: batch.prev_root,
+            new_root: batch.new_root,
+            da_height_range: batch.da_height_range,
+            batch_commitments: batch.batch_commitments.clone(),
+        }
+    }
+
+    /// Encodes the values as committed by the guests, see
+    /// [`Journal::to_bytes`].
+    pub fn to_journal(&self) -> Vec<u8> {
+        Journal::Epoch(self.clone()).to_bytes()
+    }
+
+    /// Decodes the journal of a plain proof, of any supported version.
+    pub fn from_journal(bytes: &[u8]) -> Result<Self> {
+        match Journal::from_bytes(bytes)? {
+            Journal::Epoch(values) => Ok(values),
+            _ => Err(anyhow!("Not the journal of a plain epoch proof")),
+        }
+    }
+}
+
+/// The public values committed by a guest program, by the kind of proof.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Journal {
+    Epoch(PublicValues),
+    Recursive(RecursivePublicValues),
+    Stf(StfPublicValues),
+}
+
+impl Journal {
+    /// Encodes the values as committed by the guests: the version byte and
+    /// the kind tag, followed by the fields in the encoding of that version.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_canonical_bytes()
+    }
+
+    /// Decodes a journal of any supported version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.len() {
+            LEGACY_LEN => Ok(Journal::Epoch(PublicValues {
+                version: 0,
+                prev_root: Digest::new(bytes[..32].try_into()?),
+                new_root: Digest::new(bytes[32..].try_into()?),
+                da_height_range: (0, 0),
+                batch_commitments: Vec::new(),
+            })),
+            LEGACY_RECURSIVE_LEN => {
+                let mut program_id = [0u32; 8];
+                for (word, chunk) in program_id.iter_mut().zip(bytes[64..].chunks_exact(4)) {
+                    *word = u32::from_le_bytes(chunk.try_into()?);
+                }
+                Ok(Journal::Recursive(RecursivePublicValues {
+                    genesis_root: Digest::new(bytes[..32].try_into()?),
+                    root: Digest::new(bytes[32..64].try_into()?),
+                    program_id,
+                    da_height_range: (0, 0),
+                    batch_commitments: Vec::new(),
+                }))
+            }
+            LEGACY_STF_LEN if bytes[0] == LEGACY_STF_TAG => {
+                let digest = |offset: usize| -> Result<Digest> {
+                    Ok(Digest::new(bytes[offset..offset + 32].try_into()?))
+                };
+                Ok(Journal::Stf(StfPublicValues {
+                    prev_root: digest(1)?,
+                    new_root: digest(33)?,
+                    batch_hash: digest(65)?,
+                    mint_authority: digest(97)?,
+                }))
+            }
+            _ => Self::from_canonical_bytes(bytes),
+        }
+    }
+}
+
+impl Encode for Journal {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_u8(JOURNAL_VERSION);
+        match self {
+            Journal::Epoch(values) => {
+                enc.put_u8(0);
+                values.encode(enc);
+            }
+            Journal::Recursive(values) => {
+                enc.put_u8(1);
+                values.encode(enc);
+            }
+            Journal::Stf(values) => {
+                enc.put_u8(2);
+                values.encode(enc);
+            }
+        }
+    }
+}
+
+impl Decode for Journal {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        let version = dec.u8()?;
+        match version {
+            // version 1 journals are plain proofs only and carry no kind
+            1 => Ok(Journal::Epoch(PublicValues {
+                version,
+                ..PublicValues::decode(dec)?
+            })),
+            JOURNAL_VERSION => match dec.u8()? {
+                0 => Ok(Journal::Epoch(PublicValues::decode(dec)?)),
+                1 => Ok(Journal::Recursive(RecursivePublicValues::decode(dec)?)),
+                2 => Ok(Journal::Stf(StfPublicValues::decode(dec)?)),
+                tag => Err(anyhow!("Unknown journal kind {}", tag)),
+            },
+            _ => Err(anyhow!("Unsupported journal version {}", version)),
+        }
+    }
+}
+
+/// The fields only, the version is written by [`Journal`].
+impl Encode for PublicValues {
+    fn encode(&self, enc: &mut Encoder) {
+        self.prev_root.encode(enc);
+        self.new_root.encode(enc);
+        enc.put_u64(self.da_height_range.0);
+        enc.put_u64(self.da_height_range.1);
+        self.batch_commitments.encode(enc);
+    }
+}
+
+impl Decode for PublicValues {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        Ok(PublicValues {
+            version: JOURNAL_VERSION,
+            prev_root: Digest::decode(dec)?,
+            new_root: Digest::decode(dec)?,
+            da_height_range: (dec.u64()?, dec.u64()?),
+            batch_commitments: Vec::decode(dec)?,
+        })
+    }
+}
+
+impl Encode for RecursivePublicValues {
+    fn encode(&self, enc: &mut Encoder) {
+        self.genesis_root.encode(enc);
+        self.root.encode(enc);
+        for word in self.program_id {
+            enc.put_u32(word);
+        }
+        enc.put_u64(self.da_height_range.0);
+        enc.put_u64(self.da_height_range.1);
+        self.batch_commitments.encode(enc);
+    }
+}
+
+impl Decode for RecursivePublicValues {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        let genesis_root = Digest::decode(dec)?;
+        let root = Digest::decode(dec)?;
+        let mut program_id = [0u32; 8];
+        for word in program_id.iter_mut() {
+            *word = dec.u32()?;
+        }
+        Ok(RecursivePublicValues {
+            genesis_root,
+            root,
+            program_id,
+            da_height_range: (dec.u64()?, dec.u64()?),
+            batch_commitments: Vec::decode(dec)?,
+        })
+    }
+}
+
+impl Encode for StfPublicValues {
+    fn encode(&self, enc: &mut Encoder) {
+        self.prev_root.encode(enc);
+        self.new_root.encode(enc);
+        self.batch_hash.encode(enc);
+        self.mint_authority.encode(enc);
+    }
+}
+
+impl Decode for StfPublicValues {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        Ok(StfPublicValues {
+            prev_root: Digest::decode(dec)?,
+            new_root: Digest::decode(dec)?,
+            batch_hash: Digest::decode(dec)?,
+            mint_authority: Digest::decode(dec)?,
+        })
+    }
+}
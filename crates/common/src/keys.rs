@@ -0,0 +1,323 @@
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use keystore_rs::{KeyChain, KeyStore};
+use prism_common::keys::{Signature, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// The signature schemes transactions can be signed with.
+///
+/// Secp256k1 lets Ethereum wallets sign transactions directly, secp256r1
+/// (P-256) passkeys and hardware keys.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyScheme {
+    Ed25519,
+    Secp256k1,
+    #[value(alias = "p256")]
+    #[serde(alias = "p256")]
+    Secp256r1,
+}
+
+impl KeyScheme {
+    pub fn of(vk: &VerifyingKey) -> Self {
+        match vk {
+            VerifyingKey::Ed25519(_) => KeyScheme::Ed25519,
+            VerifyingKey::Secp256k1(_) => KeyScheme::Secp256k1,
+            VerifyingKey::Secp256r1(_) => KeyScheme::Secp256r1,
+        }
+    }
+
+    pub fn of_signing_key(key: &SigningKey) -> Self {
+        match key {
+            SigningKey::Ed25519(_) => KeyScheme::Ed25519,
+            SigningKey::Secp256k1(_) => KeyScheme::Secp256k1,
+            SigningKey::Secp256r1(_) => KeyScheme::Secp256r1,
+        }
+    }
+
+    /// Appended to signing domains, so a signature under one scheme is never
+    /// valid as a signature under another.
+    pub(crate) fn domain(&self) -> &'static [u8] {
+        match self {
+            KeyScheme::Ed25519 => b"/ed25519",
+            KeyScheme::Secp256k1 => b"/secp256k1",
+            KeyScheme::Secp256r1 => b"/secp256r1",
+        }
+    }
+}
+
+/// Creates a signing key of `scheme` from its raw secret bytes.
+pub fn signing_key_from_bytes(scheme: KeyScheme, secret: &[u8]) -> Result<SigningKey> {
+    match scheme {
+        KeyScheme::Ed25519 => {
+            let secret: [u8; 32] = secret
+                .try_into()
+                .map_err(|_| anyhow!("Ed25519 secret keys are 32 bytes"))?;
+            Ok(SigningKey::Ed25519(Box::new(
+                ed25519_consensus::SigningKey::from(secret),
+            )))
+        }
+        KeyScheme::Secp256k1 => Ok(SigningKey::Secp256k1(
+            secp256k1::SecretKey::from_slice(secret).context("Invalid secp256k1 secret key")?,
+        )),
+        KeyScheme::Secp256r1 => Ok(SigningKey::Secp256r1(
+            p256::ecdsa::SigningKey::from_slice(secret).context("Invalid secp256r1 secret key")?,
+        )),
+    }
+}
+
+pub fn verifying_key(key: &SigningKey) -> VerifyingKey {
+    match key {
+        SigningKey::Ed25519(key) => VerifyingKey::Ed25519(key.verification_key()),
+        SigningKey::Secp256k1(key) => {
+            VerifyingKey::Secp256k1(key.public_key(&secp256k1::Secp256k1::new()))
+        }
+        SigningKey::Secp256r1(key) => VerifyingKey::Secp256r1(*key.verifying_key()),
+    }
+}
+
+/// Returns whether `signature` was made with a key of `scheme`. Placeholder
+/// signatures match any scheme.
+pub(crate) fn signature_matches(signature: &Signature, scheme: KeyScheme) -> bool {
+    match signature {
+        Signature::Ed25519(_) => scheme == KeyScheme::Ed25519,
+        Signature::Secp256k1(_) => scheme == KeyScheme::Secp256k1,
+        Signature::Secp256r1(_) => scheme == KeyScheme::Secp256r1,
+        Signature::Placeholder => true,
+    }
+}
+
+/// Where key files are stored unless another directory is configured.
+pub const DEFAULT_KEYS_DIR: &str = "keys";
+
+/// Read the password of encrypted key files from this variable before
+/// prompting for it, so nodes on headless servers can start unattended.
+pub const KEY_PASSWORD_ENV: &str = "SHARD_KEY_PASSWORD";
+
+/// scrypt cost of newly encrypted key files, stored per file so it can be
+/// raised without breaking existing ones.
+const SCRYPT_LOG_N: u8 = 17;
+
+/// Derives a secret key from a BIP-39 mnemonic: the first 32 bytes of its
+/// seed. This is not BIP-32 derivation, so the key differs from
+/// the accounts a wallet derives from the same mnemonic.
+pub fn secret_from_mnemonic(phrase: &str) -> Result<Vec<u8>> {
+    let mnemonic = bip39::Mnemonic::parse(phrase.trim()).context("Invalid mnemonic")?;
+    Ok(mnemonic.to_seed("")[..32].to_vec())
+}
+
+/// Returns the password of encrypted key files from [`KEY_PASSWORD_ENV`],
+/// prompting for it on stdin if it isn't set.
+pub fn key_password() -> Result<String> {
+    if let Ok(password) = std::env::var(KEY_PASSWORD_ENV) {
+        return Ok(password);
+    }
+    eprint!("Key password: ");
+    std::io::stderr().flush()?;
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']).to_string();
+    if password.is_empty() {
+        return Err(anyhow!(
+            "No key password given, set {} or enter it when prompted",
+            KEY_PASSWORD_ENV
+        ));
+    }
+    Ok(password)
+}
+
+/// Loads the key `name`, preferring a key file in `keys_dir` over a key in
+/// the OS keychain.
+pub fn load_signing_key(keys_dir: &Path, name: &str) -> Result<SigningKey> {
+    if let Some(key_file) = KeyFile::load(keys_dir, name)? {
+        return key_file.signing_key();
+    }
+    let signer = KeyChain
+        .get_signing_key(name)
+        .map_err(|e| anyhow!("Key '{}' not found: {}", name, e))?;
+    Ok(SigningKey::Ed25519(Box::new(signer)))
+}
+
+/// How the secret of an encrypted [`KeyFile`] is encrypted: AES-256-GCM
+/// under a key derived from the password with scrypt.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KeyEncryption {
+    /// The hex encoded scrypt salt
+    pub salt: String,
+    pub log_n: u8,
+    /// The hex encoded AES-GCM nonce
+    pub nonce: String,
+}
+
+impl KeyEncryption {
+    fn cipher(&self, password: &str) -> Result<Aes256Gcm> {
+        let salt = hex::decode(&self.salt).context("Invalid salt hex")?;
+        let params = scrypt::Params::new(self.log_n, 8, 1, 32)
+            .map_err(|e| anyhow!("Invalid scrypt parameters: {}", e))?;
+        let mut key = [0u8; 32];
+        scrypt::scrypt(password.as_bytes(), &salt, &params, &mut key)
+            .map_err(|e| anyhow!("Failed to derive key: {}", e))?;
+        Ok(Aes256Gcm::new(&key.into()))
+    }
+}
+
+/// A signing key stored as a JSON file in the keys directory, optionally
+/// encrypted with a password. Unlike the OS keychain used by
+/// `create-signer`, key files work on headless servers and can hold keys of
+/// any scheme.
+#[derive(Serialize, Deserialize)]
+pub struct KeyFile {
+    pub scheme: KeyScheme,
+    /// The hex encoded secret key, or its ciphertext if `encryption` is set.
+    pub secret: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<KeyEncryption>,
+}
+
+impl KeyFile {
+    /// Creates a key file of the raw secret key, encrypted with `password`
+    /// if one is given.
+    pub fn new(scheme: KeyScheme, secret: &[u8], password: Option<&str>) -> Result<KeyFile> {
+        // fails early on secrets that aren't valid keys of `scheme`
+        signing_key_from_bytes(scheme, secret)?;
+        let Some(password) = password else {
+            return Ok(KeyFile {
+                scheme,
+                secret: hex::encode(secret),
+                encryption: None,
+            });
+        };
+
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let encryption = KeyEncryption {
+            salt: hex::encode(salt),
+            log_n: SCRYPT_LOG_N,
+            nonce: hex::encode(nonce),
+        };
+        let ciphertext = encryption
+            .cipher(password)?
+            .encrypt(&nonce, secret)
+            .map_err(|_| anyhow!("Failed to encrypt key"))?;
+        Ok(KeyFile {
+            scheme,
+            secret: hex::encode(ciphertext),
+            encryption: Some(encryption),
+        })
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    /// Returns the raw secret key, asking for the password via
+    /// [`key_password`] if the file is encrypted.
+    pub fn secret_bytes(&self) -> Result<Vec<u8>> {
+        let secret = hex::decode(&self.secret).context("Invalid secret key hex")?;
+        let Some(encryption) = &self.encryption else {
+            return Ok(secret);
+        };
+        let nonce = hex::decode(&encryption.nonce).context("Invalid nonce hex")?;
+        if nonce.len() != 12 {
+            return Err(anyhow!("Invalid nonce length {}", nonce.len()));
+        }
+        encryption
+            .cipher(&key_password()?)?
+            .decrypt(Nonce::from_slice(&nonce), secret.as_slice())
+            .map_err(|_| anyhow!("Wrong key password"))
+    }
+
+    pub fn signing_key(&self) -> Result<SigningKey> {
+        signing_key_from_bytes(self.scheme, &self.secret_bytes()?)
+    }
+
+    fn path(dir: &Path, name: &str) -> PathBuf {
+        dir.join(format!("{}.json", name))
+    }
+
+    /// Reads a key file from `path`.
+    pub fn read(path: &Path) -> Result<KeyFile> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read key file {}", path.display()))?;
+        serde_json::from_str(&data).with_context(|| format!("Invalid key file {}", path.display()))
+    }
+
+    /// Loads the key `name` from `dir`, returning `None` if it doesn't exist.
+    pub fn load(dir: &Path, name: &str) -> Result<Option<KeyFile>> {
+        let path = Self::path(dir, name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::read(&path).map(Some)
+    }
+
+    /// Writes the key to `path`, readable only by the current user.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if path.exists() {
+            return Err(anyhow!("{} already exists", path.display()));
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the key to `dir` as `name`, failing if it already exists.
+    pub fn save(&self, dir: &Path, name: &str) -> Result<()> {
+        if KeyFile::load(dir, name)?.is_some() {
+            return Err(anyhow!("Key {} already exists", name));
+        }
+        self.write(&Self::path(dir, name))
+    }
+
+    /// Deletes the key `name` from `dir`, returning whether it existed.
+    pub fn delete(dir: &Path, name: &str) -> Result<bool> {
+        let path = Self::path(dir, name);
+        if !path.exists() {
+            return Ok(false);
+        }
+        fs::remove_file(&path)
+            .with_context(|| format!("Failed to delete key file {}", path.display()))?;
+        Ok(true)
+    }
+
+    /// Lists the keys in `dir` by name. Files that aren't valid key files
+    /// are skipped.
+    pub fn list(dir: &Path) -> Result<Vec<(String, KeyFile)>> {
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            match Self::read(&path) {
+                Ok(key_file) => keys.push((name.to_string(), key_file)),
+                Err(e) => warn!("Skipping {}: {:?}", path.display(), e),
+            }
+        }
+        keys.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(keys)
+    }
+}
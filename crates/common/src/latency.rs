@@ -0,0 +1,172 @@
+//! Where time is spent between a transaction's submission and its
+//! execution. Nodes record when they observe a transaction reach each stage
+//! of its lifecycle, see [`TxTimings`], and [`LatencyStats`] aggregates the
+//! time between consecutive stages. Only the sequencer observes every stage;
+//! other nodes only observe the execution.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use utoipa::ToSchema;
+
+use crate::{storage::NodeStore, tree::Digest};
+
+/// A stage of a transaction's lifecycle, in order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxStage {
+    /// Added to the sequencer's mempool
+    Queued,
+    /// Drained from the mempool into a batch
+    Batched,
+    /// Handed to Celestia in a blob
+    Submitted,
+    /// Celestia confirmed the blob's inclusion
+    Confirmed,
+    /// Executed from Celestia, successfully or not
+    Executed,
+}
+
+/// When a transaction reached each stage, in milliseconds since the Unix
+/// epoch. Stages the node didn't observe are unset. A batch that is retried
+/// overwrites the stages after [`TxStage::Queued`].
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema, Default, Debug, PartialEq, Eq)]
+pub struct TxTimings {
+    pub queued_at: Option<u64>,
+    pub batched_at: Option<u64>,
+    pub submitted_at: Option<u64>,
+    pub confirmed_at: Option<u64>,
+    pub executed_at: Option<u64>,
+}
+
+impl TxTimings {
+    fn stages(&self) -> [Option<u64>; 5] {
+        [
+            self.queued_at,
+            self.batched_at,
+            self.submitted_at,
+            self.confirmed_at,
+            self.executed_at,
+        ]
+    }
+
+    fn stage_mut(&mut self, stage: TxStage) -> &mut Option<u64> {
+        match stage {
+            TxStage::Queued => &mut self.queued_at,
+            TxStage::Batched => &mut self.batched_at,
+            TxStage::Submitted => &mut self.submitted_at,
+            TxStage::Confirmed => &mut self.confirmed_at,
+            TxStage::Executed => &mut self.executed_at,
+        }
+    }
+
+    /// Returns when the last stage observed before `stage` was reached.
+    fn previous(&self, stage: TxStage) -> Option<u64> {
+        self.stages()[..stage as usize]
+            .iter()
+            .rev()
+            .find_map(|at| *at)
+    }
+}
+
+fn timings_key(tx_hash: &Digest) -> String {
+    format!("tx_timings:{}", hex::encode(tx_hash.0))
+}
+
+pub fn get_tx_timings<S: NodeStore + ?Sized>(
+    store: &S,
+    tx_hash: &Digest,
+) -> Result<Option<TxTimings>> {
+    match store.get_metadata(&timings_key(tx_hash))? {
+        Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Records that the transaction reached `stage` now, returning its updated
+/// timings.
+pub fn record_tx_stage<S: NodeStore + ?Sized>(
+    store: &S,
+    tx_hash: &Digest,
+    stage: TxStage,
+) -> Result<TxTimings> {
+    let mut timings = get_tx_timings(store, tx_hash)?.unwrap_or_default();
+    *timings.stage_mut(stage) = Some(now_millis());
+    store.put_metadata(&timings_key(tx_hash), &bincode::serialize(&timings)?)?;
+    Ok(timings)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// The latency of one step of the lifecycle, over the transactions observed
+/// taking it.
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema, Default, Debug)]
+pub struct StageLatency {
+    pub count: u64,
+    pub mean_ms: u64,
+    pub max_ms: u64,
+    #[serde(skip)]
+    total_ms: u64,
+}
+
+impl StageLatency {
+    fn add(&mut self, ms: u64) {
+        self.count += 1;
+        self.total_ms = self.total_ms.saturating_add(ms);
+        self.mean_ms = self.total_ms / self.count;
+        self.max_ms = self.max_ms.max(ms);
+    }
+}
+
+/// The latencies of the transactions that reached a stage since the node
+/// started. Each step is measured from the last stage the node observed
+/// before it.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Default, Debug)]
+pub struct LatencyReport {
+    pub queued_to_batched: StageLatency,
+    pub batched_to_submitted: StageLatency,
+    pub submitted_to_confirmed: StageLatency,
+    pub confirmed_to_executed: StageLatency,
+    /// From queued to executed
+    pub end_to_end: StageLatency,
+}
+
+/// Aggregates the [`TxTimings`] recorded by the node.
+#[derive(Default)]
+pub struct LatencyStats {
+    report: Mutex<LatencyReport>,
+}
+
+impl LatencyStats {
+    /// Adds the step to `stage` of a transaction whose timings were just
+    /// updated to `timings`.
+    pub fn observe(&self, timings: &TxTimings, stage: TxStage) {
+        let (Some(at), Some(previous)) =
+            (timings.stages()[stage as usize], timings.previous(stage))
+        else {
+            return;
+        };
+        let mut report = self.report.lock().unwrap();
+        let step = match stage {
+            TxStage::Queued => return,
+            TxStage::Batched => &mut report.queued_to_batched,
+            TxStage::Submitted => &mut report.batched_to_submitted,
+            TxStage::Confirmed => &mut report.submitted_to_confirmed,
+            TxStage::Executed => &mut report.confirmed_to_executed,
+        };
+        step.add(at.saturating_sub(previous));
+        if let (TxStage::Executed, Some(queued_at)) = (stage, timings.queued_at) {
+            report.end_to_end.add(at.saturating_sub(queued_at));
+        }
+    }
+
+    pub fn report(&self) -> LatencyReport {
+        self.report.lock().unwrap().clone()
+    }
+}
@@ -1,9 +1,48 @@
+pub mod archive;
+pub mod block;
+pub mod bridge;
+#[cfg(feature = "contracts")]
+pub mod contracts;
+pub mod da;
+pub mod diff;
+pub mod encoding;
+pub mod epoch;
+pub mod error;
+pub mod events;
+pub mod fees;
+pub mod finality;
+pub mod fraud;
+pub mod genesis;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod history;
+pub mod journal;
+pub mod keys;
+pub mod latency;
+pub mod mempool;
+pub mod middleware;
 pub mod node;
-mod proofs;
+pub mod ordering;
+#[cfg(feature = "p2p")]
+pub mod p2p;
+pub mod params;
+pub mod proofs;
+pub mod protocol;
+pub mod receipt;
+pub mod settlement;
+pub mod simulate;
+pub mod snapshot;
+pub mod soft;
 pub mod state;
-mod tree;
+pub mod status;
+pub mod stf;
+pub mod storage;
+pub mod telemetry;
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
+pub mod tree;
 pub mod tx;
-mod webserver;
+pub mod webserver;
 
 #[macro_use]
-extern crate log;
+extern crate tracing;
@@ -1,46 +1,492 @@
 use anyhow::{Context, Result};
-use celestia_types::nmt::Namespace;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use celestia_types::{nmt::Namespace, Blob};
 use clap::{Parser, Subcommand};
 use keystore_rs::KeyStore;
-use prism_common::keys::{Signature, VerifyingKey};
+use prism_common::keys::{SigningKey, VerifyingKey};
+use serde::Deserialize;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
-use tx::{Transaction, TransactionType, SIGNATURE_VERIFICATION_ENABLED};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tx::{Batch, Transaction, TransactionBuilder, TransactionType};
 
+mod archive;
+mod bench;
+mod block;
+mod bridge;
+mod config;
+#[cfg(feature = "contracts")]
+mod contracts;
+mod da;
+mod devnet;
+mod diff;
+mod encoding;
+mod epoch;
+mod error;
+mod events;
+mod fees;
+mod finality;
+mod fraud;
+mod genesis;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod history;
+mod journal;
+mod keys;
+mod latency;
+mod mempool;
+mod middleware;
 mod node;
+mod ordering;
+#[cfg(feature = "p2p")]
+mod p2p;
+mod params;
+mod proofs;
+mod protocol;
+mod receipt;
+mod settlement;
+mod simulate;
+mod snapshot;
+mod soft;
 mod state;
+mod status;
+mod stf;
+mod storage;
+mod telemetry;
+mod tree;
 mod tx;
+mod watch;
 mod webserver;
-use node::{Config, Node};
+#[cfg(feature = "lumina")]
+use da::lumina::LuminaNetwork;
+use da::{CelestiaDA, CelestiaTxOptions, DaKind, DaMode, DataAvailability, RetryPolicy};
+use diff::DaFormat;
+use encoding::encode_blob;
+use fraud::{OptimisticProver, ProofMode};
+use keys::{KeyFile, KeyScheme, DEFAULT_KEYS_DIR};
+use mempool::BatchTriggers;
+use node::{BatchAuth, Config, Node, NodeRole};
+use ordering::BatchOrdering;
+use protocol::ProtocolSchedule;
+use state::NoncePolicy;
+use storage::NodeStore;
 
 #[macro_use]
-extern crate log;
+extern crate tracing;
 
-#[derive(Parser, Debug)]
+/// Node configuration, settable via CLI flags or a TOML config file. Flags
+/// take precedence over the file, unset values fall back to
+/// [`Config::default`].
+#[derive(Parser, Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
 struct CommonArgs {
-    /// The namespace used by this rollup (hex encoded)
-    #[arg(long, default_value = "2a2a2a2a")]
-    namespace: String,
+    /// Path to a TOML config file (see `init-config`)
+    #[arg(long)]
+    #[serde(skip)]
+    config: Option<PathBuf>,
 
-    /// The height from which to start syncing
-    #[arg(long, default_value_t = 1)]
-    start_height: u64,
+    /// Which tasks this node runs [default: sequencer]
+    #[arg(long, value_enum)]
+    role: Option<NodeRole>,
 
-    /// The URL of the Celestia node to connect to
-    #[arg(long, default_value = "ws://0.0.0.0:26658")]
-    celestia_url: String,
+    /// The URL of the sequencer's webserver, required for full and light
+    /// nodes
+    #[arg(long)]
+    sequencer_url: Option<String>,
+
+    /// The namespace used by this rollup (hex encoded) [default: 2a2a2a2a]
+    #[arg(long)]
+    namespace: Option<String>,
+
+    /// The chain id transactions are signed for and must carry [default: 0]
+    #[arg(long)]
+    chain_id: Option<u64>,
+
+    /// Comma separated namespaces (hex encoded) of additional transaction
+    /// lanes executed alongside the rollup namespace
+    #[arg(long, value_delimiter = ',')]
+    lane_namespaces: Option<Vec<String>>,
+
+    /// Whether any batch is executed or only those signed by the sequencer
+    /// [default: permissionless]
+    #[arg(long, value_enum)]
+    batch_auth: Option<BatchAuth>,
 
-    /// The address to listen on for the node's webserver
-    #[arg(long, default_value = "0.0.0.0:3000")]
-    listen_addr: String,
+    /// The number of Celestia blocks after which unsigned batches are
+    /// force-included under signed batch auth [default: 30]
+    #[arg(long)]
+    forced_inclusion_delay: Option<u64>,
+
+    /// The base64 encoded key batches must be signed with. Sequencers derive
+    /// it from their signing key
+    #[arg(long)]
+    sequencer_vk: Option<String>,
+
+    /// The base64 encoded key of the bridge relayer, the only sender allowed
+    /// to credit deposits. Deposits are rejected if unset
+    #[arg(long)]
+    bridge_vk: Option<String>,
+
+    /// The base64 encoded key of the governance account, the only sender
+    /// allowed to change the live chain parameters. Parameters can't be
+    /// changed if unset
+    #[arg(long)]
+    governance_vk: Option<String>,
+
+    /// Comma separated protocol upgrades of the chain, each written as
+    /// `<version>@<celestia height>`. All nodes must configure the same
+    /// upgrades
+    #[arg(long, value_delimiter = ',')]
+    protocol_upgrades: Option<Vec<String>>,
+
+    /// The namespace (hex encoded) the bridge relayer posts deposits to
+    #[arg(long)]
+    deposit_namespace: Option<String>,
+
+    /// The base64 encoded key of the mint authority, the only sender allowed
+    /// to mint new tokens. Mints are rejected if unset
+    #[arg(long)]
+    mint_vk: Option<String>,
+
+    /// Whether transactions may be scheduled for a later celestia height.
+    /// Epochs including them can't be proven yet, so sequencers proving
+    /// their epochs refuse to start with it, and all nodes must agree
+    /// [default: false]
+    #[arg(long)]
+    scheduled_txs: Option<bool>,
+
+    /// The name of the key the sequencer signs batches with, a key file in
+    /// `--keys-dir` or a key in the OS keychain
+    #[arg(long)]
+    sequencer_key_name: Option<String>,
+
+    /// The directory of key files [default: keys]
+    #[arg(long)]
+    keys_dir: Option<PathBuf>,
+
+    /// The namespace epoch proofs are posted to (hex encoded) [default:
+    /// 2a2a2a2b]
+    #[arg(long)]
+    proof_namespace: Option<String>,
+
+    /// How often processed batches are sealed into an epoch and proven, as a
+    /// number of batches (e.g. `10`) or seconds (e.g. `30s`) [default: 1]
+    #[arg(long)]
+    epoch_interval: Option<String>,
+
+    /// Whether epochs are proven with a zkVM or only claimed and challenged
+    /// by watchtowers [default: validity]
+    #[arg(long, value_enum)]
+    proof_mode: Option<ProofMode>,
+
+    /// Run a full node that checks the root claims of an optimistic
+    /// sequencer and posts fraud proofs of wrong ones [default: false]
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    watchtower: Option<bool>,
+
+    /// The namespace fraud proofs are posted to (hex encoded) [default:
+    /// 2a2a2a2d]
+    #[arg(long)]
+    challenge_namespace: Option<String>,
+
+    /// The height from which to start syncing [default: 1]
+    #[arg(long)]
+    start_height: Option<u64>,
+
+    /// Roll the state back and process every height from the start height
+    /// again, instead of resuming after the last processed one [default:
+    /// false]
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    resync: Option<bool>,
+
+    /// How many Celestia heights historical sync fetches concurrently
+    /// [default: 8]
+    #[arg(long)]
+    sync_parallelism: Option<usize>,
+
+    /// Comma separated URLs of the Celestia nodes to connect to, failing over
+    /// to the next one if the current one keeps failing [default:
+    /// ws://0.0.0.0:26658]
+    #[arg(long, value_delimiter = ',')]
+    #[serde(default, deserialize_with = "one_or_many")]
+    celestia_url: Option<Vec<String>>,
+
+    /// The address to listen on for the node's webserver [default:
+    /// 0.0.0.0:3000]
+    #[arg(long)]
+    listen_addr: Option<String>,
+
+    /// The address to serve the gRPC API on (requires the `grpc` feature).
+    /// Disabled if unset
+    #[arg(long)]
+    grpc_addr: Option<String>,
+
+    /// The multiaddr to listen on for p2p transaction gossip (requires the
+    /// `p2p` feature), e.g. /ip4/0.0.0.0/tcp/4001. Disabled if unset
+    #[arg(long)]
+    p2p_listen_addr: Option<String>,
+
+    /// Comma separated multiaddrs of peers to connect to on startup
+    #[arg(long, value_delimiter = ',')]
+    bootnodes: Option<Vec<String>>,
+
+    /// An OpenTelemetry collector to export spans to via OTLP/gRPC (requires
+    /// the `otlp` feature). Disabled if unset
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
+
+    /// The Ethereum RPC endpoint to settle epoch proofs through (requires the
+    /// `settlement` feature). The relayer key is read from
+    /// SETTLEMENT_PRIVATE_KEY. Disabled if unset
+    #[arg(long)]
+    settlement_rpc_url: Option<String>,
+
+    /// The address of the deployed settlement contract (see
+    /// `export-verifier`)
+    #[arg(long)]
+    settlement_contract: Option<String>,
+
+    /// The maximum number of /submit_tx requests per client IP and minute.
+    /// Unlimited if unset
+    #[arg(long)]
+    submit_rate_limit: Option<u32>,
+
+    /// A bearer token required for admin endpoints (/snapshot,
+    /// /admin/log_level). Admin endpoints aren't served if unset
+    #[arg(long)]
+    admin_token: Option<String>,
+
+    /// Comma separated origins allowed to make cross-origin requests, `*` for
+    /// any. CORS is disabled if unset
+    #[arg(long, value_delimiter = ',')]
+    cors_origins: Option<Vec<String>>,
+
+    /// The data availability layer to use [default: celestia]
+    #[arg(long, value_enum)]
+    da: Option<DaKind>,
+
+    /// Whether Celestia is synced via RPC or an embedded Lumina light node
+    /// (requires the `lumina` feature) [default: rpc]
+    #[arg(long, value_enum)]
+    da_mode: Option<DaMode>,
+
+    /// The network the embedded Lumina node joins [default: mainnet]
+    #[cfg(feature = "lumina")]
+    #[arg(long, value_enum)]
+    lumina_network: Option<LuminaNetwork>,
+
+    /// The interval at which the mock DA layer, or a sequencer on the file
+    /// DA layer, produces blocks (in seconds) [default: 2]
+    #[arg(long)]
+    mock_block_time: Option<u64>,
+
+    /// The directory shared between the nodes the file DA layer stores
+    /// blocks in [default: da]
+    #[arg(long)]
+    da_dir: Option<PathBuf>,
 
     /// The auth token to use when connecting to Celestia
     #[arg(long)]
     auth_token: Option<String>,
 
+    /// The gas price (utia per unit of gas) to post blobs with, estimated by
+    /// the celestia-node if unset. Raised automatically after insufficient
+    /// fee errors
+    #[arg(long)]
+    celestia_gas_price: Option<f64>,
+
+    /// The upper bound for automatically raised gas prices
+    #[arg(long)]
+    celestia_max_gas_price: Option<f64>,
+
+    /// The key of the celestia-node's keyring to sign blob submissions with
+    #[arg(long)]
+    celestia_key_name: Option<String>,
+
+    /// The address to sign blob submissions with
+    #[arg(long)]
+    celestia_signer: Option<String>,
+
+    /// An address granting the signer a fee allowance to pay blob
+    /// submissions from
+    #[arg(long)]
+    celestia_fee_granter: Option<String>,
+
     /// The interval at which to post batches of transactions (in seconds)
-    #[arg(long, default_value_t = 3)]
-    batch_interval: u64,
+    /// [default: 3]
+    #[arg(long)]
+    batch_interval: Option<u64>,
+
+    /// Post a batch before the interval elapses once the queued
+    /// transactions reach this many bytes
+    #[arg(long)]
+    batch_max_bytes: Option<usize>,
+
+    /// Post a batch right away when a transaction paying at least this gas
+    /// price is queued
+    #[arg(long)]
+    priority_gas_price: Option<u64>,
+
+    /// How many times a batch or proof submission is attempted before giving
+    /// up [default: 5]
+    #[arg(long)]
+    submit_max_attempts: Option<u32>,
+
+    /// The delay before retrying a failed submission, doubled on every
+    /// further failure (in milliseconds) [default: 1000]
+    #[arg(long)]
+    submit_initial_backoff: Option<u64>,
+
+    /// The maximum number of transactions held in the mempool [default:
+    /// 10000]
+    #[arg(long)]
+    mempool_size: Option<usize>,
+
+    /// The order the sequencer posts queued transactions in [default: fifo]
+    #[arg(long, value_enum)]
+    batch_ordering: Option<BatchOrdering>,
+
+    /// Whether the sequencer posts transactions or the state diffs they
+    /// result in [default: transactions]
+    #[arg(long, value_enum)]
+    da_format: Option<DaFormat>,
+
+    /// Whether the sequencer soft-confirms transactions by executing them
+    /// as soon as they are queued [default: false]
+    #[arg(long)]
+    soft_confirmations: Option<bool>,
+
+    /// Whether nonces must be sequential or may contain gaps [default:
+    /// strict]
+    #[arg(long, value_enum)]
+    nonce_policy: Option<NoncePolicy>,
+
+    /// Accept transactions without checking their signatures, for
+    /// development only. `submit-tx` then sends unsigned transactions
+    /// [default: false]
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    insecure_signatures: Option<bool>,
+
+    /// The minimum fee per unit of gas for queued transactions [default: 0]
+    #[arg(long)]
+    min_gas_price: Option<u64>,
+
+    /// The directory to persist state in (RocksDB). State is kept in memory if
+    /// unset
+    #[arg(long)]
+    db_path: Option<PathBuf>,
+
+    /// How many tree nodes to cache in front of the database, 0 disables the
+    /// cache [default: 100000]
+    #[arg(long)]
+    node_cache_size: Option<usize>,
+
+    /// Run a read-only full node that keeps the complete history and serves
+    /// history queries [default: false]
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    archive: Option<bool>,
+
+    /// How many of the latest Celestia heights to keep the tree versions of,
+    /// older ones are pruned. At least 100 to cover reorgs, every version is
+    /// kept if unset
+    #[arg(long)]
+    retain_heights: Option<u64>,
+
+    /// A snapshot file or URL to start from if the store is empty. Its root
+    /// is verified against the proof namespace
+    #[arg(long)]
+    trusted_snapshot: Option<String>,
+
+    /// The root the trusted snapshot must have, as `<hex root>@<celestia
+    /// height>`. Replaces checking the root against the proof namespace
+    #[arg(long)]
+    trusted_root: Option<String>,
+
+    /// The webserver URL of a node with the full block history, used to
+    /// backfill blocks from before the trusted snapshot
+    #[arg(long)]
+    archive_url: Option<String>,
+
+    /// A genesis file (JSON or TOML) with initial accounts and chain
+    /// parameters, applied if the store is empty
+    #[arg(long)]
+    genesis: Option<PathBuf>,
+}
+
+impl CommonArgs {
+    /// Fills all values not set in `self` from `other`.
+    fn or(self, other: CommonArgs) -> CommonArgs {
+        CommonArgs {
+            config: self.config.or(other.config),
+            role: self.role.or(other.role),
+            sequencer_url: self.sequencer_url.or(other.sequencer_url),
+            namespace: self.namespace.or(other.namespace),
+            chain_id: self.chain_id.or(other.chain_id),
+            lane_namespaces: self.lane_namespaces.or(other.lane_namespaces),
+            batch_auth: self.batch_auth.or(other.batch_auth),
+            forced_inclusion_delay: self.forced_inclusion_delay.or(other.forced_inclusion_delay),
+            sequencer_vk: self.sequencer_vk.or(other.sequencer_vk),
+            bridge_vk: self.bridge_vk.or(other.bridge_vk),
+            governance_vk: self.governance_vk.or(other.governance_vk),
+            protocol_upgrades: self.protocol_upgrades.or(other.protocol_upgrades),
+            deposit_namespace: self.deposit_namespace.or(other.deposit_namespace),
+            mint_vk: self.mint_vk.or(other.mint_vk),
+            scheduled_txs: self.scheduled_txs.or(other.scheduled_txs),
+            sequencer_key_name: self.sequencer_key_name.or(other.sequencer_key_name),
+            keys_dir: self.keys_dir.or(other.keys_dir),
+            proof_namespace: self.proof_namespace.or(other.proof_namespace),
+            epoch_interval: self.epoch_interval.or(other.epoch_interval),
+            proof_mode: self.proof_mode.or(other.proof_mode),
+            watchtower: self.watchtower.or(other.watchtower),
+            challenge_namespace: self.challenge_namespace.or(other.challenge_namespace),
+            start_height: self.start_height.or(other.start_height),
+            resync: self.resync.or(other.resync),
+            sync_parallelism: self.sync_parallelism.or(other.sync_parallelism),
+            celestia_url: self.celestia_url.or(other.celestia_url),
+            listen_addr: self.listen_addr.or(other.listen_addr),
+            grpc_addr: self.grpc_addr.or(other.grpc_addr),
+            p2p_listen_addr: self.p2p_listen_addr.or(other.p2p_listen_addr),
+            bootnodes: self.bootnodes.or(other.bootnodes),
+            otlp_endpoint: self.otlp_endpoint.or(other.otlp_endpoint),
+            settlement_rpc_url: self.settlement_rpc_url.or(other.settlement_rpc_url),
+            settlement_contract: self.settlement_contract.or(other.settlement_contract),
+            submit_rate_limit: self.submit_rate_limit.or(other.submit_rate_limit),
+            admin_token: self.admin_token.or(other.admin_token),
+            cors_origins: self.cors_origins.or(other.cors_origins),
+            da: self.da.or(other.da),
+            da_mode: self.da_mode.or(other.da_mode),
+            #[cfg(feature = "lumina")]
+            lumina_network: self.lumina_network.or(other.lumina_network),
+            mock_block_time: self.mock_block_time.or(other.mock_block_time),
+            da_dir: self.da_dir.or(other.da_dir),
+            auth_token: self.auth_token.or(other.auth_token),
+            celestia_gas_price: self.celestia_gas_price.or(other.celestia_gas_price),
+            celestia_max_gas_price: self.celestia_max_gas_price.or(other.celestia_max_gas_price),
+            celestia_key_name: self.celestia_key_name.or(other.celestia_key_name),
+            celestia_signer: self.celestia_signer.or(other.celestia_signer),
+            celestia_fee_granter: self.celestia_fee_granter.or(other.celestia_fee_granter),
+            batch_interval: self.batch_interval.or(other.batch_interval),
+            batch_max_bytes: self.batch_max_bytes.or(other.batch_max_bytes),
+            priority_gas_price: self.priority_gas_price.or(other.priority_gas_price),
+            submit_max_attempts: self.submit_max_attempts.or(other.submit_max_attempts),
+            submit_initial_backoff: self.submit_initial_backoff.or(other.submit_initial_backoff),
+            mempool_size: self.mempool_size.or(other.mempool_size),
+            batch_ordering: self.batch_ordering.or(other.batch_ordering),
+            da_format: self.da_format.or(other.da_format),
+            soft_confirmations: self.soft_confirmations.or(other.soft_confirmations),
+            nonce_policy: self.nonce_policy.or(other.nonce_policy),
+            insecure_signatures: self.insecure_signatures.or(other.insecure_signatures),
+            min_gas_price: self.min_gas_price.or(other.min_gas_price),
+            db_path: self.db_path.or(other.db_path),
+            node_cache_size: self.node_cache_size.or(other.node_cache_size),
+            archive: self.archive.or(other.archive),
+            retain_heights: self.retain_heights.or(other.retain_heights),
+            trusted_snapshot: self.trusted_snapshot.or(other.trusted_snapshot),
+            trusted_root: self.trusted_root.or(other.trusted_root),
+            archive_url: self.archive_url.or(other.archive_url),
+            genesis: self.genesis.or(other.genesis),
+        }
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -51,6 +497,233 @@ enum Command {
     SubmitTx(SubmitTxArgs),
     /// Create a signer
     CreateSigner(CreateSignerArgs),
+    /// Manage signing keys
+    #[command(subcommand)]
+    Key(KeyCommand),
+    /// Write a commented default config file
+    InitConfig(InitConfigArgs),
+    /// Manage state snapshots
+    #[command(subcommand)]
+    Snapshot(SnapshotCommand),
+    /// Export and import the state as JSON, for audits and migrations
+    #[command(subcommand)]
+    State(StateCommand),
+    /// Rebuild the state of a stopped node from its archived batches,
+    /// without fetching anything from Celestia
+    Replay(ReplayArgs),
+    /// Query a running node's API
+    Query(QueryArgs),
+    /// Write the Ethereum settlement contract for a guest program
+    ExportVerifier(ExportVerifierArgs),
+    /// Submit transfers between generated accounts at a fixed rate and
+    /// report inclusion latencies and rejections
+    Bench(BenchArgs),
+    /// Run a local network of a sequencer and full nodes
+    #[command(subcommand)]
+    Devnet(DevnetCommand),
+    /// Show a running node's activity live
+    Watch(WatchArgs),
+}
+
+#[derive(Subcommand, Debug)]
+enum DevnetCommand {
+    /// Launch the nodes as child processes and stop them on ctrl-c
+    Up(DevnetUpArgs),
+}
+
+#[derive(Parser, Debug)]
+struct DevnetUpArgs {
+    /// The directory to keep generated configs, keys, logs and DA blocks in
+    #[arg(long, default_value = "devnet")]
+    dir: PathBuf,
+
+    /// The number of full nodes to run besides the sequencer
+    #[arg(long, default_value = "2")]
+    full_nodes: usize,
+
+    /// The webserver port of the sequencer, full nodes listen on the
+    /// following ports
+    #[arg(long, default_value = "3000")]
+    base_port: u16,
+
+    /// The data availability layer the nodes share, "file" or "celestia"
+    #[arg(long, value_enum, default_value = "file")]
+    da: DaKind,
+
+    /// The interval at which the sequencer produces file DA blocks (in
+    /// seconds)
+    #[arg(long, default_value = "2")]
+    block_time: u64,
+
+    /// The URL of the celestia-node to connect to
+    #[arg(long, default_value = "ws://0.0.0.0:26658")]
+    celestia_url: String,
+
+    /// The auth token to use when connecting to Celestia
+    #[arg(long)]
+    auth_token: Option<String>,
+
+    /// A shell command launching a local celestia-node, e.g.
+    /// "celestia light start --p2p.network mocha", stopped with the devnet
+    #[arg(long)]
+    celestia_node_cmd: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct BenchArgs {
+    /// The number of accounts to generate and send transfers between
+    #[arg(long, default_value = "10")]
+    accounts: usize,
+
+    /// Transactions submitted per second, across all accounts
+    #[arg(long, default_value = "10")]
+    tps: u32,
+
+    /// How long to submit transactions for (in seconds)
+    #[arg(long, default_value = "60")]
+    duration: u64,
+
+    /// The fee every transaction pays
+    #[arg(long, default_value = "0")]
+    fee: u64,
+
+    /// How long to wait for submitted transactions to be executed once the
+    /// load ends (in seconds)
+    #[arg(long, default_value = "60")]
+    drain_timeout: u64,
+
+    /// The key of the node's mint authority, which funds the generated
+    /// accounts. Loaded like the `submit-tx` key
+    #[arg(long, default_value = "default")]
+    mint_key_name: String,
+
+    #[command(flatten)]
+    common: CommonArgs,
+}
+
+#[derive(Parser, Debug)]
+struct WatchArgs {
+    /// The URL of the node's webserver
+    #[arg(long, default_value = "http://127.0.0.1:3000")]
+    node: String,
+}
+
+#[derive(Parser, Debug)]
+struct ExportVerifierArgs {
+    /// The 0x prefixed verification key of the SP1 guest program, printed by
+    /// the `program-vkey` binary of `shard-prover`
+    #[arg(long)]
+    program_vkey: String,
+
+    /// The directory to write the contract to
+    #[arg(long, default_value = "contracts")]
+    out: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+struct ReplayArgs {
+    /// The first Celestia height to replay
+    #[arg(long)]
+    from_height: u64,
+
+    #[command(flatten)]
+    common: CommonArgs,
+}
+
+#[derive(Parser, Debug)]
+struct QueryArgs {
+    #[command(subcommand)]
+    query: QueryCommand,
+
+    #[command(flatten)]
+    common: CommonArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum QueryCommand {
+    /// Show the account of a verifying key
+    Account {
+        /// The base64 encoded verifying key
+        vk: String,
+    },
+    /// Show the current state root
+    Root,
+    /// Show the block at a height
+    Block { height: u64 },
+    /// Show the Celestia inclusion proof of the batch of the block at a
+    /// height
+    InclusionProof { height: u64 },
+    /// Show the status of a transaction
+    Tx {
+        /// The hex encoded transaction hash
+        hash: String,
+    },
+    /// Show the receipt of an executed transaction
+    Receipt {
+        /// The hex encoded transaction hash
+        hash: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotCommand {
+    /// Export the state of a stopped node to a snapshot file
+    Export(SnapshotExportArgs),
+}
+
+#[derive(Parser, Debug)]
+struct SnapshotExportArgs {
+    /// The directory of the node's database
+    #[arg(long)]
+    db_path: PathBuf,
+
+    /// The epoch to export, defaults to the latest epoch
+    #[arg(long)]
+    epoch: Option<u64>,
+
+    /// Where to write the snapshot
+    #[arg(long, default_value = "snapshot.bin")]
+    out: PathBuf,
+}
+
+#[derive(Subcommand, Debug)]
+enum StateCommand {
+    /// Export the accounts and values of a stopped node's state to JSON
+    Export(StateExportArgs),
+    /// Import an exported state into an empty database, checking the
+    /// resulting root
+    Import(StateImportArgs),
+}
+
+#[derive(Parser, Debug)]
+struct StateExportArgs {
+    /// The directory of the node's database
+    #[arg(long)]
+    db_path: PathBuf,
+
+    /// The epoch to export, defaults to the latest epoch
+    #[arg(long)]
+    epoch: Option<u64>,
+
+    /// Where to write the export
+    #[arg(long, default_value = "state.json")]
+    out: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+struct StateImportArgs {
+    /// The directory of the new database, which must not contain state yet
+    #[arg(long)]
+    db_path: PathBuf,
+
+    /// The export to import
+    #[arg(long = "in", default_value = "state.json")]
+    input: PathBuf,
+
+    /// The hex encoded root the imported state must have, defaults to the
+    /// exported root. A migration to a new tree layout changes the root
+    #[arg(long)]
+    expected_root: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -58,12 +731,39 @@ struct SubmitTxArgs {
     #[command(subcommand)]
     tx: TransactionType,
 
+    /// The key to sign with: a key file in `--keys-dir`, or a keychain key
+    /// created with `create-signer`
     #[arg(long, default_value = "default")]
     key_name: String,
 
+    /// Further keys to sign with, for multisig accounts requiring more than
+    /// one signature. Loaded like `--key-name`
+    #[arg(long, value_delimiter = ',')]
+    cosign_key_names: Vec<String>,
+
     #[arg(long, default_value = "0")]
     nonce: u64,
 
+    /// The fee to pay for inclusion
+    #[arg(long, default_value = "0")]
+    fee: u64,
+
+    /// The last Celestia height the transaction may be executed at. Never
+    /// expires if unset
+    #[arg(long)]
+    valid_until: Option<u64>,
+
+    /// The Celestia height the transaction is executed at. It is included
+    /// right away, but its effects wait until that height
+    #[arg(long)]
+    execute_at: Option<u64>,
+
+    /// Post the transaction to Celestia directly instead of sending it to
+    /// the sequencer. It is force-included after the inclusion delay if the
+    /// sequencer doesn't include it first
+    #[arg(long)]
+    direct: bool,
+
     #[command(flatten)]
     common: CommonArgs,
 }
@@ -72,6 +772,105 @@ struct SubmitTxArgs {
 struct CreateSignerArgs {
     /// The name of the key to create (used for signing transactions)
     key_name: String,
+
+    /// Store the key as a file in `--keys-dir` instead of the OS keychain,
+    /// e.g. on headless servers
+    #[arg(long)]
+    file: bool,
+
+    /// Encrypt the key file with a password, read from SHARD_KEY_PASSWORD or
+    /// prompted for. Implies `--file`
+    #[arg(long)]
+    encrypt: bool,
+
+    #[arg(long, default_value = DEFAULT_KEYS_DIR)]
+    keys_dir: PathBuf,
+}
+
+#[derive(Subcommand, Debug)]
+enum KeyCommand {
+    /// Import a signing key of any supported scheme, e.g. an Ethereum wallet
+    /// key or a P-256 key
+    Import(KeyImportArgs),
+    /// Print the secret of a key, or write it to a key file
+    Export(KeyExportArgs),
+    /// List the key files in the keys directory. Keys in the OS keychain
+    /// can't be enumerated and aren't listed
+    List {
+        #[arg(long, default_value = DEFAULT_KEYS_DIR)]
+        keys_dir: PathBuf,
+    },
+    /// Delete a key file
+    Delete {
+        key_name: String,
+
+        #[arg(long, default_value = DEFAULT_KEYS_DIR)]
+        keys_dir: PathBuf,
+    },
+}
+
+#[derive(Parser, Debug)]
+struct KeyImportArgs {
+    /// The name to store the key under
+    key_name: String,
+
+    /// The signature scheme of the key
+    #[arg(long, value_enum, required_unless_present = "file")]
+    scheme: Option<KeyScheme>,
+
+    /// The hex encoded secret key. Read from stdin if neither this nor
+    /// `--file` is set, so it doesn't end up in the shell history
+    #[arg(long, conflicts_with = "file")]
+    secret: Option<String>,
+
+    /// Read a BIP-39 mnemonic from stdin instead of a hex secret. The key is
+    /// the first 32 bytes of its seed, which is not the key a wallet derives
+    /// from the same mnemonic
+    #[arg(long, conflicts_with_all = ["secret", "file"])]
+    mnemonic: bool,
+
+    /// Copy a key file, e.g. one written by `key export --out`. Encrypted
+    /// files stay encrypted with their password
+    #[arg(long)]
+    file: Option<PathBuf>,
+
+    /// Encrypt the key file with a password, read from SHARD_KEY_PASSWORD or
+    /// prompted for
+    #[arg(long, conflicts_with = "file")]
+    encrypt: bool,
+
+    /// The directory to store the key in
+    #[arg(long, default_value = DEFAULT_KEYS_DIR)]
+    keys_dir: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+struct KeyExportArgs {
+    /// The name of a key file in `--keys-dir` or of a keychain key
+    key_name: String,
+
+    /// Write a key file instead of printing the hex encoded secret
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// Encrypt the written key file with a password, read from
+    /// SHARD_KEY_PASSWORD or prompted for
+    #[arg(long, requires = "out")]
+    encrypt: bool,
+
+    #[arg(long, default_value = DEFAULT_KEYS_DIR)]
+    keys_dir: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+struct InitConfigArgs {
+    /// Where to write the config file
+    #[arg(default_value = "shard.toml")]
+    path: PathBuf,
+
+    /// Overwrite the file if it already exists
+    #[arg(long)]
+    force: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -83,102 +882,583 @@ struct Args {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    pretty_env_logger::init();
-
     let args = Args::parse();
+    if !matches!(args.command, Command::Serve(_)) {
+        telemetry::init(None)?;
+    }
 
     match args.command {
         Command::Serve(common_args) => {
             let config = config_from_args(common_args)?;
-            start_node(config).await
+            // initialized once the config is loaded, since it may set an
+            // OTLP endpoint
+            telemetry::init(config.otlp_endpoint.as_deref())?;
+            let result = start_node(config).await;
+            telemetry::shutdown();
+            result
         }
         Command::SubmitTx(SubmitTxArgs {
             common,
             key_name,
+            cosign_key_names,
             nonce,
+            fee,
+            valid_until,
+            execute_at,
+            direct,
             tx,
         }) => {
             let config = config_from_args(common)?;
-            submit_tx(config, key_name, nonce, tx).await
+            let signers = if config.insecure_signatures {
+                Vec::new()
+            } else {
+                std::iter::once(&key_name)
+                    .chain(&cosign_key_names)
+                    .map(|name| keys::load_signing_key(&config.keys_dir, name))
+                    .collect::<Result<Vec<_>>>()?
+            };
+            let builder = TransactionBuilder::new(tx)
+                .nonce(nonce)
+                .fee(fee)
+                .chain_id(config.chain_id)
+                .valid_until(valid_until)
+                .execute_at(execute_at);
+            submit_tx(config, &signers, builder, direct).await
+        }
+        Command::CreateSigner(args) => create_signer(args),
+        Command::Key(command) => manage_keys(command),
+        Command::Snapshot(SnapshotCommand::Export(args)) => export_snapshot(args),
+        Command::State(StateCommand::Export(args)) => export_state(args),
+        Command::State(StateCommand::Import(args)) => import_state(args),
+        Command::ExportVerifier(ExportVerifierArgs { program_vkey, out }) => {
+            settlement::export_contract(&out, &program_vkey)?;
+            info!("Settlement contract written to {}", out.display());
+            Ok(())
+        }
+        Command::Replay(ReplayArgs {
+            from_height,
+            common,
+        }) => {
+            let mut config = config_from_args(common)?;
+            // the archive holds everything replay needs
+            config.da = DaKind::Mock;
+            let node = Node::new(config).await?;
+            let last_height = node.replay(from_height).await?;
+            info!("replayed celestia heights {}-{}", from_height, last_height);
+            Ok(())
+        }
+        Command::Query(QueryArgs { query, common }) => {
+            let config = config_from_args(common)?;
+            query_node(&config, query).await
+        }
+        Command::Bench(args) => {
+            let config = config_from_args(args.common)?;
+            let options = bench::BenchOptions {
+                accounts: args.accounts,
+                tps: args.tps,
+                duration: Duration::from_secs(args.duration),
+                fee: args.fee,
+                chain_id: config.chain_id,
+                drain_timeout: Duration::from_secs(args.drain_timeout),
+            };
+            let mint_key = keys::load_signing_key(&config.keys_dir, &args.mint_key_name)?;
+            let report =
+                bench::run(&format!("http://{}", config.listen_addr), mint_key, options).await?;
+            report.print();
+            Ok(())
+        }
+        Command::Devnet(DevnetCommand::Up(args)) => {
+            devnet::up(devnet::DevnetOptions {
+                dir: args.dir,
+                full_nodes: args.full_nodes,
+                base_port: args.base_port,
+                da: args.da,
+                block_time: args.block_time,
+                celestia_url: args.celestia_url,
+                auth_token: args.auth_token,
+                celestia_node_cmd: args.celestia_node_cmd,
+            })
+            .await
+        }
+        Command::Watch(WatchArgs { node }) => watch::run(&node).await,
+        Command::InitConfig(InitConfigArgs { path, force }) => {
+            config::write_default(&path, force)?;
+            info!("Config written to {}", path.display());
+            Ok(())
         }
-        Command::CreateSigner(CreateSignerArgs { key_name }) => create_signer(key_name),
     }
 }
 
-fn create_signer(key_name: String) -> Result<()> {
+fn create_signer(args: CreateSignerArgs) -> Result<()> {
     let signer = keystore_rs::create_signing_key();
-    keystore_rs::KeyChain
-        .add_signing_key(key_name.as_str(), &signer)
-        .map_err(|e| anyhow::anyhow!("Failed to create signer: {}", e))?;
-    info!("Signer '{}' created successfully", key_name);
+    if args.file || args.encrypt {
+        let password = args.encrypt.then(keys::key_password).transpose()?;
+        KeyFile::new(KeyScheme::Ed25519, &signer.to_bytes(), password.as_deref())?
+            .save(&args.keys_dir, &args.key_name)?;
+    } else {
+        keystore_rs::KeyChain
+            .add_signing_key(args.key_name.as_str(), &signer)
+            .map_err(|e| anyhow::anyhow!("Failed to create signer: {}", e))?;
+    }
+    info!(
+        "Signer '{}' created successfully, verifying key: {}",
+        args.key_name,
+        BASE64.encode(signer.verification_key().as_bytes())
+    );
     Ok(())
 }
 
+fn manage_keys(command: KeyCommand) -> Result<()> {
+    match command {
+        KeyCommand::Import(args) => import_key(args),
+        KeyCommand::Export(args) => export_key(args),
+        KeyCommand::List { keys_dir } => {
+            for (name, key_file) in KeyFile::list(&keys_dir)? {
+                if key_file.is_encrypted() {
+                    println!("{}\t{:?}\tencrypted", name, key_file.scheme);
+                    continue;
+                }
+                let vk = keys::verifying_key(&key_file.signing_key()?);
+                println!(
+                    "{}\t{:?}\t{}",
+                    name,
+                    key_file.scheme,
+                    BASE64.encode(vk.as_bytes())
+                );
+            }
+            Ok(())
+        }
+        KeyCommand::Delete { key_name, keys_dir } => {
+            if !KeyFile::delete(&keys_dir, &key_name)? {
+                return Err(anyhow::anyhow!(
+                    "No key file '{}' in {}. Keys in the OS keychain have to be deleted with the \
+                     keychain's own tools",
+                    key_name,
+                    keys_dir.display()
+                ));
+            }
+            info!("Key '{}' deleted", key_name);
+            Ok(())
+        }
+    }
+}
+
+fn import_key(args: KeyImportArgs) -> Result<()> {
+    let key_file = match (&args.file, args.scheme) {
+        (Some(path), _) => KeyFile::read(path)?,
+        (None, Some(scheme)) => {
+            let input = match args.secret {
+                Some(secret) => secret,
+                None => {
+                    let mut input = String::new();
+                    std::io::stdin().read_line(&mut input)?;
+                    input
+                }
+            };
+            let secret = if args.mnemonic {
+                keys::secret_from_mnemonic(&input)?
+            } else {
+                hex::decode(input.trim().trim_start_matches("0x"))
+                    .context("Invalid secret key hex")?
+            };
+            let password = args.encrypt.then(keys::key_password).transpose()?;
+            KeyFile::new(scheme, &secret, password.as_deref())?
+        }
+        (None, None) => return Err(anyhow::anyhow!("--scheme is required")),
+    };
+    key_file.save(&args.keys_dir, &args.key_name)?;
+    if key_file.is_encrypted() {
+        info!("Key '{}' imported", args.key_name);
+        return Ok(());
+    }
+    let vk = keys::verifying_key(&key_file.signing_key()?);
+    info!(
+        "Key '{}' imported, verifying key: {}",
+        args.key_name,
+        BASE64.encode(vk.as_bytes())
+    );
+    Ok(())
+}
+
+fn export_key(args: KeyExportArgs) -> Result<()> {
+    let signer = keys::load_signing_key(&args.keys_dir, &args.key_name)?;
+    let scheme = KeyScheme::of_signing_key(&signer);
+    let secret = match &signer {
+        SigningKey::Ed25519(key) => key.to_bytes().to_vec(),
+        SigningKey::Secp256k1(key) => key.secret_bytes().to_vec(),
+        SigningKey::Secp256r1(key) => key.to_bytes().to_vec(),
+    };
+    match args.out {
+        Some(out) => {
+            let password = args.encrypt.then(keys::key_password).transpose()?;
+            KeyFile::new(scheme, &secret, password.as_deref())?.write(&out)?;
+            info!("Key '{}' written to {}", args.key_name, out.display());
+        }
+        None => println!("{:?}\t{}", scheme, hex::encode(secret)),
+    }
+    Ok(())
+}
+
+fn export_snapshot(args: SnapshotExportArgs) -> Result<()> {
+    let store = Arc::new(storage::RocksDBStore::open(&args.db_path)?);
+    let snapshot = snapshot::Snapshot::export(store, args.epoch)?;
+    snapshot.write(&args.out)?;
+    info!(
+        "Snapshot of epoch {} (celestia height {}) written to {}",
+        snapshot.epoch,
+        snapshot.da_height,
+        args.out.display()
+    );
+    Ok(())
+}
+
+fn export_state(args: StateExportArgs) -> Result<()> {
+    let store = Arc::new(storage::RocksDBStore::open(&args.db_path)?);
+    let snapshot = snapshot::Snapshot::export(store.clone(), args.epoch)?;
+    let export = snapshot::StateExport::new(&snapshot, state::indexed_vks(store.as_ref())?)?;
+    export.write(&args.out)?;
+    info!(
+        "State of epoch {} (celestia height {}, root {}) with {} accounts written to {}",
+        export.epoch,
+        export.da_height,
+        export.root,
+        export.accounts.len(),
+        args.out.display()
+    );
+    Ok(())
+}
+
+/// Restores the exported state into a new database, which fails if the
+/// restored tree doesn't have the expected root. A node can then be started
+/// on the database, resuming after the exported Celestia height.
+fn import_state(args: StateImportArgs) -> Result<()> {
+    let export = snapshot::StateExport::read(&args.input)?;
+    let expected_root = args
+        .expected_root
+        .as_deref()
+        .map(snapshot::parse_root)
+        .transpose()
+        .context("Invalid expected root")?;
+    let snapshot = export.to_snapshot(expected_root)?;
+
+    let store = Arc::new(storage::RocksDBStore::open(&args.db_path)?);
+    if store.get_epoch()?.is_some() {
+        return Err(anyhow::anyhow!(
+            "{} already contains state",
+            args.db_path.display()
+        ));
+    }
+    tree::check_store_hasher(store.as_ref())?;
+    let _: state::State<_> =
+        state::State::from_snapshot(store.clone(), &snapshot, NoncePolicy::default())
+            .context("Imported state doesn't reproduce the expected root")?;
+    for exported in &export.accounts {
+        state::index_account(store.as_ref(), &exported.vk, snapshot.epoch)?;
+    }
+    info!(
+        "Imported epoch {} (celestia height {}) with root {} into {}",
+        snapshot.epoch,
+        snapshot.da_height,
+        hex::encode(snapshot.root.0),
+        args.db_path.display()
+    );
+    Ok(())
+}
+
+/// Deserializes a config value given either as a single string or as a list
+/// of strings.
+fn one_or_many<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        Some(OneOrMany::One(value)) => Some(vec![value]),
+        Some(OneOrMany::Many(values)) => Some(values),
+        None => None,
+    })
+}
+
 fn config_from_args(args: CommonArgs) -> Result<Config> {
-    let namespace =
-        Namespace::new_v0(&hex::decode(&args.namespace).context("Invalid namespace hex")?)
-            .context("Failed to create namespace")?;
+    let args = match &args.config {
+        Some(path) => args.or(config::load(path)?),
+        None => args,
+    };
+    let defaults = Config::default();
 
     Ok(Config {
-        namespace,
-        start_height: args.start_height,
-        celestia_url: args.celestia_url,
-        listen_addr: args.listen_addr,
-        auth_token: args.auth_token,
-        batch_interval: Duration::from_secs(args.batch_interval),
+        role: args.role.unwrap_or(defaults.role),
+        sequencer_url: args.sequencer_url.or(defaults.sequencer_url),
+        namespace: match args.namespace {
+            Some(namespace) => parse_namespace(&namespace).context("Invalid namespace")?,
+            None => defaults.namespace,
+        },
+        chain_id: args.chain_id.unwrap_or(defaults.chain_id),
+        lane_namespaces: match args.lane_namespaces {
+            Some(namespaces) => namespaces
+                .iter()
+                .map(|namespace| parse_namespace(namespace))
+                .collect::<Result<_>>()
+                .context("Invalid lane namespace")?,
+            None => defaults.lane_namespaces,
+        },
+        batch_auth: args.batch_auth.unwrap_or(defaults.batch_auth),
+        forced_inclusion_delay: args
+            .forced_inclusion_delay
+            .unwrap_or(defaults.forced_inclusion_delay),
+        sequencer_vk: match args.sequencer_vk {
+            Some(vk) => Some(VerifyingKey::try_from(vk).context("Invalid sequencer key")?),
+            None => defaults.sequencer_vk,
+        },
+        bridge_vk: match args.bridge_vk {
+            Some(vk) => Some(VerifyingKey::try_from(vk).context("Invalid bridge key")?),
+            None => defaults.bridge_vk,
+        },
+        governance_vk: match args.governance_vk {
+            Some(vk) => Some(VerifyingKey::try_from(vk).context("Invalid governance key")?),
+            None => defaults.governance_vk,
+        },
+        protocol_upgrades: match args.protocol_upgrades {
+            Some(upgrades) => ProtocolSchedule::parse(&upgrades)?,
+            None => defaults.protocol_upgrades,
+        },
+        deposit_namespace: match args.deposit_namespace {
+            Some(namespace) => {
+                Some(parse_namespace(&namespace).context("Invalid deposit namespace")?)
+            }
+            None => defaults.deposit_namespace,
+        },
+        mint_vk: match args.mint_vk {
+            Some(vk) => Some(VerifyingKey::try_from(vk).context("Invalid mint key")?),
+            None => defaults.mint_vk,
+        },
+        scheduled_txs: args.scheduled_txs.unwrap_or(defaults.scheduled_txs),
+        sequencer_key_name: args.sequencer_key_name.or(defaults.sequencer_key_name),
+        keys_dir: args.keys_dir.unwrap_or(defaults.keys_dir),
+        proof_namespace: match args.proof_namespace {
+            Some(namespace) => parse_namespace(&namespace).context("Invalid proof namespace")?,
+            None => defaults.proof_namespace,
+        },
+        epoch_interval: match args.epoch_interval {
+            Some(interval) => interval.parse().context("Invalid epoch interval")?,
+            None => defaults.epoch_interval,
+        },
+        proof_mode: args.proof_mode.unwrap_or(defaults.proof_mode),
+        watchtower: args.watchtower.unwrap_or(defaults.watchtower),
+        challenge_namespace: match args.challenge_namespace {
+            Some(namespace) => {
+                parse_namespace(&namespace).context("Invalid challenge namespace")?
+            }
+            None => defaults.challenge_namespace,
+        },
+        start_height: args.start_height.unwrap_or(defaults.start_height),
+        resync: args.resync.unwrap_or(defaults.resync),
+        sync_parallelism: args.sync_parallelism.unwrap_or(defaults.sync_parallelism),
+        celestia_urls: args.celestia_url.unwrap_or(defaults.celestia_urls),
+        listen_addr: args.listen_addr.unwrap_or(defaults.listen_addr),
+        grpc_addr: args.grpc_addr,
+        p2p_listen_addr: args.p2p_listen_addr,
+        bootnodes: args.bootnodes.unwrap_or(defaults.bootnodes),
+        otlp_endpoint: args.otlp_endpoint,
+        settlement_rpc_url: args.settlement_rpc_url,
+        settlement_contract: args.settlement_contract,
+        submit_rate_limit: args.submit_rate_limit,
+        admin_token: args.admin_token,
+        cors_origins: args.cors_origins.unwrap_or(defaults.cors_origins),
+        da: args.da.unwrap_or(defaults.da),
+        da_mode: args.da_mode.unwrap_or(defaults.da_mode),
+        #[cfg(feature = "lumina")]
+        lumina_network: args.lumina_network.unwrap_or(defaults.lumina_network),
+        mock_block_time: args
+            .mock_block_time
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.mock_block_time),
+        da_dir: args.da_dir.unwrap_or(defaults.da_dir),
+        auth_token: args.auth_token.or(defaults.auth_token),
+        celestia_tx: CelestiaTxOptions {
+            gas_price: args.celestia_gas_price,
+            max_gas_price: args.celestia_max_gas_price,
+            key_name: args.celestia_key_name,
+            signer_address: args.celestia_signer,
+            fee_granter_address: args.celestia_fee_granter,
+        },
+        batch_interval: args
+            .batch_interval
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.batch_interval),
+        batch_triggers: BatchTriggers {
+            max_bytes: args.batch_max_bytes,
+            priority_gas_price: args.priority_gas_price,
+        },
+        submit_retry: RetryPolicy {
+            max_attempts: args
+                .submit_max_attempts
+                .unwrap_or(defaults.submit_retry.max_attempts),
+            initial_backoff: args
+                .submit_initial_backoff
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.submit_retry.initial_backoff),
+            ..defaults.submit_retry
+        },
+        mempool_size: args.mempool_size.unwrap_or(defaults.mempool_size),
+        batch_ordering: args.batch_ordering.unwrap_or(defaults.batch_ordering),
+        da_format: args.da_format.unwrap_or(defaults.da_format),
+        soft_confirmations: args
+            .soft_confirmations
+            .unwrap_or(defaults.soft_confirmations),
+        nonce_policy: args.nonce_policy.unwrap_or(defaults.nonce_policy),
+        insecure_signatures: args
+            .insecure_signatures
+            .unwrap_or(defaults.insecure_signatures),
+        min_gas_price: args.min_gas_price.unwrap_or(defaults.min_gas_price),
+        db_path: args.db_path.or(defaults.db_path),
+        node_cache_size: args.node_cache_size.unwrap_or(defaults.node_cache_size),
+        archive: args.archive.unwrap_or(defaults.archive),
+        retain_heights: args.retain_heights,
+        trusted_snapshot: args.trusted_snapshot.or(defaults.trusted_snapshot),
+        trusted_root: match args.trusted_root {
+            Some(trusted_root) => Some(trusted_root.parse().context("Invalid trusted root")?),
+            None => defaults.trusted_root,
+        },
+        archive_url: args.archive_url.or(defaults.archive_url),
+        genesis: args.genesis.or(defaults.genesis),
     })
 }
 
+fn parse_namespace(namespace: &str) -> Result<Namespace> {
+    Namespace::new_v0(&hex::decode(namespace).context("Invalid namespace hex")?)
+        .context("Failed to create namespace")
+}
+
 async fn start_node(config: Config) -> Result<()> {
-    let node = Arc::new(Node::new(config).await?);
+    let optimistic =
+        config.proof_mode == ProofMode::Optimistic && config.role == NodeRole::Sequencer;
+    let mut node = Node::new(config).await?;
+    if optimistic {
+        node = node.with_prover(Arc::new(OptimisticProver));
+    }
+    let node = Arc::new(node);
 
     node.start().await?;
 
     Ok(())
 }
 
+/// Signs the transaction built by `builder` with the first of `signers` and
+/// cosigns it with the rest. Unsigned if `signers` is empty.
 async fn submit_tx(
     config: Config,
-    key_name: String,
-    nonce: u64,
-    tx_variant: TransactionType,
+    signers: &[SigningKey],
+    builder: TransactionBuilder,
+    direct: bool,
 ) -> Result<()> {
-    let url = format!("http://{}/submit_tx", config.listen_addr);
-
-    let tx = if SIGNATURE_VERIFICATION_ENABLED {
-        let signer = keystore_rs::KeyChain
-            .get_signing_key(key_name.as_str())
-            .unwrap();
-        let vk: VerifyingKey = signer.clone().into();
-        let mut tx = Transaction {
-            signature: Signature::default(),
-            nonce,
-            vk,
-            tx_type: tx_variant,
-        };
-
-        // TODO: ugly api
-        tx.sign(&prism_common::keys::SigningKey::Ed25519(Box::new(signer)))?;
-        tx
-    } else {
-        Transaction {
-            signature: Signature::default(),
-            nonce: 0,
-            vk: VerifyingKey::Ed25519(keystore_rs::create_signing_key().verification_key()),
-            tx_type: tx_variant,
-        }
+    let tx = match signers.split_first() {
+        Some((signer, cosigners)) => builder.sign_with_cosigners(signer, cosigners)?,
+        None => builder.unsigned(VerifyingKey::Ed25519(
+            keystore_rs::create_signing_key().verification_key(),
+        )),
     };
 
+    if direct {
+        return submit_tx_direct(&config, tx).await;
+    }
+
+    let url = format!("http://{}/submit_tx", config.listen_addr);
     let client = reqwest::Client::new();
     let response = client.post(url).json(&tx).send().await?;
 
     if response.status().is_success() {
-        info!("Transaction submitted successfully");
+        let response: webserver::SubmitTxResponse = response.json().await?;
+        info!("Transaction submitted successfully: {}", response.tx_hash);
         Ok(())
     } else {
-        Err(anyhow::anyhow!(
-            "Failed to submit transaction: {}",
-            response.text().await?
-        ))
+        let reason = error_message(response).await?;
+        Err(anyhow::anyhow!("Failed to submit transaction: {}", reason))
+    }
+}
+
+/// Returns the message of an error response of the node's API.
+async fn error_message(response: reqwest::Response) -> Result<String> {
+    let body = response.text().await?;
+    Ok(
+        match serde_json::from_str::<webserver::ErrorResponse>(&body) {
+            Ok(error) => error.message,
+            Err(_) => body,
+        },
+    )
+}
+
+/// Fetches the queried resource from the node's webserver and prints it as
+/// pretty JSON.
+async fn query_node(config: &Config, query: QueryCommand) -> Result<()> {
+    let mut url = reqwest::Url::parse(&format!("http://{}", config.listen_addr))
+        .context("Invalid listen address")?;
+    {
+        // pushed as segments, so base64 keys containing `/` are escaped
+        let mut segments = url
+            .path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("Invalid listen address"))?;
+        match &query {
+            QueryCommand::Account { vk } => segments.extend(["account", vk.as_str()]),
+            QueryCommand::Root => segments.push("root"),
+            QueryCommand::Block { height } => {
+                segments.extend(["block", height.to_string().as_str()])
+            }
+            QueryCommand::InclusionProof { height } => {
+                segments.extend(["block", height.to_string().as_str(), "inclusion_proof"])
+            }
+            QueryCommand::Tx { hash } => segments.extend(["tx", hash.as_str()]),
+            QueryCommand::Receipt { hash } => segments.extend(["receipt", hash.as_str()]),
+        };
     }
+
+    let response = reqwest::get(url).await?;
+    if !response.status().is_success() {
+        let reason = error_message(response).await?;
+        return Err(anyhow::anyhow!("Query failed: {}", reason));
+    }
+    let value: serde_json::Value = response.json().await?;
+    println!("{}", serde_json::to_string_pretty(&value)?);
+    Ok(())
+}
+
+/// Posts `tx` as a single-transaction batch to the rollup namespace, or a
+/// deposit to the deposit namespace, if configured.
+async fn submit_tx_direct(config: &Config, tx: Transaction) -> Result<()> {
+    if config.da != DaKind::Celestia {
+        return Err(anyhow::anyhow!(
+            "Direct submission is only supported with the celestia DA layer"
+        ));
+    }
+    let da = CelestiaDA::new(
+        &config.celestia_urls,
+        config.auth_token.as_deref(),
+        &config.celestia_tx,
+    )
+    .await?;
+
+    if let (TransactionType::Deposit { .. }, Some(namespace)) =
+        (&tx.tx_type, config.deposit_namespace)
+    {
+        let da_height = bridge::post_deposits(&da, namespace, vec![tx])
+            .await?
+            .height;
+        info!("Deposit posted at celestia height {}", da_height);
+        return Ok(());
+    }
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let batch = Batch::with_header(block::DIRECT_BATCH_HEIGHT, timestamp, vec![tx]);
+    let blob = Blob::new(config.namespace, encode_blob(&batch))?;
+    let da_height = da.submit(&[blob]).await?.height;
+    info!(
+        "Transaction posted directly at celestia height {}, it is force-included after {} blocks",
+        da_height, config.forced_inclusion_delay
+    );
+    Ok(())
 }
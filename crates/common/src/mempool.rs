@@ -0,0 +1,338 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    encoding::Encode,
+    error::TxError,
+    ordering::{OrderingPolicy, QueuedTx},
+    storage::NodeStore,
+    tree::Digest,
+    tx::Transaction,
+};
+
+pub const DEFAULT_MEMPOOL_SIZE: usize = 10_000;
+
+/// Metadata key prefix under which queued transactions are persisted, so they
+/// survive a crash of the sequencer.
+const PERSISTED_TX_PREFIX: &str = "mempool_tx:";
+
+/// When the sequencer posts a batch before the batch interval elapses,
+/// lowering latency under load. Without triggers, batches are only posted at
+/// the interval.
+#[derive(Clone, Debug, Default)]
+pub struct BatchTriggers {
+    /// Post once the queued transactions' encoding reaches this many bytes.
+    pub max_bytes: Option<usize>,
+    /// Post as soon as a transaction paying at least this gas price is
+    /// queued.
+    pub priority_gas_price: Option<u64>,
+}
+
+impl BatchTriggers {
+    /// Whether `mempool`, which `tx` was just added to, should be posted
+    /// right away.
+    pub fn is_triggered(&self, mempool: &Mempool, tx: &Transaction) -> bool {
+        let full = self
+            .max_bytes
+            .is_some_and(|max_bytes| mempool.bytes() >= max_bytes);
+        let priority = self
+            .priority_gas_price
+            .is_some_and(|gas_price| tx.gas_price() >= gas_price);
+        full || priority
+    }
+}
+
+/// The pending transactions of a single sender, ordered by nonce.
+struct SenderQueue {
+    txs: BTreeMap<u64, QueuedTx>,
+}
+
+/// Holds transactions that have been validated but not yet posted in a batch.
+///
+/// Transactions are grouped into per-sender queues ordered by nonce. When the
+/// pool is full, the highest-nonce transaction of the sender with the most
+/// queued transactions is evicted, so a single sender can't crowd out others.
+pub struct Mempool {
+    max_size: usize,
+    senders: HashMap<Vec<u8>, SenderQueue>,
+    known: HashSet<Digest>,
+    next_seq: u64,
+    len: usize,
+    /// The size of the queued transactions' encoding
+    bytes: usize,
+}
+
+impl Mempool {
+    pub fn new(max_size: usize) -> Self {
+        Mempool {
+            max_size,
+            senders: HashMap::new(),
+            known: HashSet::new(),
+            next_seq: 0,
+            len: 0,
+            bytes: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the size of the queued transactions in a batch blob, before
+    /// compression.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Adds a transaction to the pool, evicting another one if the pool is
+    /// full. Fails for duplicates and for transactions reusing a queued nonce.
+    /// Returns the evicted transaction, if any.
+    pub fn insert(&mut self, tx: Transaction) -> Result<Option<Transaction>> {
+        let digest = tx.hash();
+        if self.known.contains(&digest) {
+            return Err(TxError::AlreadyQueued.into());
+        }
+
+        let sender = tx.vk.as_bytes();
+        if let Some(queue) = self.senders.get(&sender) {
+            if queue.txs.contains_key(&tx.nonce) {
+                return Err(TxError::NonceAlreadyQueued { nonce: tx.nonce }.into());
+            }
+        }
+
+        let evicted = if self.len >= self.max_size {
+            self.evict_for(&sender)?
+        } else {
+            None
+        };
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.bytes += tx.to_canonical_bytes().len();
+        self.senders
+            .entry(sender)
+            .or_insert_with(|| SenderQueue {
+                txs: BTreeMap::new(),
+            })
+            .txs
+            .insert(tx.nonce, QueuedTx { tx, seq });
+        self.known.insert(digest);
+        self.len += 1;
+        Ok(evicted)
+    }
+
+    /// Returns whether the transaction with hash `tx_hash` is queued.
+    pub fn contains(&self, tx_hash: &Digest) -> bool {
+        self.known.contains(tx_hash)
+    }
+
+    /// Removes and returns all transactions in the order of `policy`, each
+    /// sender's transactions by ascending nonce.
+    pub fn drain(&mut self, policy: &dyn OrderingPolicy) -> Vec<Transaction> {
+        let senders = self
+            .senders
+            .drain()
+            .map(|(_, queue)| queue.txs.into_values().collect())
+            .collect();
+
+        self.known.clear();
+        self.len = 0;
+        self.bytes = 0;
+        policy.order(senders)
+    }
+
+    /// Removes and returns the transactions that can no longer be executed
+    /// at `da_height`, see [`Transaction::valid_until_da_height`].
+    pub fn remove_expired(&mut self, da_height: u64) -> Vec<Transaction> {
+        let mut expired = Vec::new();
+        for queue in self.senders.values_mut() {
+            let nonces: Vec<u64> = queue
+                .txs
+                .iter()
+                .filter(|(_, queued)| queued.tx.check_expiry(da_height).is_err())
+                .map(|(nonce, _)| *nonce)
+                .collect();
+            for nonce in nonces {
+                if let Some(QueuedTx { tx, .. }) = queue.txs.remove(&nonce) {
+                    self.known.remove(&tx.hash());
+                    self.len -= 1;
+                    self.bytes -= tx.to_canonical_bytes().len();
+                    expired.push(tx);
+                }
+            }
+        }
+        self.senders.retain(|_, queue| !queue.txs.is_empty());
+        expired
+    }
+
+    /// Makes room for a transaction from `sender`, returning the evicted
+    /// transaction.
+    fn evict_for(&mut self, sender: &[u8]) -> Result<Option<Transaction>> {
+        let (largest, largest_len) = self
+            .senders
+            .iter()
+            .map(|(key, queue)| (key.clone(), queue.txs.len()))
+            .max_by_key(|(_, len)| *len)
+            .ok_or(TxError::MempoolFull)?;
+
+        let sender_len = self.senders.get(sender).map_or(0, |q| q.txs.len());
+        if sender_len >= largest_len {
+            return Err(TxError::MempoolFull.into());
+        }
+
+        let queue = self.senders.get_mut(&largest).ok_or(TxError::MempoolFull)?;
+        let evicted = queue.txs.pop_last().map(|(_, evicted)| evicted.tx);
+        if let Some(evicted) = &evicted {
+            debug!("mempool full, evicting tx with nonce {}", evicted.nonce);
+            self.known.remove(&evicted.hash());
+            self.len -= 1;
+            self.bytes -= evicted.to_canonical_bytes().len();
+        }
+        if queue.txs.is_empty() {
+            self.senders.remove(&largest);
+        }
+        Ok(evicted)
+    }
+}
+
+fn persisted_tx_key(tx_hash: &Digest) -> String {
+    format!("{}{}", PERSISTED_TX_PREFIX, hex::encode(tx_hash.0))
+}
+
+/// Persists a queued transaction until [`remove_persisted_txs`] is called
+/// for it.
+pub fn persist_tx<S: NodeStore + ?Sized>(store: &S, tx: &Transaction) -> Result<()> {
+    store.put_metadata(&persisted_tx_key(&tx.hash()), &bincode::serialize(tx)?)
+}
+
+pub fn remove_persisted_txs<S: NodeStore + ?Sized>(store: &S, txs: &[Transaction]) -> Result<()> {
+    for tx in txs {
+        store.delete_metadata(&persisted_tx_key(&tx.hash()))?;
+    }
+    Ok(())
+}
+
+/// Returns all persisted transactions, ordered by sender and nonce.
+pub fn load_persisted_txs<S: NodeStore + ?Sized>(store: &S) -> Result<Vec<Transaction>> {
+    let mut txs = store
+        .iter_metadata(PERSISTED_TX_PREFIX)?
+        .into_iter()
+        .map(|(_, bytes)| Ok(bincode::deserialize::<Transaction>(&bytes)?))
+        .collect::<Result<Vec<_>>>()?;
+    txs.sort_by_key(|tx| (tx.vk.as_bytes(), tx.nonce));
+    Ok(txs)
+}
+
+#[cfg(test)]
+mod tests {
+    use prism_common::keys::SigningKey;
+
+    use super::*;
+    use crate::{
+        ordering::Fifo,
+        storage::InMemoryStore,
+        testkit::generate_key,
+        tx::{TransactionBuilder, TransactionType},
+    };
+
+    fn noop(key: &SigningKey, nonce: u64) -> Transaction {
+        TransactionBuilder::new(TransactionType::Noop)
+            .nonce(nonce)
+            .sign(key)
+            .unwrap()
+    }
+
+    #[test]
+    fn duplicates_and_queued_nonces_are_rejected() {
+        let key = generate_key();
+        let mut mempool = Mempool::new(10);
+        let tx = noop(&key, 0);
+        mempool.insert(tx.clone()).unwrap();
+        assert!(mempool.contains(&tx.hash()));
+
+        let err = mempool.insert(tx).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&TxError::AlreadyQueued));
+        let same_nonce = TransactionBuilder::new(TransactionType::Noop)
+            .fee(1)
+            .sign(&key)
+            .unwrap();
+        let err = mempool.insert(same_nonce).unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&TxError::NonceAlreadyQueued { nonce: 0 })
+        );
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn full_pool_evicts_from_the_largest_sender() {
+        let (busy, other) = (generate_key(), generate_key());
+        let mut mempool = Mempool::new(2);
+        mempool.insert(noop(&busy, 0)).unwrap();
+        mempool.insert(noop(&busy, 1)).unwrap();
+
+        let evicted = mempool.insert(noop(&other, 0)).unwrap().unwrap();
+        assert_eq!(evicted.nonce, 1);
+        assert!(!mempool.contains(&evicted.hash()));
+        assert_eq!(mempool.len(), 2);
+
+        // no sender has more queued than the one inserting
+        let err = mempool.insert(noop(&busy, 2)).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&TxError::MempoolFull));
+    }
+
+    #[test]
+    fn drain_empties_the_pool_in_nonce_order() {
+        let key = generate_key();
+        let mut mempool = Mempool::new(10);
+        mempool.insert(noop(&key, 1)).unwrap();
+        mempool.insert(noop(&key, 0)).unwrap();
+        assert!(mempool.bytes() > 0);
+
+        let nonces: Vec<_> = mempool.drain(&Fifo).iter().map(|tx| tx.nonce).collect();
+        assert_eq!(nonces, vec![0, 1]);
+        assert!(mempool.is_empty());
+        assert_eq!(mempool.bytes(), 0);
+    }
+
+    #[test]
+    fn expired_txs_are_removed() {
+        let key = generate_key();
+        let mut mempool = Mempool::new(10);
+        let expiring = TransactionBuilder::new(TransactionType::Noop)
+            .valid_until(Some(5))
+            .sign(&key)
+            .unwrap();
+        mempool.insert(expiring.clone()).unwrap();
+        mempool.insert(noop(&key, 1)).unwrap();
+
+        assert!(mempool.remove_expired(5).is_empty());
+        let expired = mempool.remove_expired(6);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].hash(), expiring.hash());
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn persisted_txs_survive_until_removed() {
+        let store = InMemoryStore::default();
+        let key = generate_key();
+        let txs = vec![noop(&key, 1), noop(&key, 0)];
+        for tx in &txs {
+            persist_tx(&store, tx).unwrap();
+        }
+
+        let loaded = load_persisted_txs(&store).unwrap();
+        let nonces: Vec<_> = loaded.iter().map(|tx| tx.nonce).collect();
+        assert_eq!(nonces, vec![0, 1]);
+
+        remove_persisted_txs(&store, &txs[..1]).unwrap();
+        let loaded = load_persisted_txs(&store).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].nonce, 0);
+    }
+}
@@ -0,0 +1,122 @@
+use axum::{
+    extract::{ConnectInfo, State as AxumState},
+    http::{header, HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use subtle::ConstantTimeEq;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::webserver::ApiError;
+
+/// The window [`RateLimiter`] counts requests in.
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+/// Above this many tracked clients, expired windows are dropped.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Limits the number of requests per client IP within a fixed window.
+///
+/// The IP is taken from the connection, so behind a reverse proxy all
+/// requests share the proxy's limit; limit at the proxy instead in that
+/// case.
+pub struct RateLimiter {
+    max_requests: u32,
+    windows: Mutex<HashMap<IpAddr, (Instant, u32)>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests: u32) -> Self {
+        RateLimiter {
+            max_requests,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request from `ip`, returning false if it exceeds the limit.
+    pub fn check(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() >= MAX_TRACKED_CLIENTS {
+            windows.retain(|_, (start, _)| now.duration_since(*start) < RATE_LIMIT_WINDOW);
+        }
+
+        let (start, count) = windows.entry(ip).or_insert((now, 0));
+        if now.duration_since(*start) >= RATE_LIMIT_WINDOW {
+            *start = now;
+            *count = 0;
+        }
+        if *count >= self.max_requests {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+pub(crate) async fn rate_limit<B>(
+    AxumState(limiter): AxumState<Arc<RateLimiter>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !limiter.check(addr.ip()) {
+        return ApiError::RateLimited.into_response();
+    }
+    next.run(request).await
+}
+
+/// Rejects requests that don't carry `Authorization: Bearer <token>`. The
+/// token is compared in constant time, so response times don't leak how
+/// much of a guess matched.
+pub(crate) async fn require_admin_token<B>(
+    AxumState(token): AxumState<Arc<String>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|provided| bool::from(provided.as_bytes().ct_eq(token.as_bytes())));
+    if !authorized {
+        return ApiError::Unauthorized.into_response();
+    }
+    next.run(request).await
+}
+
+/// Builds the CORS layer for the configured origins, where `*` allows any
+/// origin. Returns `None` if no origins are configured, in which case no
+/// CORS headers are sent.
+pub(crate) fn cors_layer(origins: &[String]) -> Option<CorsLayer> {
+    if origins.is_empty() {
+        return None;
+    }
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = origins
+            .iter()
+            .filter_map(|origin| match HeaderValue::from_str(origin) {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    warn!("ignoring invalid CORS origin {}", origin);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        AllowOrigin::list(origins)
+    };
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(Any)
+            .allow_headers(Any),
+    )
+}
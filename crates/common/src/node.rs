@@ -1,142 +1,2794 @@
-use anyhow::{Context, Result};
-use async_lock::Mutex;
-use axum::routing::post;
-use axum::Router;
-use celestia_rpc::{BlobClient, HeaderClient};
-use celestia_types::{nmt::Namespace, Blob, TxConfig};
+use anyhow::{anyhow, Context, Result};
+use arc_swap::ArcSwap;
+use async_lock::{Mutex, MutexGuard};
+use axum::routing::{get, post, put};
+use axum::{middleware, Router};
+use celestia_types::{nmt::Namespace, Blob};
+use clap::ValueEnum;
+use futures::StreamExt;
+use jmt::proof::SparseMerkleProof;
+use prism_common::keys::{SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Notify;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, mpsc, oneshot, Notify};
+use tokio_util::sync::CancellationToken;
+use tracing::{field, instrument, Span};
 
-use crate::tx::Batch;
-use crate::webserver::submit_tx;
+use crate::archive::{get_archived_height, put_archived_height, ArchivedHeight};
+use crate::block::{
+    get_block, get_claimed_root, get_da_inclusion, get_latest_block, put_block, put_claimed_root,
+    put_da_inclusion, put_historical_block, rollback_blocks, Block, DaInclusion,
+    DIRECT_BATCH_HEIGHT,
+};
+use crate::bridge::is_bridge_batch;
+use crate::da::file::FileDA;
+#[cfg(feature = "lumina")]
+use crate::da::lumina::{LuminaDA, LuminaNetwork};
+use crate::da::{
+    submit_with_retry, BlobProof, BlobStream, CelestiaDA, CelestiaTxOptions, DaKind, DaMode,
+    DataAvailability, MockDA, RetryPolicy,
+};
+use crate::diff::{execute_for_diff, DaFormat, PendingDiff, StateDiff};
+use crate::encoding::encode_blob;
+use crate::epoch::{
+    get_latest_epoch_proof_pointer, put_epoch_commitment, put_epoch_proof_pointer, EpochCommitment,
+    EpochInterval, EpochProofPointer, EpochScheduler,
+};
+use crate::error::{ExecutionError, TxError};
+use crate::events::{Event, EventBus};
+use crate::fees::{estimate_fee, FeeEstimate, DEFAULT_CELESTIA_GAS_PRICE};
+use crate::finality::Finality;
+use crate::fraud::{find_fraud, put_watched_epoch, take_watched_epoch, ProofMode};
+use crate::genesis::Genesis;
+use crate::history::{
+    get_account_history, get_account_txs, get_events, index_receipts, remove_history_after,
+    AccountTxsPage, HistoryEntry,
+};
+use crate::keys::{self, DEFAULT_KEYS_DIR};
+use crate::latency::{
+    get_tx_timings, record_tx_stage, LatencyReport, LatencyStats, TxStage, TxTimings,
+};
+use crate::mempool::{
+    load_persisted_txs, persist_tx, remove_persisted_txs, BatchTriggers, Mempool,
+    DEFAULT_MEMPOOL_SIZE,
+};
+use crate::middleware::{cors_layer, rate_limit, require_admin_token, RateLimiter};
+use crate::ordering::{BatchOrdering, OrderingPolicy};
+use crate::params::{LiveParams, ParamSchedule};
+use crate::proofs::{self, EpochProof, ProverBackend};
+use crate::protocol::ProtocolSchedule;
+use crate::receipt::{get_receipt, put_receipt, Receipt};
+use crate::simulate::{simulate, Simulation};
+use crate::snapshot::{Snapshot, TrustedRoot};
+use crate::soft::SoftState;
+use crate::state::{Account, NoncePolicy, StateReader, StateSnapshot};
+use crate::status::{get_tx_status, set_tx_status, TxStatus};
+use crate::stf::{check_mint_authority, touched_accounts, StfWitness};
+use crate::storage::{open_store, CacheStats, NodeStore, OverlayStore};
+use crate::tree::{check_not_pruned, check_store_hasher, prune, Digest, Hasher};
+use crate::tx::{Batch, LEGACY_CHAIN_ID};
+use crate::webserver::{
+    estimate_fee as estimate_fee_handler, get_account,
+    get_account_history as get_account_history_handler, get_account_txs as get_account_txs_handler,
+    get_accounts as get_accounts_handler, get_block as get_block_handler, get_block_da,
+    get_cache_stats, get_events as get_events_handler, get_height, get_inclusion_proof,
+    get_latency_stats, get_mempool, get_openapi, get_params as get_params_handler, get_proof,
+    get_proof_bundle, get_receipt as get_receipt_handler, get_rejected_data, get_root,
+    get_snapshot, get_tx, set_log_level, simulate as simulate_handler, submit_tx,
+    verify_fraud_proof, verify_root, ws_handler, ApiError, BlockResponse, ErrorResponse,
+};
 use crate::{state::State, tx::Transaction};
 
 const DEFAULT_BATCH_INTERVAL: Duration = Duration::from_secs(3);
+const DEFAULT_MOCK_BLOCK_TIME: Duration = Duration::from_secs(2);
+const DEFAULT_DA_DIR: &str = "da";
+
+/// Metadata key under which earlier versions stored unposted transactions on
+/// shutdown. Only read to migrate them.
+const PENDING_TXS_KEY: &str = "pending_transactions";
+/// Metadata key under which the sequencer stores the height of the last
+/// block it posted.
+const POSTED_BLOCK_HEIGHT_KEY: &str = "posted_block_height";
+const DEFAULT_FORCED_INCLUSION_DELAY: u64 = 30;
+const DEFAULT_SYNC_PARALLELISM: usize = 8;
+/// Metadata key under which the DA height of the snapshot the node started
+/// from is stored. Blocks up to it are backfilled from the archive.
+const SNAPSHOT_DA_HEIGHT_KEY: &str = "snapshot_da_height";
+/// How many DA blocks a reorg may revert before the node gives up, since
+/// rolling back further than any realistic reorg points to a bug or a
+/// misconfigured DA endpoint.
+const MAX_REORG_DEPTH: u64 = 100;
+/// How many sealed epochs may wait for the prover before further ones are
+/// left unproven.
+const PROOF_QUEUE_CAPACITY: usize = 16;
+/// How many transactions may wait for the p2p task to publish them before
+/// further submissions wait.
+const GOSSIP_QUEUE_CAPACITY: usize = 256;
+/// How many submitted transactions may wait for the mempool task before
+/// further submissions are turned away with [`TxError::MempoolBusy`].
+const SUBMISSION_QUEUE_CAPACITY: usize = 1024;
+/// How often the pruning task deletes the tree versions that are no longer
+/// retained.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+/// How often watchtowers look for new root claims.
+const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+/// Metadata key under which watchtowers store the next height to look for
+/// root claims at.
+const WATCHTOWER_HEIGHT_KEY: &str = "watchtower_height";
+/// Metadata key under which full nodes store the next height to look for
+/// epoch proofs at.
+const PROOF_INDEX_HEIGHT_KEY: &str = "proof_index_height";
+/// How long to wait before resubscribing to the namespace after the blob
+/// subscription ended or failed.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+/// Determines which tasks a node runs.
+#[derive(ValueEnum, Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NodeRole {
+    /// Accepts transactions and posts them in batches to Celestia.
+    #[default]
+    Sequencer,
+    /// Syncs and executes batches from Celestia and serves queries.
+    /// Received transactions are forwarded to the sequencer.
+    Full,
+    /// Neither syncs nor executes; only forwards transactions to the
+    /// sequencer.
+    Light,
+}
+
+/// Determines which batches a node executes.
+#[derive(ValueEnum, Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BatchAuth {
+    /// Every batch posted to the namespace is executed.
+    #[default]
+    Permissionless,
+    /// Batches signed by the registered sequencer key are executed
+    /// immediately. Unsigned batches, e.g. transactions users posted
+    /// themselves, are executed after [`Config::forced_inclusion_delay`].
+    Signed,
+}
+
+/// The state transitions of a sealed epoch, waiting to be proven.
+struct ProofJob {
+    /// The tree epoch the state was at when the epoch was sealed
+    epoch: u64,
+    input: ProofInput,
+}
+
+/// How the state transitions of a [`ProofJob`] are proven.
+enum ProofInput {
+    /// By a merkle proof per transaction
+    Batch(proofs::Batch),
+    /// By re-execution, for the state diffs of [`DaFormat::StateDiffs`]
+    Stf(Box<StfWitness>),
+}
+
+/// A transaction to gossip, and where to report whether publishing it
+/// succeeded.
+pub(crate) type GossipRequest = (Transaction, oneshot::Sender<Result<()>>);
+
+/// A checked transaction for the mempool task, and where to report whether
+/// it was already queued.
+type Submission = (Transaction, oneshot::Sender<Result<bool>>);
+
+/// A batch the sequencer posted whose Celestia height isn't processed yet.
+struct PostedBatch {
+    da_height: u64,
+    txs: Vec<Transaction>,
+}
+
+#[derive(Clone)]
+/// Who posted a batch, see [`BatchAuth::Signed`].
+enum BatchOrigin {
+    Sequencer,
+    Direct,
+    /// Only deposits from the bridge key, see [`crate::bridge`]
+    Bridge,
+}
+
+/// Metadata key prefix of the transactions due for forced inclusion at a
+/// height.
+const FORCED_TXS_PREFIX: &str = "forced_txs:";
+
+fn forced_txs_key(height: u64) -> String {
+    format!("{}{}", FORCED_TXS_PREFIX, height)
+}
+
+fn da_block_hash_key(height: u64) -> String {
+    format!("da_block_hash:{}", height)
+}
+
+/// Reverts the forced-inclusion queues to how they were right after
+/// `da_height` was processed. The queues the heights up to `last_height` took
+/// are restored from their archive, and the ones their direct batches added
+/// `delay` heights later are deleted.
+fn rollback_forced_txs(
+    store: &dyn NodeStore,
+    da_height: u64,
+    last_height: u64,
+    delay: u64,
+) -> Result<()> {
+    // read up front, so a missing archive leaves the queues untouched
+    let mut taken = Vec::new();
+    for height in da_height + 1..=last_height {
+        let archived = get_archived_height(store, height)?
+            .ok_or_else(|| anyhow!("Celestia height {} is not archived", height))?;
+        taken.push((height, archived.forced_txs));
+    }
+    for (height, forced_txs) in taken {
+        if forced_txs.is_empty() {
+            store.delete_metadata(&forced_txs_key(height))?;
+        } else {
+            store.put_metadata(&forced_txs_key(height), &bincode::serialize(&forced_txs)?)?;
+        }
+    }
+    for height in da_height + 1..=last_height {
+        store.delete_metadata(&forced_txs_key(height + delay))?;
+    }
+    Ok(())
+}
+
+/// Rolls the state, blocks and history back to how they were before
+/// `start_height` was processed, for [`Config::resync`].
+fn rewind_for_resync(
+    state: &mut State<Box<dyn NodeStore>>,
+    store: &dyn NodeStore,
+    start_height: u64,
+) -> Result<()> {
+    let base_height = start_height.saturating_sub(1);
+    let epoch = store.get_da_height_epoch(base_height)?.ok_or_else(|| {
+        anyhow!(
+            "The state before celestia height {} isn't recorded, resync from an empty store instead",
+            start_height
+        )
+    })?;
+    info!(
+        "resyncing from celestia height {}, rolling back to epoch {}",
+        start_height, epoch
+    );
+    state.rollback(epoch)?;
+    rollback_blocks(store, base_height)?;
+    remove_history_after(store, base_height)?;
+    // direct batches queue their transactions again when they are processed
+    for (key, _) in store.iter_metadata(FORCED_TXS_PREFIX)? {
+        store.delete_metadata(&key)?;
+    }
+    store.set_da_height(base_height, epoch)
+}
+
+/// Returns the hash of the DA block that was processed at `height`.
+fn get_da_block_hash<S: NodeStore + ?Sized>(store: &S, height: u64) -> Result<Option<Digest>> {
+    match store.get_metadata(&da_block_hash_key(height))? {
+        Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+fn put_da_block_hash<S: NodeStore + ?Sized>(store: &S, height: u64, hash: &Digest) -> Result<()> {
+    store.put_metadata(&da_block_hash_key(height), &bincode::serialize(hash)?)
+}
 
 #[derive(Clone)]
 pub struct Config {
+    /// Which tasks this node runs.
+    pub role: NodeRole,
+
+    /// The URL of the sequencer's webserver, used by non-sequencer nodes to
+    /// forward transactions. Only needed if transactions can't be gossiped,
+    /// see [`Config::p2p_listen_addr`].
+    pub sequencer_url: Option<String>,
+
     /// The namespace used by this rollup.
     pub namespace: Namespace,
 
-    /// The height from which to start syncing.
-    // TODO: Backwards sync, accepting trusted state (celestia blocks get
-    // pruned)
+    /// Additional namespaces, e.g. one per application lane, whose batches
+    /// are executed alongside those of [`Config::namespace`]. The blobs of a
+    /// height are executed ordered by namespace, then by their position in
+    /// the block, so all nodes of a rollup must configure the same lanes.
+    pub lane_namespaces: Vec<Namespace>,
+
+    /// Identifies the rollup in transaction signatures, so transactions
+    /// signed for one network can't be replayed on another. Transactions for
+    /// other chains are rejected.
+    pub chain_id: u64,
+
+    /// Which batches are executed.
+    pub batch_auth: BatchAuth,
+
+    /// The number of Celestia blocks after which unsigned batches are
+    /// executed under [`BatchAuth::Signed`]. Gives the sequencer a chance to
+    /// include the transactions itself before they are forced in.
+    pub forced_inclusion_delay: u64,
+
+    /// The registered sequencer key batches must be signed with under
+    /// [`BatchAuth::Signed`]. Sequencers derive it from their signing key.
+    pub sequencer_vk: Option<VerifyingKey>,
+
+    /// The key of the bridge relayer, the only sender allowed to credit
+    /// deposits, see [`crate::bridge`]. Deposits are rejected if unset.
+    pub bridge_vk: Option<VerifyingKey>,
+
+    /// The key of the governance account, the only sender allowed to change
+    /// the live chain parameters, see [`crate::params`]. It may be a
+    /// multisig account. Parameter changes are rejected if unset.
+    pub governance_vk: Option<VerifyingKey>,
+
+    /// The protocol versions of the chain and their activation heights.
+    /// Sequencer batches must be of the version active at the height they
+    /// are included at, see [`crate::protocol`].
+    pub protocol_upgrades: ProtocolSchedule,
+
+    /// The namespace the bridge relayer posts deposits to. Its batches are
+    /// executed alongside those of [`Config::namespace`].
+    pub deposit_namespace: Option<Namespace>,
+
+    /// The key of the mint authority, the only sender allowed to mint new
+    /// tokens, see [`crate::tx::TransactionType::Mint`]. Mints are rejected
+    /// if unset.
+    pub mint_vk: Option<VerifyingKey>,
+
+    /// Whether transactions may be scheduled for a later Celestia height, see
+    /// [`Transaction::execute_at_da_height`]. Epochs including or executing
+    /// them can't be proven yet, so sequencers with a prover in
+    /// [`ProofMode::Validity`] refuse to start with it. All nodes must agree
+    /// on it.
+    pub scheduled_txs: bool,
+
+    /// The name of the key the sequencer signs batches with, either a key
+    /// file in `keys_dir` or a key in the OS keychain.
+    pub sequencer_key_name: Option<String>,
+
+    /// The directory of key files. Encrypted key files are decrypted with
+    /// the password in [`keys::KEY_PASSWORD_ENV`].
+    pub keys_dir: PathBuf,
+
+    /// The namespace validity proofs are posted to, so light verifiers can
+    /// follow state roots without re-executing transactions.
+    pub proof_namespace: Namespace,
+
+    /// How often the proof streams of processed batches are sealed into an
+    /// epoch and handed to the prover.
+    pub epoch_interval: EpochInterval,
+
+    /// Whether epochs are proven or only claimed, see [`crate::fraud`].
+    /// Sequencers in optimistic mode post root claims to the proof
+    /// namespace.
+    pub proof_mode: ProofMode,
+
+    /// Runs a full node that re-executes the epochs claimed by an optimistic
+    /// sequencer and posts fraud proofs of wrong claims to
+    /// [`Config::challenge_namespace`].
+    pub watchtower: bool,
+
+    /// The namespace watchtowers post fraud proofs to.
+    pub challenge_namespace: Namespace,
+
+    /// The height from which to start syncing. Once Celestia has pruned it,
+    /// nodes start from a [`Config::trusted_snapshot`] instead.
     pub start_height: u64,
 
+    /// Whether to roll the state back to before the start height on boot and
+    /// process every height again, instead of resuming after the last
+    /// processed one. Meant for a single run, e.g. after a bug fix changed
+    /// execution.
+    pub resync: bool,
+
+    /// How many Celestia heights historical sync fetches concurrently. They
+    /// are still executed one after another, in order.
+    pub sync_parallelism: usize,
+
     /// The address to listen on for the node's webserver.
     pub listen_addr: String,
 
-    /// The URL of the Celestia node to connect to.
-    // TODO: Move fully to Lumina, only use a url for posting transactions
-    // until p2p tx transmission is implemented
-    pub celestia_url: String,
+    /// The address to serve the gRPC API on (requires the `grpc` feature).
+    /// Disabled if unset.
+    pub grpc_addr: Option<String>,
+
+    /// The multiaddr to listen on for p2p transaction gossip (requires the
+    /// `p2p` feature), e.g. `/ip4/0.0.0.0/tcp/4001`. Non-sequencer nodes
+    /// gossip submitted transactions instead of forwarding them to
+    /// [`Config::sequencer_url`]. Disabled if unset.
+    pub p2p_listen_addr: Option<String>,
+
+    /// The multiaddrs of the peers to connect to on startup, e.g. the
+    /// sequencer's `/ip4/1.2.3.4/tcp/4001`.
+    pub bootnodes: Vec<String>,
+
+    /// The OpenTelemetry collector spans are exported to (requires the
+    /// `otlp` feature). Disabled if unset.
+    pub otlp_endpoint: Option<String>,
+
+    /// The Ethereum RPC endpoint epoch proofs are settled through (requires
+    /// the `settlement` feature). Settlement is disabled if unset. Queued
+    /// proofs must be Groth16 wrapped.
+    pub settlement_rpc_url: Option<String>,
+
+    /// The address of the deployed `ShardSettlement` contract, see
+    /// [`crate::settlement`].
+    pub settlement_contract: Option<String>,
+
+    /// The maximum number of `/submit_tx` requests per client IP and minute.
+    /// Unlimited if unset.
+    pub submit_rate_limit: Option<u32>,
+
+    /// If set, admin endpoints (`/snapshot`, `/admin/log_level`) are served
+    /// and require this bearer token. They aren't served otherwise.
+    pub admin_token: Option<String>,
+
+    /// Origins allowed to make cross-origin requests, `*` for any. No CORS
+    /// headers are sent if empty.
+    pub cors_origins: Vec<String>,
+
+    /// The data availability layer to use.
+    pub da: DaKind,
+
+    /// Whether Celestia is synced via RPC or an embedded Lumina node.
+    pub da_mode: DaMode,
+
+    /// The network the embedded Lumina node joins.
+    #[cfg(feature = "lumina")]
+    pub lumina_network: LuminaNetwork,
+
+    /// The interval at which [`MockDA`] produces blocks, if used. Also used
+    /// by sequencers producing [`FileDA`] blocks.
+    pub mock_block_time: Duration,
+
+    /// The directory [`FileDA`] blocks are stored in, if used.
+    pub da_dir: PathBuf,
+
+    /// The URLs of the Celestia nodes to connect to, in order of preference.
+    /// The node fails over to the next one if the current one keeps
+    /// failing. With [`DaMode::Lumina`], only used by sequencers to post
+    /// blobs.
+    pub celestia_urls: Vec<String>,
     /// The auth token to use when connecting to Celestia.
     pub auth_token: Option<String>,
+    /// How blobs posted to Celestia are paid for and signed.
+    pub celestia_tx: CelestiaTxOptions,
 
     /// The interval at which to post batches of transactions.
     pub batch_interval: Duration,
+    /// When batches are posted before the interval elapses.
+    pub batch_triggers: BatchTriggers,
+
+    /// How failed batch and proof submissions are retried.
+    pub submit_retry: RetryPolicy,
+
+    /// The maximum number of transactions held in the mempool.
+    pub mempool_size: usize,
+
+    /// The order the sequencer posts queued transactions in. Custom
+    /// policies are set with [`Node::with_ordering_policy`].
+    pub batch_ordering: BatchOrdering,
+
+    /// Whether the sequencer posts transactions or the state diffs they
+    /// result in. All nodes of a rollup must use the same format; state
+    /// diffs require a sequencer key, see [`DaFormat::StateDiffs`].
+    pub da_format: DaFormat,
+
+    /// Whether the sequencer executes queued transactions right away on a
+    /// soft state, serving soft-confirmed receipts and accounts before the
+    /// transactions are read back from Celestia.
+    pub soft_confirmations: bool,
+
+    /// Which nonces are accepted for an account's next transaction.
+    pub nonce_policy: NoncePolicy,
+
+    /// Whether transactions are executed and queued without checking their
+    /// signatures, for development only. Anyone can then spend from any
+    /// account, and epochs with unsigned transactions can't be proven.
+    pub insecure_signatures: bool,
+
+    /// The minimum gas price (fee per unit of gas) a transaction must pay to
+    /// be queued.
+    pub min_gas_price: u64,
+
+    /// The directory of the RocksDB database used to persist state. If unset,
+    /// state is kept in memory and lost on shutdown.
+    pub db_path: Option<PathBuf>,
+
+    /// How many JMT nodes to keep in memory in front of the RocksDB database,
+    /// least recently used ones are evicted first. 0 disables the cache.
+    pub node_cache_size: usize,
+
+    /// Runs a read-only full node keeping the complete history: every JMT
+    /// version is kept, and `/account/:vk/history` and `/events` are served
+    /// from the index of executed transactions. Archive nodes don't accept
+    /// transactions.
+    pub archive: bool,
+
+    /// How many of the latest Celestia heights to keep the tree versions
+    /// of. Versions of epochs before them are pruned periodically, keeping
+    /// disk usage bounded. They can no longer be read or rolled back to, so
+    /// at least `MAX_REORG_DEPTH` (100) heights must be kept to cover reorgs.
+    /// Every version is kept if unset.
+    pub retain_heights: Option<u64>,
+
+    /// A snapshot (file path or URL) to start from instead of syncing from
+    /// [`Config::start_height`]. Only used if the store is empty.
+    pub trusted_snapshot: Option<String>,
+
+    /// The state root the trusted snapshot must have. If unset, the
+    /// snapshot's root is checked against the epoch proofs on the proof
+    /// namespace instead.
+    pub trusted_root: Option<TrustedRoot>,
+
+    /// The webserver of a node with the full block history, from which
+    /// blocks before the trusted snapshot are backfilled. History before the
+    /// snapshot is missing if unset.
+    pub archive_url: Option<String>,
+
+    /// The genesis file (JSON or TOML) defining the initial accounts and
+    /// chain parameters. Applied if the store is empty.
+    pub genesis: Option<PathBuf>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
+            role: NodeRole::default(),
+            sequencer_url: None,
             namespace: Namespace::new_v0(&[42, 42, 42, 42]).unwrap(),
+            lane_namespaces: Vec::new(),
+            chain_id: LEGACY_CHAIN_ID,
+            batch_auth: BatchAuth::default(),
+            forced_inclusion_delay: DEFAULT_FORCED_INCLUSION_DELAY,
+            sequencer_vk: None,
+            bridge_vk: None,
+            governance_vk: None,
+            protocol_upgrades: ProtocolSchedule::default(),
+            deposit_namespace: None,
+            mint_vk: None,
+            scheduled_txs: false,
+            sequencer_key_name: None,
+            keys_dir: PathBuf::from(DEFAULT_KEYS_DIR),
+            proof_namespace: Namespace::new_v0(&[42, 42, 42, 43]).unwrap(),
+            epoch_interval: EpochInterval::default(),
+            proof_mode: ProofMode::default(),
+            watchtower: false,
+            challenge_namespace: Namespace::new_v0(&[42, 42, 42, 45]).unwrap(),
             start_height: 1,
+            resync: false,
+            sync_parallelism: DEFAULT_SYNC_PARALLELISM,
             listen_addr: "0.0.0.0:3000".to_string(),
-            celestia_url: "ws://0.0.0.0:26658".to_string(),
+            grpc_addr: None,
+            p2p_listen_addr: None,
+            bootnodes: Vec::new(),
+            otlp_endpoint: None,
+            settlement_rpc_url: None,
+            settlement_contract: None,
+            submit_rate_limit: None,
+            admin_token: None,
+            cors_origins: Vec::new(),
+            da: DaKind::default(),
+            da_mode: DaMode::default(),
+            #[cfg(feature = "lumina")]
+            lumina_network: LuminaNetwork::default(),
+            mock_block_time: DEFAULT_MOCK_BLOCK_TIME,
+            da_dir: PathBuf::from(DEFAULT_DA_DIR),
+            celestia_urls: vec!["ws://0.0.0.0:26658".to_string()],
             auth_token: None,
+            celestia_tx: CelestiaTxOptions::default(),
             batch_interval: DEFAULT_BATCH_INTERVAL,
+            batch_triggers: BatchTriggers::default(),
+            submit_retry: RetryPolicy::default(),
+            mempool_size: DEFAULT_MEMPOOL_SIZE,
+            batch_ordering: BatchOrdering::default(),
+            da_format: DaFormat::default(),
+            soft_confirmations: false,
+            nonce_policy: NoncePolicy::default(),
+            insecure_signatures: false,
+            min_gas_price: 0,
+            db_path: None,
+            node_cache_size: 100_000,
+            archive: false,
+            retain_heights: None,
+            trusted_snapshot: None,
+            trusted_root: None,
+            archive_url: None,
+            genesis: None,
         }
     }
 }
 
+/// An account's value in the state tree with a proof against `root`.
+pub struct AccountProof {
+    /// The bincode encoded [`Account`], or `None` if it doesn't exist
+    pub value: Option<Vec<u8>>,
+    pub proof: SparseMerkleProof<Hasher>,
+    pub root: Digest,
+    pub epoch: u64,
+}
+
+/// The outcome of [`Node::queue_transaction`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubmittedTx {
+    pub tx_hash: Digest,
+    /// Whether the transaction was already known, e.g. from an earlier
+    /// attempt of a retried submission. It isn't queued a second time then.
+    pub already_known: bool,
+}
+
+/// An [`AccountProof`] against the root of the latest proven epoch, with
+/// where that epoch's proof was posted, so light clients can check both in
+/// one round trip.
+pub struct AccountProofBundle {
+    pub account_proof: AccountProof,
+    pub proof_pointer: EpochProofPointer,
+}
+
+/// Proves that a block's batch was published to Celestia, see
+/// [`BlobProof`].
+#[derive(Serialize, Deserialize)]
+pub struct BatchInclusionProof {
+    pub block: Block,
+    /// The hash of the batch blob's data under the configured [`tree::Hasher`](crate::tree::Hasher)
+    pub batch_hash: Digest,
+    pub blob_proof: BlobProof,
+}
+
 pub struct Node {
-    da_client: celestia_rpc::Client,
+    da: Arc<dyn DataAvailability>,
     cfg: Config,
 
+    /// Used to forward transactions to the sequencer
+    http_client: reqwest::Client,
+
+    /// Feeds the p2p task the transactions to gossip
+    gossip: mpsc::Sender<GossipRequest>,
+
+    /// Taken by the p2p task when it starts
+    gossip_receiver: Mutex<Option<mpsc::Receiver<GossipRequest>>>,
+
+    /// Feeds the mempool task the transactions submitted to the sequencer.
+    /// Bounded, so a burst of submissions is turned away instead of piling
+    /// up on the mempool lock
+    submissions: mpsc::Sender<Submission>,
+
+    /// Taken by the mempool task when it starts
+    submission_receiver: Mutex<Option<mpsc::Receiver<Submission>>>,
+
+    /// The store backing the state, also used for node metadata
+    store: Arc<Box<dyn NodeStore>>,
+
     /// The state of the rollup that is mutated by incoming transactions
-    state: Arc<Mutex<State>>,
+    state: Arc<Mutex<State<Box<dyn NodeStore>>>>,
+
+    /// The state as of the last processed Celestia height. Queries read from
+    /// it, so they never wait for block execution to release the state lock
+    state_snapshot: ArcSwap<StateSnapshot<Box<dyn NodeStore>>>,
 
     /// Transactions that have been queued for batch posting to Celestia
-    pending_transactions: Arc<Mutex<Vec<Transaction>>>,
+    mempool: Arc<Mutex<Mempool>>,
+
+    /// Orders the mempool's transactions in posted batches, see
+    /// [`Node::with_ordering_policy`]
+    ordering: Box<dyn OrderingPolicy>,
+
+    /// The soft state queued transactions are executed on, if soft
+    /// confirmations are enabled
+    soft_state: Option<Mutex<SoftState<Box<dyn NodeStore>>>>,
+
+    /// The Celestia height historical sync starts from
+    start_height: u64,
+
+    /// The last Celestia height that has been processed
+    da_height: AtomicU64,
+
+    /// The number of blobs in the app namespace that failed to decode
+    rejected_blobs: AtomicU64,
+
+    /// The number of transactions that failed to decode in batches that
+    /// were otherwise executed
+    skipped_txs: AtomicU64,
+
+    /// The height the sequencer assigns to the next batch it posts
+    next_block_height: AtomicU64,
+
+    /// The key the sequencer signs its batches with
+    batch_signer: Option<(SigningKey, VerifyingKey)>,
+
+    /// The key batches must be signed with under [`BatchAuth::Signed`]
+    sequencer_vk: Option<VerifyingKey>,
+
+    /// Broadcasts node activity to websocket subscribers
+    events: EventBus,
+
+    /// Aggregates the time transactions spend between lifecycle stages, see
+    /// [`crate::latency`]
+    latency: LatencyStats,
+
+    /// Proves the state transitions of processed heights, see
+    /// [`Node::with_prover`]
+    prover: Option<Arc<dyn ProverBackend>>,
+
+    /// Feeds the proving worker. Bounded, so a slow prover sheds proof jobs
+    /// instead of stalling sync and execution
+    proof_jobs: mpsc::Sender<ProofJob>,
+
+    /// Taken by the proving worker when it starts
+    proof_job_receiver: Mutex<Option<mpsc::Receiver<ProofJob>>>,
+
+    /// Accumulates proof streams until an epoch is due
+    epoch_scheduler: Mutex<EpochScheduler>,
+
+    /// The state diffs the sequencer posted that haven't been applied yet,
+    /// in posting order, see [`DaFormat::StateDiffs`]
+    pending_diffs: Mutex<Vec<PendingDiff>>,
+
+    /// The batches the sequencer posted that haven't been executed yet, in
+    /// posting order. Sealed batches claim the root after them
+    posted_batches: Mutex<Vec<PostedBatch>>,
+
+    /// Epoch proofs waiting to be posted to the proof namespace
+    pending_proofs: Arc<Mutex<Vec<EpochProof>>>,
+
+    /// Used to wake the batch poster when a batch trigger fired, see
+    /// [`BatchTriggers`]
+    batch_triggered: Notify,
+
+    /// Used to wake the proof poster when a new epoch proof has been queued
+    proof_queued: Notify,
+
+    /// Epoch proofs waiting to be settled on Ethereum
+    pending_settlements: Arc<Mutex<Vec<EpochProof>>>,
+
+    /// Used to wake the settlement relayer when a new epoch proof has been
+    /// queued
+    settlement_queued: Notify,
 
     /// Used to notify the syncer that genesis sync has completed, and queued
     /// stored blocks from incoming sync can be processed
     genesis_sync_completed: Notify,
+
+    /// Cancelled to make all node tasks wind down
+    shutdown: CancellationToken,
 }
 
 impl Node {
     pub async fn new(cfg: Config) -> Result<Self> {
-        let auth_token: Option<&str> = cfg.auth_token.as_deref();
+        let da = open_da(&cfg).await?;
+        Self::with_da(cfg, da).await
+    }
 
-        let da_client = celestia_rpc::Client::new(&cfg.celestia_url, auth_token)
-            .await
-            .context("Couldn't start RPC connection to celestia-node instance")?;
+    /// Creates a node on `da` instead of the DA layer configured in
+    /// `cfg.da`, e.g. a [`MockDA`] whose blocks are produced by a test.
+    pub async fn with_da(cfg: Config, da: Arc<dyn DataAvailability>) -> Result<Self> {
+        Self::with_da_and_prover(cfg, da, None).await
+    }
+
+    /// Like [`Node::with_da`], but with `prover` set from the start instead
+    /// of through [`Node::with_prover`], so it also verifies the epoch proof
+    /// of an untrusted [`Config::trusted_snapshot`].
+    pub async fn with_da_and_prover(
+        mut cfg: Config,
+        da: Arc<dyn DataAvailability>,
+        prover: Option<Arc<dyn ProverBackend>>,
+    ) -> Result<Self> {
+        let genesis = cfg.genesis.as_deref().map(Genesis::load).transpose()?;
+        if let Some(genesis) = &genesis {
+            let params = &genesis.params;
+            cfg.nonce_policy = params.nonce_policy.unwrap_or(cfg.nonce_policy);
+            cfg.batch_auth = params.batch_auth.unwrap_or(cfg.batch_auth);
+            cfg.sequencer_vk = genesis.sequencer_vk()?.or(cfg.sequencer_vk);
+            cfg.mint_vk = genesis.mint_vk()?.or(cfg.mint_vk);
+            cfg.bridge_vk = genesis.bridge_vk()?.or(cfg.bridge_vk);
+            cfg.governance_vk = genesis.governance_vk()?.or(cfg.governance_vk);
+            cfg.scheduled_txs = params.scheduled_txs.unwrap_or(cfg.scheduled_txs);
+            if let Some(upgrades) = &params.protocol_upgrades {
+                cfg.protocol_upgrades = ProtocolSchedule::parse(upgrades)?;
+            }
+            cfg.forced_inclusion_delay = params
+                .forced_inclusion_delay
+                .unwrap_or(cfg.forced_inclusion_delay);
+        }
+
+        if cfg.sync_parallelism == 0 {
+            return Err(anyhow!("Sync parallelism must be at least 1"));
+        }
+        if cfg.insecure_signatures {
+            warn!("transaction signatures are not checked, never run this in production");
+        }
+        if cfg.archive && cfg.role != NodeRole::Full {
+            return Err(anyhow!("Only full nodes can run in archive mode"));
+        }
+        match cfg.retain_heights {
+            Some(_) if cfg.archive => {
+                return Err(anyhow!("Archive nodes keep every epoch, they can't prune"));
+            }
+            Some(heights) if heights < MAX_REORG_DEPTH => {
+                return Err(anyhow!(
+                    "At least {} celestia heights must be retained to cover reorgs",
+                    MAX_REORG_DEPTH
+                ));
+            }
+            _ => {}
+        }
+        if cfg.watchtower {
+            if cfg.role != NodeRole::Full || cfg.proof_mode != ProofMode::Optimistic {
+                return Err(anyhow!(
+                    "Watchtowers are full nodes of a rollup in optimistic mode"
+                ));
+            }
+            // epochs must be sealed at the same heights as the sequencer's
+            if !matches!(cfg.epoch_interval, EpochInterval::Batches(_)) {
+                return Err(anyhow!("Watchtowers require an epoch interval in batches"));
+            }
+        }
+        if cfg.proof_mode == ProofMode::Optimistic && cfg.settlement_rpc_url.is_some() {
+            return Err(anyhow!(
+                "Root claims of the optimistic mode can't be settled"
+            ));
+        }
+        if cfg.role != NodeRole::Sequencer
+            && !cfg.archive
+            && cfg.sequencer_url.is_none()
+            && cfg.p2p_listen_addr.is_none()
+        {
+            return Err(anyhow!(
+                "A sequencer URL or p2p listen address is required for {:?} nodes",
+                cfg.role
+            ));
+        }
+
+        let batch_signer = match (&cfg.sequencer_key_name, cfg.role) {
+            (Some(key_name), NodeRole::Sequencer) => {
+                let signer = keys::load_signing_key(&cfg.keys_dir, key_name)
+                    .with_context(|| format!("Failed to load sequencer key {}", key_name))?;
+                let vk = keys::verifying_key(&signer);
+                Some((signer, vk))
+            }
+            _ => None,
+        };
+        let sequencer_vk = cfg
+            .sequencer_vk
+            .clone()
+            .or_else(|| batch_signer.as_ref().map(|(_, vk)| vk.clone()));
+        if cfg.batch_auth == BatchAuth::Signed {
+            if cfg.role == NodeRole::Sequencer && batch_signer.is_none() {
+                return Err(anyhow!(
+                    "Signed batches require a sequencer key name for sequencers"
+                ));
+            }
+            if sequencer_vk.is_none() {
+                return Err(anyhow!("Signed batches require a sequencer verifying key"));
+            }
+        }
+        if cfg.da_format == DaFormat::StateDiffs {
+            if cfg.role == NodeRole::Sequencer && batch_signer.is_none() {
+                return Err(anyhow!(
+                    "State diffs require a sequencer key name for sequencers"
+                ));
+            }
+            if sequencer_vk.is_none() {
+                return Err(anyhow!("State diffs require a sequencer verifying key"));
+            }
+        }
+
+        let store = Arc::new(open_store(cfg.db_path.as_deref(), cfg.node_cache_size)?);
+        check_store_hasher(store.as_ref())?;
+        let mut start_height = cfg.start_height;
+        let mut state = match (&cfg.trusted_snapshot, store.get_epoch()?) {
+            (Some(source), None) => {
+                let snapshot = Snapshot::fetch(source).await?;
+                match &cfg.trusted_root {
+                    Some(trusted_root) => snapshot.check_trusted(trusted_root)?,
+                    None => {
+                        verify_snapshot_root(
+                            da.as_ref(),
+                            prover.as_deref(),
+                            cfg.mint_vk.as_ref(),
+                            cfg.proof_namespace,
+                            &snapshot,
+                        )
+                        .await?
+                    }
+                }
+                info!(
+                    "starting from snapshot at epoch {} (celestia height {})",
+                    snapshot.epoch, snapshot.da_height
+                );
+                start_height = snapshot.da_height + 1;
+                store.put_metadata(
+                    SNAPSHOT_DA_HEIGHT_KEY,
+                    &bincode::serialize(&snapshot.da_height)?,
+                )?;
+                State::from_snapshot(store.clone(), &snapshot, cfg.nonce_policy)
+                    .context("Failed to load state from snapshot")?
+                    .with_bridge_vk(cfg.bridge_vk.clone())
+                    .with_governance_vk(cfg.governance_vk.clone())
+                    .with_mint_vk(cfg.mint_vk.clone())
+                    .with_scheduled_txs(cfg.scheduled_txs)
+                    .with_insecure_signatures(cfg.insecure_signatures)
+            }
+            (None, None) if cfg.trusted_root.is_some() => {
+                return Err(anyhow!(
+                    "A trusted root requires a trusted snapshot to load the state from"
+                ));
+            }
+            (trusted_snapshot, epoch) => {
+                if trusted_snapshot.is_some() {
+                    warn!("store already contains state, ignoring trusted snapshot");
+                }
+                let mut state = State::new(store.clone(), cfg.nonce_policy)
+                    .context("Failed to load state from store")?
+                    .with_bridge_vk(cfg.bridge_vk.clone())
+                    .with_governance_vk(cfg.governance_vk.clone())
+                    .with_mint_vk(cfg.mint_vk.clone())
+                    .with_scheduled_txs(cfg.scheduled_txs)
+                    .with_insecure_signatures(cfg.insecure_signatures);
+                match (&genesis, epoch) {
+                    (Some(genesis), None) => {
+                        let root = genesis.apply(&mut state, store.as_ref())?;
+                        info!("applied genesis, state root: {}", hex::encode(root.0));
+                    }
+                    (Some(genesis), Some(_)) => genesis.check_store(store.as_ref())?,
+                    (None, _) => {}
+                }
+                state
+            }
+        };
+
+        // resume after the last processed height instead of executing blocks
+        // a second time
+        match store.get_da_height()? {
+            Some(_) if cfg.resync => {
+                if let Some(bytes) = store.get_metadata(SNAPSHOT_DA_HEIGHT_KEY)? {
+                    start_height = bincode::deserialize::<u64>(&bytes)? + 1;
+                }
+                rewind_for_resync(&mut state, store.as_ref(), start_height)?;
+            }
+            Some(da_height) if da_height >= start_height => {
+                info!("resuming sync after celestia height {}", da_height);
+                start_height = da_height + 1;
+            }
+            Some(_) => {}
+            // recorded like the height of a snapshot, so a resync can roll
+            // back to the state sync started from
+            None => store.set_da_height(start_height.saturating_sub(1), state.epoch())?,
+        }
+
+        let mut mempool = Mempool::new(cfg.mempool_size);
+        let mut pending_txs = load_persisted_txs(store.as_ref())?;
+        // written on shutdown by earlier versions
+        if let Some(bytes) = store.get_metadata(PENDING_TXS_KEY)? {
+            let legacy_txs: Vec<Transaction> = bincode::deserialize(&bytes)?;
+            for tx in &legacy_txs {
+                persist_tx(store.as_ref(), tx)?;
+            }
+            pending_txs.extend(legacy_txs);
+            store.delete_metadata(PENDING_TXS_KEY)?;
+        }
+        if !pending_txs.is_empty() {
+            info!("restoring {} unposted transactions", pending_txs.len());
+        }
+        let mut soft_state = match (cfg.role, cfg.soft_confirmations) {
+            (NodeRole::Sequencer, true) => Some(SoftState::new(
+                store.clone(),
+                cfg.nonce_policy,
+                cfg.mint_vk.clone(),
+                cfg.bridge_vk.clone(),
+                cfg.governance_vk.clone(),
+                cfg.scheduled_txs,
+                cfg.insecure_signatures,
+            )?),
+            _ => None,
+        };
+        let next_da_height = store.get_da_height()?.unwrap_or(0) + 1;
+        for tx in pending_txs {
+            // the state may have moved on since the transaction was queued
+            let result = tx
+                .check_expiry(next_da_height)
+                .and_then(|()| state.validate_tx(tx.clone()))
+                .and_then(|()| match soft_state.as_mut() {
+                    Some(soft_state) => soft_state.execute(tx.clone()).map(|_| ()),
+                    None => Ok(()),
+                })
+                .and_then(|()| mempool.insert(tx.clone()));
+            let dropped = match result {
+                Ok(evicted) => evicted,
+                Err(e) => {
+                    warn!("dropping restored transaction: {}", e);
+                    Some(tx)
+                }
+            };
+            if let Some(dropped) = dropped {
+                remove_persisted_txs(store.as_ref(), &[dropped])?;
+            }
+        }
+
+        let posted_block_height = match store.get_metadata(POSTED_BLOCK_HEIGHT_KEY)? {
+            Some(bytes) => bincode::deserialize(&bytes)?,
+            None => get_latest_block(store.as_ref())?.map_or(0, |block| block.height),
+        };
+
+        let (proof_jobs, proof_job_receiver) = mpsc::channel(PROOF_QUEUE_CAPACITY);
+        let (gossip, gossip_receiver) = mpsc::channel(GOSSIP_QUEUE_CAPACITY);
+        let (submissions, submission_receiver) = mpsc::channel(SUBMISSION_QUEUE_CAPACITY);
 
         Ok(Node {
+            // before `cfg` is moved
+            epoch_scheduler: Mutex::new(EpochScheduler::new(cfg.epoch_interval)),
+            ordering: cfg.batch_ordering.policy(),
             cfg,
-            da_client,
+            da,
+            prover,
+            proof_jobs,
+            proof_job_receiver: Mutex::new(Some(proof_job_receiver)),
+            http_client: reqwest::Client::new(),
+            gossip,
+            gossip_receiver: Mutex::new(Some(gossip_receiver)),
+            submissions,
+            submission_receiver: Mutex::new(Some(submission_receiver)),
             genesis_sync_completed: Notify::new(),
-            pending_transactions: Arc::new(Mutex::new(Vec::new())),
-            state: Arc::new(Mutex::new(State::new())),
+            start_height,
+            da_height: AtomicU64::new(0),
+            rejected_blobs: AtomicU64::new(0),
+            skipped_txs: AtomicU64::new(0),
+            next_block_height: AtomicU64::new(posted_block_height + 1),
+            batch_signer,
+            sequencer_vk,
+            events: EventBus::new(),
+            latency: LatencyStats::default(),
+            mempool: Arc::new(Mutex::new(mempool)),
+            soft_state: soft_state.map(Mutex::new),
+            pending_diffs: Mutex::new(Vec::new()),
+            posted_batches: Mutex::new(Vec::new()),
+            pending_proofs: Arc::new(Mutex::new(Vec::new())),
+            pending_settlements: Arc::new(Mutex::new(Vec::new())),
+            settlement_queued: Notify::new(),
+            batch_triggered: Notify::new(),
+            proof_queued: Notify::new(),
+            shutdown: CancellationToken::new(),
+            state_snapshot: ArcSwap::from_pointee(state.snapshot()?),
+            state: Arc::new(Mutex::new(state)),
+            store,
+        })
+    }
+
+    /// Queues a transaction for the next batch, or forwards it to the
+    /// sequencer if this node isn't one. Submitting a transaction again is a
+    /// no-op as long as it is queued, posted or executed; only failed
+    /// transactions can be queued again.
+    pub async fn queue_transaction(&self, tx: Transaction) -> Result<SubmittedTx> {
+        if self.cfg.archive {
+            return Err(anyhow!("Archive nodes don't accept transactions"));
+        }
+        let tx_hash = tx.hash();
+        // checked first, since the state may have moved past the transaction
+        if matches!(
+            self.get_tx_status(&tx_hash)?,
+            Some(TxStatus::Batched | TxStatus::Posted { .. } | TxStatus::Executed { .. })
+        ) {
+            return Ok(SubmittedTx {
+                tx_hash,
+                already_known: true,
+            });
+        }
+        self.check_transaction(&tx)?;
+        let already_known = match self.cfg.role {
+            NodeRole::Sequencer => self.submit_to_mempool(tx).await?,
+            NodeRole::Full | NodeRole::Light => self.forward_transaction(tx).await?,
+        };
+        Ok(SubmittedTx {
+            tx_hash,
+            already_known,
         })
     }
 
-    pub async fn queue_transaction(&self, tx: Transaction) -> Result<()> {
-        self.state.lock().await.validate_tx(tx.clone())?;
-        self.pending_transactions.lock().await.push(tx);
+    /// Handles a transaction gossiped by a peer: validates it, and queues it
+    /// if this node is the sequencer. Errors decide whether the message is
+    /// relayed further.
+    pub(crate) async fn receive_gossiped_transaction(&self, tx: Transaction) -> Result<()> {
+        self.check_transaction(&tx)?;
+        if self.cfg.role == NodeRole::Sequencer {
+            self.submit_to_mempool(tx).await?;
+        }
+        Ok(())
+    }
+
+    /// Checks a transaction before it is queued or passed on. Light nodes
+    /// have no state, so they only check what doesn't depend on it.
+    fn check_transaction(&self, tx: &Transaction) -> Result<()> {
+        self.check_chain_id(tx)?;
+        // the transaction can be included at the next height at the earliest
+        tx.check_expiry(self.da_height.load(Ordering::Relaxed) + 1)?;
+        let min_gas_price = self.min_gas_price();
+        if tx.gas_price() < min_gas_price {
+            return Err(TxError::GasPriceTooLow {
+                gas_price: tx.gas_price(),
+                min_gas_price,
+            }
+            .into());
+        }
+        if self.cfg.role != NodeRole::Light {
+            self.state_snapshot.load().validate_tx(tx.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Hands a checked transaction to the mempool task, see
+    /// [`Node::start_mempool`]. Fails right away if the task is behind,
+    /// rather than holding the request until it catches up. Returns whether
+    /// the transaction was already queued.
+    async fn submit_to_mempool(&self, tx: Transaction) -> Result<bool> {
+        let (result, receiver) = oneshot::channel();
+        self.submissions
+            .try_send((tx, result))
+            .map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => TxError::MempoolBusy.into(),
+                mpsc::error::TrySendError::Closed(_) => anyhow!("Mempool task is not running"),
+            })?;
+        receiver
+            .await
+            .map_err(|_| anyhow!("Mempool task is not running"))?
+    }
+
+    /// Adds a checked transaction to the sequencer's mempool. Returns whether
+    /// it was already queued.
+    async fn insert_transaction(&self, tx: Transaction) -> Result<bool> {
+        let tx_hash = tx.hash();
+        let mut mempool = self.mempool.lock().await;
+        if mempool.contains(&tx_hash) {
+            return Ok(true);
+        }
+        let mut soft_state = match &self.soft_state {
+            Some(soft_state) => Some(soft_state.lock().await),
+            None => None,
+        };
+        // transactions that can't be soft-executed on top of the
+        // queued ones are rejected
+        if let Some(soft_state) = soft_state.as_mut() {
+            soft_state.execute(tx.clone())?;
+        }
+        let evicted = match mempool.insert(tx.clone()) {
+            Ok(evicted) => evicted,
+            Err(e) => {
+                if let Some(soft_state) = soft_state.as_mut() {
+                    soft_state.reconcile(&[tx_hash])?;
+                }
+                return Err(e);
+            }
+        };
+        persist_tx(self.store.as_ref(), &tx)?;
+        if self.cfg.batch_triggers.is_triggered(&mempool, &tx) {
+            self.batch_triggered.notify_one();
+        }
+        if let Some(evicted) = evicted {
+            if let Some(soft_state) = soft_state.as_mut() {
+                soft_state.reconcile(&[evicted.hash()])?;
+            }
+            remove_persisted_txs(self.store.as_ref(), &[evicted])?;
+        }
+        self.set_tx_status(&tx_hash, TxStatus::Queued);
+        self.record_tx_stage(&tx_hash, TxStage::Queued);
+        Ok(false)
+    }
+
+    /// Estimates the fees of `tx` without queueing it. Its share of the blob
+    /// cost assumes it is posted with the transactions queued so far.
+    pub async fn estimate_fee(&self, tx: &Transaction) -> FeeEstimate {
+        let batch_size = self.mempool.lock().await.len() + 1;
+        estimate_fee(
+            tx,
+            self.min_gas_price(),
+            self.cfg
+                .celestia_tx
+                .gas_price
+                .unwrap_or(DEFAULT_CELESTIA_GAS_PRICE),
+            batch_size,
+        )
+    }
+
+    /// Executes `txs` in order on a fork of the current state as of the next
+    /// Celestia height, without queueing them.
+    pub async fn simulate(&self, txs: Vec<Transaction>) -> Result<Simulation> {
+        // forked under the lock, so it doesn't see a half-executed block
+        let (mut fork, da_height) = {
+            let _state = self.state.lock().await;
+            let fork = State::new(
+                Arc::new(OverlayStore::new(self.store.clone())?),
+                self.cfg.nonce_policy,
+            )?
+            .with_bridge_vk(self.cfg.bridge_vk.clone())
+            .with_mint_vk(self.cfg.mint_vk.clone())
+            .with_governance_vk(self.cfg.governance_vk.clone())
+            .with_scheduled_txs(self.cfg.scheduled_txs)
+            .with_insecure_signatures(self.cfg.insecure_signatures);
+            (fork, self.store.get_da_height()?.unwrap_or(0) + 1)
+        };
+        simulate(&mut fork, txs, self.cfg.chain_id, da_height)
+    }
+
+    pub fn get_tx_status(&self, tx_hash: &Digest) -> Result<Option<TxStatus>> {
+        get_tx_status(self.store.as_ref(), tx_hash)
+    }
+
+    fn check_chain_id(&self, tx: &Transaction) -> Result<()> {
+        if tx.chain_id != self.cfg.chain_id {
+            return Err(TxError::WrongChainId {
+                chain_id: tx.chain_id,
+                expected: self.cfg.chain_id,
+            }
+            .into());
+        }
         Ok(())
     }
 
-    async fn post_pending_batch(&self) -> Result<Batch> {
-        let mut pending_txs = self.pending_transactions.lock().await;
-        if pending_txs.is_empty() {
-            return Ok(Batch::new(Vec::new()));
+    fn set_tx_status(&self, tx_hash: &Digest, status: TxStatus) {
+        if let Err(e) = set_tx_status(self.store.as_ref(), tx_hash, &status) {
+            error!("storing tx status: {}", e);
+        }
+    }
+
+    /// Returns when the node observed the transaction reach each stage of its
+    /// lifecycle.
+    pub fn get_tx_timings(&self, tx_hash: &Digest) -> Result<Option<TxTimings>> {
+        get_tx_timings(self.store.as_ref(), tx_hash)
+    }
+
+    /// Returns the time transactions spent between lifecycle stages since
+    /// the node started.
+    pub fn latency_stats(&self) -> LatencyReport {
+        self.latency.report()
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.store.cache_stats().unwrap_or_default()
+    }
+
+    fn record_tx_stage(&self, tx_hash: &Digest, stage: TxStage) {
+        match record_tx_stage(self.store.as_ref(), tx_hash, stage) {
+            Ok(timings) => self.latency.observe(&timings, stage),
+            Err(e) => error!("storing tx timings: {}", e),
+        }
+    }
+
+    fn record_batch_stage(&self, txs: &[Transaction], stage: TxStage) {
+        for tx in txs {
+            self.record_tx_stage(&tx.hash(), stage);
+        }
+    }
+
+    fn set_batch_status(&self, batch: &Batch, status: TxStatus) {
+        for tx in batch.get_transactions() {
+            self.set_tx_status(&tx.hash(), status.clone());
+        }
+    }
+
+    /// Passes a transaction on to the sequencer: gossips it if p2p is
+    /// enabled, falling back to posting it to the sequencer's webserver if
+    /// gossiping fails. Returns whether the sequencer already knew it, which
+    /// gossip can't tell.
+    async fn forward_transaction(&self, tx: Transaction) -> Result<bool> {
+        if self.cfg.p2p_listen_addr.is_some() {
+            match self.gossip_transaction(tx.clone()).await {
+                Ok(()) => return Ok(false),
+                Err(e) if self.cfg.sequencer_url.is_some() => {
+                    warn!("{}, forwarding transaction to the sequencer", e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.post_transaction(tx).await
+    }
+
+    async fn gossip_transaction(&self, tx: Transaction) -> Result<()> {
+        let (result, receiver) = oneshot::channel();
+        self.gossip
+            .send((tx, result))
+            .await
+            .map_err(|_| anyhow!("P2p task is not running"))?;
+        receiver
+            .await
+            .map_err(|_| anyhow!("P2p task is not running"))?
+    }
+
+    async fn post_transaction(&self, tx: Transaction) -> Result<bool> {
+        let sequencer_url = self
+            .cfg
+            .sequencer_url
+            .as_ref()
+            .ok_or_else(|| anyhow!("No sequencer URL configured"))?;
+        let response = self
+            .http_client
+            .post(format!("{}/submit_tx", sequencer_url.trim_end_matches('/')))
+            .json(&tx)
+            .send()
+            .await
+            .context("Failed to forward transaction to sequencer")?;
+
+        if !response.status().is_success() {
+            let body = response.text().await?;
+            // pass the sequencer's rejection reason on to the client
+            if let Ok(ErrorResponse {
+                error: ApiError::Rejected(tx_error),
+                ..
+            }) = serde_json::from_str(&body)
+            {
+                return Err(tx_error.into());
+            }
+            return Err(anyhow!("Sequencer rejected transaction: {}", body));
+        }
+        Ok(response.status() == reqwest::StatusCode::ALREADY_REPORTED)
+    }
+
+    /// Returns up to `limit` transactions that touched the account of `vk`
+    /// between the Celestia heights `from` and `to`.
+    pub fn get_account_history(
+        &self,
+        vk: &VerifyingKey,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>> {
+        get_account_history(self.store.as_ref(), vk, from, to, limit)
+    }
+
+    /// Returns up to `limit` transactions executed between the Celestia
+    /// heights `from` and `to`, with their events.
+    pub fn get_events(&self, from: u64, to: u64, limit: usize) -> Result<Vec<HistoryEntry>> {
+        get_events(self.store.as_ref(), from, to, limit)
+    }
+
+    /// Returns up to `limit` transactions executed by this node that
+    /// touched the account of `vk`, oldest first and after the cursor
+    /// `after`.
+    pub fn get_account_txs(
+        &self,
+        vk: &VerifyingKey,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<AccountTxsPage> {
+        get_account_txs(self.store.as_ref(), vk, after, limit)
+    }
+
+    pub async fn get_account(&self, vk: &VerifyingKey) -> Result<Option<Account>> {
+        self.state_snapshot.load().get_account(vk)
+    }
+
+    /// Returns the account stored under `vk` in the freshest state of at
+    /// least `min_finality`, with the finality of that state. Returns `None`
+    /// if there is no such state: soft confirmations are disabled, or no
+    /// epoch has been proven yet.
+    pub async fn get_account_with_finality(
+        &self,
+        vk: &VerifyingKey,
+        min_finality: Finality,
+    ) -> Result<Option<(Option<Account>, Finality)>> {
+        match min_finality {
+            Finality::SoftConfirmed => Ok(self
+                .get_soft_account(vk)
+                .await?
+                .map(|account| (account, Finality::SoftConfirmed))),
+            Finality::DaIncluded => {
+                let state = self.state_snapshot.load();
+                Ok(Some((
+                    state.get_account(vk)?,
+                    self.epoch_finality(state.epoch())?,
+                )))
+            }
+            Finality::ProofFinalized => {
+                let Some(proof_pointer) = get_latest_epoch_proof_pointer(self.store.as_ref())?
+                else {
+                    return Ok(None);
+                };
+                check_not_pruned(self.store.as_ref(), proof_pointer.epoch)
+                    .context("The latest proven state was pruned")?;
+                let state = self.state_snapshot.load().at(proof_pointer.epoch)?;
+                Ok(Some((state.get_account(vk)?, Finality::ProofFinalized)))
+            }
+        }
+    }
+
+    /// Returns the finality of the state as of `epoch`, which was executed
+    /// from Celestia.
+    pub fn epoch_finality(&self, epoch: u64) -> Result<Finality> {
+        let proven = get_latest_epoch_proof_pointer(self.store.as_ref())?;
+        if proven.is_some_and(|proof_pointer| proof_pointer.epoch >= epoch) {
+            Ok(Finality::ProofFinalized)
+        } else {
+            Ok(Finality::DaIncluded)
+        }
+    }
+
+    /// Returns the finality of what was executed from Celestia height
+    /// `da_height`.
+    pub fn da_height_finality(&self, da_height: u64) -> Result<Finality> {
+        match self.store.get_da_height_epoch(da_height)? {
+            Some(epoch) => self.epoch_finality(epoch),
+            None => Ok(Finality::DaIncluded),
+        }
+    }
+
+    /// Returns the finality of the execution of a transaction with `status`,
+    /// `None` if it hasn't been executed yet.
+    pub fn tx_finality(&self, status: &TxStatus) -> Result<Option<Finality>> {
+        match status {
+            TxStatus::SoftConfirmed => Ok(Some(Finality::SoftConfirmed)),
+            TxStatus::Executed { da_height } | TxStatus::Failed { da_height, .. } => {
+                Ok(Some(self.da_height_finality(*da_height)?))
+            }
+            TxStatus::Queued | TxStatus::Batched | TxStatus::Posted { .. } => Ok(None),
+        }
+    }
+
+    /// Returns up to `limit` accounts of the account index after the hex
+    /// encoded tree key `after`, and the key the next page starts after,
+    /// see [`StateReader::accounts_page`].
+    pub fn get_accounts(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<(Vec<(VerifyingKey, Account)>, Option<String>)> {
+        self.state_snapshot.load().accounts_page(after, limit)
+    }
+
+    /// Returns the account stored under `vk` with its inclusion proof,
+    /// and the root and epoch the proof is against.
+    pub async fn get_account_proof(&self, vk: &VerifyingKey) -> Result<AccountProof> {
+        let state = self.state_snapshot.load();
+        let (value, proof) = state.get_account_with_proof(vk)?;
+        Ok(AccountProof {
+            value,
+            proof,
+            root: state.get_commitment()?,
+            epoch: state.epoch(),
+        })
+    }
+
+    /// Returns the account stored under `vk` with its inclusion proof
+    /// against the root of the latest proven epoch, or `None` if no proof
+    /// has been recorded yet.
+    pub async fn get_account_proof_bundle(
+        &self,
+        vk: &VerifyingKey,
+    ) -> Result<Option<AccountProofBundle>> {
+        let Some(proof_pointer) = get_latest_epoch_proof_pointer(self.store.as_ref())? else {
+            return Ok(None);
+        };
+        let state = self.state_snapshot.load().at(proof_pointer.epoch)?;
+        let (value, proof) = state.get_account_with_proof(vk)?;
+        Ok(Some(AccountProofBundle {
+            account_proof: AccountProof {
+                value,
+                proof,
+                root: state.get_commitment()?,
+                epoch: proof_pointer.epoch,
+            },
+            proof_pointer,
+        }))
+    }
+
+    /// Returns the receipt of an executed transaction, or its soft receipt
+    /// if it has only been executed on the soft state so far.
+    pub async fn get_receipt(&self, tx_hash: &Digest) -> Result<Option<Receipt>> {
+        if let Some(receipt) = get_receipt(self.store.as_ref(), tx_hash)? {
+            return Ok(Some(receipt));
+        }
+        match &self.soft_state {
+            Some(soft_state) => Ok(soft_state.lock().await.get_receipt(tx_hash)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the account stored under `vk` in the soft state, or `None`
+    /// if soft confirmations are disabled.
+    pub async fn get_soft_account(&self, vk: &VerifyingKey) -> Result<Option<Option<Account>>> {
+        match &self.soft_state {
+            Some(soft_state) => Ok(Some(soft_state.lock().await.get_account(vk)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_block(&self, height: u64) -> Result<Option<Block>> {
+        get_block(self.store.as_ref(), height)
+    }
+
+    /// Returns where the batch of the block at `height` was posted, if this
+    /// node is the sequencer that posted it.
+    pub fn get_da_inclusion(&self, height: u64) -> Result<Option<DaInclusion>> {
+        get_da_inclusion(self.store.as_ref(), height)
+    }
+
+    /// Returns the state root the sequencer claimed for the block at
+    /// `height`, if it claimed one.
+    pub fn get_claimed_root(&self, height: u64) -> Result<Option<Digest>> {
+        get_claimed_root(self.store.as_ref(), height)
+    }
+
+    /// Fetches the Celestia inclusion proof of the batch of the block at
+    /// `height`, or returns `None` if there is no such block.
+    pub async fn get_inclusion_proof(&self, height: u64) -> Result<Option<BatchInclusionProof>> {
+        let Some(block) = self.get_block(height)? else {
+            return Ok(None);
+        };
+        let blobs = self.get_merged_blobs(block.da_height, None).await?;
+        let blob = blobs
+            .into_iter()
+            .find(|blob| {
+                Batch::try_from(blob).is_ok_and(|batch| {
+                    batch
+                        .header()
+                        .map_or(true, |header| header.height == height)
+                        && batch.tx_root().is_ok_and(|root| root == block.tx_root)
+                })
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "Batch of block {} not found at celestia height {}",
+                    height,
+                    block.da_height
+                )
+            })?;
+
+        let blob_proof = self
+            .da
+            .blob_proof(block.da_height, blob.namespace, blob.commitment)
+            .await?;
+        Ok(Some(BatchInclusionProof {
+            block,
+            batch_hash: Digest::hash(&blob.data),
+            blob_proof,
+        }))
+    }
+
+    /// Returns the current state root and the epoch it was committed in.
+    pub async fn get_root(&self) -> Result<(Digest, u64)> {
+        let state = self.state_snapshot.load();
+        Ok((state.get_commitment()?, state.epoch()))
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Returns the role the node was configured with.
+    pub fn role(&self) -> NodeRole {
+        self.cfg.role
+    }
+
+    /// Returns the last Celestia height that has been processed.
+    pub fn da_height(&self) -> u64 {
+        self.da_height.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of undecodable blobs and of undecodable
+    /// transactions in decodable batches seen since the node started.
+    pub fn rejected_da_data(&self) -> (u64, u64) {
+        (
+            self.rejected_blobs.load(Ordering::Relaxed),
+            self.skipped_txs.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Returns the number of queued transactions and of submissions waiting
+    /// for the mempool task.
+    pub async fn mempool_depth(&self) -> (usize, usize) {
+        let pending = self.submissions.max_capacity() - self.submissions.capacity();
+        (self.mempool.lock().await.len(), pending)
+    }
+
+    /// Returns the chain parameters governance has set or scheduled, see
+    /// [`crate::params`].
+    pub fn get_params(&self) -> Result<ParamSchedule> {
+        self.state_snapshot.load().param_schedule()
+    }
+
+    /// Returns the parameters governance set that are in effect at the next
+    /// Celestia height.
+    fn next_live_params(&self) -> Result<LiveParams> {
+        self.state_snapshot
+            .load()
+            .live_params(self.da_height.load(Ordering::Relaxed) + 1)
+    }
+
+    /// Returns the minimum gas price in effect at the next Celestia height,
+    /// the configured one unless governance changed it.
+    fn min_gas_price(&self) -> u64 {
+        match self.next_live_params() {
+            Ok(params) => params.min_gas_price.unwrap_or(self.cfg.min_gas_price),
+            Err(e) => {
+                error!("reading live chain parameters: {}", e);
+                self.cfg.min_gas_price
+            }
+        }
+    }
+
+    /// Returns the protocol version active at the next Celestia height, the
+    /// one batches are sealed under. A batch included after an upgrade
+    /// activates is skipped, so sequencers should stop posting shortly
+    /// before the activation height.
+    fn next_protocol_version(&self) -> u32 {
+        self.cfg
+            .protocol_upgrades
+            .version_at(self.da_height.load(Ordering::Relaxed) + 1)
+    }
+
+    /// Exports the state at `epoch`, or at the latest epoch if `None`.
+    pub async fn export_snapshot(&self, epoch: Option<u64>) -> Result<Snapshot> {
+        // hold the state lock so the export doesn't race block processing
+        let _state = self.state.lock().await;
+        Snapshot::export(self.store.clone(), epoch)
+    }
+
+    /// Signals all node tasks to finish their in-flight work and exit.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// Shuts the node down after a failure that leaves the store behind the
+    /// state in memory, so nothing builds on the unpersisted state.
+    fn halt(&self) {
+        error!("halting, the store is behind the state in memory");
+        self.shutdown.cancel();
+    }
+
+    /// Removes the queued transactions that expire before the batch could be
+    /// included, marking them as failed.
+    fn drop_expired_txs(&self, mempool: &mut Mempool) -> Result<()> {
+        let da_height = self.da_height.load(Ordering::Relaxed) + 1;
+        let expired = mempool.remove_expired(da_height);
+        if expired.is_empty() {
+            return Ok(());
+        }
+        warn!("dropping {} expired transactions", expired.len());
+        for tx in &expired {
+            if let Err(e) = tx.check_expiry(da_height) {
+                let status = TxStatus::Failed {
+                    da_height,
+                    error: ExecutionError::from(&e),
+                };
+                self.set_tx_status(&tx.hash(), status);
+            }
+        }
+        remove_persisted_txs(self.store.as_ref(), &expired)
+    }
+
+    /// Drains the mempool into a batch and posts it. If the submission
+    /// ultimately fails, the transactions are put back into the mempool to be
+    /// retried with the next batch.
+    #[instrument(skip_all, fields(block_height = field::Empty, tx_count = field::Empty))]
+    async fn post_pending_batch(&self) -> Result<Batch> {
+        let (batch, blob) = {
+            // the mempool stays unlocked during submission, so incoming
+            // transactions aren't blocked by retries
+            let mut mempool = self.mempool.lock().await;
+            self.drop_expired_txs(&mut mempool)?;
+            if mempool.is_empty() {
+                return Ok(Batch::new(Vec::new()));
+            }
+            let block_height = self.next_block_height.load(Ordering::Relaxed);
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            let mut txs = mempool.drain(self.ordering.as_ref());
+            // larger batches would be skipped, the rest waits for the next one
+            if let Some(max_batch_txs) = self.next_live_params()?.max_batch_txs {
+                if txs.len() > max_batch_txs as usize {
+                    for tx in txs.split_off(max_batch_txs as usize) {
+                        if let Err(e) = mempool.insert(tx) {
+                            error!("requeuing transaction: {}", e);
+                        }
+                    }
+                    self.batch_triggered.notify_one();
+                }
+            }
+            self.record_batch_stage(&txs, TxStage::Batched);
+            // sealing executes on the state, whose lock is taken before the
+            // mempool's elsewhere
+            drop(mempool);
+            if self.cfg.da_format == DaFormat::StateDiffs {
+                match self.seal_state_diff(block_height, timestamp, txs).await? {
+                    Some(sealed) => sealed,
+                    None => return Ok(Batch::new(Vec::new())),
+                }
+            } else {
+                self.seal_batch(block_height, timestamp, txs).await?
+            }
+        };
+        self.set_batch_status(&batch, TxStatus::Batched);
+        self.record_batch_stage(&batch.get_transactions(), TxStage::Submitted);
+
+        let commitment = blob.commitment;
+        let submission =
+            match submit_with_retry(self.da.as_ref(), &[blob], &self.cfg.submit_retry).await {
+                Ok(submission) => submission,
+                Err(e) => {
+                    if self.cfg.da_format == DaFormat::StateDiffs {
+                        self.pending_diffs.lock().await.pop();
+                    }
+                    self.requeue_batch(batch).await;
+                    return Err(e);
+                }
+            };
+        let da_height = submission.height;
+        if self.cfg.da_format == DaFormat::Transactions {
+            self.posted_batches.lock().await.push(PostedBatch {
+                da_height,
+                txs: batch.get_transactions(),
+            });
+        }
+        if let Some(header) = batch.header() {
+            let inclusion = DaInclusion {
+                da_height,
+                commitment,
+                tx_hash: submission.tx_hash,
+            };
+            put_da_inclusion(self.store.as_ref(), header.height, &inclusion)?;
+            // the height is only used up once the batch is posted, a requeued
+            // batch is retried under the same height
+            self.next_block_height
+                .store(header.height + 1, Ordering::Relaxed);
+            self.store.put_metadata(
+                POSTED_BLOCK_HEIGHT_KEY,
+                &bincode::serialize(&header.height)?,
+            )?;
+        }
+        self.set_batch_status(&batch, TxStatus::Posted { da_height });
+        self.record_batch_stage(&batch.get_transactions(), TxStage::Confirmed);
+        remove_persisted_txs(self.store.as_ref(), &batch.get_transactions())?;
+        self.events.publish(Event::BatchPosted {
+            tx_count: batch.get_transactions().len(),
+            da_height,
+        });
+
+        Ok(batch)
+    }
+
+    /// Builds and signs the batch of `txs` as the block at `block_height`,
+    /// returning it with its blob. The batch claims the root full nodes
+    /// will compute after it, see [`Node::execute_for_claim`].
+    async fn seal_batch(
+        &self,
+        block_height: u64,
+        timestamp: u64,
+        txs: Vec<Transaction>,
+    ) -> Result<(Batch, Blob)> {
+        let claimed_root = match self.execute_for_claim(&txs).await {
+            Ok(root) => Some(root),
+            Err(e) => {
+                // the batch is still valid without a claim
+                warn!("executing batch {} for its state root: {}", block_height, e);
+                None
+            }
+        };
+        let mut batch = Batch::with_header(block_height, timestamp, txs)
+            .with_protocol_version(self.next_protocol_version());
+        if let Some(claimed_root) = claimed_root {
+            batch = batch.with_state_root(claimed_root);
+        }
+        Span::current()
+            .record("block_height", block_height)
+            .record("tx_count", batch.get_transactions().len());
+        if let Some((key, vk)) = &self.batch_signer {
+            batch.sign(key, vk.clone())?;
+        }
+        let blob = Blob::new(self.cfg.namespace, encode_blob(&batch))?;
+        Ok((batch, blob))
+    }
+
+    /// Returns the root after executing `txs` as the next batch, the way
+    /// full nodes will: on a fork of the canonical state, the batches posted
+    /// before are executed at their Celestia heights and `txs` at the height
+    /// after the last one, each after the scheduled and forced transactions
+    /// due at its height. Blobs other posters include at those heights,
+    /// like deposits, aren't known yet and make the claim diverge.
+    async fn execute_for_claim(&self, txs: &[Transaction]) -> Result<Digest> {
+        // executed under the state lock, so the fork doesn't see a
+        // half-executed block
+        let _state = self.state.lock().await;
+        let last_height = self.store.get_da_height()?.unwrap_or(0);
+        let mut posted_batches = self.posted_batches.lock().await;
+        // batches at processed heights were executed, or skipped for good
+        posted_batches.retain(|posted| posted.da_height > last_height);
+        let inclusion_height = posted_batches
+            .last()
+            .map_or(last_height, |posted| posted.da_height)
+            + 1;
+
+        let mut fork = self.fork_state()?;
+        for height in last_height + 1..=inclusion_height {
+            for tx in fork.take_scheduled_txs(height)? {
+                // failed transactions leave the state as it was
+                let _ = fork.execute_scheduled(tx, height);
+            }
+            let mut due = self.forced_txs_due(height)?;
+            for posted in posted_batches
+                .iter()
+                .filter(|posted| posted.da_height == height)
+            {
+                due.extend(posted.txs.iter().cloned());
+            }
+            if height == inclusion_height {
+                due.extend(txs.iter().cloned());
+            }
+            for tx in due {
+                let _ = self
+                    .check_chain_id(&tx)
+                    .and_then(|()| fork.process_tx(tx, height));
+            }
+            if height < inclusion_height {
+                fork.set_last_da_height(height)?;
+            }
+        }
+        fork.get_commitment()
+    }
+
+    /// Returns a fork of the canonical state whose writes stay in memory.
+    fn fork_state(&self) -> Result<State<OverlayStore<Box<dyn NodeStore>>>> {
+        let fork = State::new(
+            Arc::new(OverlayStore::new(self.store.clone())?),
+            self.cfg.nonce_policy,
+        )?;
+        Ok(fork
+            .with_bridge_vk(self.cfg.bridge_vk.clone())
+            .with_mint_vk(self.cfg.mint_vk.clone())
+            .with_governance_vk(self.cfg.governance_vk.clone())
+            .with_scheduled_txs(self.cfg.scheduled_txs)
+            .with_insecure_signatures(self.cfg.insecure_signatures))
+    }
+
+    /// Executes `txs` on top of the posted diffs and builds the signed state
+    /// diff of the block at `block_height`, returning the batch of the
+    /// executed transactions with the diff's blob. Failed transactions are
+    /// left out and dropped. The diff is added to the pending diffs right
+    /// away, so it is known once it is included.
+    async fn seal_state_diff(
+        &self,
+        block_height: u64,
+        timestamp: u64,
+        txs: Vec<Transaction>,
+    ) -> Result<Option<(Batch, Blob)>> {
+        let (key, vk) = self
+            .batch_signer
+            .as_ref()
+            .ok_or_else(|| anyhow!("State diffs require a sequencer key"))?;
+        // executed under the state lock, so the fork doesn't see a
+        // half-executed block
+        let state = self.state.lock().await;
+        let mut pending_diffs = self.pending_diffs.lock().await;
+        let da_height = self.store.get_da_height()?.unwrap_or(0) + 1;
+        let result = self.fork_state().and_then(|mut fork| {
+            execute_for_diff(
+                &mut fork,
+                &pending_diffs,
+                txs.clone(),
+                self.cfg.chain_id,
+                da_height,
+            )
+        });
+        drop(state);
+        let execution = match result {
+            Ok(execution) => execution,
+            Err(e) => {
+                drop(pending_diffs);
+                self.requeue_batch(Batch::new(txs)).await;
+                return Err(e);
+            }
+        };
+
+        let mut dropped = Vec::new();
+        for (tx, error) in &execution.failed {
+            let tx_hash = tx.hash();
+            warn!(
+                "dropping transaction {} failing on the posted state: {:?}",
+                hex::encode(tx_hash.0),
+                error
+            );
+            self.set_tx_status(
+                &tx_hash,
+                TxStatus::Failed {
+                    da_height,
+                    error: error.clone(),
+                },
+            );
+            dropped.push(tx_hash);
+        }
+        let failed: Vec<_> = execution.failed.into_iter().map(|(tx, _)| tx).collect();
+        remove_persisted_txs(self.store.as_ref(), &failed)?;
+        if !dropped.is_empty() {
+            self.reconcile_soft_state(&dropped).await;
+        }
+        if execution.txs.is_empty() {
+            return Ok(None);
+        }
+
+        let mut diff = StateDiff::new(
+            block_height,
+            timestamp,
+            self.next_protocol_version(),
+            &execution.txs,
+            execution.prev_root,
+            execution.new_root,
+            execution.writes,
+        );
+        diff.sign(key, vk.clone());
+        let batch = Batch::with_header(block_height, timestamp, execution.txs.clone())
+            .with_state_root(execution.new_root);
+        Span::current()
+            .record("block_height", block_height)
+            .record("tx_count", execution.txs.len());
+        let blob = Blob::new(self.cfg.namespace, encode_blob(&diff))?;
+        pending_diffs.push(PendingDiff {
+            diff,
+            txs: execution.txs,
+            receipts: execution.receipts,
+        });
+        Ok(Some((batch, blob)))
+    }
+
+    /// Puts the transactions of a batch that couldn't be posted back into
+    /// the mempool.
+    async fn requeue_batch(&self, batch: Batch) {
+        let mut mempool = self.mempool.lock().await;
+        let txs = batch.get_transactions();
+        warn!("requeuing {} transactions of failed batch", txs.len());
+        let mut dropped_hashes = Vec::new();
+        for tx in txs {
+            let tx_hash = tx.hash();
+            let dropped = match mempool.insert(tx.clone()) {
+                Ok(evicted) => {
+                    self.set_tx_status(&tx_hash, TxStatus::Queued);
+                    evicted
+                }
+                Err(e) => {
+                    warn!("dropping transaction of failed batch: {}", e);
+                    Some(tx)
+                }
+            };
+            if let Some(dropped) = dropped {
+                dropped_hashes.push(dropped.hash());
+                if let Err(e) = remove_persisted_txs(self.store.as_ref(), &[dropped]) {
+                    error!("removing persisted tx: {}", e);
+                }
+            }
+        }
+        if !dropped_hashes.is_empty() {
+            self.reconcile_soft_state(&dropped_hashes).await;
+        }
+    }
+
+    /// Proves the epochs sealed according to [`Config::epoch_interval`] with
+    /// `prover` in the background. Only sequencers prove, since they post
+    /// the proofs. Must be set before the node is started.
+    pub fn with_prover(mut self, prover: Arc<dyn ProverBackend>) -> Self {
+        self.prover = Some(prover);
+        self
+    }
+
+    /// Posts queued transactions in the order of `policy` instead of the
+    /// one selected by [`Config::batch_ordering`]. Must be set before the
+    /// node is started.
+    pub fn with_ordering_policy(mut self, policy: Box<dyn OrderingPolicy>) -> Self {
+        self.ordering = policy;
+        self
+    }
+
+    /// Queues the proof of a completed epoch to be posted to the proof
+    /// namespace, and settled on Ethereum if configured.
+    pub async fn queue_proof(&self, proof: EpochProof) {
+        if self.cfg.settlement_rpc_url.is_some() {
+            self.pending_settlements.lock().await.push(proof.clone());
+            self.settlement_queued.notify_one();
+        }
+        self.pending_proofs.lock().await.push(proof);
+        self.proof_queued.notify_one();
+    }
+
+    async fn post_pending_proofs(&self) -> Result<usize> {
+        let mut pending_proofs = self.pending_proofs.lock().await;
+        if pending_proofs.is_empty() {
+            return Ok(0);
+        }
+
+        let blobs = pending_proofs
+            .iter()
+            .map(|proof| Blob::new(self.cfg.proof_namespace, bincode::serialize(proof)?))
+            .collect::<Result<Vec<_>>>()?;
+
+        let submission =
+            submit_with_retry(self.da.as_ref(), &blobs, &self.cfg.submit_retry).await?;
+        for (proof, blob) in pending_proofs.iter().zip(&blobs) {
+            if proof.is_optimistic() {
+                continue;
+            }
+            let pointer = EpochProofPointer {
+                epoch: proof.epoch,
+                root: proof.new_root,
+                da_height: submission.height,
+                commitment: blob.commitment,
+            };
+            if let Err(e) = put_epoch_proof_pointer(self.store.as_ref(), &pointer) {
+                error!("storing proof pointer of epoch {}: {}", proof.epoch, e);
+            }
+        }
+
+        Ok(pending_proofs.drain(..).count())
+    }
+
+    /// Processes a DA height exactly once. Heights that have already been
+    /// processed are skipped, so the historical sync and the subscription may
+    /// overlap. If the DA block a height builds on differs from the one that
+    /// was processed, the state is rolled back to the fork point and the new
+    /// fork is replayed first.
+    ///
+    /// Callers must not process heights concurrently.
+    async fn apply_da_height(&self, height: u64, blobs: Vec<Blob>) -> Result<()> {
+        let last_height = self.store.get_da_height()?;
+        if last_height.is_some_and(|last_height| height <= last_height) {
+            debug!("skipping already processed celestia height {}", height);
+            return Ok(());
+        }
+
+        let block_id = self.da.block_id(height).await?;
+        if let Some(last_height) = last_height.filter(|last_height| last_height + 1 == height) {
+            let processed_hash = get_da_block_hash(self.store.as_ref(), last_height)?;
+            if processed_hash.is_some_and(|hash| hash != block_id.parent_hash) {
+                let fork_height = self.rollback_reorg(last_height).await?;
+                for replay_height in fork_height + 1..height {
+                    let blobs = self.get_merged_blobs(replay_height, None).await?;
+                    let replay_id = self.da.block_id(replay_height).await?;
+                    self.process_l1_block(replay_height, blobs, Some(&replay_id.hash))
+                        .await?;
+                }
+            }
+        }
+
+        self.process_l1_block(height, blobs, Some(&block_id.hash))
+            .await
+    }
+
+    /// Returns the blobs of [`Config::namespace`], all lanes and the deposit
+    /// namespace at `height`, ordered by namespace and then by position in
+    /// the block. `primary` are the already fetched blobs of the main
+    /// namespace, if any; only the other namespaces are fetched then.
+    async fn get_merged_blobs(&self, height: u64, primary: Option<Vec<Blob>>) -> Result<Vec<Blob>> {
+        let mut namespaces = self.cfg.lane_namespaces.clone();
+        namespaces.push(self.cfg.namespace);
+        namespaces.extend(self.cfg.deposit_namespace);
+        namespaces.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        namespaces.dedup();
+
+        let mut primary = primary;
+        let mut blobs = Vec::new();
+        for namespace in namespaces {
+            if namespace == self.cfg.namespace {
+                if let Some(primary) = primary.take() {
+                    blobs.extend(primary);
+                    continue;
+                }
+            }
+            blobs.extend(self.da.get_blobs(height, namespace).await?);
+        }
+        Ok(blobs)
+    }
+
+    /// Finds the last processed height still on the canonical DA chain,
+    /// below `reorged_height`, and rolls state and blocks back to it.
+    /// Returns the fork height.
+    #[instrument(skip(self))]
+    async fn rollback_reorg(&self, reorged_height: u64) -> Result<u64> {
+        let mut fork_height = reorged_height;
+        loop {
+            if reorged_height - fork_height >= MAX_REORG_DEPTH {
+                return Err(anyhow!(
+                    "Celestia reorg at height {} is deeper than {} blocks",
+                    reorged_height,
+                    MAX_REORG_DEPTH
+                ));
+            }
+            match get_da_block_hash(self.store.as_ref(), fork_height)? {
+                Some(hash) if hash != self.da.block_id(fork_height).await?.hash => {
+                    fork_height -= 1;
+                }
+                // either still canonical, or the state started after it
+                _ => break,
+            }
+        }
+
+        warn!(
+            "celestia reorg detected, rolling back to height {}",
+            fork_height
+        );
+        self.rollback_to(fork_height).await?;
+        Ok(fork_height)
+    }
+
+    /// Rolls state and blocks back to how they were right after `da_height`
+    /// was processed. The rollback is persisted at once, so a crash can't
+    /// leave the state rolled back but not the blocks and indexes. If it
+    /// fails halfway, the node halts rather than committing the buffered
+    /// part with the next height.
+    async fn rollback_to(&self, da_height: u64) -> Result<()> {
+        self.store.begin_writes()?;
+        let result = match self.rollback_buffered(da_height).await {
+            Ok(state) => self.store.commit_writes().map(|()| state),
+            Err(e) => Err(e),
+        };
+        match result {
+            // only published once persisted, like the heights processed
+            Ok(state) => {
+                self.publish_snapshot(&state);
+                self.da_height.store(da_height, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(e) => {
+                error!("rolling back to celestia height {}: {}", da_height, e);
+                self.halt();
+                Err(e)
+            }
+        }
+    }
+
+    /// Rolls the state and the store back to `da_height` between
+    /// [`NodeStore::begin_writes`] and [`NodeStore::commit_writes`].
+    /// Returns the rolled-back state, still locked, for the caller to
+    /// publish once the writes are committed.
+    async fn rollback_buffered(
+        &self,
+        da_height: u64,
+    ) -> Result<MutexGuard<'_, State<Box<dyn NodeStore>>>> {
+        let epoch = self
+            .store
+            .get_da_height_epoch(da_height)?
+            .ok_or_else(|| anyhow!("No epoch recorded for celestia height {}", da_height))?;
+        // checked before anything is written, so a pruned epoch doesn't
+        // leave the rollback half done
+        check_not_pruned(self.store.as_ref(), epoch)?;
+        debug!("rolling back to epoch {}", epoch);
+        if let Some(last_height) = self.store.get_da_height()? {
+            rollback_forced_txs(
+                self.store.as_ref(),
+                da_height,
+                last_height,
+                self.cfg.forced_inclusion_delay,
+            )?;
+        }
+        let mut state = self.state.lock().await;
+        state.rollback(epoch)?;
+        self.epoch_scheduler.lock().await.reset();
+        rollback_blocks(self.store.as_ref(), da_height)?;
+        remove_history_after(self.store.as_ref(), da_height)?;
+        self.store.set_da_height(da_height, epoch)?;
+        Ok(state)
+    }
+
+    /// Rebuilds the state from `from_height` on from the archived input of
+    /// every processed height, without fetching anything from Celestia.
+    /// Returns the last replayed height.
+    pub async fn replay(&self, from_height: u64) -> Result<u64> {
+        let last_height = self
+            .store
+            .get_da_height()?
+            .ok_or_else(|| anyhow!("No celestia heights have been processed"))?;
+        if from_height == 0 || from_height > last_height {
+            return Err(anyhow!(
+                "Replay height must be between 1 and the last processed height {}",
+                last_height
+            ));
+        }
+        // checked up front, so a gap doesn't leave the state half replayed
+        for height in from_height..=last_height {
+            if get_archived_height(self.store.as_ref(), height)?.is_none() {
+                return Err(anyhow!("Celestia height {} is not archived", height));
+            }
+        }
+
+        self.rollback_to(from_height - 1).await?;
+        for height in from_height..=last_height {
+            let archived = get_archived_height(self.store.as_ref(), height)?
+                .ok_or_else(|| anyhow!("Celestia height {} is not archived", height))?;
+            let blobs = archived
+                .batches
+                .into_iter()
+                .map(|data| Blob::new(self.cfg.namespace, data))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.process_l1_block(height, blobs, None).await?;
+        }
+        Ok(last_height)
+    }
+
+    /// Executes the blobs of a DA height and records `block_hash` as the
+    /// hash of its DA block, if given. Fails only if the writes of the
+    /// height can't be persisted, in which case the node halts.
+    #[instrument(skip_all, fields(da_height = height, blobs = blobs.len()))]
+    async fn process_l1_block(
+        &self,
+        height: u64,
+        blobs: Vec<Blob>,
+        block_hash: Option<&Digest>,
+    ) -> Result<()> {
+        let mut state = self.state.lock().await;
+        // the tree, receipts, indexes, the processed height of the block and
+        // its hash are persisted together, so a crash can't leave the store
+        // torn
+        self.store.begin_writes()?;
+        // watchtowers seal epochs like the sequencer to check its claims.
+        // State diffs are proven one by one instead, see `apply_state_diff`
+        let seals_epochs = match self.cfg.role {
+            _ if self.cfg.da_format == DaFormat::StateDiffs => false,
+            NodeRole::Sequencer => self.prover.is_some(),
+            NodeRole::Full => self.cfg.watchtower,
+            NodeRole::Light => false,
+        };
+        let prev_root = if seals_epochs {
+            state.record_proofs();
+            state.get_commitment().ok()
+        } else {
+            None
+        };
+
+        let mut archived = ArchivedHeight::default();
+        let mut batch_commitments = Vec::new();
+        if self.cfg.da_format == DaFormat::StateDiffs {
+            self.apply_state_diffs(&mut state, height, blobs, &mut archived)
+                .await;
+        } else {
+            match state.take_scheduled_txs(height) {
+                Ok(scheduled_txs) if !scheduled_txs.is_empty() => {
+                    info!("executing {} scheduled transactions", scheduled_txs.len());
+                    self.execute_txs(&mut state, scheduled_txs, height, true);
+                }
+                Ok(_) => {}
+                Err(e) => error!("loading scheduled transactions: {}", e),
+            }
+
+            // forced transactions are due before this height's batches, so
+            // the sequencer can't front-run them indefinitely
+            match self.take_forced_txs(height) {
+                Ok(forced_txs) if !forced_txs.is_empty() => {
+                    info!("executing {} forced transactions", forced_txs.len());
+                    archived.forced_txs = forced_txs.clone();
+                    self.execute_txs(&mut state, forced_txs, height, false);
+                }
+                Ok(_) => {}
+                Err(e) => error!("loading forced transactions: {}", e),
+            }
+
+            // parameter changes executed at this height activate later, so
+            // every batch of the height sees the same parameters
+            let params = state.live_params(height).unwrap_or_else(|e| {
+                error!("reading live chain parameters: {}", e);
+                LiveParams::default()
+            });
+            let sequencer_vk = params.sequencer_vk.as_ref().or(self.sequencer_vk.as_ref());
+            for blob in blobs {
+                let batch = match Batch::try_from(&blob) {
+                    Ok(batch) => batch,
+                    Err(e) => {
+                        debug!("skipping undecodable blob: {}", e);
+                        self.rejected_blobs.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                };
+                if batch.skipped_txs() > 0 {
+                    warn!(
+                        "skipping {} undecodable transactions of a batch at celestia height {}",
+                        batch.skipped_txs(),
+                        height
+                    );
+                    self.skipped_txs
+                        .fetch_add(batch.skipped_txs() as u64, Ordering::Relaxed);
+                }
+                let commitment = Digest::new(blob.commitment.0);
+                archived.batches.push(blob.data);
+                match self.batch_origin(&batch, sequencer_vk) {
+                    Ok(BatchOrigin::Sequencer) => {
+                        let max_batch_txs = params.max_batch_txs;
+                        match self.execute_batch(&mut state, batch, height, max_batch_txs) {
+                            Ok(()) => batch_commitments.push(commitment),
+                            Err(e) => {
+                                error!("executing batch at celestia height {}: {}", height, e)
+                            }
+                        }
+                    }
+                    Ok(BatchOrigin::Bridge) => {
+                        self.execute_txs(&mut state, batch.get_transactions(), height, false);
+                        batch_commitments.push(commitment);
+                    }
+                    Ok(BatchOrigin::Direct) if self.cfg.batch_auth == BatchAuth::Permissionless => {
+                        self.execute_txs(&mut state, batch.get_transactions(), height, false);
+                        batch_commitments.push(commitment);
+                    }
+                    Ok(BatchOrigin::Direct) => {
+                        let due_height = height + self.cfg.forced_inclusion_delay;
+                        let txs = batch.get_transactions();
+                        if let Err(e) = self.queue_forced_txs(due_height, txs) {
+                            error!("queuing forced transactions: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("skipping batch at celestia height {}: {}", height, e),
+                }
+            }
+
+            // commits the root to how far the chain has processed. Diffs
+            // are applied as signed, so they can't carry it
+            if let Err(e) = state.set_last_da_height(height) {
+                error!("recording celestia height {} in the state: {}", height, e);
+            }
+        }
+
+        match state.get_commitment() {
+            Ok(root) => self.events.publish(Event::StateRoot {
+                root: hex::encode(root.0),
+                epoch: state.epoch(),
+            }),
+            Err(e) => error!("getting state root: {}", e),
+        }
+        if let Err(e) = put_archived_height(self.store.as_ref(), height, &archived) {
+            error!("archiving celestia height: {}", e);
+        }
+        if let Err(e) = self.store.set_da_height(height, state.epoch()) {
+            error!("storing processed celestia height: {}", e);
+        }
+        if let Some(block_hash) = block_hash {
+            if let Err(e) = put_da_block_hash(self.store.as_ref(), height, block_hash) {
+                error!("storing celestia block hash: {}", e);
+            }
+        }
+        if let Some(prev_root) = prev_root {
+            self.queue_proof_job(&mut state, prev_root, height, batch_commitments)
+                .await;
+        }
+        // if this fails, the state in memory is ahead of the store. Later
+        // heights would build on it and persist this one with their writes,
+        // so stop instead and resume from the store after a restart
+        if let Err(e) = self.store.commit_writes() {
+            error!("committing writes of celestia height {}: {}", height, e);
+            self.halt();
+            return Err(e);
+        }
+        self.publish_snapshot(&state);
+        self.reconcile_soft_state(&[]).await;
+        self.da_height.store(height, Ordering::Relaxed);
+        self.events.publish(Event::DaHeightProcessed { height });
+        Ok(())
+    }
+
+    /// Adds the proofs of the transactions executed since `prev_root`, by
+    /// the batches with blob commitments `batch_commitments`, to the current
+    /// epoch. Once the epoch is due, its commitment is stored and it
+    /// is handed to the proving worker. Never waits: if the worker is behind,
+    /// the epoch stays unproven.
+    async fn queue_proof_job(
+        &self,
+        state: &mut State<Box<dyn NodeStore>>,
+        prev_root: Digest,
+        da_height: u64,
+        batch_commitments: Vec<Digest>,
+    ) {
+        let proofs = state.take_proofs();
+        let new_root = match state.get_commitment() {
+            Ok(root) => root,
+            Err(e) => {
+                error!("getting state root: {}", e);
+                return;
+            }
+        };
+        let Some(batch) = self.epoch_scheduler.lock().await.push(
+            prev_root,
+            new_root,
+            proofs,
+            da_height,
+            batch_commitments,
+        ) else {
+            return;
+        };
+
+        let commitment = EpochCommitment {
+            epoch: state.epoch(),
+            prev_root: batch.prev_root,
+            new_root,
+            da_height,
+        };
+        if let Err(e) = put_epoch_commitment(self.store.as_ref(), &commitment) {
+            error!("storing epoch commitment: {}", e);
+        }
+        if self.cfg.watchtower {
+            // kept until the sequencer's claim for the epoch is checked
+            if let Err(e) = put_watched_epoch(self.store.as_ref(), commitment.epoch, &batch) {
+                error!("storing watched epoch: {}", e);
+            }
+            return;
+        }
+        self.send_proof_job(ProofJob {
+            epoch: commitment.epoch,
+            input: ProofInput::Batch(batch),
+        });
+    }
+
+    /// Hands `job` to the proving worker, leaving the epoch unproven if the
+    /// worker is behind.
+    fn send_proof_job(&self, job: ProofJob) {
+        if let Err(e) = self.proof_jobs.try_send(job) {
+            let epoch = match e {
+                mpsc::error::TrySendError::Full(job) | mpsc::error::TrySendError::Closed(job) => {
+                    job.epoch
+                }
+            };
+            warn!("proving queue is full, epoch {} stays unproven", epoch);
+        }
+    }
+
+    /// Applies the state diffs among `blobs`, see [`DaFormat::StateDiffs`].
+    /// Blobs that aren't diffs are skipped.
+    async fn apply_state_diffs(
+        &self,
+        state: &mut State<Box<dyn NodeStore>>,
+        da_height: u64,
+        blobs: Vec<Blob>,
+        archived: &mut ArchivedHeight,
+    ) {
+        for blob in blobs {
+            let diff = match StateDiff::try_from(&blob) {
+                Ok(diff) => diff,
+                Err(e) => {
+                    debug!("skipping blob that isn't a state diff: {}", e);
+                    self.rejected_blobs.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+            };
+            archived.batches.push(blob.data);
+            if let Err(e) = self.apply_state_diff(state, diff, da_height).await {
+                error!(
+                    "applying state diff at celestia height {}: {}",
+                    da_height, e
+                );
+            }
+        }
+    }
+
+    /// Applies a state diff signed by the sequencer and stores its block. On
+    /// the sequencer that posted it, its transactions get their receipts and
+    /// are proven by re-execution if a prover is set.
+    #[instrument(skip_all, fields(block_height = diff.header().height))]
+    async fn apply_state_diff(
+        &self,
+        state: &mut State<Box<dyn NodeStore>>,
+        diff: StateDiff,
+        da_height: u64,
+    ) -> Result<()> {
+        let params = state.live_params(da_height)?;
+        let vk = params
+            .sequencer_vk
+            .as_ref()
+            .or(self.sequencer_vk.as_ref())
+            .ok_or_else(|| anyhow!("No sequencer key registered"))?;
+        diff.verify_signature(vk)?;
+        let header = diff.header();
+        self.cfg
+            .protocol_upgrades
+            .check(header.protocol_version, da_height)?;
+        if let Some(latest_block) = get_latest_block(self.store.as_ref())? {
+            if header.height <= latest_block.height {
+                return Err(anyhow!(
+                    "State diff height {} doesn't extend latest block {}",
+                    header.height,
+                    latest_block.height
+                ));
+            }
+        }
+        let from_epoch = state.epoch();
+        if let Err(e) = state.apply_diff(&diff) {
+            // the diffs posted after it build on it, so they are posted again
+            let discarded = std::mem::take(&mut *self.pending_diffs.lock().await);
+            if !discarded.is_empty() {
+                let txs = discarded.into_iter().flat_map(|pending| pending.txs);
+                self.requeue_batch(Batch::new(txs.collect())).await;
+            }
+            return Err(e);
+        }
+
+        let block = Block {
+            height: header.height,
+            prev_root: diff.prev_root(),
+            new_root: diff.new_root(),
+            tx_root: header.tx_root,
+            da_height,
+            timestamp: header.timestamp,
+        };
+        put_block(self.store.as_ref(), &block)?;
+        self.events.publish(Event::BlockProduced {
+            height: block.height,
+            root: hex::encode(block.new_root.0),
+            da_height,
+        });
+
+        // diffs are posted one after another, so the sequencer's own diff is
+        // always the first pending one
+        let pending = {
+            let mut pending_diffs = self.pending_diffs.lock().await;
+            match pending_diffs.first() {
+                Some(pending) if pending.diff.header() == header => Some(pending_diffs.remove(0)),
+                _ => None,
+            }
+        };
+        let Some(pending) = pending else {
+            return Ok(());
+        };
+        let mut indexed = Vec::new();
+        for (tx, mut receipt) in pending.txs.iter().zip(pending.receipts) {
+            let tx_hash = tx.hash();
+            receipt.status = TxStatus::Executed { da_height };
+            put_receipt(self.store.as_ref(), &tx_hash, &receipt)?;
+            self.set_tx_status(&tx_hash, receipt.status.clone());
+            self.record_tx_stage(&tx_hash, TxStage::Executed);
+            self.events.publish(Event::TxIncluded {
+                vk: tx.vk.clone(),
+                nonce: tx.nonce,
+                success: true,
+                accounts: touched_accounts(std::slice::from_ref(tx)),
+            });
+            indexed.push((tx.vk.clone(), tx_hash, receipt));
+        }
+        index_receipts(self.store.as_ref(), da_height, &indexed)?;
+
+        if self.prover.is_some() {
+            let witness = state.stf_witness(from_epoch, pending.txs)?;
+            let commitment = EpochCommitment {
+                epoch: state.epoch(),
+                prev_root: witness.prev_root,
+                new_root: witness.new_root,
+                da_height,
+            };
+            put_epoch_commitment(self.store.as_ref(), &commitment)?;
+            self.send_proof_job(ProofJob {
+                epoch: commitment.epoch,
+                input: ProofInput::Stf(Box::new(witness)),
+            });
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the soft state on top of the canonical state, dropping the
+    /// transactions in `dropped`.
+    async fn reconcile_soft_state(&self, dropped: &[Digest]) {
+        let Some(soft_state) = &self.soft_state else {
+            return;
+        };
+        match soft_state.lock().await.reconcile(dropped) {
+            Ok(rolled_back) if !rolled_back.is_empty() => warn!(
+                "rolled back soft confirmations of {} transactions",
+                rolled_back.len()
+            ),
+            Ok(_) => {}
+            Err(e) => error!("reconciling soft state: {}", e),
+        }
+    }
+
+    /// Makes the current state visible to queries.
+    fn publish_snapshot(&self, state: &State<Box<dyn NodeStore>>) {
+        match state.snapshot() {
+            Ok(snapshot) => self.state_snapshot.store(Arc::new(snapshot)),
+            Err(e) => error!("taking state snapshot: {}", e),
+        }
+    }
+
+    /// Determines whether a batch was posted by the sequencer or directly by
+    /// a user. Fails for batches signed by anyone but the sequencer.
+    /// Batches of the sequencer must be signed with `sequencer_vk` under
+    /// [`BatchAuth::Signed`].
+    fn batch_origin(
+        &self,
+        batch: &Batch,
+        sequencer_vk: Option<&VerifyingKey>,
+    ) -> Result<BatchOrigin> {
+        if self
+            .cfg
+            .bridge_vk
+            .as_ref()
+            .is_some_and(|bridge_vk| is_bridge_batch(batch, bridge_vk))
+        {
+            return Ok(BatchOrigin::Bridge);
+        }
+        if batch
+            .header()
+            .is_some_and(|header| header.height == DIRECT_BATCH_HEIGHT)
+        {
+            return Ok(BatchOrigin::Direct);
+        }
+        match (self.cfg.batch_auth, sequencer_vk) {
+            (BatchAuth::Permissionless, _) => Ok(BatchOrigin::Sequencer),
+            (BatchAuth::Signed, Some(_)) if !batch.is_signed() => Ok(BatchOrigin::Direct),
+            (BatchAuth::Signed, Some(vk)) => {
+                batch.verify_signature(vk)?;
+                Ok(BatchOrigin::Sequencer)
+            }
+            (BatchAuth::Signed, None) => Err(anyhow!("No sequencer key registered")),
+        }
+    }
+
+    /// Stores transactions to be force-included at `due_height`.
+    fn queue_forced_txs(&self, due_height: u64, txs: Vec<Transaction>) -> Result<()> {
+        let key = forced_txs_key(due_height);
+        let mut queued: Vec<Transaction> = match self.store.get_metadata(&key)? {
+            Some(bytes) => bincode::deserialize(&bytes)?,
+            None => Vec::new(),
+        };
+        queued.extend(txs);
+        self.store.put_metadata(&key, &bincode::serialize(&queued)?)
+    }
+
+    /// Returns the transactions due for forced inclusion at `height`.
+    fn forced_txs_due(&self, height: u64) -> Result<Vec<Transaction>> {
+        match self.store.get_metadata(&forced_txs_key(height))? {
+            Some(bytes) => Ok(bincode::deserialize(&bytes)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Removes and returns the transactions due for forced inclusion at
+    /// `height`.
+    fn take_forced_txs(&self, height: u64) -> Result<Vec<Transaction>> {
+        let txs = self.forced_txs_due(height)?;
+        if !txs.is_empty() {
+            self.store.delete_metadata(&forced_txs_key(height))?;
+        }
+        Ok(txs)
+    }
+
+    /// Executes the transactions of a sequencer batch and stores the
+    /// resulting block. Batches whose header doesn't match their transactions
+    /// or doesn't extend the chain are skipped entirely.
+    #[instrument(skip_all, fields(block_height = field::Empty))]
+    fn execute_batch(
+        &self,
+        state: &mut State<Box<dyn NodeStore>>,
+        batch: Batch,
+        da_height: u64,
+        max_batch_txs: Option<u32>,
+    ) -> Result<()> {
+        let protocol_version = batch.header().map_or(0, |header| header.protocol_version);
+        self.cfg
+            .protocol_upgrades
+            .check(protocol_version, da_height)?;
+        let txs = batch.get_transactions();
+        if let Some(max_batch_txs) = max_batch_txs.filter(|max| txs.len() > *max as usize) {
+            return Err(anyhow!(
+                "Batch of {} transactions exceeds the maximum of {}",
+                txs.len(),
+                max_batch_txs
+            ));
         }
+        let tx_root = batch.tx_root()?;
+        let latest_block = get_latest_block(self.store.as_ref())?;
+        let (block_height, timestamp) = match batch.header() {
+            Some(header) => {
+                if header.tx_root != tx_root {
+                    return Err(anyhow!("Batch tx root doesn't match its transactions"));
+                }
+                if let Some(latest_block) = &latest_block {
+                    if header.height <= latest_block.height {
+                        return Err(anyhow!(
+                            "Batch height {} doesn't extend latest block {}",
+                            header.height,
+                            latest_block.height
+                        ));
+                    }
+                }
+                (header.height, header.timestamp)
+            }
+            // batches posted before headers were introduced
+            None => (latest_block.map_or(1, |block| block.height + 1), 0),
+        };
+        Span::current().record("block_height", block_height);
 
-        let batch = Batch::new(pending_txs.drain(..).collect());
-        let encoded_batch = bincode::serialize(&batch)?;
-        let blob = Blob::new(self.cfg.namespace, encoded_batch)?;
-
-        BlobClient::blob_submit(&self.da_client, &[blob], TxConfig::default()).await?;
+        let prev_root = state.get_commitment()?;
+        self.execute_txs(state, txs, da_height, false);
 
-        Ok(batch)
+        let block = Block {
+            height: block_height,
+            prev_root,
+            new_root: state.get_commitment()?,
+            tx_root,
+            da_height,
+            timestamp,
+        };
+        let claimed_root = batch.header().and_then(|header| header.state_root);
+        if let Some(claimed_root) = claimed_root.filter(|root| *root != block.new_root) {
+            error!(
+                "state root of block {} diverges from the sequencer's: executed {}, claimed {}",
+                block.height,
+                hex::encode(block.new_root.0),
+                hex::encode(claimed_root.0)
+            );
+        }
+        put_claimed_root(self.store.as_ref(), block.height, claimed_root.as_ref())?;
+        put_block(self.store.as_ref(), &block)?;
+        self.events.publish(Event::BlockProduced {
+            height: block.height,
+            root: hex::encode(block.new_root.0),
+            da_height,
+        });
+        Ok(())
     }
 
-    async fn process_l1_block(&self, blobs: Vec<Blob>) {
-        let txs: Vec<Transaction> = blobs
-            .into_iter()
-            .flat_map(|blob| {
-                Batch::try_from(&blob)
-                    .map(|b| b.get_transactions())
-                    .unwrap_or_default()
-            })
-            .collect();
-
-        let mut state = self.state.lock().await;
+    /// Executes transactions one by one, recording their status. Forced
+    /// transactions are executed through this directly and aren't part of
+    /// any block, so a block's `prev_root` may differ from its predecessor's
+    /// `new_root`. So are `scheduled` transactions, which were included at
+    /// an earlier height and are due at this one.
+    fn execute_txs(
+        &self,
+        state: &mut State<Box<dyn NodeStore>>,
+        txs: Vec<Transaction>,
+        da_height: u64,
+        scheduled: bool,
+    ) {
+        let mut indexed = Vec::new();
         for tx in txs {
-            if let Err(e) = state.process_tx(tx) {
-                error!("processing tx: {}", e);
+            let (vk, nonce) = (tx.vk.clone(), tx.nonce);
+            let accounts = touched_accounts(std::slice::from_ref(&tx));
+            let tx_hash = tx.hash();
+            let span = debug_span!("execute_tx", nonce, hash = hex::encode(tx_hash.0));
+            let _entered = span.entered();
+            let gas = tx.tx_type.gas();
+            let result = if scheduled {
+                state.execute_scheduled(tx, da_height)
+            } else {
+                self.check_chain_id(&tx)
+                    .and_then(|()| state.process_tx(tx, da_height))
+            };
+            let success = result.is_ok();
+            let (status, gas_used, events) = match result {
+                Ok(events) => (TxStatus::Executed { da_height }, gas, events),
+                Err(e) => {
+                    error!("processing tx: {}", e);
+                    let status = TxStatus::Failed {
+                        da_height,
+                        error: ExecutionError::from(&e),
+                    };
+                    (status, 0, Vec::new())
+                }
+            };
+            let receipt = Receipt {
+                tx_hash: hex::encode(tx_hash.0),
+                status: status.clone(),
+                gas_used,
+                events,
+            };
+            if let Err(e) = put_receipt(self.store.as_ref(), &tx_hash, &receipt) {
+                error!("storing receipt: {}", e);
+            }
+            self.set_tx_status(&tx_hash, status);
+            self.record_tx_stage(&tx_hash, TxStage::Executed);
+            indexed.push((vk.clone(), tx_hash, receipt));
+            self.events.publish(Event::TxIncluded {
+                vk,
+                nonce,
+                success,
+                accounts,
+            });
+        }
+        if !indexed.is_empty() {
+            if let Err(e) = index_receipts(self.store.as_ref(), da_height, &indexed) {
+                error!("indexing receipts: {}", e);
             }
         }
     }
 
     async fn sync_historical(&self) -> Result<()> {
-        let network_head = HeaderClient::header_network_head(&self.da_client).await?;
-        let network_height = network_head.height();
+        let network_height = self.da.network_height().await?;
         info!(
             "syncing historical blocks from {}-{}",
-            self.cfg.start_height,
-            network_height.value()
+            self.start_height, network_height
         );
 
-        for height in self.cfg.start_height..network_height.value() {
-            let blobs =
-                BlobClient::blob_get_all(&self.da_client, height, &[self.cfg.namespace]).await?;
-            if let Some(blobs) = blobs {
-                self.process_l1_block(blobs).await;
+        // the next heights are fetched while one is executed, and buffered
+        // so they are executed in order
+        let mut fetched = futures::stream::iter(self.start_height..network_height)
+            .map(|height| async move { (height, self.get_merged_blobs(height, None).await) })
+            .buffered(self.cfg.sync_parallelism);
+        while let Some((height, blobs)) = fetched.next().await {
+            if self.shutdown.is_cancelled() {
+                return Ok(());
             }
+            self.apply_da_height(height, blobs?).await?;
         }
 
         info!("historical sync completed");
@@ -146,30 +2798,107 @@ impl Node {
     }
 
     async fn sync_incoming_blocks(&self) -> Result<()> {
-        let mut blobsub = BlobClient::blob_subscribe(&self.da_client, self.cfg.namespace)
+        let mut blobsub = self
+            .da
+            .subscribe(self.cfg.namespace)
             .await
             .context("Failed to subscribe to app namespace")?;
 
-        self.genesis_sync_completed.notified().await;
+        tokio::select! {
+            _ = self.genesis_sync_completed.notified() => {}
+            _ = self.shutdown.cancelled() => return Ok(()),
+        }
 
-        while let Some(result) = blobsub.next().await {
+        loop {
+            let result = tokio::select! {
+                result = blobsub.next() => result,
+                _ = self.shutdown.cancelled() => break,
+            };
             match result {
-                Ok(blob_response) => {
-                    info!(
-                        "processing incoming celestia height: {}",
-                        blob_response.height
-                    );
-                    if let Some(blobs) = blob_response.blobs {
-                        self.process_l1_block(blobs).await;
+                Some(Ok((height, blobs))) => {
+                    // heights the subscription skipped, e.g. after it failed
+                    // to deliver a block, are fetched first
+                    if let Err(e) = self.backfill(height - 1).await {
+                        error!("backfilling celestia heights before {}: {}", height, e);
+                        continue;
+                    }
+                    info!("processing incoming DA height: {}", height);
+                    let result = match self.get_merged_blobs(height, Some(blobs)).await {
+                        Ok(blobs) => self.apply_da_height(height, blobs).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        error!("processing celestia height {}: {}", height, e);
+                    }
+                }
+                Some(Err(e)) => error!("retrieving blobs from DA layer: {}", e),
+                None => {
+                    warn!("blob subscription ended, resubscribing");
+                    match self.resubscribe().await {
+                        Some(subscription) => blobsub = subscription,
+                        None => break,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Subscribes to the namespace again, e.g. after the connection to the
+    /// DA layer was lost, retrying until it succeeds. Then applies the blocks
+    /// produced since the last processed height. Returns `None` on shutdown.
+    async fn resubscribe(&self) -> Option<BlobStream> {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(RESUBSCRIBE_DELAY) => {}
+                _ = self.shutdown.cancelled() => return None,
+            }
+            match self.da.subscribe(self.cfg.namespace).await {
+                Ok(subscription) => {
+                    let caught_up = match self.da.network_height().await {
+                        Ok(network_height) => self.backfill(network_height).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = caught_up {
+                        error!("catching up after resubscribing: {}", e);
                     }
+                    return Some(subscription);
                 }
-                Err(e) => error!("retrieving blobs from DA layer: {}", e),
+                Err(e) => warn!("resubscribing to app namespace: {}", e),
+            }
+        }
+    }
+
+    /// Fetches and applies the blocks after the last processed height up to
+    /// `height`, so no block is skipped when the subscription misses some.
+    /// Blocks later delivered by the subscription are skipped then.
+    async fn backfill(&self, height: u64) -> Result<()> {
+        let Some(last_height) = self.store.get_da_height()? else {
+            return Ok(());
+        };
+        if last_height < height {
+            warn!(
+                "backfilling missed celestia heights {}-{}",
+                last_height + 1,
+                height
+            );
+        }
+        for height in last_height + 1..=height {
+            if self.shutdown.is_cancelled() {
+                break;
             }
+            let blobs = self.get_merged_blobs(height, None).await?;
+            self.apply_da_height(height, blobs).await?;
         }
         Ok(())
     }
 
     async fn sync(self: Arc<Self>) -> Result<()> {
+        if self.cfg.role == NodeRole::Light {
+            self.shutdown.cancelled().await;
+            return Ok(());
+        }
+
         let genesis_sync = {
             let node = self.clone();
             tokio::spawn(async move { node.sync_historical().await })
@@ -190,8 +2919,17 @@ impl Node {
     }
 
     async fn start_batch_posting(&self) -> Result<()> {
+        if self.cfg.role != NodeRole::Sequencer {
+            self.shutdown.cancelled().await;
+            return Ok(());
+        }
+
         loop {
-            tokio::time::sleep(self.cfg.batch_interval).await;
+            let shutting_down = tokio::select! {
+                _ = tokio::time::sleep(self.cfg.batch_interval) => false,
+                _ = self.batch_triggered.notified() => false,
+                _ = self.shutdown.cancelled() => true,
+            };
             match self.post_pending_batch().await {
                 Ok(batch) => {
                     let tx_count = batch.get_transactions().len();
@@ -203,46 +2941,921 @@ impl Node {
                 }
                 Err(e) => error!("posting batch: {}", e),
             }
+            if shutting_down {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn start_proof_posting(&self) -> Result<()> {
+        if self.cfg.role != NodeRole::Sequencer {
+            self.shutdown.cancelled().await;
+            return Ok(());
+        }
+
+        loop {
+            let shutting_down = tokio::select! {
+                _ = self.proof_queued.notified() => false,
+                _ = self.shutdown.cancelled() => true,
+            };
+            match self.post_pending_proofs().await {
+                Ok(proof_count) if proof_count > 0 => {
+                    info!("posted {} epoch proofs", proof_count);
+                }
+                Ok(_) => {}
+                Err(e) if shutting_down => error!("posting epoch proofs: {}", e),
+                Err(e) => {
+                    error!("posting epoch proofs: {}", e);
+                    // retry with the next batch of proofs
+                    tokio::time::sleep(self.cfg.batch_interval).await;
+                    self.proof_queued.notify_one();
+                }
+            }
+            if shutting_down {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Inserts the transactions submitted to the sequencer into the mempool
+    /// one at a time, so request handlers don't contend for the mempool and
+    /// soft state locks.
+    async fn start_mempool(&self) -> Result<()> {
+        let mut submissions = self
+            .submission_receiver
+            .lock()
+            .await
+            .take()
+            .ok_or_else(|| anyhow!("Mempool task already started"))?;
+        loop {
+            let submission = tokio::select! {
+                submission = submissions.recv() => submission,
+                _ = self.shutdown.cancelled() => return Ok(()),
+            };
+            let Some((tx, result)) = submission else {
+                return Ok(());
+            };
+            // the submitter may have gone away, e.g. on a client timeout
+            let _ = result.send(self.insert_transaction(tx).await);
+        }
+    }
+
+    /// Proves the epochs queued by block processing one at a time, so proving
+    /// latency never holds up sync or execution.
+    async fn start_proving(&self) -> Result<()> {
+        let receiver = self.proof_job_receiver.lock().await.take();
+        let (Some(prover), Some(mut jobs), NodeRole::Sequencer) =
+            (self.prover.clone(), receiver, self.cfg.role)
+        else {
+            self.shutdown.cancelled().await;
+            return Ok(());
+        };
+
+        loop {
+            let job = tokio::select! {
+                job = jobs.recv() => job,
+                _ = self.shutdown.cancelled() => return Ok(()),
+            };
+            let Some(ProofJob { epoch, input }) = job else {
+                return Ok(());
+            };
+            let prover = prover.clone();
+            let result = tokio::task::spawn_blocking(move || match input {
+                ProofInput::Batch(batch) => {
+                    batch.verify()?;
+                    prover.prove(epoch, &batch)
+                }
+                ProofInput::Stf(witness) => prover.prove_stf(epoch, &witness),
+            })
+            .await;
+            match result {
+                Ok(Ok(proof)) => {
+                    info!("proved epoch {}", epoch);
+                    self.queue_proof(proof).await;
+                }
+                Ok(Err(e)) => error!("proving epoch {}: {}", epoch, e),
+                Err(e) => error!("proving task for epoch {} panicked: {}", epoch, e),
+            }
         }
     }
 
     pub async fn start_server(self: Arc<Self>) -> Result<()> {
-        let app = Router::new()
-            .route("/submit_tx", post(submit_tx))
-            .with_state(self.clone());
+        let mut submit = post(submit_tx);
+        if let Some(max_requests) = self.cfg.submit_rate_limit {
+            let limiter = Arc::new(RateLimiter::new(max_requests));
+            submit = submit.route_layer(middleware::from_fn_with_state(limiter, rate_limit));
+        }
+
+        let mut app = Router::new()
+            .route("/submit_tx", submit)
+            .route("/estimate_fee", post(estimate_fee_handler))
+            .route("/height", get(get_height))
+            .route("/mempool", get(get_mempool))
+            .route("/openapi.json", get(get_openapi))
+            .route("/ws", get(ws_handler));
+        let mut admin = Router::new().route("/admin/log_level", put(set_log_level));
+        if self.cfg.role != NodeRole::Light {
+            // light nodes don't execute transactions, so they have no state
+            // to query
+            app = app
+                .route("/account/:vk", get(get_account))
+                .route("/accounts", get(get_accounts_handler))
+                .route("/proof/:vk", get(get_proof))
+                .route("/account/:vk/proof_bundle", get(get_proof_bundle))
+                .route("/simulate", post(simulate_handler))
+                .route("/root", get(get_root))
+                .route("/block/:height", get(get_block_handler))
+                .route("/block/:height/da", get(get_block_da))
+                .route("/block/:height/inclusion_proof", get(get_inclusion_proof))
+                .route("/verify_root", get(verify_root))
+                .route("/verify_fraud_proof", post(verify_fraud_proof))
+                .route("/rejected_data", get(get_rejected_data))
+                .route("/stats/latency", get(get_latency_stats))
+                .route("/stats/cache", get(get_cache_stats))
+                .route("/params", get(get_params_handler))
+                .route("/account/:vk/txs", get(get_account_txs_handler))
+                .route("/tx/:hash", get(get_tx))
+                .route("/receipt/:tx_hash", get(get_receipt_handler));
+            if self.cfg.archive {
+                app = app
+                    .route("/account/:vk/history", get(get_account_history_handler))
+                    .route("/events", get(get_events_handler));
+            }
+
+            admin = admin.route("/snapshot", get(get_snapshot));
+        }
+        // without a token anyone could change the log level or pull
+        // snapshots, so the admin endpoints aren't served at all
+        if let Some(token) = self.cfg.admin_token.clone() {
+            admin = admin.route_layer(middleware::from_fn_with_state(
+                Arc::new(token),
+                require_admin_token,
+            ));
+            app = app.merge(admin);
+        }
+        if let Some(cors) = cors_layer(&self.cfg.cors_origins) {
+            app = app.layer(cors);
+        }
+        let app = app.with_state(self.clone());
 
         let listen_addr = self.cfg.listen_addr.clone();
         info!("webserver listening on {}", listen_addr);
+        let shutdown = self.shutdown.clone();
         axum::Server::bind(&listen_addr.parse().unwrap())
-            .serve(app.into_make_service())
+            // the peer address is needed for per-IP rate limits
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(async move { shutdown.cancelled().await })
             .await
             .context("Failed to start server")
     }
 
+    /// Serves the gRPC API until shutdown, or idles if no address is
+    /// configured.
+    async fn start_grpc(self: Arc<Self>) -> Result<()> {
+        let Some(grpc_addr) = self.cfg.grpc_addr.clone() else {
+            self.shutdown.cancelled().await;
+            return Ok(());
+        };
+
+        #[cfg(feature = "grpc")]
+        {
+            let addr = grpc_addr
+                .parse()
+                .with_context(|| format!("Invalid gRPC address {}", grpc_addr))?;
+            let shutdown = self.shutdown.clone();
+            crate::grpc::serve(self, addr, async move { shutdown.cancelled().await }).await
+        }
+        #[cfg(not(feature = "grpc"))]
+        {
+            Err(anyhow!(
+                "Serving gRPC on {} requires building with the `grpc` feature",
+                grpc_addr
+            ))
+        }
+    }
+
+    /// Periodically prunes the tree versions of the epochs before the
+    /// retained Celestia heights, or idles if every version is kept.
+    async fn start_pruning(&self) -> Result<()> {
+        let Some(retain_heights) = self.cfg.retain_heights else {
+            self.shutdown.cancelled().await;
+            return Ok(());
+        };
+
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.shutdown.cancelled() => return Ok(()),
+            }
+            let min_epoch = match self.oldest_retained_epoch(retain_heights) {
+                Ok(Some(min_epoch)) => min_epoch,
+                Ok(None) => continue,
+                Err(e) => {
+                    error!("finding the oldest retained epoch: {}", e);
+                    continue;
+                }
+            };
+            let store = self.store.clone();
+            match tokio::task::spawn_blocking(move || prune(store.as_ref(), min_epoch)).await? {
+                Ok(0) => {}
+                Ok(pruned) => debug!("pruned {} entries before epoch {}", pruned, min_epoch),
+                Err(e) => error!("pruning epochs before {}: {}", min_epoch, e),
+            }
+        }
+    }
+
+    /// Returns the epoch the tree was at after the oldest of the latest
+    /// `retain_heights` Celestia heights, which rolling back to that height
+    /// needs. `None` if fewer heights were processed.
+    fn oldest_retained_epoch(&self, retain_heights: u64) -> Result<Option<u64>> {
+        let Some(last_height) = self.store.get_da_height()? else {
+            return Ok(None);
+        };
+        let Some(oldest_height) = last_height.checked_sub(retain_heights) else {
+            return Ok(None);
+        };
+        self.store.get_da_height_epoch(oldest_height)
+    }
+
+    /// Checks the root claims an optimistic sequencer posts to the proof
+    /// namespace against the epochs this watchtower executed, posting a
+    /// fraud proof to the challenge namespace for every wrong one. Idles
+    /// unless the node is a watchtower.
+    async fn start_watchtower(&self) -> Result<()> {
+        if !self.cfg.watchtower {
+            self.shutdown.cancelled().await;
+            return Ok(());
+        }
+
+        let mut next_height = match self.store.get_metadata(WATCHTOWER_HEIGHT_KEY)? {
+            Some(bytes) => bincode::deserialize(&bytes)?,
+            None => self.start_height,
+        };
+        // claims of epochs this node hasn't executed yet
+        let mut pending: Vec<EpochProof> = Vec::new();
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.shutdown.cancelled() => return Ok(()),
+            }
+            // claims are posted after their epoch, so scanning up to the
+            // processed height finds them once the epoch was executed
+            while next_height <= self.da_height.load(Ordering::Relaxed) {
+                let blobs = match self
+                    .da
+                    .get_blobs(next_height, self.cfg.proof_namespace)
+                    .await
+                {
+                    Ok(blobs) => blobs,
+                    Err(e) => {
+                        warn!("fetching claims at celestia height {}: {}", next_height, e);
+                        break;
+                    }
+                };
+                pending.extend(
+                    blobs
+                        .iter()
+                        .filter_map(|blob| EpochProof::try_from(blob).ok())
+                        .filter(EpochProof::is_optimistic),
+                );
+                next_height += 1;
+                self.store
+                    .put_metadata(WATCHTOWER_HEIGHT_KEY, &bincode::serialize(&next_height)?)?;
+            }
+
+            let mut waiting = Vec::new();
+            for claim in pending.drain(..) {
+                match self.check_claim(&claim).await {
+                    Ok(true) => {}
+                    Ok(false) => waiting.push(claim),
+                    Err(e) => error!("checking the claim of epoch {}: {}", claim.epoch, e),
+                }
+            }
+            pending = waiting;
+        }
+    }
+
+    /// Records where the validity proofs of epochs this full node executed
+    /// were posted, for [`Node::get_account_proof_bundle`]. Proofs that don't
+    /// verify with the node's prover backend, or whose root differs from the
+    /// executed one, are skipped. Sequencers record the proofs they post
+    /// instead.
+    async fn start_proof_indexing(&self) -> Result<()> {
+        let (NodeRole::Full, Some(verifier)) = (self.cfg.role, self.prover.clone()) else {
+            if self.cfg.role == NodeRole::Full {
+                warn!("no prover backend to verify epoch proofs with, not indexing them");
+            }
+            self.shutdown.cancelled().await;
+            return Ok(());
+        };
+
+        let mut next_height = match self.store.get_metadata(PROOF_INDEX_HEIGHT_KEY)? {
+            Some(bytes) => bincode::deserialize(&bytes)?,
+            None => self.start_height,
+        };
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.shutdown.cancelled() => return Ok(()),
+            }
+            // proofs are posted after their epoch, so scanning up to the
+            // processed height finds them once the epoch was executed
+            while next_height <= self.da_height.load(Ordering::Relaxed) {
+                let blobs = match self
+                    .da
+                    .get_blobs(next_height, self.cfg.proof_namespace)
+                    .await
+                {
+                    Ok(blobs) => blobs,
+                    Err(e) => {
+                        warn!("fetching proofs at celestia height {}: {}", next_height, e);
+                        break;
+                    }
+                };
+                for blob in &blobs {
+                    let Ok(proof) = EpochProof::try_from(blob) else {
+                        continue;
+                    };
+                    if proof.is_optimistic() {
+                        continue;
+                    }
+                    if let Err(e) = self.index_proof(verifier.as_ref(), &proof, next_height, blob) {
+                        warn!("indexing the proof of epoch {}: {}", proof.epoch, e);
+                    }
+                }
+                next_height += 1;
+                self.store
+                    .put_metadata(PROOF_INDEX_HEIGHT_KEY, &bincode::serialize(&next_height)?)?;
+            }
+        }
+    }
+
+    fn index_proof(
+        &self,
+        verifier: &dyn ProverBackend,
+        proof: &EpochProof,
+        da_height: u64,
+        blob: &Blob,
+    ) -> Result<()> {
+        // the executed root can't be read anymore, so don't verify either
+        check_not_pruned(self.store.as_ref(), proof.epoch)?;
+        if !verifier.verify(proof)? {
+            return Err(anyhow!("invalid proof"));
+        }
+        check_mint_authority(proof, self.cfg.mint_vk.as_ref())?;
+        let root = self
+            .state_snapshot
+            .load()
+            .at(proof.epoch)?
+            .get_commitment()?;
+        if root != proof.new_root {
+            return Err(anyhow!(
+                "proven root {} differs from the executed root {}",
+                hex::encode(proof.new_root.0),
+                hex::encode(root.0)
+            ));
+        }
+        put_epoch_proof_pointer(
+            self.store.as_ref(),
+            &EpochProofPointer {
+                epoch: proof.epoch,
+                root,
+                da_height,
+                commitment: blob.commitment,
+            },
+        )
+    }
+
+    /// Compares `claim` with the epoch as executed by this node and posts a
+    /// fraud proof if it is wrong. Returns `false` if the epoch hasn't been
+    /// executed yet.
+    async fn check_claim(&self, claim: &EpochProof) -> Result<bool> {
+        let Some(executed) = take_watched_epoch(self.store.as_ref(), claim.epoch)? else {
+            if self.state_snapshot.load().epoch() < claim.epoch {
+                return Ok(false);
+            }
+            debug!("epoch {} wasn't executed by this watchtower", claim.epoch);
+            return Ok(true);
+        };
+        let Some(fraud_proof) = find_fraud(claim, executed)? else {
+            debug!("claim of epoch {} is correct", claim.epoch);
+            return Ok(true);
+        };
+
+        error!(
+            "sequencer claimed a wrong root for transition {} of epoch {}, posting a fraud proof",
+            fraud_proof.index, claim.epoch
+        );
+        let blob = Blob::new(
+            self.cfg.challenge_namespace,
+            bincode::serialize(&fraud_proof)?,
+        )?;
+        let da_height = submit_with_retry(self.da.as_ref(), &[blob], &self.cfg.submit_retry)
+            .await?
+            .height;
+        info!(
+            "fraud proof of epoch {} posted at celestia height {}",
+            claim.epoch, da_height
+        );
+        Ok(true)
+    }
+
+    /// Gossips transactions with the peers of the p2p network until
+    /// shutdown, or idles if no listen address is configured.
+    async fn start_p2p(self: Arc<Self>) -> Result<()> {
+        let Some(listen_addr) = self.cfg.p2p_listen_addr.clone() else {
+            self.shutdown.cancelled().await;
+            return Ok(());
+        };
+
+        #[cfg(feature = "p2p")]
+        {
+            use crate::p2p::{load_or_generate_keypair, tx_topic, Gossip};
+
+            let outgoing = self
+                .gossip_receiver
+                .lock()
+                .await
+                .take()
+                .ok_or_else(|| anyhow!("P2p task already started"))?;
+            let keypair = load_or_generate_keypair(self.store.as_ref())?;
+            let topic = tx_topic(self.cfg.namespace.as_bytes(), self.cfg.chain_id);
+            let gossip = Gossip::new(keypair, &listen_addr, &self.cfg.bootnodes, topic)?;
+            let shutdown = self.shutdown.clone();
+            gossip.run(self, outgoing, shutdown).await
+        }
+        #[cfg(not(feature = "p2p"))]
+        {
+            Err(anyhow!(
+                "Gossiping transactions on {} requires building with the `p2p` feature",
+                listen_addr
+            ))
+        }
+    }
+
+    /// Fetches the blocks included up to the trusted snapshot's DA height
+    /// from the archive node, so the block history is complete even though
+    /// those blocks were never executed locally. Heights already stored are
+    /// skipped, so an interrupted backfill resumes where it stopped.
+    async fn start_backfill(&self) -> Result<()> {
+        let (Some(archive_url), Some(bytes)) = (
+            self.cfg.archive_url.as_deref(),
+            self.store.get_metadata(SNAPSHOT_DA_HEIGHT_KEY)?,
+        ) else {
+            self.shutdown.cancelled().await;
+            return Ok(());
+        };
+        let snapshot_da_height: u64 = bincode::deserialize(&bytes)?;
+        let archive_url = archive_url.trim_end_matches('/');
+
+        let mut height = 1;
+        let mut backfilled = 0;
+        while !self.shutdown.is_cancelled() {
+            if self.get_block(height)?.is_some() {
+                height += 1;
+                continue;
+            }
+            let response = self
+                .http_client
+                .get(format!("{}/block/{}", archive_url, height))
+                .send()
+                .await
+                .context("Failed to fetch block from archive")?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                break;
+            }
+            let block =
+                Block::try_from(response.error_for_status()?.json::<BlockResponse>().await?)?;
+            if block.da_height > snapshot_da_height {
+                break;
+            }
+            put_historical_block(self.store.as_ref(), &block)?;
+            backfilled += 1;
+            height += 1;
+        }
+        info!(
+            "backfilled {} blocks up to celestia height {}",
+            backfilled, snapshot_da_height
+        );
+
+        self.shutdown.cancelled().await;
+        Ok(())
+    }
+
+    /// Submits queued epoch proofs to the settlement contract in order, or
+    /// idles if settlement isn't configured.
+    async fn start_settlement(&self) -> Result<()> {
+        let Some(rpc_url) = self.cfg.settlement_rpc_url.clone() else {
+            self.shutdown.cancelled().await;
+            return Ok(());
+        };
+
+        #[cfg(feature = "settlement")]
+        {
+            let contract = self
+                .cfg
+                .settlement_contract
+                .as_deref()
+                .ok_or_else(|| anyhow!("Settlement requires a contract address"))?;
+            let relayer = crate::settlement::Relayer::new(&rpc_url, contract)?;
+            loop {
+                let next = {
+                    let mut pending = self.pending_settlements.lock().await;
+                    pending.sort_by_key(|proof| proof.epoch);
+                    pending.first().cloned()
+                };
+                let Some(proof) = next else {
+                    tokio::select! {
+                        _ = self.settlement_queued.notified() => continue,
+                        _ = self.shutdown.cancelled() => return Ok(()),
+                    }
+                };
+
+                match relayer.submit(&proof).await {
+                    Ok(()) => self
+                        .pending_settlements
+                        .lock()
+                        .await
+                        .retain(|pending| pending.epoch != proof.epoch),
+                    Err(e) => {
+                        error!("settling epoch {}: {}", proof.epoch, e);
+                        // later epochs build on this one, so retry it first
+                        tokio::select! {
+                            _ = tokio::time::sleep(self.cfg.batch_interval) => {}
+                            _ = self.shutdown.cancelled() => return Ok(()),
+                        }
+                    }
+                }
+            }
+        }
+        #[cfg(not(feature = "settlement"))]
+        {
+            Err(anyhow!(
+                "Settling through {} requires building with the `settlement` feature",
+                rpc_url
+            ))
+        }
+    }
+
+    /// Flushes pending tree writes. Unposted transactions are already
+    /// persisted as they are queued.
+    async fn persist_on_shutdown(&self) -> Result<()> {
+        self.state.lock().await.flush()?;
+
+        let pending_txs = self.mempool.lock().await.len();
+        if pending_txs > 0 {
+            info!(
+                "{} unposted transactions will be restored on restart",
+                pending_txs
+            );
+        }
+        Ok(())
+    }
+
+    /// Runs the node's tasks until one of them fails. Refuses to start a
+    /// sequencer proving its epochs with [`Config::scheduled_txs`] on.
     pub async fn start(self: Arc<Self>) -> Result<()> {
-        let sync_handle = self.clone().sync();
+        // the epochs would silently stay unproven, see
+        // `State::take_scheduled_txs`
+        if self.cfg.scheduled_txs
+            && self.cfg.role == NodeRole::Sequencer
+            && self.cfg.proof_mode == ProofMode::Validity
+            && self.prover.is_some()
+        {
+            return Err(anyhow!(
+                "Epochs with scheduled transactions can't be proven, disable scheduled_txs on chains with validity proofs"
+            ));
+        }
+        let mut sync_handle = tokio::spawn(self.clone().sync());
 
-        let webserver = {
+        let mut webserver = {
             let node = self.clone();
             tokio::spawn(async move { node.start_server().await })
         };
 
-        let batch_posting = {
+        let mut grpc = {
+            let node = self.clone();
+            tokio::spawn(async move { node.start_grpc().await })
+        };
+
+        let mut mempool = {
+            let node = self.clone();
+            tokio::spawn(async move { node.start_mempool().await })
+        };
+
+        let mut batch_posting = {
             let node = self.clone();
             tokio::spawn(async move { node.start_batch_posting().await })
         };
 
+        let mut proof_posting = {
+            let node = self.clone();
+            tokio::spawn(async move { node.start_proof_posting().await })
+        };
+
+        let mut proving = {
+            let node = self.clone();
+            tokio::spawn(async move { node.start_proving().await })
+        };
+
+        let mut p2p = {
+            let node = self.clone();
+            tokio::spawn(async move { node.start_p2p().await })
+        };
+
+        let mut settlement = {
+            let node = self.clone();
+            tokio::spawn(async move { node.start_settlement().await })
+        };
+
+        let mut backfill = {
+            let node = self.clone();
+            tokio::spawn(async move { node.start_backfill().await })
+        };
+
+        let mut pruning = {
+            let node = self.clone();
+            tokio::spawn(async move { node.start_pruning().await })
+        };
+
+        let mut watchtower = {
+            let node = self.clone();
+            tokio::spawn(async move { node.start_watchtower().await })
+        };
+
+        let mut proof_indexing = {
+            let node = self.clone();
+            tokio::spawn(async move { node.start_proof_indexing().await })
+        };
+
         tokio::select! {
-            _ = sync_handle => {
+            _ = shutdown_signal() => {
+                info!("received shutdown signal");
+            }
+            _ = self.shutdown.cancelled() => {}
+            _ = &mut sync_handle => {
                 error!("sync task exited");
             }
-            _ = webserver => {
+            _ = &mut webserver => {
                 error!("webserver task exited");
             }
-            _ = batch_posting => {
+            result = &mut grpc => {
+                error!("grpc task exited: {:?}", result);
+            }
+            result = &mut p2p => {
+                error!("p2p task exited: {:?}", result);
+            }
+            result = &mut mempool => {
+                error!("mempool task exited: {:?}", result);
+            }
+            _ = &mut batch_posting => {
                 error!("batch posting task exited");
             }
+            _ = &mut proof_posting => {
+                error!("proof posting task exited");
+            }
+            result = &mut proving => {
+                error!("proving task exited: {:?}", result);
+            }
+            result = &mut settlement => {
+                error!("settlement task exited: {:?}", result);
+            }
+            result = &mut backfill => {
+                error!("backfill task exited: {:?}", result);
+            }
+            result = &mut pruning => {
+                error!("pruning task exited: {:?}", result);
+            }
+            result = &mut watchtower => {
+                error!("watchtower task exited: {:?}", result);
+            }
+            result = &mut proof_indexing => {
+                error!("proof indexing task exited: {:?}", result);
+            }
         }
+
+        info!("shutting down");
+        self.shutdown();
+        // the servers stop first, so no new transactions are queued while
+        // the batch poster posts its final batch
+        let _ = tokio::join!(
+            webserver,
+            grpc,
+            p2p,
+            mempool,
+            batch_posting,
+            proof_posting,
+            proving,
+            settlement,
+            backfill,
+            pruning,
+            watchtower,
+            proof_indexing,
+            sync_handle
+        );
+
+        self.persist_on_shutdown().await?;
+        // the DA client closes its connection when the node is dropped
+        info!("shutdown complete");
         Ok(())
     }
 }
+
+/// Connects to the DA layer configured in `cfg`.
+async fn open_da(cfg: &Config) -> Result<Arc<dyn DataAvailability>> {
+    let da: Arc<dyn DataAvailability> = match cfg.da {
+        DaKind::Celestia => {
+            let auth_token: Option<&str> = cfg.auth_token.as_deref();
+            match cfg.da_mode {
+                DaMode::Rpc => Arc::new(
+                    CelestiaDA::new(&cfg.celestia_urls, auth_token, &cfg.celestia_tx).await?,
+                ),
+                #[cfg(feature = "lumina")]
+                DaMode::Lumina => {
+                    let submitter = match cfg.role {
+                        NodeRole::Sequencer => Some(
+                            CelestiaDA::new(&cfg.celestia_urls, auth_token, &cfg.celestia_tx)
+                                .await?,
+                        ),
+                        NodeRole::Full | NodeRole::Light => None,
+                    };
+                    Arc::new(LuminaDA::new(cfg.lumina_network, submitter).await?)
+                }
+                #[cfg(not(feature = "lumina"))]
+                DaMode::Lumina => {
+                    return Err(anyhow!(
+                        "DA mode lumina requires building with the `lumina` feature"
+                    ))
+                }
+            }
+        }
+        DaKind::Mock => {
+            info!(
+                "using mock DA with a block time of {:?}",
+                cfg.mock_block_time
+            );
+            let mock = Arc::new(MockDA::new());
+            mock.spawn_block_production(cfg.mock_block_time);
+            mock
+        }
+        DaKind::File => {
+            info!("using file DA in {}", cfg.da_dir.display());
+            let file = Arc::new(FileDA::new(&cfg.da_dir)?);
+            // other nodes only read the blocks the sequencer writes
+            if cfg.role == NodeRole::Sequencer {
+                file.spawn_block_production(cfg.mock_block_time);
+            }
+            file
+        }
+    };
+    Ok(da)
+}
+
+/// Checks that a valid epoch proof on the proof namespace attests to the
+/// snapshot's root. Proofs are posted after their epoch, so the search starts
+/// at the snapshot's DA height. Anyone can post to the proof namespace, so
+/// proofs that don't verify with `verifier`, or for another `mint_vk`, are
+/// skipped.
+async fn verify_snapshot_root(
+    da: &dyn DataAvailability,
+    verifier: Option<&dyn ProverBackend>,
+    mint_vk: Option<&VerifyingKey>,
+    proof_namespace: Namespace,
+    snapshot: &Snapshot,
+) -> Result<()> {
+    let verifier = verifier.ok_or_else(|| {
+        anyhow!("Verifying an untrusted snapshot requires a prover backend, or a trusted root")
+    })?;
+    let network_height = da.network_height().await?;
+    for height in snapshot.da_height..=network_height {
+        for blob in da.get_blobs(height, proof_namespace).await? {
+            let proof = match EpochProof::try_from(&blob) {
+                Ok(proof) => proof,
+                Err(e) => {
+                    debug!("skipping proof namespace blob: {}", e);
+                    continue;
+                }
+            };
+            if proof.epoch != snapshot.epoch {
+                continue;
+            }
+            match verifier
+                .verify(&proof)
+                .map(|valid| valid && check_mint_authority(&proof, mint_vk).is_ok())
+            {
+                Ok(true) => {}
+                Ok(false) => {
+                    warn!("skipping invalid epoch proof posted at height {}", height);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("skipping epoch proof posted at height {}: {}", height, e);
+                    continue;
+                }
+            }
+            if proof.new_root != snapshot.root {
+                return Err(anyhow!(
+                    "Snapshot root does not match epoch proof posted at height {}",
+                    height
+                ));
+            }
+            info!(
+                "snapshot root matches epoch proof posted at height {}",
+                height
+            );
+            return Ok(());
+        }
+    }
+    Err(anyhow!(
+        "No epoch proof found for snapshot epoch {}",
+        snapshot.epoch
+    ))
+}
+
+/// Resolves on SIGINT or SIGTERM.
+pub(crate) async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("listening for ctrl-c: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("listening for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        storage::InMemoryStore,
+        testkit::generate_key,
+        tx::{TransactionBuilder, TransactionType},
+    };
+
+    fn forced_tx(nonce: u64) -> Transaction {
+        let key = generate_key();
+        TransactionBuilder::new(TransactionType::Noop)
+            .nonce(nonce)
+            .sign(&key)
+            .unwrap()
+    }
+
+    /// Returns the hashes of the transactions queued at `height`.
+    fn forced_txs(store: &dyn NodeStore, height: u64) -> Option<Vec<Digest>> {
+        let bytes = store.get_metadata(&forced_txs_key(height)).unwrap()?;
+        let txs: Vec<Transaction> = bincode::deserialize(&bytes).unwrap();
+        Some(txs.iter().map(|tx| tx.hash()).collect())
+    }
+
+    #[test]
+    fn rollback_restores_taken_forced_txs_and_drops_added_ones() {
+        let store = InMemoryStore::default();
+        let (taken_2, taken_3) = (forced_tx(0), forced_tx(1));
+        for (height, forced_txs) in [
+            (1, vec![]),
+            (2, vec![taken_2.clone()]),
+            (3, vec![taken_3.clone()]),
+        ] {
+            let archived = ArchivedHeight {
+                batches: Vec::new(),
+                forced_txs,
+            };
+            put_archived_height(&store, height, &archived).unwrap();
+        }
+        // queued by the direct batches of heights 2 and 3, with a delay of 2
+        for height in [4, 5] {
+            let queued = bincode::serialize(&vec![forced_tx(height)]).unwrap();
+            store
+                .put_metadata(&forced_txs_key(height), &queued)
+                .unwrap();
+        }
+
+        rollback_forced_txs(&store, 1, 3, 2).unwrap();
+        assert_eq!(forced_txs(&store, 2), Some(vec![taken_2.hash()]));
+        assert_eq!(forced_txs(&store, 3), Some(vec![taken_3.hash()]));
+        assert_eq!(forced_txs(&store, 4), None);
+        assert_eq!(forced_txs(&store, 5), None);
+    }
+}
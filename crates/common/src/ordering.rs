@@ -0,0 +1,198 @@
+//! The order the sequencer posts queued transactions in. The policy is a
+//! hook point for experimenting with e.g. MEV-resistant ordering, see
+//! [`crate::node::Node::with_ordering_policy`]; the shipped ones are
+//! selected by [`BatchOrdering`].
+//!
+//! Whatever the policy, a sender's transactions stay in nonce order, since
+//! executing them out of order would fail.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{BinaryHeap, VecDeque};
+
+use crate::tx::Transaction;
+
+/// A queued transaction with its position in the arrival order.
+pub struct QueuedTx {
+    pub tx: Transaction,
+    /// Increases with every transaction added to the mempool
+    pub seq: u64,
+}
+
+/// Orders the transactions drained from the mempool into a batch.
+pub trait OrderingPolicy: Send + Sync {
+    /// Merges the queued transactions of every sender, each in nonce order,
+    /// into the order they are posted in. The policy may only choose which
+    /// sender's next transaction comes next.
+    fn order(&self, senders: Vec<VecDeque<QueuedTx>>) -> Vec<Transaction>;
+}
+
+/// Selects one of the shipped [`OrderingPolicy`]s.
+#[derive(ValueEnum, Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BatchOrdering {
+    /// In arrival order, see [`Fifo`].
+    #[default]
+    Fifo,
+    /// Highest gas price first, see [`FeePriority`].
+    FeePriority,
+    /// One transaction per sender in turn, see [`RoundRobin`].
+    RoundRobin,
+}
+
+impl BatchOrdering {
+    pub fn policy(&self) -> Box<dyn OrderingPolicy> {
+        match self {
+            BatchOrdering::Fifo => Box::new(Fifo),
+            BatchOrdering::FeePriority => Box::new(FeePriority),
+            BatchOrdering::RoundRobin => Box::new(RoundRobin),
+        }
+    }
+}
+
+/// Posts transactions in the order they arrived in. A transaction that
+/// arrived before a lower nonce of its sender waits for it.
+pub struct Fifo;
+
+impl OrderingPolicy for Fifo {
+    fn order(&self, senders: Vec<VecDeque<QueuedTx>>) -> Vec<Transaction> {
+        merge_by(senders, |tx| std::cmp::Reverse(tx.seq))
+    }
+}
+
+/// Posts the transactions paying the highest gas price first, in arrival
+/// order among equal prices. A sender's low-paying transaction still goes
+/// before their higher nonces.
+pub struct FeePriority;
+
+impl OrderingPolicy for FeePriority {
+    fn order(&self, senders: Vec<VecDeque<QueuedTx>>) -> Vec<Transaction> {
+        merge_by(senders, |tx| (tx.tx.gas_price(), std::cmp::Reverse(tx.seq)))
+    }
+}
+
+/// Takes one transaction from every sender in turn, senders ordered by
+/// their oldest queued transaction, so no sender can fill a batch before
+/// the others get a transaction in.
+pub struct RoundRobin;
+
+impl OrderingPolicy for RoundRobin {
+    fn order(&self, mut senders: Vec<VecDeque<QueuedTx>>) -> Vec<Transaction> {
+        senders.sort_by_key(|queue| queue.iter().map(|tx| tx.seq).min());
+        let mut ordered = Vec::new();
+        while !senders.is_empty() {
+            for queue in &mut senders {
+                if let Some(tx) = queue.pop_front() {
+                    ordered.push(tx.tx);
+                }
+            }
+            senders.retain(|queue| !queue.is_empty());
+        }
+        ordered
+    }
+}
+
+/// Repeatedly takes the sender's next transaction that ranks highest by
+/// `rank`.
+fn merge_by<K: Ord>(
+    mut senders: Vec<VecDeque<QueuedTx>>,
+    rank: impl Fn(&QueuedTx) -> K,
+) -> Vec<Transaction> {
+    let mut heads: BinaryHeap<(K, usize)> = senders
+        .iter()
+        .enumerate()
+        .filter_map(|(i, queue)| queue.front().map(|tx| (rank(tx), i)))
+        .collect();
+    let mut ordered = Vec::new();
+    while let Some((_, i)) = heads.pop() {
+        if let Some(tx) = senders[i].pop_front() {
+            ordered.push(tx.tx);
+        }
+        if let Some(next) = senders[i].front() {
+            heads.push((rank(next), i));
+        }
+    }
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use prism_common::keys::{SigningKey, VerifyingKey};
+
+    use super::*;
+    use crate::{
+        keys,
+        testkit::generate_key,
+        tx::{TransactionBuilder, TransactionType, BASE_GAS},
+    };
+
+    /// A queued transaction of `key` paying `gas_price`, arrived as the
+    /// `seq`th.
+    fn queued(key: &SigningKey, nonce: u64, gas_price: u64, seq: u64) -> QueuedTx {
+        let tx = TransactionBuilder::new(TransactionType::Noop)
+            .nonce(nonce)
+            .fee(gas_price * BASE_GAS)
+            .sign(key)
+            .unwrap();
+        QueuedTx { tx, seq }
+    }
+
+    fn order(policy: &dyn OrderingPolicy, senders: Vec<Vec<QueuedTx>>) -> Vec<(VerifyingKey, u64)> {
+        policy
+            .order(senders.into_iter().map(VecDeque::from).collect())
+            .into_iter()
+            .map(|tx| (tx.vk, tx.nonce))
+            .collect()
+    }
+
+    #[test]
+    fn fifo_keeps_nonce_order_over_arrival_order() {
+        let (a, b) = (generate_key(), generate_key());
+        let (a_vk, b_vk) = (keys::verifying_key(&a), keys::verifying_key(&b));
+        // a's second transaction arrived before its first
+        let ordered = order(
+            &Fifo,
+            vec![
+                vec![queued(&a, 0, 1, 5), queued(&a, 1, 1, 0)],
+                vec![queued(&b, 0, 1, 3)],
+            ],
+        );
+        assert_eq!(ordered, vec![(b_vk, 0), (a_vk.clone(), 0), (a_vk, 1)]);
+    }
+
+    #[test]
+    fn fee_priority_takes_the_highest_paying_next_transaction() {
+        let (a, b) = (generate_key(), generate_key());
+        let (a_vk, b_vk) = (keys::verifying_key(&a), keys::verifying_key(&b));
+        // a's well paying transaction waits for its cheap first one
+        let ordered = order(
+            &FeePriority,
+            vec![
+                vec![queued(&a, 0, 1, 0), queued(&a, 1, 10, 1)],
+                vec![queued(&b, 0, 5, 2)],
+            ],
+        );
+        assert_eq!(ordered, vec![(b_vk, 0), (a_vk.clone(), 0), (a_vk, 1)]);
+    }
+
+    #[test]
+    fn round_robin_alternates_senders() {
+        let (a, b) = (generate_key(), generate_key());
+        let (a_vk, b_vk) = (keys::verifying_key(&a), keys::verifying_key(&b));
+        let ordered = order(
+            &RoundRobin,
+            vec![
+                vec![queued(&b, 0, 1, 3)],
+                vec![
+                    queued(&a, 0, 1, 0),
+                    queued(&a, 1, 1, 1),
+                    queued(&a, 2, 1, 2),
+                ],
+            ],
+        );
+        assert_eq!(
+            ordered,
+            vec![(a_vk.clone(), 0), (b_vk, 0), (a_vk.clone(), 1), (a_vk, 2)]
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use anyhow::{anyhow, Context, Result};
+use futures::StreamExt;
+use libp2p::{
+    gossipsub::{self, IdentTopic, MessageAcceptance, MessageAuthenticity, ValidationMode},
+    identity::Keypair,
+    noise,
+    swarm::{NetworkBehaviour, SwarmEvent},
+    tcp, yamux, Multiaddr, Swarm, SwarmBuilder,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    encoding::{Decode, Encode},
+    error::TxError,
+    node::{GossipRequest, Node},
+    storage::NodeStore,
+    tree::Digest,
+    tx::Transaction,
+};
+
+const KEYPAIR_KEY: &str = "p2p_keypair";
+
+/// Messages larger than this are dropped before validation. Leaves room for
+/// a transaction deploying a contract of the maximum code size.
+const MAX_MESSAGE_SIZE: usize = 512 * 1024;
+
+const IDLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Loads the node's peer identity, generating and storing one on first
+/// start so its peer id stays the same across restarts.
+pub fn load_or_generate_keypair<S: NodeStore + ?Sized>(store: &S) -> Result<Keypair> {
+    if let Some(bytes) = store.get_metadata(KEYPAIR_KEY)? {
+        return Keypair::from_protobuf_encoding(&bytes).context("Invalid stored p2p keypair");
+    }
+    let keypair = Keypair::generate_ed25519();
+    store.put_metadata(KEYPAIR_KEY, &keypair.to_protobuf_encoding()?)?;
+    Ok(keypair)
+}
+
+/// The gossip topic of a rollup's transactions. Includes the namespace and
+/// chain id, so nodes of different rollups sharing peers don't relay each
+/// other's transactions.
+pub fn tx_topic(namespace: &[u8], chain_id: u64) -> IdentTopic {
+    IdentTopic::new(format!(
+        "/shard/{}/{}/txs",
+        hex::encode(namespace),
+        chain_id
+    ))
+}
+
+#[derive(NetworkBehaviour)]
+struct Behaviour {
+    gossipsub: gossipsub::Behaviour,
+}
+
+/// Gossips transactions between shard nodes, so a transaction submitted to
+/// any node reaches the sequencer without clients knowing its address.
+///
+/// Messages are only relayed once the receiving node validated them (see
+/// [`Node::receive_gossiped_transaction`]). Peers aren't discovered, so
+/// every node must reach the sequencer through its bootnodes.
+pub struct Gossip {
+    swarm: Swarm<Behaviour>,
+    topic: IdentTopic,
+}
+
+impl Gossip {
+    pub fn new(
+        keypair: Keypair,
+        listen_addr: &str,
+        bootnodes: &[String],
+        topic: IdentTopic,
+    ) -> Result<Self> {
+        let listen_addr: Multiaddr = listen_addr
+            .parse()
+            .with_context(|| format!("Invalid p2p listen address {}", listen_addr))?;
+        let bootnodes = bootnodes
+            .iter()
+            .map(|addr| {
+                addr.parse::<Multiaddr>()
+                    .with_context(|| format!("Invalid bootnode address {}", addr))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let gossipsub_config = gossipsub::ConfigBuilder::default()
+            // messages must be signed by their publisher
+            .validation_mode(ValidationMode::Strict)
+            // relay messages only after they were validated
+            .validate_messages()
+            .max_transmit_size(MAX_MESSAGE_SIZE)
+            // the same transaction published by different nodes is only
+            // relayed once
+            .message_id_fn(|message| Digest::hash(&message.data).0.to_vec().into())
+            .build()
+            .map_err(|e| anyhow!("Invalid gossipsub config: {}", e))?;
+
+        let mut swarm = SwarmBuilder::with_existing_identity(keypair)
+            .with_tokio()
+            .with_tcp(
+                tcp::Config::default(),
+                noise::Config::new,
+                yamux::Config::default,
+            )?
+            .with_behaviour(|key| {
+                let gossipsub = gossipsub::Behaviour::new(
+                    MessageAuthenticity::Signed(key.clone()),
+                    gossipsub_config,
+                )?;
+                Ok(Behaviour { gossipsub })
+            })
+            .map_err(|e| anyhow!("Failed to create gossipsub behaviour: {}", e))?
+            .with_swarm_config(|config| {
+                config.with_idle_connection_timeout(IDLE_CONNECTION_TIMEOUT)
+            })
+            .build();
+
+        swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
+        swarm
+            .listen_on(listen_addr.clone())
+            .with_context(|| format!("Failed to listen on {}", listen_addr))?;
+        for bootnode in bootnodes {
+            if let Err(e) = swarm.dial(bootnode.clone()) {
+                warn!("dialing bootnode {}: {}", bootnode, e);
+            }
+        }
+
+        Ok(Gossip { swarm, topic })
+    }
+
+    /// Publishes the transactions queued by the node and validates those
+    /// received from peers until shutdown.
+    pub async fn run(
+        mut self,
+        node: Arc<Node>,
+        mut outgoing: mpsc::Receiver<GossipRequest>,
+        shutdown: CancellationToken,
+    ) -> Result<()> {
+        info!("p2p peer id: {}", self.swarm.local_peer_id());
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => return Ok(()),
+                request = outgoing.recv() => {
+                    let Some((tx, result)) = request else {
+                        return Ok(());
+                    };
+                    // the caller may have given up waiting
+                    let _ = result.send(self.publish(&tx));
+                }
+                event = self.swarm.select_next_some() => self.handle_event(&node, event).await,
+            }
+        }
+    }
+
+    fn publish(&mut self, tx: &Transaction) -> Result<()> {
+        self.swarm
+            .behaviour_mut()
+            .gossipsub
+            .publish(self.topic.clone(), tx.to_canonical_bytes())
+            .map_err(|e| anyhow!("Failed to gossip transaction: {}", e))?;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, node: &Node, event: SwarmEvent<BehaviourEvent>) {
+        match event {
+            SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            })) => {
+                let acceptance = match Transaction::from_canonical_bytes(&message.data) {
+                    Ok(tx) => match node.receive_gossiped_transaction(tx).await {
+                        Ok(()) => MessageAcceptance::Accept,
+                        Err(e) => {
+                            debug!("gossiped tx from {}: {}", propagation_source, e);
+                            acceptance(&e)
+                        }
+                    },
+                    Err(e) => {
+                        debug!("undecodable tx from {}: {}", propagation_source, e);
+                        MessageAcceptance::Reject
+                    }
+                };
+                self.swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .report_message_validation_result(&message_id, &propagation_source, acceptance);
+            }
+            SwarmEvent::NewListenAddr { address, .. } => {
+                info!(
+                    "p2p listening on {}/p2p/{}",
+                    address,
+                    self.swarm.local_peer_id()
+                );
+            }
+            SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                debug!("connected to peer {}", peer_id);
+            }
+            SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                debug!("disconnected from peer {}: {:?}", peer_id, cause);
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                warn!("connecting to peer {:?}: {}", peer_id, error);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Transactions that are invalid regardless of state are rejected, which
+/// penalizes the peer relaying them. Those that may just be stale or ahead
+/// of this node's state are ignored, i.e. dropped without a penalty.
+fn acceptance(e: &anyhow::Error) -> MessageAcceptance {
+    match e.downcast_ref::<TxError>() {
+        Some(
+            TxError::InvalidSignature { .. }
+            | TxError::ZeroAmount
+            | TxError::PayloadTooLarge { .. }
+            | TxError::WrongChainId { .. },
+        ) => MessageAcceptance::Reject,
+        _ => MessageAcceptance::Ignore,
+    }
+}
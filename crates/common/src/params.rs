@@ -0,0 +1,162 @@
+//! Chain parameters that can change while the rollup runs. The governance
+//! account, see [`crate::node::Config::governance_vk`], schedules new values
+//! with a [`TransactionType::ParamChange`](crate::tx::TransactionType) for a
+//! future Celestia height. The schedule is kept in the state under a
+//! reserved key, so every node switches at the same height, and parameters
+//! governance never set fall back to the node's configuration.
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+use jmt::KeyHash;
+use prism_common::keys::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    encoding::{Decode, Decoder, Encode, Encoder},
+    tree::Hasher,
+};
+
+/// The parameters a parameter change can set, unset ones are left as they
+/// are.
+#[derive(Args, Clone, Serialize, Deserialize, ToSchema, Default, Debug, PartialEq, Eq)]
+pub struct LiveParams {
+    /// Overrides the minimum gas price transactions must pay to be queued
+    #[arg(long)]
+    pub min_gas_price: Option<u64>,
+    /// The maximum number of transactions in a sequencer batch. Larger
+    /// batches are skipped
+    #[arg(long)]
+    pub max_batch_txs: Option<u32>,
+    /// Overrides the key sequencer batches and state diffs must be signed
+    /// with
+    #[arg(long, value_parser = crate::tx::parse_verifying_key)]
+    #[schema(value_type = Option<String>)]
+    pub sequencer_vk: Option<VerifyingKey>,
+}
+
+impl LiveParams {
+    /// Returns whether no parameter is set.
+    pub fn is_empty(&self) -> bool {
+        self.min_gas_price.is_none() && self.max_batch_txs.is_none() && self.sequencer_vk.is_none()
+    }
+
+    /// Overwrites the parameters `change` sets.
+    fn apply(&mut self, change: &LiveParams) {
+        if let Some(min_gas_price) = change.min_gas_price {
+            self.min_gas_price = Some(min_gas_price);
+        }
+        if let Some(max_batch_txs) = change.max_batch_txs {
+            self.max_batch_txs = Some(max_batch_txs);
+        }
+        if let Some(sequencer_vk) = &change.sequencer_vk {
+            self.sequencer_vk = Some(sequencer_vk.clone());
+        }
+    }
+}
+
+impl Encode for LiveParams {
+    fn encode(&self, enc: &mut Encoder) {
+        match self.min_gas_price {
+            Some(min_gas_price) => {
+                enc.put_u8(1);
+                enc.put_u64(min_gas_price);
+            }
+            None => enc.put_u8(0),
+        }
+        match self.max_batch_txs {
+            Some(max_batch_txs) => {
+                enc.put_u8(1);
+                enc.put_u32(max_batch_txs);
+            }
+            None => enc.put_u8(0),
+        }
+        match &self.sequencer_vk {
+            Some(sequencer_vk) => {
+                enc.put_u8(1);
+                sequencer_vk.encode(enc);
+            }
+            None => enc.put_u8(0),
+        }
+    }
+}
+
+impl Decode for LiveParams {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        let min_gas_price = match dec.u8()? {
+            0 => None,
+            1 => Some(dec.u64()?),
+            tag => return Err(anyhow!("Invalid min gas price tag {}", tag)),
+        };
+        let max_batch_txs = match dec.u8()? {
+            0 => None,
+            1 => Some(dec.u32()?),
+            tag => return Err(anyhow!("Invalid max batch size tag {}", tag)),
+        };
+        let sequencer_vk = match dec.u8()? {
+            0 => None,
+            1 => Some(VerifyingKey::decode(dec)?),
+            tag => return Err(anyhow!("Invalid sequencer key tag {}", tag)),
+        };
+        Ok(LiveParams {
+            min_gas_price,
+            max_batch_txs,
+            sequencer_vk,
+        })
+    }
+}
+
+/// A parameter change taking effect at `activation_height`.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq, Eq)]
+pub struct ScheduledChange {
+    pub activation_height: u64,
+    pub params: LiveParams,
+}
+
+/// The parameters stored in the state: the ones in effect and the changes
+/// still to come.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Default, Debug, PartialEq, Eq)]
+pub struct ParamSchedule {
+    /// The parameters set by the changes activated so far
+    pub active: LiveParams,
+    /// Ordered by activation height, changes at the same height in the order
+    /// they were executed
+    pub scheduled: Vec<ScheduledChange>,
+}
+
+impl ParamSchedule {
+    /// Returns the parameters in effect at Celestia height `da_height`.
+    pub fn at(&self, da_height: u64) -> LiveParams {
+        let mut params = self.active.clone();
+        for change in &self.scheduled {
+            if change.activation_height > da_height {
+                break;
+            }
+            params.apply(&change.params);
+        }
+        params
+    }
+
+    /// Schedules `params` to take effect at `activation_height`, after
+    /// folding the changes active at `da_height` into the active parameters.
+    pub(crate) fn schedule(&mut self, da_height: u64, activation_height: u64, params: LiveParams) {
+        self.active = self.at(da_height);
+        self.scheduled
+            .retain(|change| change.activation_height > da_height);
+        let position = self
+            .scheduled
+            .partition_point(|change| change.activation_height <= activation_height);
+        self.scheduled.insert(
+            position,
+            ScheduledChange {
+                activation_height,
+                params,
+            },
+        );
+    }
+}
+
+/// Returns the reserved key the [`ParamSchedule`] is stored under.
+pub(crate) fn params_key() -> KeyHash {
+    KeyHash::with::<Hasher>(b"params")
+}
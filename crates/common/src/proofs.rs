@@ -1,26 +1,295 @@
-use anyhow::{Context, Result};
-use jmt::{proof::SparseMerkleProof, KeyHash};
+use anyhow::{anyhow, Context, Result};
+use celestia_types::Blob;
+use jmt::{
+    proof::{SparseMerkleProof, UpdateMerkleProof},
+    KeyHash,
+};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    state::Account,
+    journal::Journal,
+    state::{da_height_key, Account, AccountData},
+    stf::{self, AccountWitness, StfPublicValues, StfWitness},
     tree::{Digest, Hasher},
-    tx::Transaction,
+    tx::{Transaction, TransactionType},
 };
 
 /// Represents a contiguous stream of [`Proof`]s leading from [`Batch::prev_root`] to [`Batch::new_root`].
 /// Used as the input to the circuit.
-pub struct Batch {
+#[derive(Serialize, Deserialize)]
+pub struct Batch<D = ()> {
+    pub prev_root: Digest,
+    pub new_root: Digest,
+
+    pub proofs: Vec<Proof<D>>,
+
+    /// The first and last Celestia height the batches were read from
+    pub da_height_range: (u64, u64),
+    /// The blob commitments of the batches the proofs were generated for,
+    /// committed to the [`crate::journal::PublicValues`] for verifiers to
+    /// match against the DA layer
+    pub batch_commitments: Vec<Digest>,
+}
+
+impl<D: AccountData> Batch<D> {
+    /// Verifies that the proofs form a valid chain of state transitions from
+    /// [`Batch::prev_root`] to [`Batch::new_root`]. This is the logic run by
+    /// the guest programs.
+    pub fn verify(&self) -> Result<()> {
+        let mut current = self.prev_root;
+        for (i, proof) in self.proofs.iter().enumerate() {
+            let (old_root, new_root) = proof.roots();
+            if old_root != current {
+                return Err(anyhow!("Proof {} does not start at the current root", i));
+            }
+            proof
+                .verify()
+                .with_context(|| format!("Invalid proof {}", i))?;
+            current = new_root;
+        }
+
+        if current != self.new_root {
+            return Err(anyhow!("Proofs do not end at the new root"));
+        }
+        Ok(())
+    }
+}
+
+/// Read by the guest programs first, selecting what they prove.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuestMode {
+    /// Verify the merkle proofs of a [`Batch`], read after an optional
+    /// [`Recursion`].
+    Batch,
+    /// Re-execute the transactions of a [`StfWitness`], read next.
+    Stf,
+}
+
+/// Read by the guest programs before the [`Batch`]. If set, the guest runs in
+/// recursive mode: it verifies the previous epoch's proof and commits
+/// [`RecursivePublicValues`] instead of the batch roots.
+#[derive(Serialize, Deserialize)]
+pub struct Recursion {
+    /// The id of the guest program itself, which the previous proof must be
+    /// for. Guests can't know their own id, so it is committed and checked
+    /// by the verifier.
+    pub program_id: [u32; 8],
+    /// The public values of the previous epoch's proof, `None` for the first
+    /// epoch after genesis.
+    pub prev: Option<RecursivePublicValues>,
+}
+
+impl Recursion {
+    /// Verifies `batch` and checks that it continues the chain attested to
+    /// by the previous proof, returning the public values to commit.
+    /// Verifying the previous proof itself is zkVM-specific and left to the
+    /// guest.
+    pub fn next(&self, batch: &Batch) -> Result<RecursivePublicValues> {
+        batch.verify()?;
+        let genesis_root = match &self.prev {
+            None => batch.prev_root,
+            Some(prev) => {
+                if prev.program_id != self.program_id {
+                    return Err(anyhow!("Previous proof is for a different program"));
+                }
+                if prev.root != batch.prev_root {
+                    return Err(anyhow!("Batch does not start at the previous proof's root"));
+                }
+                prev.genesis_root
+            }
+        };
+        Ok(RecursivePublicValues {
+            genesis_root,
+            root: batch.new_root,
+            program_id: self.program_id,
+            da_height_range: batch.da_height_range,
+            batch_commitments: batch.batch_commitments.clone(),
+        })
+    }
+}
+
+/// The public values of a recursive proof, attesting that the state
+/// transitioned from `genesis_root` to `root` through valid epochs only.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecursivePublicValues {
+    pub genesis_root: Digest,
+    pub root: Digest,
+    pub program_id: [u32; 8],
+    /// The first and last Celestia height the latest epoch's batches were
+    /// read from. Earlier epochs' are attested to by the proofs the chain
+    /// verified. `(0, 0)` for legacy journals.
+    pub da_height_range: (u64, u64),
+    /// The blob commitments of the latest epoch's batches, in execution
+    /// order. Empty for legacy journals.
+    pub batch_commitments: Vec<Digest>,
+}
+
+impl RecursivePublicValues {
+    /// Encodes the values as committed by the guests, see
+    /// [`Journal::to_bytes`].
+    pub fn to_journal(&self) -> Vec<u8> {
+        Journal::Recursive(self.clone()).to_bytes()
+    }
+
+    /// Decodes the journal of a recursive proof, of any supported version.
+    pub fn from_journal(bytes: &[u8]) -> Result<Self> {
+        match Journal::from_bytes(bytes)? {
+            Journal::Recursive(values) => Ok(values),
+            _ => Err(anyhow!("Not the journal of a recursive proof")),
+        }
+    }
+}
+
+/// A zkVM that can prove the validity of a [`Batch`]. Implementations live
+/// in the `shard-prover` crate behind a feature flag per zkVM, so the epoch
+/// pipeline does not depend on a specific one.
+pub trait ProverBackend: Send + Sync {
+    /// Generates a proof that the state transitioned from
+    /// [`Batch::prev_root`] to [`Batch::new_root`] in `epoch`.
+    fn prove(&self, epoch: u64, batch: &Batch) -> Result<EpochProof>;
+
+    /// Generates a recursive proof for `epoch` that also verifies `prev`,
+    /// the recursive proof of the previous epoch. The result attests to the
+    /// whole chain from genesis, so verifiers only need the latest proof.
+    /// `prev` is `None` for the first epoch, whose batch must start at the
+    /// genesis root.
+    fn prove_recursive(
+        &self,
+        epoch: u64,
+        batch: &Batch,
+        prev: Option<&EpochProof>,
+    ) -> Result<EpochProof>;
+
+    /// Generates a proof for `epoch` by re-executing its transactions, see
+    /// [`crate::stf`]. The public values are [`StfPublicValues`].
+    fn prove_stf(&self, _epoch: u64, _witness: &StfWitness) -> Result<EpochProof> {
+        Err(anyhow!("STF proofs require a zkVM backend"))
+    }
+
+    /// Returns whether the proof is valid and attests to its claimed roots.
+    /// Recursive proofs must also be for this backend's program.
+    fn verify(&self, proof: &EpochProof) -> Result<bool>;
+}
+
+/// A validity proof for an epoch, as posted to the proof namespace. The
+/// proof bytes are specific to the zkVM that generated them. The public
+/// values of plain proofs are a [`crate::journal::PublicValues`] journal.
+///
+/// For recursive proofs, `prev_root` is the genesis root and the public
+/// values are [`RecursivePublicValues`].
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct EpochProof {
+    pub epoch: u64,
     pub prev_root: Digest,
     pub new_root: Digest,
 
-    pub proofs: Vec<Proof>,
+    pub proof: Vec<u8>,
+    pub public_values: Vec<u8>,
+}
+
+impl EpochProof {
+    pub fn is_recursive(&self) -> bool {
+        !self.is_optimistic() && RecursivePublicValues::from_journal(&self.public_values).is_ok()
+    }
+
+    /// Whether the epoch was proven by re-execution, see [`crate::stf`].
+    pub fn is_stf(&self) -> bool {
+        !self.is_optimistic() && StfPublicValues::from_journal(&self.public_values).is_ok()
+    }
+
+    /// Whether this is a root claim of the optimistic mode rather than a
+    /// validity proof, see [`crate::fraud`].
+    pub fn is_optimistic(&self) -> bool {
+        self.proof.is_empty()
+    }
+
+    /// The first and last Celestia height the epoch's batches were read
+    /// from, as committed to by the proof's journal. `None` for optimistic
+    /// claims, STF proofs and legacy journals, which don't commit to them.
+    pub fn da_height_range(&self) -> Option<(u64, u64)> {
+        if self.is_optimistic() {
+            return None;
+        }
+        let range = match Journal::from_bytes(&self.public_values).ok()? {
+            Journal::Epoch(values) => values.da_height_range,
+            Journal::Recursive(values) => values.da_height_range,
+            Journal::Stf(_) => return None,
+        };
+        (range != (0, 0)).then_some(range)
+    }
+}
+
+impl TryFrom<&Blob> for EpochProof {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &Blob) -> Result<Self, Self::Error> {
+        bincode::deserialize(&value.data)
+            .context(format!("Failed to decode blob into EpochProof: {value:?}"))
+    }
 }
 
-pub enum Proof {
+/// The proof of one state transition, with `D` the application-defined data
+/// of the accounts, see [`AccountData`].
+#[derive(Serialize, Deserialize)]
+pub enum Proof<D = ()> {
     Insert(InsertProof),
-    Update(UpdateProof),
+    Update(UpdateProof<D>),
+    Delete(DeleteProof<D>),
+    Transfer(TransferProof<D>),
+    DaHeight(DaHeightProof),
+}
+
+impl<D: AccountData> Proof<D> {
+    /// Returns the roots before and after the proven transition.
+    pub fn roots(&self) -> (Digest, Digest) {
+        match self {
+            Proof::Insert(p) => (p.old_root, p.new_root),
+            Proof::Update(p) => (p.old_root, p.new_root),
+            Proof::Delete(p) => (p.old_root, p.new_root),
+            Proof::Transfer(p) => (p.old_root, p.new_root),
+            Proof::DaHeight(p) => (p.old_root, p.new_root),
+        }
+    }
+
+    /// Returns the transaction that caused the transition, `None` for the
+    /// DA height watermark.
+    pub fn tx(&self) -> Option<&Transaction> {
+        match self {
+            Proof::Insert(p) => Some(&p.tx),
+            Proof::Update(p) => Some(&p.tx),
+            Proof::Delete(p) => Some(&p.tx),
+            Proof::Transfer(p) => Some(&p.tx),
+            Proof::DaHeight(_) => None,
+        }
+    }
+
+    /// Identifies the transition: the hash of its transaction, or of the DA
+    /// height it records.
+    pub fn id(&self) -> Digest {
+        match self {
+            Proof::DaHeight(p) => Digest::hash_items(&[
+                b"da_height:".as_slice(),
+                p.da_height.to_be_bytes().as_slice(),
+            ]),
+            _ => self
+                .tx()
+                .expect("transaction proofs have a transaction")
+                .hash(),
+        }
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        match self {
+            Proof::Insert(p) => p.verify::<D>(),
+            Proof::Update(p) => p.verify(),
+            Proof::Delete(p) => p.verify(),
+            Proof::Transfer(p) => p.verify(),
+            Proof::DaHeight(p) => p.verify(),
+        }
+    }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct InsertProof {
     /// Proof that the key does not already exist in the tree (i.e. it's not overwriting an existing key)
     pub non_membership_proof: SparseMerkleProof<Hasher>,
@@ -30,12 +299,14 @@ pub struct InsertProof {
     pub membership_proof: SparseMerkleProof<Hasher>,
     pub new_root: Digest,
 
-    /// The transaction matching the new account (vk = key)
+    /// The transaction matching the new account (vk = key), signed by the
+    /// account's own key
     pub tx: Transaction,
 }
 
 impl InsertProof {
-    pub fn verify(&self) -> Result<()> {
+    /// Verifies the insertion of an account with data of type `D`.
+    pub fn verify<D: AccountData>(&self) -> Result<()> {
         let key = KeyHash::with::<Hasher>(self.tx.vk.as_bytes());
 
         self.non_membership_proof
@@ -43,7 +314,10 @@ impl InsertProof {
             .context("Invalid NonMembershipProof")?;
 
         // verify that the account is correct
-        let mut new_account = Account::default();
+        let mut new_account = Account::<D>::default();
+        new_account
+            .authorize(&self.tx)
+            .context("Transaction is not signed by the account's key")?;
         new_account
             .apply_tx(&self.tx)
             .context("Transaction could not be applied to account")?;
@@ -58,21 +332,23 @@ impl InsertProof {
     }
 }
 
-pub struct UpdateProof {
+#[derive(Serialize, Deserialize)]
+pub struct UpdateProof<D = ()> {
     /// Proof that [`old_account`] account is in the tree under [`old_root`]
     pub old_membership_proof: SparseMerkleProof<Hasher>,
     pub old_root: Digest,
-    pub old_account: Account,
+    pub old_account: Account<D>,
 
     /// Proof that [`new_account`] account is now in the tree under [`new_root`]
     pub membership_proof: SparseMerkleProof<Hasher>,
     pub new_root: Digest,
 
-    /// The transaction that verifies the state transition from [`old_account`].
+    /// The transaction that verifies the state transition from [`old_account`],
+    /// signed by one of its authorized keys.
     pub tx: Transaction,
 }
 
-impl UpdateProof {
+impl<D: AccountData> UpdateProof<D> {
     pub fn verify(&self) -> Result<()> {
         let key = KeyHash::with::<Hasher>(self.tx.vk.as_bytes());
         let old_value = bincode::serialize(&self.old_account)?;
@@ -80,6 +356,9 @@ impl UpdateProof {
             .verify_existence(self.old_root.into(), key, old_value)
             .context("Invalid OldMembershipProof")?;
 
+        self.old_account
+            .authorize(&self.tx)
+            .context("Transaction is not signed by a key authorized on the account")?;
         let mut new_account = self.old_account.clone();
         new_account
             .apply_tx(&self.tx)
@@ -93,3 +372,136 @@ impl UpdateProof {
         Ok(())
     }
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteProof<D = ()> {
+    /// Proof that [`old_account`] account is in the tree under [`old_root`]
+    pub old_membership_proof: SparseMerkleProof<Hasher>,
+    pub old_root: Digest,
+    pub old_account: Account<D>,
+
+    /// Proof that the account no longer exists under [`new_root`]
+    pub non_membership_proof: SparseMerkleProof<Hasher>,
+    pub new_root: Digest,
+
+    /// The [`TransactionType::CloseAccount`] transaction that removed the
+    /// account, signed by one of its authorized keys.
+    pub tx: Transaction,
+}
+
+impl<D: AccountData> DeleteProof<D> {
+    pub fn verify(&self) -> Result<()> {
+        if !matches!(self.tx.tx_type, TransactionType::CloseAccount) {
+            return Err(anyhow!("Only CloseAccount transactions remove accounts"));
+        }
+        let key = KeyHash::with::<Hasher>(self.tx.vk.as_bytes());
+        let old_value = bincode::serialize(&self.old_account)?;
+        self.old_membership_proof
+            .verify_existence(self.old_root.into(), key, old_value)
+            .context("Invalid OldMembershipProof")?;
+
+        self.old_account
+            .authorize(&self.tx)
+            .context("Transaction is not signed by a key authorized on the account")?;
+        let mut closed_account = self.old_account.clone();
+        closed_account
+            .apply_tx(&self.tx)
+            .context("Transaction could not be applied to account")?;
+        if closed_account.balance() != 0 {
+            return Err(anyhow!("Closed account still holds a balance"));
+        }
+
+        self.non_membership_proof
+            .verify_nonexistence(self.new_root.into(), key)
+            .context("Invalid NonMembershipProof")?;
+
+        Ok(())
+    }
+}
+
+/// Proves a transfer to another account, which writes the recipient's
+/// account besides the sender's: both accounts under [`old_root`], and the
+/// update writing them once the transfer is re-executed on them, as in
+/// [`crate::stf`].
+#[derive(Serialize, Deserialize)]
+pub struct TransferProof<D = ()> {
+    pub old_root: Digest,
+    /// The sender's and the recipient's account, with proofs against
+    /// [`old_root`]
+    pub accounts: Vec<AccountWitness<D>>,
+
+    /// Proof that writing the changed accounts to the tree at [`old_root`]
+    /// results in [`new_root`]
+    pub update_proof: UpdateMerkleProof<Hasher>,
+    pub new_root: Digest,
+
+    /// The [`TransactionType::Transfer`] transaction, signed by a key
+    /// authorized on the sender's account
+    pub tx: Transaction,
+}
+
+impl<D: AccountData> TransferProof<D> {
+    pub fn verify(&self) -> Result<()> {
+        if !matches!(self.tx.tx_type, TransactionType::Transfer { .. }) {
+            return Err(anyhow!("Only transfers write a recipient's account"));
+        }
+        let mut accounts = stf::witnessed_accounts(self.old_root, &self.accounts)?;
+        let initial = accounts.clone();
+        stf::execute_tx(&mut accounts, &self.tx, None)
+            .context("Transaction could not be applied to the accounts")?;
+        self.update_proof
+            .verify_update(
+                self.old_root.into(),
+                self.new_root.into(),
+                stf::changed_accounts(&initial, &accounts)?,
+            )
+            .map_err(|e| anyhow!("Invalid update proof: {}", e))?;
+        Ok(())
+    }
+}
+
+/// Proves that the DA height watermark advanced to `da_height`, see
+/// [`crate::state::State::set_last_da_height`], binding the epoch to the
+/// order of the DA layer.
+#[derive(Serialize, Deserialize)]
+pub struct DaHeightProof {
+    /// Proof that the watermark is `old_height` under `old_root`, or absent
+    pub old_proof: SparseMerkleProof<Hasher>,
+    pub old_root: Digest,
+    pub old_height: Option<u64>,
+
+    /// Proof that the watermark is `da_height` under `new_root`
+    pub membership_proof: SparseMerkleProof<Hasher>,
+    pub new_root: Digest,
+    pub da_height: u64,
+}
+
+impl DaHeightProof {
+    pub fn verify(&self) -> Result<()> {
+        let key = da_height_key();
+        match self.old_height {
+            Some(old_height) => {
+                if self.da_height <= old_height {
+                    return Err(anyhow!("DA height watermark doesn't advance"));
+                }
+                self.old_proof
+                    .verify_existence(self.old_root.into(), key, bincode::serialize(&old_height)?)
+                    .context("Invalid OldMembershipProof")?;
+            }
+            None => self
+                .old_proof
+                .verify_nonexistence(self.old_root.into(), key)
+                .context("Invalid NonMembershipProof")?,
+        }
+
+        self.membership_proof
+            .verify_existence(
+                self.new_root.into(),
+                key,
+                bincode::serialize(&self.da_height)?,
+            )
+            .context("Invalid MembershipProof")?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! Coordinating hard forks. Every batch header carries the protocol version
+//! the sequencer executed it under, and the [`ProtocolSchedule`] all nodes
+//! of a chain share says which version is active from which Celestia
+//! height on. Nodes skip sequencer batches of any other version, so a batch
+//! relying on new rules, e.g. a new transaction type, is never executed
+//! under the old ones.
+//!
+//! An upgrade is rolled out by releasing nodes implementing the new version
+//! and adding it to the schedule at a height far enough ahead for operators
+//! to upgrade. Nodes refuse to start with a schedule activating a version
+//! they don't implement.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// The highest protocol version this node implements. Version 0 is the
+/// protocol of batches from before protocol versions, and stays active until
+/// the schedule activates another one.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Activates protocol `version` from the Celestia height `da_height` on.
+/// Written as `<version>@<celestia height>`.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ProtocolUpgrade {
+    pub version: u32,
+    pub da_height: u64,
+}
+
+impl FromStr for ProtocolUpgrade {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (version, da_height) = s
+            .split_once('@')
+            .ok_or_else(|| anyhow!("Expected <version>@<celestia height>"))?;
+        Ok(ProtocolUpgrade {
+            version: version.parse().context("Invalid protocol version")?,
+            da_height: da_height.parse().context("Invalid celestia height")?,
+        })
+    }
+}
+
+impl fmt::Display for ProtocolUpgrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.version, self.da_height)
+    }
+}
+
+/// The protocol upgrades of a chain, ordered by activation height.
+#[derive(Clone, Serialize, Deserialize, Default, Debug, PartialEq, Eq)]
+pub struct ProtocolSchedule(Vec<ProtocolUpgrade>);
+
+impl ProtocolSchedule {
+    /// Checks that each upgrade activates a higher version than the one
+    /// before it, at a later height, and that this node implements every
+    /// version.
+    pub fn new(mut upgrades: Vec<ProtocolUpgrade>) -> Result<Self> {
+        upgrades.sort_by_key(|upgrade| upgrade.da_height);
+        let mut active = 0;
+        for (i, upgrade) in upgrades.iter().enumerate() {
+            if upgrade.version > PROTOCOL_VERSION {
+                return Err(anyhow!(
+                    "Protocol version {} activates at celestia height {}, but this node only implements up to version {}",
+                    upgrade.version,
+                    upgrade.da_height,
+                    PROTOCOL_VERSION
+                ));
+            }
+            if upgrade.version <= active
+                || (i > 0 && upgrade.da_height == upgrades[i - 1].da_height)
+            {
+                return Err(anyhow!(
+                    "Protocol upgrade {} doesn't follow the upgrade to version {}",
+                    upgrade,
+                    active
+                ));
+            }
+            active = upgrade.version;
+        }
+        Ok(ProtocolSchedule(upgrades))
+    }
+
+    /// Parses upgrades written as `<version>@<celestia height>`.
+    pub fn parse(upgrades: &[String]) -> Result<Self> {
+        Self::new(
+            upgrades
+                .iter()
+                .map(|upgrade| upgrade.parse())
+                .collect::<Result<_>>()?,
+        )
+    }
+
+    /// Returns the protocol version active at Celestia height `da_height`.
+    pub fn version_at(&self, da_height: u64) -> u32 {
+        self.0
+            .iter()
+            .take_while(|upgrade| upgrade.da_height <= da_height)
+            .last()
+            .map_or(0, |upgrade| upgrade.version)
+    }
+
+    /// Fails unless `version` is the one active at `da_height`.
+    pub fn check(&self, version: u32, da_height: u64) -> Result<()> {
+        let expected = self.version_at(da_height);
+        if version != expected {
+            return Err(anyhow!(
+                "Protocol version {} is not the active version {} at celestia height {}",
+                version,
+                expected,
+                da_height
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrade_round_trips_through_its_string_form() {
+        let upgrade: ProtocolUpgrade = "1@100".parse().unwrap();
+        assert_eq!(
+            upgrade,
+            ProtocolUpgrade {
+                version: 1,
+                da_height: 100
+            }
+        );
+        assert_eq!(upgrade.to_string(), "1@100");
+        assert!("1".parse::<ProtocolUpgrade>().is_err());
+        assert!("x@100".parse::<ProtocolUpgrade>().is_err());
+    }
+
+    #[test]
+    fn version_is_active_from_its_height() {
+        let schedule = ProtocolSchedule::parse(&["1@100".to_string()]).unwrap();
+        assert_eq!(schedule.version_at(99), 0);
+        assert_eq!(schedule.version_at(100), 1);
+        assert_eq!(schedule.version_at(u64::MAX), 1);
+
+        schedule.check(0, 99).unwrap();
+        assert!(schedule.check(1, 99).is_err());
+        schedule.check(1, 100).unwrap();
+        assert!(schedule.check(0, 100).is_err());
+        assert_eq!(ProtocolSchedule::default().version_at(100), 0);
+    }
+
+    #[test]
+    fn schedule_rejects_unknown_and_non_increasing_versions() {
+        let unknown = format!("{}@100", PROTOCOL_VERSION + 1);
+        assert!(ProtocolSchedule::parse(&[unknown]).is_err());
+        assert!(ProtocolSchedule::parse(&["0@100".to_string()]).is_err());
+        assert!(ProtocolSchedule::parse(&["1@100".to_string(), "1@200".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::Result;
+use prism_common::keys::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{params::LiveParams, status::TxStatus, storage::NodeStore, tree::Digest};
+
+/// A state change made by an executed transaction, recorded in its
+/// [`Receipt`] for indexers and frontends.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TxEvent {
+    /// The first transaction of `vk` or a transfer to it created its account.
+    AccountCreated {
+        #[schema(value_type = String)]
+        vk: VerifyingKey,
+    },
+    Transfer {
+        #[schema(value_type = String)]
+        from: VerifyingKey,
+        #[schema(value_type = String)]
+        to: VerifyingKey,
+        amount: u64,
+    },
+    Mint {
+        #[schema(value_type = String)]
+        to: VerifyingKey,
+        amount: u64,
+    },
+    Burn {
+        #[schema(value_type = String)]
+        from: VerifyingKey,
+        amount: u64,
+    },
+    KeyAdded {
+        #[schema(value_type = String)]
+        account: VerifyingKey,
+        #[schema(value_type = String)]
+        key: VerifyingKey,
+    },
+    KeyRevoked {
+        #[schema(value_type = String)]
+        account: VerifyingKey,
+        #[schema(value_type = String)]
+        key: VerifyingKey,
+    },
+    AccountClosed {
+        #[schema(value_type = String)]
+        vk: VerifyingKey,
+    },
+    /// The account now requires `threshold` signatures of `keys`.
+    MultisigSet {
+        #[schema(value_type = String)]
+        account: VerifyingKey,
+        #[schema(value_type = Vec<String>)]
+        keys: Vec<VerifyingKey>,
+        threshold: u32,
+    },
+    /// A contract was deployed at the hex encoded `address`.
+    ContractDeployed { address: String },
+    /// The contract at the hex encoded `contract` was called successfully.
+    ContractCalled { contract: String },
+    FeePaid {
+        #[schema(value_type = String)]
+        payer: VerifyingKey,
+        amount: u64,
+    },
+    /// The bridged deposit with the hex encoded `id` was credited to `to`.
+    Deposit {
+        id: String,
+        #[schema(value_type = String)]
+        to: VerifyingKey,
+        amount: u64,
+    },
+    /// The transaction was included before its execution height and is
+    /// queued until then. Its effects are recorded in the receipt it gets
+    /// once executed.
+    Scheduled { execute_at_da_height: u64 },
+    /// Governance scheduled `params` to take effect at the Celestia height
+    /// `activation_height`.
+    ParamsScheduled {
+        activation_height: u64,
+        params: LiveParams,
+    },
+}
+
+/// The outcome of executing a transaction.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq, Eq)]
+pub struct Receipt {
+    /// The hex encoded transaction hash
+    pub tx_hash: String,
+    /// Either [`TxStatus::Executed`] or [`TxStatus::Failed`]
+    pub status: TxStatus,
+    /// Zero for failed transactions, which don't pay their fee
+    pub gas_used: u64,
+    /// Empty for failed transactions
+    pub events: Vec<TxEvent>,
+}
+
+fn receipt_key(tx_hash: &Digest) -> String {
+    format!("receipt:{}", hex::encode(tx_hash.0))
+}
+
+pub fn get_receipt<S: NodeStore + ?Sized>(store: &S, tx_hash: &Digest) -> Result<Option<Receipt>> {
+    match store.get_metadata(&receipt_key(tx_hash))? {
+        Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn put_receipt<S: NodeStore + ?Sized>(
+    store: &S,
+    tx_hash: &Digest,
+    receipt: &Receipt,
+) -> Result<()> {
+    store.put_metadata(&receipt_key(tx_hash), &bincode::serialize(receipt)?)
+}
@@ -0,0 +1,191 @@
+//! Settlement of state roots on an EVM chain.
+//!
+//! [`export_contract`] writes a `ShardSettlement` contract that tracks the
+//! shard's state root and only advances it with valid Groth16 wrapped SP1
+//! epoch proofs, checked through the SP1 verifier gateway. With the
+//! `settlement` feature, the node runs a relayer submitting queued epoch
+//! proofs to the deployed contract.
+
+use anyhow::{anyhow, Context, Result};
+use std::{fs, path::Path};
+
+/// The environment variable holding the hex encoded key the relayer signs
+/// Ethereum transactions with. Kept out of the config file on purpose.
+pub const PRIVATE_KEY_ENV: &str = "SETTLEMENT_PRIVATE_KEY";
+
+const PROGRAM_VKEY_PLACEHOLDER: &str = "{{PROGRAM_VKEY}}";
+
+/// The settlement contract, with [`PROGRAM_VKEY_PLACEHOLDER`] standing in
+/// for the guest program's verification key.
+const SETTLEMENT_CONTRACT: &str = r#"// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+/// @notice The SP1 verifier gateway, see
+/// https://github.com/succinctlabs/sp1-contracts for deployments.
+interface ISP1Verifier {
+    function verifyProof(
+        bytes32 programVKey,
+        bytes calldata publicValues,
+        bytes calldata proofBytes
+    ) external view;
+}
+
+/// @title ShardSettlement
+/// @notice Tracks the state root of a zk-shard rollup. The root only advances
+/// with Groth16 wrapped SP1 proofs of epochs starting at the current root.
+contract ShardSettlement {
+    /// @notice The verification key of the shard's SP1 guest program.
+    bytes32 public constant PROGRAM_VKEY = {{PROGRAM_VKEY}};
+    /// @notice The version of the guest's public values journal.
+    uint8 public constant JOURNAL_VERSION = 1;
+
+    ISP1Verifier public immutable verifier;
+    bytes32 public stateRoot;
+    uint64 public latestEpoch;
+
+    event EpochSettled(uint64 indexed epoch, bytes32 prevRoot, bytes32 newRoot);
+
+    constructor(address _verifier, bytes32 genesisRoot) {
+        verifier = ISP1Verifier(_verifier);
+        stateRoot = genesisRoot;
+    }
+
+    /// @param publicValues The journal committed by the guest: the version
+    /// byte, the previous and the new state root, then the DA height range
+    /// and batch commitments, which aren't checked here.
+    function submitEpoch(
+        uint64 epoch,
+        bytes calldata publicValues,
+        bytes calldata proof
+    ) external {
+        require(publicValues.length >= 85, "invalid public values");
+        require(uint8(publicValues[0]) == JOURNAL_VERSION, "unsupported journal version");
+        bytes32 prevRoot = bytes32(publicValues[1:33]);
+        bytes32 newRoot = bytes32(publicValues[33:65]);
+        require(prevRoot == stateRoot, "proof does not extend the settled root");
+
+        verifier.verifyProof(PROGRAM_VKEY, publicValues, proof);
+
+        stateRoot = newRoot;
+        latestEpoch = epoch;
+        emit EpochSettled(epoch, prevRoot, newRoot);
+    }
+}
+"#;
+
+/// Writes `ShardSettlement.sol` and `program_vkey.txt` for the guest program
+/// with verification key `program_vkey` (0x prefixed hex, as returned by
+/// `Sp1Prover::program_vkey`) to `dir`.
+pub fn export_contract(dir: &Path, program_vkey: &str) -> Result<()> {
+    let digits = program_vkey
+        .strip_prefix("0x")
+        .ok_or_else(|| anyhow!("Program verification key must be 0x prefixed"))?;
+    if digits.len() != 64 || hex::decode(digits).is_err() {
+        return Err(anyhow!(
+            "Program verification key must be 32 hex encoded bytes"
+        ));
+    }
+
+    fs::create_dir_all(dir)?;
+    let contract = SETTLEMENT_CONTRACT.replace(PROGRAM_VKEY_PLACEHOLDER, program_vkey);
+    fs::write(dir.join("ShardSettlement.sol"), contract)
+        .context("Failed to write settlement contract")?;
+    fs::write(dir.join("program_vkey.txt"), format!("{}\n", program_vkey))
+        .context("Failed to write program verification key")?;
+    Ok(())
+}
+
+#[cfg(feature = "settlement")]
+mod relayer {
+    use alloy::{
+        network::EthereumWallet,
+        primitives::{Address, Bytes, FixedBytes},
+        providers::ProviderBuilder,
+        signers::local::PrivateKeySigner,
+        sol,
+    };
+    use anyhow::{anyhow, Context, Result};
+
+    use super::PRIVATE_KEY_ENV;
+    use crate::proofs::EpochProof;
+
+    sol! {
+        #[sol(rpc)]
+        interface IShardSettlement {
+            function stateRoot() external view returns (bytes32);
+            function submitEpoch(uint64 epoch, bytes calldata publicValues, bytes calldata proof) external;
+        }
+    }
+
+    /// Submits epoch proofs to a deployed `ShardSettlement` contract.
+    pub struct Relayer {
+        rpc_url: reqwest::Url,
+        contract: Address,
+        wallet: EthereumWallet,
+    }
+
+    impl Relayer {
+        /// Creates a relayer signing with the key in [`PRIVATE_KEY_ENV`].
+        pub fn new(rpc_url: &str, contract: &str) -> Result<Self> {
+            let key = std::env::var(PRIVATE_KEY_ENV)
+                .with_context(|| format!("Settlement requires {} to be set", PRIVATE_KEY_ENV))?;
+            let signer: PrivateKeySigner = key.trim().parse().context("Invalid settlement key")?;
+            Ok(Relayer {
+                rpc_url: rpc_url.parse().context("Invalid settlement RPC URL")?,
+                contract: contract
+                    .parse()
+                    .context("Invalid settlement contract address")?,
+                wallet: EthereumWallet::from(signer),
+            })
+        }
+
+        /// Submits `proof`, which must be Groth16 wrapped, and waits for the
+        /// transaction to be included. Proofs the contract has already moved
+        /// past are skipped, so proofs can be resubmitted after a restart.
+        pub async fn submit(&self, proof: &EpochProof) -> Result<()> {
+            let provider = ProviderBuilder::new()
+                .with_recommended_fillers()
+                .wallet(self.wallet.clone())
+                .on_http(self.rpc_url.clone());
+            let contract = IShardSettlement::new(self.contract, &provider);
+
+            let settled_root = contract.stateRoot().call().await?._0;
+            if settled_root == FixedBytes(proof.new_root.0) {
+                debug!("epoch {} is already settled", proof.epoch);
+                return Ok(());
+            }
+            if settled_root != FixedBytes(proof.prev_root.0) {
+                return Err(anyhow!(
+                    "Epoch {} does not extend the settled root {}",
+                    proof.epoch,
+                    settled_root
+                ));
+            }
+
+            let receipt = contract
+                .submitEpoch(
+                    proof.epoch,
+                    Bytes::from(proof.public_values.clone()),
+                    Bytes::from(proof.proof.clone()),
+                )
+                .send()
+                .await?
+                .get_receipt()
+                .await?;
+            if !receipt.status() {
+                return Err(anyhow!(
+                    "Settlement transaction {} reverted",
+                    receipt.transaction_hash
+                ));
+            }
+            info!(
+                "settled epoch {} in transaction {}",
+                proof.epoch, receipt.transaction_hash
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "settlement")]
+pub use relayer::Relayer;
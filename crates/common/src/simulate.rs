@@ -0,0 +1,115 @@
+//! Dry runs of transactions: executing them on a fork of the state, e.g. an
+//! [`OverlayStore`](crate::storage::OverlayStore) over the node's store,
+//! to see their receipts and the accounts they would change without queueing
+//! them.
+
+use anyhow::Result;
+use prism_common::keys::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::{ExecutionError, TxError},
+    receipt::Receipt,
+    state::{Account, State, StateReader},
+    status::TxStatus,
+    stf::touched_accounts,
+    storage::NodeStore,
+    tx::Transaction,
+};
+
+/// The maximum number of transactions simulated in one request.
+pub const MAX_SIMULATED_TXS: usize = 100;
+
+/// An account a simulated transaction changed.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug)]
+pub struct AccountDiff {
+    /// The base64 encoded verifying key of the account
+    #[schema(value_type = String)]
+    pub vk: VerifyingKey,
+    /// Absent if the transaction creates the account
+    pub before: Option<Account>,
+    /// Absent if the transaction closes the account
+    pub after: Option<Account>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug)]
+pub struct SimulatedTx {
+    /// The receipt the transaction would get if executed at the next
+    /// Celestia height
+    pub receipt: Receipt,
+    /// The accounts it changes. Empty if it fails.
+    pub diffs: Vec<AccountDiff>,
+}
+
+/// The outcome of simulating a list of transactions.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug)]
+pub struct Simulation {
+    /// The results in execution order. Each transaction is executed on top
+    /// of the ones before it; failed ones are skipped like in a batch.
+    pub txs: Vec<SimulatedTx>,
+    /// The hex encoded state root after executing every transaction
+    pub root: String,
+}
+
+/// Executes `txs` in order on `state`, which should be a fork, as of
+/// `da_height`. Transactions for other chains than `chain_id` fail.
+pub fn simulate<S: NodeStore>(
+    state: &mut State<S>,
+    txs: Vec<Transaction>,
+    chain_id: u64,
+    da_height: u64,
+) -> Result<Simulation> {
+    let mut simulated = Vec::with_capacity(txs.len());
+    for tx in txs {
+        let tx_hash = tx.hash();
+        let gas = tx.tx_type.gas();
+        let accounts = touched_accounts(std::slice::from_ref(&tx));
+        let before = accounts
+            .iter()
+            .map(|vk| state.get_account(vk))
+            .collect::<Result<Vec<_>>>()?;
+
+        let result = if tx.chain_id == chain_id {
+            state.process_tx(tx, da_height)
+        } else {
+            Err(TxError::WrongChainId {
+                chain_id: tx.chain_id,
+                expected: chain_id,
+            }
+            .into())
+        };
+        let (status, gas_used, events, diffs) = match result {
+            Ok(events) => {
+                let mut diffs = Vec::new();
+                for (vk, before) in accounts.into_iter().zip(before) {
+                    let after = state.get_account(&vk)?;
+                    if before != after {
+                        diffs.push(AccountDiff { vk, before, after });
+                    }
+                }
+                (TxStatus::Executed { da_height }, gas, events, diffs)
+            }
+            Err(e) => {
+                let status = TxStatus::Failed {
+                    da_height,
+                    error: ExecutionError::from(&e),
+                };
+                (status, 0, Vec::new(), Vec::new())
+            }
+        };
+        simulated.push(SimulatedTx {
+            receipt: Receipt {
+                tx_hash: hex::encode(tx_hash.0),
+                status,
+                gas_used,
+                events,
+            },
+            diffs,
+        });
+    }
+    Ok(Simulation {
+        txs: simulated,
+        root: hex::encode(state.get_commitment()?.0),
+    })
+}
@@ -0,0 +1,309 @@
+use anyhow::{anyhow, Context, Result};
+use jmt::KeyHash;
+use prism_common::keys::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path, str::FromStr, sync::Arc};
+
+use crate::{
+    state::{account_key, Account},
+    storage::NodeStore,
+    tree::{Digest, KeyDirectoryTree},
+};
+
+/// The full tree contents at an epoch ending on a DA block boundary. Nodes
+/// can start from a snapshot instead of syncing from genesis, which matters
+/// once old Celestia blocks have been pruned.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub epoch: u64,
+
+    /// The DA height after which the tree was at [`Snapshot::epoch`]. Sync
+    /// resumes from the next height.
+    pub da_height: u64,
+
+    pub root: Digest,
+    pub values: Vec<([u8; 32], Vec<u8>)>,
+}
+
+impl Snapshot {
+    /// Exports the tree at `epoch`, or at the latest committed epoch if
+    /// `None`.
+    pub fn export<S: NodeStore>(store: Arc<S>, epoch: Option<u64>) -> Result<Self> {
+        let latest_epoch = store
+            .get_epoch()?
+            .ok_or_else(|| anyhow!("Store contains no state"))?;
+        let epoch = epoch.unwrap_or(latest_epoch);
+        if epoch > latest_epoch {
+            return Err(anyhow!(
+                "Epoch {} is ahead of the latest epoch {}",
+                epoch,
+                latest_epoch
+            ));
+        }
+
+        let da_height = store
+            .get_epoch_da_height(epoch)?
+            .ok_or_else(|| anyhow!("Epoch {} does not end on a DA block boundary", epoch))?;
+        let values = store
+            .iter_values(epoch)?
+            .into_iter()
+            .map(|(key, value)| (key.0, value))
+            .collect();
+        let root = KeyDirectoryTree::load(store, epoch).get_commitment()?;
+
+        Ok(Snapshot {
+            epoch,
+            da_height,
+            root,
+            values,
+        })
+    }
+
+    /// Checks that the snapshot is of the trusted root at its DA height.
+    pub fn check_trusted(&self, trusted: &TrustedRoot) -> Result<()> {
+        if self.da_height != trusted.da_height {
+            return Err(anyhow!(
+                "Snapshot is at celestia height {}, trusted root at {}",
+                self.da_height,
+                trusted.da_height
+            ));
+        }
+        if self.root != trusted.root {
+            return Err(anyhow!(
+                "Snapshot root {} does not match trusted root {}",
+                hex::encode(self.root.0),
+                hex::encode(trusted.root.0)
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn key_values(&self) -> Vec<(KeyHash, Vec<u8>)> {
+        self.values
+            .iter()
+            .map(|(key, value)| (KeyHash(*key), value.clone()))
+            .collect()
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, bincode::serialize(self)?)
+            .with_context(|| format!("Failed to write snapshot to {}", path.display()))
+    }
+
+    /// Loads a snapshot from a file path or an `http(s)://` URL.
+    pub async fn fetch(source: &str) -> Result<Self> {
+        let bytes = if source.starts_with("http://") || source.starts_with("https://") {
+            let response = reqwest::get(source)
+                .await
+                .context("Failed to fetch snapshot")?
+                .error_for_status()?;
+            response.bytes().await?.to_vec()
+        } else {
+            fs::read(source).with_context(|| format!("Failed to read snapshot {}", source))?
+        };
+        bincode::deserialize(&bytes).context("Failed to decode snapshot")
+    }
+}
+
+/// A [`Snapshot`] in JSON, for audits and migrations, e.g. to a new tree
+/// layout. Accounts are listed by their verifying key, so an import derives
+/// their tree keys again; the other values are kept by their tree key.
+#[derive(Serialize, Deserialize)]
+pub struct StateExport {
+    pub epoch: u64,
+    pub da_height: u64,
+    /// The hex encoded root of the exported tree
+    pub root: String,
+    pub accounts: Vec<ExportedAccount>,
+    /// The values that aren't accounts in the account index, e.g. deposit
+    /// markers or accounts of nodes started from a snapshot
+    pub values: Vec<ExportedValue>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportedAccount {
+    pub vk: VerifyingKey,
+    pub account: Account,
+}
+
+/// A raw tree value by its key, both hex encoded.
+#[derive(Serialize, Deserialize)]
+pub struct ExportedValue {
+    pub key: String,
+    pub value: String,
+}
+
+impl StateExport {
+    /// Converts `snapshot`, decoding the values stored under the tree key of
+    /// one of `vks` as accounts.
+    pub fn new(snapshot: &Snapshot, vks: Vec<VerifyingKey>) -> Result<Self> {
+        let vks: HashMap<[u8; 32], VerifyingKey> =
+            vks.into_iter().map(|vk| (account_key(&vk).0, vk)).collect();
+        let mut accounts = Vec::new();
+        let mut values = Vec::new();
+        for (key, value) in &snapshot.values {
+            match vks.get(key) {
+                Some(vk) => accounts.push(ExportedAccount {
+                    vk: vk.clone(),
+                    account: bincode::deserialize(value)?,
+                }),
+                None => values.push(ExportedValue {
+                    key: hex::encode(key),
+                    value: hex::encode(value),
+                }),
+            }
+        }
+        Ok(StateExport {
+            epoch: snapshot.epoch,
+            da_height: snapshot.da_height,
+            root: hex::encode(snapshot.root.0),
+            accounts,
+            values,
+        })
+    }
+
+    /// Converts the export back into a snapshot expected to restore to
+    /// `root`, or to the exported root if `None`.
+    pub fn to_snapshot(&self, root: Option<Digest>) -> Result<Snapshot> {
+        let mut values = Vec::with_capacity(self.accounts.len() + self.values.len());
+        for exported in &self.accounts {
+            values.push((
+                account_key(&exported.vk).0,
+                bincode::serialize(&exported.account)?,
+            ));
+        }
+        for exported in &self.values {
+            let key: [u8; 32] = hex::decode(&exported.key)
+                .context("Invalid key hex")?
+                .try_into()
+                .map_err(|_| anyhow!("Key {} is not 32 bytes", exported.key))?;
+            let value = hex::decode(&exported.value)
+                .with_context(|| format!("Invalid value hex of key {}", exported.key))?;
+            values.push((key, value));
+        }
+        let root = match root {
+            Some(root) => root,
+            None => parse_root(&self.root)?,
+        };
+        Ok(Snapshot {
+            epoch: self.epoch,
+            da_height: self.da_height,
+            root,
+            values,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write state export to {}", path.display()))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)
+            .with_context(|| format!("Failed to read state export {}", path.display()))?;
+        serde_json::from_slice(&bytes).context("Failed to decode state export")
+    }
+}
+
+/// Parses a hex encoded state root.
+pub fn parse_root(s: &str) -> Result<Digest> {
+    let root: [u8; 32] = hex::decode(s)
+        .context("Invalid root hex")?
+        .try_into()
+        .map_err(|_| anyhow!("Root must be 32 bytes"))?;
+    Ok(Digest::new(root))
+}
+
+/// A state root the operator trusts at a DA height, e.g. taken from a block
+/// explorer or a node they run. Written as `<hex root>@<celestia height>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrustedRoot {
+    pub root: Digest,
+    pub da_height: u64,
+}
+
+impl FromStr for TrustedRoot {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (root, da_height) = s
+            .split_once('@')
+            .ok_or_else(|| anyhow!("Expected <hex root>@<celestia height>"))?;
+        Ok(TrustedRoot {
+            root: parse_root(root)?,
+            da_height: da_height.parse().context("Invalid celestia height")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{keys, storage::InMemoryStore, testkit::generate_key};
+
+    /// A store with an account of `vk` and a raw value, at an epoch ending
+    /// on celestia height 7.
+    fn store_with_account(vk: &VerifyingKey) -> Arc<InMemoryStore> {
+        let store = Arc::new(InMemoryStore::default());
+        let mut tree = KeyDirectoryTree::<InMemoryStore>::new(store.clone());
+        let mut account = Account::default();
+        account.credit(10).unwrap();
+        tree.put(vec![
+            (account_key(vk), bincode::serialize(&account).unwrap()),
+            (KeyHash([1; 32]), b"raw".to_vec()),
+        ])
+        .unwrap();
+        store.set_da_height(7, tree.epoch).unwrap();
+        store
+    }
+
+    #[test]
+    fn exported_snapshot_restores_to_its_root() {
+        let vk = keys::verifying_key(&generate_key());
+        let snapshot = Snapshot::export(store_with_account(&vk), None).unwrap();
+        assert_eq!(snapshot.da_height, 7);
+        assert!(Snapshot::export(store_with_account(&vk), Some(snapshot.epoch + 1)).is_err());
+
+        let restored = KeyDirectoryTree::<InMemoryStore>::restore(
+            Arc::new(InMemoryStore::default()),
+            snapshot.epoch,
+            snapshot.key_values(),
+        )
+        .unwrap();
+        assert_eq!(restored.get_commitment().unwrap(), snapshot.root);
+
+        let trusted: TrustedRoot = format!("{}@7", hex::encode(snapshot.root.0))
+            .parse()
+            .unwrap();
+        snapshot.check_trusted(&trusted).unwrap();
+        let elsewhere = TrustedRoot {
+            da_height: 8,
+            ..trusted
+        };
+        assert!(snapshot.check_trusted(&elsewhere).is_err());
+        let other_root = TrustedRoot {
+            root: Digest::new([2; 32]),
+            ..trusted
+        };
+        assert!(snapshot.check_trusted(&other_root).is_err());
+    }
+
+    #[test]
+    fn state_export_round_trips_accounts_and_values() {
+        let vk = keys::verifying_key(&generate_key());
+        let snapshot = Snapshot::export(store_with_account(&vk), None).unwrap();
+
+        let export = StateExport::new(&snapshot, vec![vk.clone()]).unwrap();
+        assert_eq!(export.accounts.len(), 1);
+        assert_eq!(export.accounts[0].vk, vk);
+        assert_eq!(export.accounts[0].account.balance(), 10);
+        assert_eq!(export.values.len(), 1);
+
+        let imported = export.to_snapshot(None).unwrap();
+        assert_eq!(imported.root, snapshot.root);
+        let (mut imported, mut exported) = (imported.values, snapshot.values);
+        imported.sort();
+        exported.sort();
+        assert_eq!(imported, exported);
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::Result;
+use prism_common::keys::VerifyingKey;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    receipt::{get_receipt, Receipt},
+    state::{Account, NoncePolicy, State, StateReader},
+    status::TxStatus,
+    storage::{NodeStore, OverlayStore},
+    tree::Digest,
+    tx::Transaction,
+};
+
+/// A fork of the canonical state on which the sequencer executes queued
+/// transactions as soon as they arrive, so clients get soft confirmations
+/// before their batch round-trips through Celestia.
+///
+/// Soft confirmations are only a promise of the sequencer: the fork is
+/// rebuilt on top of the canonical state after every processed DA block,
+/// replaying the transactions that haven't been executed canonically yet.
+pub struct SoftState<S: NodeStore> {
+    base: Arc<S>,
+    nonce_policy: NoncePolicy,
+    mint_vk: Option<VerifyingKey>,
+    bridge_vk: Option<VerifyingKey>,
+    governance_vk: Option<VerifyingKey>,
+    scheduled_txs: bool,
+    insecure_signatures: bool,
+    state: State<OverlayStore<S>>,
+    /// Soft-executed transactions not yet executed canonically, in
+    /// execution order
+    txs: Vec<(Digest, Transaction)>,
+    receipts: HashMap<Digest, Receipt>,
+}
+
+impl<S: NodeStore> SoftState<S> {
+    pub fn new(
+        base: Arc<S>,
+        nonce_policy: NoncePolicy,
+        mint_vk: Option<VerifyingKey>,
+        bridge_vk: Option<VerifyingKey>,
+        governance_vk: Option<VerifyingKey>,
+        scheduled_txs: bool,
+        insecure_signatures: bool,
+    ) -> Result<Self> {
+        let state = State::new(Arc::new(OverlayStore::new(base.clone())?), nonce_policy)?
+            .with_mint_vk(mint_vk.clone())
+            .with_bridge_vk(bridge_vk.clone())
+            .with_governance_vk(governance_vk.clone())
+            .with_scheduled_txs(scheduled_txs)
+            .with_insecure_signatures(insecure_signatures);
+        Ok(SoftState {
+            base,
+            nonce_policy,
+            mint_vk,
+            bridge_vk,
+            governance_vk,
+            scheduled_txs,
+            insecure_signatures,
+            state,
+            txs: Vec::new(),
+            receipts: HashMap::new(),
+        })
+    }
+
+    /// Executes `tx` on the fork, failing if it can't be executed on top of
+    /// the transactions before it. It is executed as of the Celestia height
+    /// after the last processed one, the earliest it can be included at.
+    pub fn execute(&mut self, tx: Transaction) -> Result<Receipt> {
+        let tx_hash = tx.hash();
+        let gas_used = tx.tx_type.gas();
+        let da_height = self.base.get_da_height()?.unwrap_or(0) + 1;
+        let events = self.state.process_tx(tx.clone(), da_height)?;
+        let receipt = Receipt {
+            tx_hash: hex::encode(tx_hash.0),
+            status: TxStatus::SoftConfirmed,
+            gas_used,
+            events,
+        };
+        self.txs.push((tx_hash, tx));
+        self.receipts.insert(tx_hash, receipt.clone());
+        Ok(receipt)
+    }
+
+    /// Returns the root after executing every soft-executed transaction.
+    pub fn get_commitment(&self) -> Result<Digest> {
+        self.state.get_commitment()
+    }
+
+    pub fn get_receipt(&self, tx_hash: &Digest) -> Option<Receipt> {
+        self.receipts.get(tx_hash).cloned()
+    }
+
+    pub fn get_account(&self, vk: &VerifyingKey) -> Result<Option<Account>> {
+        self.state.get_account(vk)
+    }
+
+    /// Rebuilds the fork on top of the current canonical state. Transactions
+    /// with a canonical receipt are final and dropped, as are `dropped`
+    /// (e.g. evicted from the mempool); the rest are replayed. Returns the
+    /// hashes of transactions whose soft confirmation had to be rolled back.
+    pub fn reconcile(&mut self, dropped: &[Digest]) -> Result<Vec<Digest>> {
+        let txs = std::mem::take(&mut self.txs);
+        self.receipts.clear();
+        self.state = State::new(
+            Arc::new(OverlayStore::new(self.base.clone())?),
+            self.nonce_policy,
+        )?
+        .with_mint_vk(self.mint_vk.clone())
+        .with_bridge_vk(self.bridge_vk.clone())
+        .with_governance_vk(self.governance_vk.clone())
+        .with_scheduled_txs(self.scheduled_txs)
+        .with_insecure_signatures(self.insecure_signatures);
+
+        let mut rolled_back = Vec::new();
+        for (tx_hash, tx) in txs {
+            if get_receipt(self.base.as_ref(), &tx_hash)?.is_some() {
+                continue;
+            }
+            if dropped.contains(&tx_hash) || self.execute(tx).is_err() {
+                rolled_back.push(tx_hash);
+            }
+        }
+        Ok(rolled_back)
+    }
+}
@@ -1,63 +1,1695 @@
-use std::sync::Arc;
+#[cfg(feature = "contracts")]
+use std::collections::BTreeMap;
+use std::{fmt::Debug, marker::PhantomData, sync::Arc};
 
+#[cfg(feature = "contracts")]
+use crate::contracts::ContractStorage;
 use crate::{
-    tree::KeyDirectoryTree,
-    tx::{Transaction, TransactionType},
+    diff::StateDiff,
+    error::TxError,
+    params::{params_key, LiveParams, ParamSchedule},
+    proofs::{DaHeightProof, DeleteProof, InsertProof, Proof, TransferProof, UpdateProof},
+    receipt::TxEvent,
+    snapshot::Snapshot,
+    stf::{self, AccountWitness, Accounts, StfWitness},
+    storage::NodeStore,
+    tree::{Digest, Hasher, KeyDirectoryTree, TreeView},
+    tx::{check_multisig, contract_address, Transaction, TransactionType},
 };
 use anyhow::{anyhow, Result};
-use jmt::storage::{TreeReader, TreeWriter};
-use serde::{Deserialize, Serialize};
+use clap::ValueEnum;
+use jmt::{proof::SparseMerkleProof, KeyHash};
+use prism_common::keys::VerifyingKey;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize, Default, Clone)]
-pub struct Account {
+/// Determines which nonces [`State`] accepts for an account's next
+/// transaction.
+#[derive(ValueEnum, Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NoncePolicy {
+    /// The nonce must equal the account's current nonce.
+    #[default]
+    Strict,
+    /// Nonces may be skipped, as long as they are strictly increasing.
+    AllowGaps,
+}
+
+impl NoncePolicy {
+    pub fn check(&self, account_nonce: u64, tx_nonce: u64) -> Result<()> {
+        if tx_nonce < account_nonce {
+            return Err(TxError::NonceTooLow {
+                nonce: tx_nonce,
+                account_nonce,
+            }
+            .into());
+        }
+        match self {
+            NoncePolicy::Strict if tx_nonce != account_nonce => Err(TxError::NonceGap {
+                nonce: tx_nonce,
+                expected: account_nonce,
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The maximum number of keys that can be authorized on an account.
+pub const MAX_ACCOUNT_KEYS: usize = 16;
+
+/// The maximum number of transactions scheduled for execution at the same
+/// Celestia height, see [`Transaction::execute_at_da_height`].
+pub const MAX_SCHEDULED_TXS: usize = 1_000;
+
+/// Application-defined data stored with every account, e.g. a profile or
+/// registry entries. Applications extend the account model by implementing
+/// it for their own type and running [`State`] with it; the proofs and the
+/// re-execution in the guests cover it like the rest of the account. `()`
+/// adds no data.
+pub trait AccountData:
+    Serialize + DeserializeOwned + Default + Clone + Debug + PartialEq + Eq + Send + Sync + 'static
+{
+    /// Applies a transaction the account sent to its data, after the
+    /// built-in effects of its type. Runs in the guests too, so it must only
+    /// depend on the data and the transaction.
+    fn on_tx(&mut self, _tx: &Transaction) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl AccountData for () {}
+
+#[derive(Serialize, Deserialize, ToSchema, Default, Clone, Debug, PartialEq, Eq)]
+pub struct Account<D = ()> {
     nonce: u64,
+    balance: u64,
+    /// Keys authorized to sign for the account. Empty until the keys are
+    /// first changed, meaning only the key the account is stored under is
+    /// authorized.
+    #[schema(value_type = Vec<String>)]
+    keys: Vec<VerifyingKey>,
+    /// How many of the authorized keys must sign a transaction. Zero for
+    /// accounts that were never made multisig, which any one key signs for.
+    threshold: u32,
+    /// Encoded last, so accounts without data keep the encoding they had
+    /// before accounts carried data
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    data: D,
 }
 
-impl Account {
-    pub fn apply_tx(&mut self, tx: &Transaction) -> Result<()> {
-        if tx.nonce != self.nonce {
-            return Err(anyhow!("Invalid nonce"));
+impl<D: AccountData> Account<D> {
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub fn balance(&self) -> u64 {
+        self.balance
+    }
+
+    /// Returns the keys authorized to sign transactions for the account
+    /// stored under `vk`.
+    pub fn authorized_keys(&self, vk: &VerifyingKey) -> Vec<VerifyingKey> {
+        if self.keys.is_empty() {
+            return vec![vk.clone()];
         }
+        self.keys.clone()
+    }
+
+    /// Returns how many authorized keys must sign a transaction.
+    pub fn threshold(&self) -> usize {
+        self.threshold.max(1) as usize
+    }
+
+    /// Returns the application-defined data of the account, see
+    /// [`AccountData`].
+    pub fn data(&self) -> &D {
+        &self.data
+    }
+
+    /// Checks that `tx` is signed by enough keys authorized on the account
+    /// to meet its threshold.
+    pub fn authorize(&self, tx: &Transaction) -> Result<()> {
+        tx.verify_signature(&self.authorized_keys(&tx.vk), self.threshold())
+    }
+
+    /// Applies the sender side of a transaction to the account, including
+    /// paying its fee.
+    /// Crediting the recipient of a [`TransactionType::Transfer`] is done
+    /// separately via [`Account::credit`].
+    ///
+    /// Only enforces that nonces are strictly increasing, which is what the
+    /// proofs rely on for replay protection. The stricter sequential policy is
+    /// enforced by [`State`]. The signature is checked separately by
+    /// [`Account::authorize`].
+    pub fn apply_tx(&mut self, tx: &Transaction) -> Result<()> {
+        NoncePolicy::AllowGaps.check(self.nonce, tx.nonce)?;
+        self.charge(tx)?;
+        self.apply_effects(tx)
+    }
+
+    /// Applies the sender side of a transaction included before its
+    /// [`Transaction::execute_at_da_height`]: it only uses its nonce and
+    /// pays its fee, its effects are applied once it is executed.
+    pub(crate) fn schedule_tx(&mut self, tx: &Transaction) -> Result<()> {
+        NoncePolicy::AllowGaps.check(self.nonce, tx.nonce)?;
+        self.charge(tx)
+    }
+
+    /// Applies the sender side of a transaction's type, without its nonce
+    /// and fee.
+    pub(crate) fn apply_effects(&mut self, tx: &Transaction) -> Result<()> {
         match tx.tx_type {
-            TransactionType::Noop => {}
+            TransactionType::Noop
+            | TransactionType::Deploy { .. }
+            | TransactionType::Call { .. }
+            | TransactionType::CloseAccount
+            | TransactionType::Deposit { .. }
+            | TransactionType::ParamChange { .. } => {}
+            TransactionType::Mint { amount } => self.credit(amount)?,
+            TransactionType::Transfer { amount, .. } | TransactionType::Burn { amount } => {
+                self.debit(amount)?
+            }
+            TransactionType::AddKey { ref key } => self.add_key(&tx.vk, key)?,
+            TransactionType::RevokeKey { ref key } => self.revoke_key(&tx.vk, key)?,
+            TransactionType::SetMultisig {
+                ref keys,
+                threshold,
+            } => self.set_multisig(keys, threshold)?,
+        }
+        self.data.on_tx(tx)
+    }
+
+    /// Pays the fee of `tx` and uses its nonce.
+    fn charge(&mut self, tx: &Transaction) -> Result<()> {
+        let nonce = tx.nonce.checked_add(1).ok_or(TxError::NonceOverflow)?;
+        self.debit(tx.fee)
+            .map_err(|_| anyhow!("Insufficient balance to pay fee of {}", tx.fee))?;
+        self.nonce = nonce;
+        Ok(())
+    }
+
+    fn add_key(&mut self, vk: &VerifyingKey, key: &VerifyingKey) -> Result<()> {
+        let mut keys = self.authorized_keys(vk);
+        if keys.contains(key) {
+            return Err(TxError::KeyAlreadyAuthorized.into());
+        }
+        if keys.len() >= MAX_ACCOUNT_KEYS {
+            return Err(TxError::TooManyKeys {
+                max: MAX_ACCOUNT_KEYS,
+            }
+            .into());
+        }
+        keys.push(key.clone());
+        self.keys = keys;
+        Ok(())
+    }
+
+    fn revoke_key(&mut self, vk: &VerifyingKey, key: &VerifyingKey) -> Result<()> {
+        let mut keys = self.authorized_keys(vk);
+        let index = keys
+            .iter()
+            .position(|k| k == key)
+            .ok_or(TxError::KeyNotAuthorized)?;
+        if keys.len() == 1 {
+            return Err(TxError::LastKey.into());
+        }
+        if keys.len() == self.threshold() {
+            return Err(TxError::InvalidThreshold {
+                threshold: self.threshold,
+                keys: keys.len() - 1,
+            }
+            .into());
+        }
+        keys.remove(index);
+        self.keys = keys;
+        Ok(())
+    }
+
+    fn set_multisig(&mut self, keys: &[VerifyingKey], threshold: u32) -> Result<()> {
+        check_multisig(keys, threshold)?;
+        self.keys = keys.to_vec();
+        self.threshold = threshold;
+        Ok(())
+    }
+
+    pub fn credit(&mut self, amount: u64) -> Result<()> {
+        self.balance = self
+            .balance
+            .checked_add(amount)
+            .ok_or(TxError::BalanceOverflow)?;
+        Ok(())
+    }
+
+    pub fn debit(&mut self, amount: u64) -> Result<()> {
+        self.balance = self
+            .balance
+            .checked_sub(amount)
+            .ok_or(TxError::InsufficientBalance)?;
+        Ok(())
+    }
+}
+
+/// Read access to the accounts of a state, implemented by the executing
+/// [`State`] and by its [`StateSnapshot`]s.
+pub trait StateReader<D: AccountData = ()> {
+    /// Returns the account stored under `vk`, if it exists.
+    fn get_account(&self, vk: &VerifyingKey) -> Result<Option<Account<D>>>;
+
+    /// Returns the raw value stored under `vk` together with a proof of its
+    /// inclusion, or of the account's absence, against the current root.
+    fn get_account_with_proof(
+        &self,
+        vk: &VerifyingKey,
+    ) -> Result<(Option<Vec<u8>>, SparseMerkleProof<Hasher>)>;
+
+    /// Returns the current state root.
+    fn get_commitment(&self) -> Result<Digest>;
+
+    /// Returns the epoch the state is at.
+    fn epoch(&self) -> u64;
+
+    /// Whether transactions are accepted without checking their signatures,
+    /// see [`State::with_insecure_signatures`].
+    fn insecure_signatures(&self) -> bool;
+
+    /// Returns up to `limit` verifying keys of the account index after the
+    /// hex encoded tree key `after`, ordered by tree key, and the key to
+    /// continue after, `None` once the index is exhausted. Keys indexed
+    /// after the state's epoch are skipped, and keys whose account was
+    /// closed stay indexed, so fewer keys may be returned.
+    fn indexed_vks(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<(Vec<VerifyingKey>, Option<String>)>;
+
+    /// Returns the chain parameters governance has set or scheduled, see
+    /// [`crate::params`].
+    fn param_schedule(&self) -> Result<ParamSchedule>;
+
+    /// Whether transactions may be scheduled for a later Celestia height,
+    /// see [`State::with_scheduled_txs`].
+    fn scheduled_txs(&self) -> bool;
+
+    /// Returns the parameters governance set that are in effect at Celestia
+    /// height `da_height`.
+    fn live_params(&self, da_height: u64) -> Result<LiveParams> {
+        Ok(self.param_schedule()?.at(da_height))
+    }
+
+    /// Returns up to `limit` existing accounts with their verifying keys
+    /// after the hex encoded tree key `after`, ordered by tree key, and the
+    /// key to continue after, `None` on the last page. Tree keys are hashes,
+    /// so only accounts in the index are found: the ones executed
+    /// transactions touched on this node. Nodes started from a snapshot or
+    /// applying state diffs miss the others.
+    fn accounts_page(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<(Vec<(VerifyingKey, Account<D>)>, Option<String>)> {
+        let mut accounts = Vec::new();
+        let mut after = after.map(str::to_string);
+        while accounts.len() < limit {
+            let (vks, next) = self.indexed_vks(after.as_deref(), limit - accounts.len())?;
+            for vk in vks {
+                if let Some(account) = self.get_account(&vk)? {
+                    accounts.push((vk, account));
+                }
+            }
+            after = next;
+            if after.is_none() {
+                break;
+            }
+        }
+        Ok((accounts, after))
+    }
+
+    /// Returns the key allowed to send [`TransactionType::Mint`]s, see
+    /// [`State::with_mint_vk`].
+    fn mint_vk(&self) -> Option<&VerifyingKey>;
+
+    /// Validates a transaction against the state.
+    /// Called during [`State::process_tx`], but can also be used
+    /// independently, for example when queuing transactions to be batched.
+    ///
+    /// Nonces are only checked for replays here, since transactions with
+    /// higher nonces may still be waiting to be batched.
+    fn validate_tx(&self, tx: Transaction) -> Result<()> {
+        tx.verify()?;
+        let account = self.get_account(&tx.vk)?.unwrap_or_default();
+        if !self.insecure_signatures() {
+            account.authorize(&tx)?;
+        }
+        if tx.nonce < account.nonce {
+            return Err(TxError::NonceTooLow {
+                nonce: tx.nonce,
+                account_nonce: account.nonce,
+            }
+            .into());
+        }
+
+        if matches!(tx.tx_type, TransactionType::Mint { .. }) && self.mint_vk() != Some(&tx.vk) {
+            return Err(TxError::UnauthorizedMint.into());
+        }
+        if tx.execute_at_da_height.is_some() && !self.scheduled_txs() {
+            return Err(TxError::SchedulingUnsupported.into());
+        }
+
+        // the fee is paid before minted amounts are credited
+        let spent = match tx.tx_type {
+            TransactionType::Noop
+            | TransactionType::Deploy { .. }
+            | TransactionType::Call { .. }
+            | TransactionType::AddKey { .. }
+            | TransactionType::RevokeKey { .. }
+            | TransactionType::CloseAccount
+            | TransactionType::SetMultisig { .. }
+            | TransactionType::Deposit { .. }
+            | TransactionType::ParamChange { .. }
+            | TransactionType::Mint { .. } => 0,
+            TransactionType::Transfer { amount, .. } | TransactionType::Burn { amount } => {
+                amount as u128
+            }
+        };
+        if (account.balance as u128) < spent + tx.fee as u128 {
+            return Err(TxError::InsufficientBalance.into());
         }
-        self.nonce += 1;
         Ok(())
     }
 }
 
-pub struct State<S>
+/// A read-only view of the state at a committed epoch. Taking one is cheap
+/// and it doesn't borrow the [`State`], so queries can be served while
+/// execution continues.
+pub struct StateSnapshot<S, D = ()>
 where
-    S: TreeReader + TreeWriter,
+    S: NodeStore,
+{
+    tree: TreeView<S>,
+    root: Digest,
+    store: Arc<S>,
+    insecure_signatures: bool,
+    mint_vk: Option<VerifyingKey>,
+    scheduled_txs: bool,
+    _data: PhantomData<D>,
+}
+
+impl<S, D> StateReader<D> for StateSnapshot<S, D>
+where
+    S: NodeStore,
+    D: AccountData,
+{
+    fn get_account(&self, vk: &VerifyingKey) -> Result<Option<Account<D>>> {
+        match self.tree.get(account_key(vk))? {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_account_with_proof(
+        &self,
+        vk: &VerifyingKey,
+    ) -> Result<(Option<Vec<u8>>, SparseMerkleProof<Hasher>)> {
+        self.tree.get_with_proof(account_key(vk))
+    }
+
+    fn get_commitment(&self) -> Result<Digest> {
+        Ok(self.root)
+    }
+
+    fn epoch(&self) -> u64 {
+        self.tree.epoch()
+    }
+
+    fn insecure_signatures(&self) -> bool {
+        self.insecure_signatures
+    }
+
+    fn indexed_vks(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<(Vec<VerifyingKey>, Option<String>)> {
+        indexed_vks_page(self.store.as_ref(), after, limit, self.epoch())
+    }
+
+    fn param_schedule(&self) -> Result<ParamSchedule> {
+        match self.tree.get(params_key())? {
+            Some(value) => Ok(bincode::deserialize(&value)?),
+            None => Ok(ParamSchedule::default()),
+        }
+    }
+
+    fn mint_vk(&self) -> Option<&VerifyingKey> {
+        self.mint_vk.as_ref()
+    }
+
+    fn scheduled_txs(&self) -> bool {
+        self.scheduled_txs
+    }
+}
+
+impl<S, D> StateSnapshot<S, D>
+where
+    S: NodeStore,
+    D: AccountData,
+{
+    /// Returns a snapshot of the state as it was at an earlier `epoch`,
+    /// which must not have been pruned. Unlike [`State::snapshot_at`], this
+    /// doesn't need the state itself.
+    pub fn at(&self, epoch: u64) -> Result<Self> {
+        let tree = self.tree.at(epoch)?;
+        let root = tree.get_commitment()?;
+        Ok(StateSnapshot {
+            tree,
+            root,
+            store: self.store.clone(),
+            insecure_signatures: self.insecure_signatures,
+            mint_vk: self.mint_vk.clone(),
+            scheduled_txs: self.scheduled_txs,
+            _data: PhantomData,
+        })
+    }
+}
+
+/// The state of the rollup, with `D` the application-defined data of its
+/// accounts.
+pub struct State<S, D = ()>
+where
+    S: NodeStore,
 {
     jmt: KeyDirectoryTree<S>,
+    nonce_policy: NoncePolicy,
+    /// The key allowed to send [`TransactionType::Deposit`]s, none if the
+    /// bridge is disabled
+    bridge_vk: Option<VerifyingKey>,
+    /// The key allowed to send [`TransactionType::ParamChange`]s, none if
+    /// the parameters can't be changed
+    governance_vk: Option<VerifyingKey>,
+    /// The key allowed to send [`TransactionType::Mint`]s, none if minting
+    /// is disabled
+    mint_vk: Option<VerifyingKey>,
+    /// Whether transactions may be scheduled for a later Celestia height
+    scheduled_txs: bool,
+    insecure_signatures: bool,
+    /// Proofs of the executed transactions, if they are being recorded
+    proofs: Option<Vec<Proof<D>>>,
 }
 
-impl<S> State<S>
+/// What the proof of a transaction needs from the state before it executes.
+struct ProofWitness<D> {
+    old_epoch: u64,
+    old_root: Digest,
+    old_account: Option<Account<D>>,
+    proof: SparseMerkleProof<Hasher>,
+    /// The recipient's account, for transfers to another account
+    recipient: Option<AccountWitness<D>>,
+}
+
+impl<S, D> State<S, D>
 where
-    S: TreeReader + TreeWriter,
+    S: NodeStore,
+    D: AccountData,
 {
-    pub fn new(store: Arc<S>) -> Self {
-        State {
-            jmt: KeyDirectoryTree::new(store),
+    /// Creates the state on top of `store`, resuming from the last
+    /// committed epoch if the store has been written to before.
+    pub fn new(store: Arc<S>, nonce_policy: NoncePolicy) -> Result<Self> {
+        let jmt = match store.get_epoch()? {
+            Some(epoch) => KeyDirectoryTree::load(store, epoch),
+            None => KeyDirectoryTree::new(store),
+        };
+        Ok(State {
+            jmt,
+            nonce_policy,
+            bridge_vk: None,
+            governance_vk: None,
+            mint_vk: None,
+            scheduled_txs: false,
+            insecure_signatures: false,
+            proofs: None,
+        })
+    }
+
+    /// Allows `mint_vk` to mint new tokens to its own account.
+    pub fn with_mint_vk(mut self, mint_vk: Option<VerifyingKey>) -> Self {
+        self.mint_vk = mint_vk;
+        self
+    }
+
+    /// Creates the state from a snapshot, checking that the restored tree
+    /// matches the snapshot's root.
+    pub fn from_snapshot(
+        store: Arc<S>,
+        snapshot: &Snapshot,
+        nonce_policy: NoncePolicy,
+    ) -> Result<Self> {
+        let jmt = KeyDirectoryTree::restore(store.clone(), snapshot.epoch, snapshot.key_values())?;
+        let root = jmt.get_commitment()?;
+        if root != snapshot.root {
+            return Err(anyhow!(
+                "Restored root {} does not match snapshot root {}",
+                hex::encode(root.0),
+                hex::encode(snapshot.root.0)
+            ));
         }
+        store.set_da_height(snapshot.da_height, snapshot.epoch)?;
+        Ok(State {
+            jmt,
+            nonce_policy,
+            bridge_vk: None,
+            governance_vk: None,
+            mint_vk: None,
+            scheduled_txs: false,
+            insecure_signatures: false,
+            proofs: None,
+        })
     }
 
-    /// Validates a transaction against the current chain state.
-    /// Called during [`process_tx`], but can also be used independently, for
-    /// example when queuing transactions to be batched.
-    pub(crate) fn validate_tx(&self, tx: Transaction) -> Result<()> {
-        tx.verify()?;
-        match tx.tx_type {
-            TransactionType::Noop => Ok(()),
+    /// Allows `bridge_vk` to send deposits, see [`crate::bridge`].
+    pub fn with_bridge_vk(mut self, bridge_vk: Option<VerifyingKey>) -> Self {
+        self.bridge_vk = bridge_vk;
+        self
+    }
+
+    /// Allows `governance_vk` to change the live chain parameters, see
+    /// [`crate::params`].
+    pub fn with_governance_vk(mut self, governance_vk: Option<VerifyingKey>) -> Self {
+        self.governance_vk = governance_vk;
+        self
+    }
+
+    /// Accepts transactions scheduled for a later Celestia height if
+    /// `scheduled_txs`. Epochs including or executing them can't be proven
+    /// yet, see [`State::take_scheduled_txs`].
+    pub fn with_scheduled_txs(mut self, scheduled_txs: bool) -> Self {
+        self.scheduled_txs = scheduled_txs;
+        self
+    }
+
+    /// Executes transactions without checking their signatures if
+    /// `insecure_signatures`, for development only. Proofs still check
+    /// them, so epochs with unsigned transactions can't be proven.
+    pub fn with_insecure_signatures(mut self, insecure_signatures: bool) -> Self {
+        self.insecure_signatures = insecure_signatures;
+        self
+    }
+
+    /// Returns whether the deposit `id` has already been credited.
+    pub fn is_deposit_processed(&self, id: &Digest) -> Result<bool> {
+        Ok(self.jmt.get(deposit_key(id))?.is_some())
+    }
+
+    /// Starts recording an [`InsertProof`], [`UpdateProof`] or
+    /// [`DeleteProof`] of the sender's account for every transaction executed from now on,
+    /// or a [`TransferProof`] of both accounts of a transfer.
+    ///
+    /// Contract storage, deposit markers and parameter schedules aren't
+    /// proven yet, so transactions writing them get no proof and their epoch
+    /// can't be proven in batch mode.
+    pub(crate) fn record_proofs(&mut self) {
+        self.proofs.get_or_insert_with(Vec::new);
+        self.jmt.record_written_keys();
+    }
+
+    /// Returns the proofs recorded since the last call, in execution order.
+    pub(crate) fn take_proofs(&mut self) -> Vec<Proof<D>> {
+        self.proofs.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Returns the last DA height recorded in the tree, see
+    /// [`State::set_last_da_height`].
+    pub fn last_da_height(&self) -> Result<Option<u64>> {
+        self.jmt
+            .get(da_height_key())?
+            .map(|value| bincode::deserialize(&value))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Records `da_height` as the last processed DA height under a reserved
+    /// key, so the state root commits to how far the chain has processed.
+    /// The watermark only advances; it is proven like a transaction if
+    /// proofs are being recorded.
+    pub(crate) fn set_last_da_height(&mut self, da_height: u64) -> Result<()> {
+        let key = da_height_key();
+        let old_root = self.get_commitment()?;
+        let (old_value, old_proof) = self.jmt.get_with_proof(key)?;
+        let old_height: Option<u64> = old_value.map(|v| bincode::deserialize(&v)).transpose()?;
+        if let Some(old_height) = old_height.filter(|old_height| *old_height >= da_height) {
+            return Err(anyhow!(
+                "DA height {} doesn't advance the recorded height {}",
+                da_height,
+                old_height
+            ));
+        }
+        self.jmt.put(vec![(key, bincode::serialize(&da_height)?)])?;
+
+        if let Some(proofs) = &mut self.proofs {
+            let (_, membership_proof) = self.jmt.get_with_proof(key)?;
+            proofs.push(Proof::DaHeight(DaHeightProof {
+                old_proof,
+                old_root,
+                old_height,
+                membership_proof,
+                new_root: self.jmt.get_commitment()?,
+                da_height,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Starts recording the tree writes of everything executed from now on,
+    /// see [`State::take_writes`].
+    pub(crate) fn record_writes(&mut self) {
+        self.jmt.record_writes();
+    }
+
+    /// Returns the tree writes recorded since the last call, as posted in a
+    /// [`StateDiff`].
+    pub(crate) fn take_writes(&mut self) -> Vec<(KeyHash, Option<Vec<u8>>)> {
+        self.jmt.take_writes()
+    }
+
+    /// Applies the writes of `diff` as a new epoch, without executing its
+    /// transactions. Fails without writing anything if the diff doesn't
+    /// start at the current root or its writes don't lead to its new root.
+    pub fn apply_diff(&mut self, diff: &StateDiff) -> Result<()> {
+        let root = self.get_commitment()?;
+        if diff.prev_root() != root {
+            return Err(anyhow!(
+                "State diff starts at root {}, not the current root {}",
+                hex::encode(diff.prev_root().0),
+                hex::encode(root.0)
+            ));
+        }
+        let writes = diff.writes();
+        if writes.is_empty() {
+            if diff.new_root() != root {
+                return Err(anyhow!("Empty state diff changes the root"));
+            }
+            return Ok(());
+        }
+        let (new_root, _) = self.jmt.view().prove_update(writes.clone())?;
+        if new_root != diff.new_root() {
+            return Err(anyhow!(
+                "State diff writes lead to root {}, not the signed root {}",
+                hex::encode(new_root.0),
+                hex::encode(diff.new_root().0)
+            ));
+        }
+        self.jmt.put_value_set(writes)
+    }
+
+    /// Builds the witness for proving `txs`, executed since `from_epoch`,
+    /// by re-execution. Fails if the epoch wrote more than the accounts of
+    /// its transactions, e.g. contract storage.
+    pub fn stf_witness(&self, from_epoch: u64, txs: Vec<Transaction>) -> Result<StfWitness<D>> {
+        let view = self.jmt.view_at(from_epoch)?;
+        let prev_root = view.get_commitment()?;
+        let mut accounts = Vec::new();
+        let mut initial = Accounts::<D>::new();
+        for vk in stf::touched_accounts(&txs) {
+            let (value, proof) = view.get_with_proof(account_key(&vk))?;
+            let account: Option<Account<D>> =
+                value.map(|v| bincode::deserialize(&v)).transpose()?;
+            initial.insert(account_key(&vk).0, account.clone());
+            accounts.push(AccountWitness { vk, account, proof });
+        }
+
+        let mut executed = initial.clone();
+        for tx in &txs {
+            stf::execute_tx(&mut executed, tx, self.mint_vk.as_ref())?;
+        }
+        let (new_root, update_proof) =
+            view.prove_update(stf::changed_accounts(&initial, &executed)?)?;
+        if new_root != self.get_commitment()? {
+            return Err(anyhow!(
+                "Epoch changed more than the accounts of its transactions"
+            ));
+        }
+        Ok(StfWitness {
+            prev_root,
+            new_root,
+            txs,
+            accounts,
+            update_proof,
+            mint_vk: self.mint_vk.clone(),
+        })
+    }
+
+    /// Writes any pending tree batch to the store.
+    pub fn flush(&mut self) -> Result<()> {
+        self.jmt.write_batch()
+    }
+
+    /// Returns a snapshot of the state at the current epoch.
+    pub fn snapshot(&self) -> Result<StateSnapshot<S, D>> {
+        let tree = self.jmt.view();
+        let root = tree.get_commitment()?;
+        Ok(StateSnapshot {
+            tree,
+            root,
+            store: self.jmt.store().clone(),
+            insecure_signatures: self.insecure_signatures,
+            mint_vk: self.mint_vk.clone(),
+            scheduled_txs: self.scheduled_txs,
+            _data: PhantomData,
+        })
+    }
+
+    /// Returns a snapshot of the state as it was at a past `epoch`, which
+    /// must not have been pruned.
+    pub fn snapshot_at(&self, epoch: u64) -> Result<StateSnapshot<S, D>> {
+        let tree = self.jmt.view_at(epoch)?;
+        let root = tree.get_commitment()?;
+        Ok(StateSnapshot {
+            tree,
+            root,
+            store: self.jmt.store().clone(),
+            insecure_signatures: self.insecure_signatures,
+            mint_vk: self.mint_vk.clone(),
+            scheduled_txs: self.scheduled_txs,
+            _data: PhantomData,
+        })
+    }
+
+    /// Reverts the state to how it was at `epoch`.
+    pub(crate) fn rollback(&mut self, epoch: u64) -> Result<()> {
+        self.jmt.rollback(epoch)?;
+        unindex_accounts_after(self.jmt.store().as_ref(), epoch)
+    }
+
+    /// Creates accounts with the given balances in a single epoch, e.g. from
+    /// a genesis file.
+    pub(crate) fn init_accounts(&mut self, balances: Vec<(VerifyingKey, u64)>) -> Result<()> {
+        let values = balances
+            .iter()
+            .map(|(vk, balance)| {
+                let account = Account::<D> {
+                    balance: *balance,
+                    ..Default::default()
+                };
+                Ok((account_key(vk), bincode::serialize(&account)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if values.is_empty() {
+            return Ok(());
+        }
+        self.jmt.put(values)?;
+        for (vk, _) in &balances {
+            index_account(self.jmt.store().as_ref(), vk, self.jmt.epoch)?;
         }
+        Ok(())
+    }
+
+    fn put_account(&mut self, vk: &VerifyingKey, account: &Account<D>) -> Result<()> {
+        self.jmt
+            .put(vec![(account_key(vk), bincode::serialize(account)?)])
     }
 
-    /// Processes a transaction by validating it and updating the state.
-    pub(crate) fn process_tx(&mut self, tx: Transaction) -> Result<()> {
+    /// Processes a transaction executed at Celestia height `da_height` by
+    /// validating it and updating the state. Returns the events of the state
+    /// changes it made.
+    pub(crate) fn process_tx(&mut self, tx: Transaction, da_height: u64) -> Result<Vec<TxEvent>> {
+        tx.check_expiry(da_height)?;
         self.validate_tx(tx.clone())?;
+
+        let mut events = Vec::new();
+        let existing = self.get_account(&tx.vk)?;
+        if matches!(tx.tx_type, TransactionType::CloseAccount) && existing.is_none() {
+            return Err(TxError::AccountNotFound.into());
+        }
+        let witness = match self.proofs {
+            Some(_) => {
+                // only the keys written by the transaction itself count
+                self.jmt.take_written_keys();
+                Some(ProofWitness {
+                    old_epoch: self.jmt.epoch,
+                    old_root: self.get_commitment()?,
+                    old_account: existing.clone(),
+                    proof: self.get_account_with_proof(&tx.vk)?.1,
+                    recipient: match &tx.tx_type {
+                        TransactionType::Transfer { to, .. } if *to != tx.vk => {
+                            Some(self.account_witness(to)?)
+                        }
+                        _ => None,
+                    },
+                })
+            }
+            None => None,
+        };
+        if existing.is_none() {
+            events.push(TxEvent::AccountCreated { vk: tx.vk.clone() });
+        }
+        let mut sender = existing.unwrap_or_default();
+        self.nonce_policy.check(sender.nonce, tx.nonce)?;
+        match tx.execute_at_da_height {
+            Some(execute_at_da_height) if execute_at_da_height > da_height => {
+                sender.schedule_tx(&tx)?;
+                self.schedule(&tx, &sender, execute_at_da_height)?;
+                events.push(TxEvent::Scheduled {
+                    execute_at_da_height,
+                });
+            }
+            _ => {
+                sender.apply_tx(&tx)?;
+                self.execute_effects(&tx, sender, da_height, &mut events)?;
+            }
+        }
+
+        for vk in stf::touched_accounts(std::slice::from_ref(&tx)) {
+            index_account(self.jmt.store().as_ref(), &vk, self.jmt.epoch)?;
+        }
+        if tx.fee > 0 {
+            events.push(TxEvent::FeePaid {
+                payer: tx.vk.clone(),
+                amount: tx.fee,
+            });
+        }
+        if let Some(witness) = witness {
+            // the transaction has been applied either way, a missing proof
+            // only fails the proof of its epoch
+            match self.prove_tx(tx, witness) {
+                Ok(proof) => self.proofs.get_or_insert_with(Vec::new).push(proof),
+                Err(e) => error!("generating transaction proof: {}", e),
+            }
+        }
+        Ok(events)
+    }
+
+    /// Executes a transaction taken from the queue at its
+    /// [`Transaction::execute_at_da_height`], see
+    /// [`State::take_scheduled_txs`]. Its nonce and fee were settled and its
+    /// signature checked when it was included.
+    pub(crate) fn execute_scheduled(
+        &mut self,
+        tx: Transaction,
+        da_height: u64,
+    ) -> Result<Vec<TxEvent>> {
+        let mut events = Vec::new();
+        let existing = self.get_account(&tx.vk)?;
+        if matches!(tx.tx_type, TransactionType::CloseAccount) && existing.is_none() {
+            return Err(TxError::AccountNotFound.into());
+        }
+        if existing.is_none() {
+            events.push(TxEvent::AccountCreated { vk: tx.vk.clone() });
+        }
+        let mut sender = existing.unwrap_or_default();
+        sender.apply_effects(&tx)?;
+        self.execute_effects(&tx, sender, da_height, &mut events)?;
+        for vk in stf::touched_accounts(std::slice::from_ref(&tx)) {
+            index_account(self.jmt.store().as_ref(), &vk, self.jmt.epoch)?;
+        }
+        Ok(events)
+    }
+
+    /// Writes the effects of `tx` on the state, with `sender` the sender's
+    /// account after applying its sender side.
+    fn execute_effects(
+        &mut self,
+        tx: &Transaction,
+        mut sender: Account<D>,
+        da_height: u64,
+        events: &mut Vec<TxEvent>,
+    ) -> Result<()> {
         match tx.tx_type {
-            TransactionType::Noop => Ok(()),
+            TransactionType::Noop => self.put_account(&tx.vk, &sender)?,
+            TransactionType::Mint { amount } => {
+                self.put_account(&tx.vk, &sender)?;
+                events.push(TxEvent::Mint {
+                    to: tx.vk.clone(),
+                    amount,
+                });
+            }
+            TransactionType::Burn { amount } => {
+                self.put_account(&tx.vk, &sender)?;
+                events.push(TxEvent::Burn {
+                    from: tx.vk.clone(),
+                    amount,
+                });
+            }
+            TransactionType::AddKey { ref key } => {
+                self.put_account(&tx.vk, &sender)?;
+                events.push(TxEvent::KeyAdded {
+                    account: tx.vk.clone(),
+                    key: key.clone(),
+                });
+            }
+            TransactionType::RevokeKey { ref key } => {
+                self.put_account(&tx.vk, &sender)?;
+                events.push(TxEvent::KeyRevoked {
+                    account: tx.vk.clone(),
+                    key: key.clone(),
+                });
+            }
+            TransactionType::SetMultisig {
+                ref keys,
+                threshold,
+            } => {
+                self.put_account(&tx.vk, &sender)?;
+                events.push(TxEvent::MultisigSet {
+                    account: tx.vk.clone(),
+                    keys: keys.clone(),
+                    threshold,
+                });
+            }
+            TransactionType::CloseAccount => {
+                if sender.balance != 0 {
+                    return Err(TxError::AccountNotEmpty {
+                        balance: sender.balance,
+                    }
+                    .into());
+                }
+                self.jmt.remove(vec![account_key(&tx.vk)])?;
+                events.push(TxEvent::AccountClosed { vk: tx.vk.clone() });
+            }
+            TransactionType::Deploy { ref code } => {
+                self.deploy(tx, &sender, code)?;
+                events.push(TxEvent::ContractDeployed {
+                    address: hex::encode(contract_address(&tx.vk, tx.nonce).0),
+                });
+            }
+            TransactionType::Call {
+                contract,
+                ref input,
+            } => {
+                self.call(tx, &sender, contract, input.clone(), da_height)?;
+                events.push(TxEvent::ContractCalled {
+                    contract: hex::encode(contract.0),
+                });
+            }
+            TransactionType::Transfer { ref to, amount } => {
+                if *to == tx.vk {
+                    // a self-transfer only bumps the nonce
+                    sender.credit(amount)?;
+                    self.put_account(&tx.vk, &sender)?;
+                } else {
+                    let existing = self.get_account(to)?;
+                    if existing.is_none() {
+                        events.push(TxEvent::AccountCreated { vk: to.clone() });
+                    }
+                    let mut recipient = existing.unwrap_or_default();
+                    recipient.credit(amount)?;
+
+                    self.jmt.put(vec![
+                        (account_key(&tx.vk), bincode::serialize(&sender)?),
+                        (account_key(to), bincode::serialize(&recipient)?),
+                    ])?;
+                }
+                events.push(TxEvent::Transfer {
+                    from: tx.vk.clone(),
+                    to: to.clone(),
+                    amount,
+                });
+            }
+            TransactionType::Deposit { id, ref to, amount } => {
+                self.deposit(tx, sender, &id, to, amount, events)?;
+            }
+            TransactionType::ParamChange {
+                activation_height,
+                ref params,
+            } => {
+                self.change_params(tx, &sender, da_height, activation_height, params)?;
+                events.push(TxEvent::ParamsScheduled {
+                    activation_height,
+                    params: params.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Queues `tx` for execution at `execute_at_da_height`, in the same
+    /// epoch as the sender's nonce bump.
+    fn schedule(
+        &mut self,
+        tx: &Transaction,
+        sender: &Account<D>,
+        execute_at_da_height: u64,
+    ) -> Result<()> {
+        let key = scheduled_txs_key(execute_at_da_height);
+        let mut scheduled: Vec<Transaction> = match self.jmt.get(key)? {
+            Some(value) => bincode::deserialize(&value)?,
+            None => Vec::new(),
+        };
+        if scheduled.len() >= MAX_SCHEDULED_TXS {
+            return Err(anyhow!(
+                "Already {} transactions scheduled at celestia height {}",
+                scheduled.len(),
+                execute_at_da_height
+            ));
+        }
+        scheduled.push(tx.clone());
+        self.jmt.put(vec![
+            (account_key(&tx.vk), bincode::serialize(sender)?),
+            (key, bincode::serialize(&scheduled)?),
+        ])
+    }
+
+    /// Removes and returns the transactions scheduled for execution at
+    /// `da_height`, in the order they were included. They are executed with
+    /// [`State::execute_scheduled`] before the batches of the height.
+    ///
+    /// The transaction proofs re-apply transactions at inclusion, so epochs
+    /// including or executing scheduled transactions can't be proven yet.
+    /// They are rejected unless the chain enables them, see
+    /// [`State::with_scheduled_txs`], which proving sequencers refuse to.
+    pub fn take_scheduled_txs(&mut self, da_height: u64) -> Result<Vec<Transaction>> {
+        let key = scheduled_txs_key(da_height);
+        match self.jmt.get(key)? {
+            Some(value) => {
+                self.jmt.remove(vec![key])?;
+                Ok(bincode::deserialize(&value)?)
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the account of `vk` with a proof against the current root.
+    fn account_witness(&self, vk: &VerifyingKey) -> Result<AccountWitness<D>> {
+        let (value, proof) = self.get_account_with_proof(vk)?;
+        Ok(AccountWitness {
+            vk: vk.clone(),
+            account: value.map(|v| bincode::deserialize(&v)).transpose()?,
+            proof,
+        })
+    }
+
+    /// Builds the proof of an executed transaction from the witness taken
+    /// before it executed. Fails if the transaction wrote more than its
+    /// accounts, which the proofs can't cover.
+    fn prove_tx(&mut self, tx: Transaction, mut witness: ProofWitness<D>) -> Result<Proof<D>> {
+        let written_keys = self.jmt.take_written_keys();
+        if let Some(recipient) = witness.recipient.take() {
+            return self.prove_transfer(tx, witness, recipient);
+        }
+        let sender_key = account_key(&tx.vk).0;
+        if let Some(key) = written_keys.into_iter().find(|key| *key != sender_key) {
+            return Err(anyhow!(
+                "Transaction {} writes key {} besides the sender's account, its epoch can't be proven in batch mode",
+                hex::encode(tx.hash().0),
+                hex::encode(key)
+            ));
+        }
+        let new_root = self.get_commitment()?;
+        let (_, membership_proof) = self.get_account_with_proof(&tx.vk)?;
+        Ok(match witness.old_account {
+            None => Proof::Insert(InsertProof {
+                non_membership_proof: witness.proof,
+                old_root: witness.old_root,
+                membership_proof,
+                new_root,
+                tx,
+            }),
+            Some(old_account) if matches!(tx.tx_type, TransactionType::CloseAccount) => {
+                Proof::Delete(DeleteProof {
+                    old_membership_proof: witness.proof,
+                    old_root: witness.old_root,
+                    old_account,
+                    // the proof of the account's absence after removal
+                    non_membership_proof: membership_proof,
+                    new_root,
+                    tx,
+                })
+            }
+            Some(old_account) => Proof::Update(UpdateProof {
+                old_membership_proof: witness.proof,
+                old_root: witness.old_root,
+                old_account,
+                membership_proof,
+                new_root,
+                tx,
+            }),
+        })
+    }
+
+    /// Builds the [`TransferProof`] of an executed transfer by re-executing
+    /// it on the accounts witnessed before, like the circuit does.
+    fn prove_transfer(
+        &self,
+        tx: Transaction,
+        witness: ProofWitness<D>,
+        recipient: AccountWitness<D>,
+    ) -> Result<Proof<D>> {
+        let accounts = vec![
+            AccountWitness {
+                vk: tx.vk.clone(),
+                account: witness.old_account,
+                proof: witness.proof,
+            },
+            recipient,
+        ];
+        let initial: Accounts<D> = accounts
+            .iter()
+            .map(|account| (account_key(&account.vk).0, account.account.clone()))
+            .collect();
+        let mut executed = initial.clone();
+        stf::execute_tx(&mut executed, &tx, None)?;
+        let (new_root, update_proof) = self
+            .jmt
+            .view_at(witness.old_epoch)?
+            .prove_update(stf::changed_accounts(&initial, &executed)?)?;
+        if new_root != self.get_commitment()? {
+            return Err(anyhow!(
+                "Transfer {} changed more than its accounts",
+                hex::encode(tx.hash().0)
+            ));
+        }
+        Ok(Proof::Transfer(TransferProof {
+            old_root: witness.old_root,
+            accounts,
+            update_proof,
+            new_root,
+            tx,
+        }))
+    }
+
+    /// Schedules a parameter change of the governance account, in the same
+    /// epoch as its nonce bump.
+    fn change_params(
+        &mut self,
+        tx: &Transaction,
+        sender: &Account<D>,
+        da_height: u64,
+        activation_height: u64,
+        params: &LiveParams,
+    ) -> Result<()> {
+        if self.governance_vk.as_ref() != Some(&tx.vk) {
+            return Err(anyhow!(
+                "Parameter changes must be sent by the governance key"
+            ));
+        }
+        if activation_height <= da_height {
+            return Err(anyhow!(
+                "Parameter change must activate after celestia height {}",
+                da_height
+            ));
+        }
+        let mut schedule = self.param_schedule()?;
+        schedule.schedule(da_height, activation_height, params.clone());
+        self.jmt.put(vec![
+            (account_key(&tx.vk), bincode::serialize(sender)?),
+            (params_key(), bincode::serialize(&schedule)?),
+        ])
+    }
+
+    /// Credits a bridged deposit to `to` and marks its `id` as processed,
+    /// in the same epoch as the bridge account's nonce bump.
+    fn deposit(
+        &mut self,
+        tx: &Transaction,
+        mut sender: Account<D>,
+        id: &Digest,
+        to: &VerifyingKey,
+        amount: u64,
+        events: &mut Vec<TxEvent>,
+    ) -> Result<()> {
+        if self.bridge_vk.as_ref() != Some(&tx.vk) {
+            return Err(anyhow!("Deposits must be sent by the bridge key"));
+        }
+        if self.is_deposit_processed(id)? {
+            return Err(anyhow!(
+                "Deposit {} has already been processed",
+                hex::encode(id.0)
+            ));
+        }
+
+        let mut values = Vec::new();
+        if *to == tx.vk {
+            sender.credit(amount)?;
+        } else {
+            let existing = self.get_account(to)?;
+            if existing.is_none() {
+                events.push(TxEvent::AccountCreated { vk: to.clone() });
+            }
+            let mut recipient = existing.unwrap_or_default();
+            recipient.credit(amount)?;
+            values.push((account_key(to), bincode::serialize(&recipient)?));
+        }
+        values.push((account_key(&tx.vk), bincode::serialize(&sender)?));
+        values.push((deposit_key(id), amount.to_be_bytes().to_vec()));
+        self.jmt.put(values)?;
+
+        events.push(TxEvent::Deposit {
+            id: hex::encode(id.0),
+            to: to.clone(),
+            amount,
+        });
+        Ok(())
+    }
+
+    /// Stores the code of a new contract alongside the deployer's account.
+    #[cfg(feature = "contracts")]
+    fn deploy(&mut self, tx: &Transaction, sender: &Account<D>, code: &[u8]) -> Result<()> {
+        crate::contracts::validate_code(code)?;
+        let address = contract_address(&tx.vk, tx.nonce);
+        if self.jmt.get(contract_code_key(&address))?.is_some() {
+            return Err(anyhow!(
+                "Contract {} already exists",
+                hex::encode(address.0)
+            ));
+        }
+        self.jmt.put(vec![
+            (account_key(&tx.vk), bincode::serialize(sender)?),
+            (contract_code_key(&address), code.to_vec()),
+        ])
+    }
+
+    /// Executes a contract call, writing the caller's account and the
+    /// contract's storage writes in one epoch. Nothing is written if the
+    /// call fails.
+    #[cfg(feature = "contracts")]
+    fn call(
+        &mut self,
+        tx: &Transaction,
+        sender: &Account<D>,
+        contract: Digest,
+        input: Vec<u8>,
+        da_height: u64,
+    ) -> Result<()> {
+        let code = self
+            .jmt
+            .get(contract_code_key(&contract))?
+            .ok_or_else(|| anyhow!("Contract {} not found", hex::encode(contract.0)))?;
+        let storage = ContractStorageView {
+            tree: self.jmt.view(),
+            contract,
+            writes: BTreeMap::new(),
+        };
+        let caller = Digest::hash(tx.vk.as_bytes());
+        let storage = crate::contracts::call(&code, caller, da_height, input, storage)?;
+
+        let mut values = vec![(account_key(&tx.vk), bincode::serialize(sender)?)];
+        values.extend(
+            storage
+                .writes
+                .into_iter()
+                .map(|(key, value)| (contract_storage_key(&contract, &key), value)),
+        );
+        self.jmt.put(values)
+    }
+
+    #[cfg(not(feature = "contracts"))]
+    fn deploy(&mut self, _tx: &Transaction, _sender: &Account<D>, _code: &[u8]) -> Result<()> {
+        Err(anyhow!(
+            "Contract transactions require building with the `contracts` feature"
+        ))
+    }
+
+    #[cfg(not(feature = "contracts"))]
+    fn call(
+        &mut self,
+        _tx: &Transaction,
+        _sender: &Account<D>,
+        _contract: Digest,
+        _input: Vec<u8>,
+        _da_height: u64,
+    ) -> Result<()> {
+        Err(anyhow!(
+            "Contract transactions require building with the `contracts` feature"
+        ))
+    }
+}
+
+impl<S, D> StateReader<D> for State<S, D>
+where
+    S: NodeStore,
+    D: AccountData,
+{
+    fn get_account(&self, vk: &VerifyingKey) -> Result<Option<Account<D>>> {
+        match self.jmt.get(account_key(vk))? {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
         }
     }
+
+    fn get_account_with_proof(
+        &self,
+        vk: &VerifyingKey,
+    ) -> Result<(Option<Vec<u8>>, SparseMerkleProof<Hasher>)> {
+        self.jmt.get_with_proof(account_key(vk))
+    }
+
+    fn get_commitment(&self) -> Result<Digest> {
+        self.jmt.get_commitment()
+    }
+
+    fn epoch(&self) -> u64 {
+        self.jmt.epoch
+    }
+
+    fn insecure_signatures(&self) -> bool {
+        self.insecure_signatures
+    }
+
+    fn indexed_vks(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<(Vec<VerifyingKey>, Option<String>)> {
+        indexed_vks_page(self.jmt.store().as_ref(), after, limit, self.jmt.epoch)
+    }
+
+    fn mint_vk(&self) -> Option<&VerifyingKey> {
+        self.mint_vk.as_ref()
+    }
+
+    fn param_schedule(&self) -> Result<ParamSchedule> {
+        match self.jmt.get(params_key())? {
+            Some(value) => Ok(bincode::deserialize(&value)?),
+            None => Ok(ParamSchedule::default()),
+        }
+    }
+
+    fn scheduled_txs(&self) -> bool {
+        self.scheduled_txs
+    }
+}
+
+/// Returns the key an account is stored under in the tree.
+pub(crate) fn account_key(vk: &VerifyingKey) -> KeyHash {
+    KeyHash::with::<Hasher>(vk.as_bytes())
+}
+
+/// Metadata prefix of the account index, which maps the hex encoded tree
+/// key of every account to its verifying key and the epoch it was indexed
+/// at.
+const ACCOUNT_INDEX_PREFIX: &str = "account_vk:";
+
+/// An entry of the account index.
+#[derive(Serialize, Deserialize)]
+struct IndexedAccount {
+    vk: VerifyingKey,
+    /// The epoch the account was indexed at, so the entry can be rolled back
+    epoch: u64,
+}
+
+impl IndexedAccount {
+    /// Decodes an entry, where entries written before the epoch was recorded
+    /// read as indexed at epoch 0.
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        match bincode::deserialize(bytes) {
+            Ok(entry) => Ok(entry),
+            Err(_) => Ok(IndexedAccount {
+                vk: bincode::deserialize(bytes)?,
+                epoch: 0,
+            }),
+        }
+    }
+}
+
+/// Adds the preimage of `vk`'s tree key to the account index, as of `epoch`
+/// if it isn't indexed yet.
+pub(crate) fn index_account<S: NodeStore>(store: &S, vk: &VerifyingKey, epoch: u64) -> Result<()> {
+    let key = format!("{}{}", ACCOUNT_INDEX_PREFIX, hex::encode(account_key(vk).0));
+    if store.get_metadata(&key)?.is_some() {
+        return Ok(());
+    }
+    let entry = IndexedAccount {
+        vk: vk.clone(),
+        epoch,
+    };
+    store.put_metadata(&key, &bincode::serialize(&entry)?)
+}
+
+/// Removes the accounts indexed after `epoch` from the account index.
+fn unindex_accounts_after<S: NodeStore>(store: &S, epoch: u64) -> Result<()> {
+    for (key, value) in store.iter_metadata(ACCOUNT_INDEX_PREFIX)? {
+        if IndexedAccount::decode(&value)?.epoch > epoch {
+            store.delete_metadata(&key)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the verifying keys in the account index, ordered by their tree
+/// key.
+pub(crate) fn indexed_vks<S: NodeStore>(store: &S) -> Result<Vec<VerifyingKey>> {
+    let mut entries = store.iter_metadata(ACCOUNT_INDEX_PREFIX)?;
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    entries
+        .into_iter()
+        .map(|(_, value)| Ok(IndexedAccount::decode(&value)?.vk))
+        .collect()
+}
+
+/// Reads up to `limit` entries of the account index after the hex encoded
+/// tree key `after`, see [`StateReader::indexed_vks`].
+fn indexed_vks_page<S: NodeStore>(
+    store: &S,
+    after: Option<&str>,
+    limit: usize,
+    max_epoch: u64,
+) -> Result<(Vec<VerifyingKey>, Option<String>)> {
+    let entries = store.iter_metadata_range(ACCOUNT_INDEX_PREFIX, after, limit)?;
+    let next = match entries.last() {
+        Some((key, _)) if entries.len() == limit => {
+            Some(key[ACCOUNT_INDEX_PREFIX.len()..].to_string())
+        }
+        _ => None,
+    };
+    let mut vks = Vec::new();
+    for (_, value) in entries {
+        let entry = IndexedAccount::decode(&value)?;
+        if entry.epoch <= max_epoch {
+            vks.push(entry.vk);
+        }
+    }
+    Ok((vks, next))
+}
+
+/// Verifies an account proof as served by `/proof/<vk>`: that the account
+/// of `vk` is stored as the raw `value` under `root`, or doesn't exist if
+/// `value` is `None`.
+pub fn verify_account_proof(
+    root: Digest,
+    vk: &VerifyingKey,
+    value: Option<Vec<u8>>,
+    proof: &SparseMerkleProof<Hasher>,
+) -> Result<()> {
+    let key = account_key(vk);
+    match value {
+        Some(value) => proof.verify_existence(root.into(), key, value),
+        None => proof.verify_nonexistence(root.into(), key),
+    }
+}
+
+/// Returns the reserved key the last processed DA height is stored under,
+/// see [`State::set_last_da_height`].
+pub(crate) fn da_height_key() -> KeyHash {
+    KeyHash::with::<Hasher>(b"da_height")
+}
+
+/// Returns the key a processed deposit is recorded under, so its id can't
+/// be credited twice.
+fn deposit_key(id: &Digest) -> KeyHash {
+    KeyHash::with::<Hasher>([b"deposit:".as_slice(), &id.0].concat())
+}
+
+/// Returns the key the transactions scheduled for execution at `da_height`
+/// are queued under, see [`State::take_scheduled_txs`].
+fn scheduled_txs_key(da_height: u64) -> KeyHash {
+    KeyHash::with::<Hasher>([b"scheduled:".as_slice(), &da_height.to_be_bytes()].concat())
+}
+
+/// Returns the key the code of the contract at `address` is stored under.
+#[cfg(feature = "contracts")]
+pub(crate) fn contract_code_key(address: &Digest) -> KeyHash {
+    KeyHash::with::<Hasher>([b"contract_code:".as_slice(), &address.0].concat())
+}
+
+/// Returns the key `key` of the storage of the contract at `address` is
+/// stored under. Contract storage shares the tree with accounts, so every
+/// (contract, key) pair gets its own hashed path.
+#[cfg(feature = "contracts")]
+pub(crate) fn contract_storage_key(address: &Digest, key: &[u8]) -> KeyHash {
+    KeyHash::with::<Hasher>([b"contract_storage:".as_slice(), &address.0, key].concat())
+}
+
+/// The storage of a single contract during a call. Writes are buffered and
+/// only committed to the tree if the call succeeds.
+#[cfg(feature = "contracts")]
+struct ContractStorageView<S: NodeStore> {
+    tree: TreeView<S>,
+    contract: Digest,
+    writes: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+#[cfg(feature = "contracts")]
+impl<S: NodeStore> ContractStorage for ContractStorageView<S> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.writes.get(key) {
+            return Ok(Some(value.clone()));
+        }
+        self.tree.get(contract_storage_key(&self.contract, key))
+    }
+
+    fn set(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.writes.insert(key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{keys, storage::InMemoryStore, testkit::generate_key, tx::TransactionBuilder};
+
+    fn state_with_mint_vk(mint_vk: &VerifyingKey) -> State<InMemoryStore> {
+        State::new(Arc::new(InMemoryStore::default()), NoncePolicy::default())
+            .unwrap()
+            .with_mint_vk(Some(mint_vk.clone()))
+    }
+
+    #[test]
+    fn mint_from_non_authority_is_rejected() {
+        let authority = generate_key();
+        let authority_vk = keys::verifying_key(&authority);
+        let other = generate_key();
+        let other_vk = keys::verifying_key(&other);
+        let mut state = state_with_mint_vk(&authority_vk);
+
+        let mint = TransactionBuilder::new(TransactionType::Mint { amount: 100 })
+            .sign(&other)
+            .unwrap();
+        let err = state.process_tx(mint, 1).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&TxError::UnauthorizedMint));
+        assert!(state.get_account(&other_vk).unwrap().is_none());
+
+        let mint = TransactionBuilder::new(TransactionType::Mint { amount: 100 })
+            .sign(&authority)
+            .unwrap();
+        state.process_tx(mint, 1).unwrap();
+        let account = state.get_account(&authority_vk).unwrap().unwrap();
+        assert_eq!(account.balance(), 100);
+    }
+
+    #[test]
+    fn minted_amount_does_not_pay_the_mint_fee() {
+        let authority = generate_key();
+        let authority_vk = keys::verifying_key(&authority);
+        let mut state = state_with_mint_vk(&authority_vk);
+
+        let mint = TransactionBuilder::new(TransactionType::Mint { amount: 100 })
+            .fee(1)
+            .sign(&authority)
+            .unwrap();
+        let err = state.process_tx(mint, 1).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&TxError::InsufficientBalance));
+    }
+
+    #[test]
+    fn accounts_are_paged_by_tree_key_and_unindexed_on_rollback() {
+        let mut state: State<InMemoryStore> =
+            State::new(Arc::new(InMemoryStore::default()), NoncePolicy::default()).unwrap();
+        let vks: Vec<_> = (0..3)
+            .map(|_| keys::verifying_key(&generate_key()))
+            .collect();
+        state
+            .init_accounts(vks.iter().map(|vk| (vk.clone(), 10)).collect())
+            .unwrap();
+        let epoch = state.epoch();
+
+        let (first, after) = state.accounts_page(None, 2).unwrap();
+        assert_eq!(first.len(), 2);
+        let (rest, next) = state.accounts_page(after.as_deref(), 2).unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(next, None);
+        let paged: Vec<_> = first
+            .iter()
+            .chain(&rest)
+            .map(|(vk, _)| account_key(vk))
+            .collect();
+        let mut expected: Vec<_> = vks.iter().map(account_key).collect();
+        expected.sort();
+        assert_eq!(paged, expected);
+
+        let key = generate_key();
+        let vk = keys::verifying_key(&key);
+        let tx = TransactionBuilder::new(TransactionType::Noop)
+            .sign(&key)
+            .unwrap();
+        state.process_tx(tx, 1).unwrap();
+        assert!(indexed_vks(state.jmt.store().as_ref())
+            .unwrap()
+            .contains(&vk));
+
+        state.rollback(epoch).unwrap();
+        let indexed = indexed_vks(state.jmt.store().as_ref()).unwrap();
+        assert_eq!(indexed.len(), 3);
+        assert!(!indexed.contains(&vk));
+    }
+
+    #[test]
+    fn transfer_epoch_is_provable() {
+        let authority = generate_key();
+        let authority_vk = keys::verifying_key(&authority);
+        let other_vk = keys::verifying_key(&generate_key());
+        let mut state = state_with_mint_vk(&authority_vk);
+        let prev_root = state.get_commitment().unwrap();
+        state.record_proofs();
+
+        let mint = TransactionBuilder::new(TransactionType::Mint { amount: 100 })
+            .sign(&authority)
+            .unwrap();
+        state.process_tx(mint, 1).unwrap();
+
+        let transfer = TransactionBuilder::new(TransactionType::Transfer {
+            to: other_vk.clone(),
+            amount: 10,
+        })
+        .nonce(1)
+        .sign(&authority)
+        .unwrap();
+        state.process_tx(transfer, 1).unwrap();
+        assert_eq!(state.get_account(&other_vk).unwrap().unwrap().balance(), 10);
+
+        let proofs = state.take_proofs();
+        assert_eq!(proofs.len(), 2);
+        assert!(matches!(proofs[1], Proof::Transfer(_)));
+        let batch = crate::proofs::Batch {
+            prev_root,
+            new_root: state.get_commitment().unwrap(),
+            proofs,
+            da_height_range: (1, 1),
+            batch_commitments: vec![],
+        };
+        batch.verify().unwrap();
+    }
+
+    #[test]
+    fn scheduled_tx_is_rejected_unless_enabled() {
+        let key = generate_key();
+        let tx = TransactionBuilder::new(TransactionType::Noop)
+            .execute_at(Some(10))
+            .sign(&key)
+            .unwrap();
+
+        let mut state: State<InMemoryStore> =
+            State::new(Arc::new(InMemoryStore::default()), NoncePolicy::default()).unwrap();
+        let err = state.process_tx(tx.clone(), 1).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&TxError::SchedulingUnsupported));
+
+        let mut state = state.with_scheduled_txs(true);
+        state.process_tx(tx, 1).unwrap();
+        assert_eq!(state.take_scheduled_txs(10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn last_nonce_is_rejected() {
+        let key = generate_key();
+        let vk = keys::verifying_key(&key);
+        let mut state: State<InMemoryStore> =
+            State::new(Arc::new(InMemoryStore::default()), NoncePolicy::AllowGaps).unwrap();
+
+        let tx = TransactionBuilder::new(TransactionType::Noop)
+            .nonce(u64::MAX)
+            .sign(&key)
+            .unwrap();
+        let err = state.process_tx(tx, 1).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&TxError::NonceOverflow));
+        assert!(state.get_account(&vk).unwrap().is_none());
+    }
 }
@@ -0,0 +1,52 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{error::ExecutionError, storage::NodeStore, tree::Digest};
+
+/// Where a transaction is in its lifecycle. Only the sequencer observes the
+/// `Queued` and `Batched` stages; every node that executes observes the final
+/// `Executed` or `Failed` stage.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TxStatus {
+    /// Waiting in the mempool.
+    Queued,
+    /// Executed on the sequencer's soft state, see
+    /// [`SoftState`](crate::soft::SoftState). Only reported in receipts, and
+    /// only final once the transaction is executed from Celestia.
+    SoftConfirmed,
+    /// Drained from the mempool into a batch that is being posted.
+    Batched,
+    /// Posted to Celestia, but not yet executed.
+    Posted { da_height: u64 },
+    /// Executed successfully.
+    Executed { da_height: u64 },
+    /// Included on Celestia, but execution failed.
+    Failed {
+        da_height: u64,
+        error: ExecutionError,
+    },
+}
+
+fn status_key(tx_hash: &Digest) -> String {
+    format!("tx_status:{}", hex::encode(tx_hash.0))
+}
+
+pub fn get_tx_status<S: NodeStore + ?Sized>(
+    store: &S,
+    tx_hash: &Digest,
+) -> Result<Option<TxStatus>> {
+    match store.get_metadata(&status_key(tx_hash))? {
+        Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn set_tx_status<S: NodeStore + ?Sized>(
+    store: &S,
+    tx_hash: &Digest,
+    status: &TxStatus,
+) -> Result<()> {
+    store.put_metadata(&status_key(tx_hash), &bincode::serialize(status)?)
+}
@@ -0,0 +1,319 @@
+//! Proving epochs by re-executing them. Instead of a merkle proof per
+//! transaction, the guest gets the accounts the epoch touches as of its
+//! previous root and a single proof of the tree update, re-executes the
+//! transactions on them and checks that writing the resulting accounts
+//! leads to the new root. It commits [`StfPublicValues`].
+//!
+//! Transfers of [`crate::proofs::Batch`]es are proven the same way, one by
+//! one, see [`crate::proofs::TransferProof`]. Contract transactions, deposits, parameter changes and scheduled
+//! transactions can't be re-executed in the guest yet.
+
+use anyhow::{anyhow, Context, Result};
+use jmt::{
+    proof::{SparseMerkleProof, UpdateMerkleProof},
+    KeyHash,
+};
+use prism_common::keys::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    block::tx_root,
+    journal::Journal,
+    proofs::EpochProof,
+    state::{account_key, Account, AccountData},
+    tree::{Digest, Hasher},
+    tx::{Transaction, TransactionType},
+};
+
+/// The accounts touched by an epoch by their tree key, `None` for absent
+/// ones.
+pub(crate) type Accounts<D = ()> = BTreeMap<[u8; 32], Option<Account<D>>>;
+
+/// An account as of the epoch's previous root, with a proof of its
+/// inclusion or absence.
+#[derive(Serialize, Deserialize)]
+pub struct AccountWitness<D = ()> {
+    pub vk: VerifyingKey,
+    pub account: Option<Account<D>>,
+    pub proof: SparseMerkleProof<Hasher>,
+}
+
+/// The input of the guest in STF mode.
+#[derive(Serialize, Deserialize)]
+pub struct StfWitness<D = ()> {
+    pub prev_root: Digest,
+    pub new_root: Digest,
+    /// The transactions executed in the epoch, in order. Transactions that
+    /// failed on the host aren't part of it.
+    pub txs: Vec<Transaction>,
+    /// Every account the transactions read or write
+    pub accounts: Vec<AccountWitness<D>>,
+    /// Proves that writing the changed accounts to the tree at `prev_root`
+    /// results in `new_root`
+    pub update_proof: UpdateMerkleProof<Hasher>,
+    /// The only key allowed to mint, committed to by
+    /// [`StfPublicValues::mint_authority`]
+    pub mint_vk: Option<VerifyingKey>,
+}
+
+impl<D: AccountData> StfWitness<D> {
+    /// Re-executes the transactions on the witnessed accounts and checks
+    /// that the resulting accounts lead to the new root. This is the logic
+    /// run by the guest programs in STF mode.
+    pub fn verify(self) -> Result<StfPublicValues> {
+        let mint_vk = self.mint_vk;
+        let mut accounts = witnessed_accounts(self.prev_root, &self.accounts)?;
+        let initial = accounts.clone();
+
+        for (i, tx) in self.txs.iter().enumerate() {
+            execute_tx(&mut accounts, tx, mint_vk.as_ref())
+                .with_context(|| format!("Invalid transaction {}", i))?;
+        }
+        self.update_proof
+            .verify_update(
+                self.prev_root.into(),
+                self.new_root.into(),
+                changed_accounts(&initial, &accounts)?,
+            )
+            .map_err(|e| anyhow!("Invalid update proof: {}", e))?;
+
+        Ok(StfPublicValues {
+            prev_root: self.prev_root,
+            new_root: self.new_root,
+            batch_hash: tx_root(&self.txs),
+            mint_authority: mint_authority(mint_vk.as_ref()),
+        })
+    }
+}
+
+/// Checks `witnesses` against `root`, returning the witnessed accounts.
+pub(crate) fn witnessed_accounts<D: AccountData>(
+    root: Digest,
+    witnesses: &[AccountWitness<D>],
+) -> Result<Accounts<D>> {
+    let mut accounts = Accounts::<D>::new();
+    for witness in witnesses {
+        let key = account_key(&witness.vk);
+        match &witness.account {
+            Some(account) => {
+                witness
+                    .proof
+                    .verify_existence(root.into(), key, bincode::serialize(account)?)
+            }
+            None => witness.proof.verify_nonexistence(root.into(), key),
+        }
+        .context("Invalid account witness")?;
+        accounts.insert(key.0, witness.account.clone());
+    }
+    Ok(accounts)
+}
+
+/// Returns the accounts `txs` read or write.
+pub(crate) fn touched_accounts(txs: &[Transaction]) -> Vec<VerifyingKey> {
+    let mut touched: BTreeMap<[u8; 32], VerifyingKey> = BTreeMap::new();
+    for tx in txs {
+        touched.insert(account_key(&tx.vk).0, tx.vk.clone());
+        if let TransactionType::Transfer { to, .. } | TransactionType::Deposit { to, .. } =
+            &tx.tx_type
+        {
+            touched.insert(account_key(to).0, to.clone());
+        }
+    }
+    touched.into_values().collect()
+}
+
+/// Applies `tx` to the accounts it touches, like
+/// [`crate::state::State::process_tx`], with `mint_vk` the only key allowed
+/// to mint. Nonces only have to increase, as for the merkle proofs.
+pub(crate) fn execute_tx<D: AccountData>(
+    accounts: &mut Accounts<D>,
+    tx: &Transaction,
+    mint_vk: Option<&VerifyingKey>,
+) -> Result<()> {
+    tx.verify()?;
+    if tx.execute_at_da_height.is_some() {
+        return Err(anyhow!(
+            "Scheduled transactions can't be re-executed in the guest"
+        ));
+    }
+    let sender_key = account_key(&tx.vk).0;
+    let existing = accounts
+        .get(&sender_key)
+        .ok_or_else(|| anyhow!("Sender account missing from witness"))?;
+    if matches!(tx.tx_type, TransactionType::CloseAccount) && existing.is_none() {
+        return Err(anyhow!("Closing an account that doesn't exist"));
+    }
+    let mut sender = existing.clone().unwrap_or_default();
+    sender.authorize(tx)?;
+    if matches!(tx.tx_type, TransactionType::Mint { .. }) && mint_vk != Some(&tx.vk) {
+        return Err(anyhow!("Mint not sent by the mint authority"));
+    }
+    sender.apply_tx(tx)?;
+
+    match &tx.tx_type {
+        TransactionType::Deploy { .. } | TransactionType::Call { .. } => {
+            return Err(anyhow!(
+                "Contract transactions can't be re-executed in the guest"
+            ));
+        }
+        TransactionType::Deposit { .. } => {
+            return Err(anyhow!("Deposits can't be re-executed in the guest"));
+        }
+        TransactionType::ParamChange { .. } => {
+            return Err(anyhow!(
+                "Parameter changes can't be re-executed in the guest"
+            ));
+        }
+        TransactionType::CloseAccount => {
+            if sender.balance() != 0 {
+                return Err(anyhow!("Closed account still holds a balance"));
+            }
+            accounts.insert(sender_key, None);
+        }
+        TransactionType::Transfer { to, amount } if *to != tx.vk => {
+            let recipient_key = account_key(to).0;
+            let mut recipient = accounts
+                .get(&recipient_key)
+                .ok_or_else(|| anyhow!("Recipient account missing from witness"))?
+                .clone()
+                .unwrap_or_default();
+            recipient.credit(*amount)?;
+            accounts.insert(sender_key, Some(sender));
+            accounts.insert(recipient_key, Some(recipient));
+        }
+        TransactionType::Transfer { amount, .. } => {
+            // a self-transfer only bumps the nonce
+            sender.credit(*amount)?;
+            accounts.insert(sender_key, Some(sender));
+        }
+        _ => {
+            accounts.insert(sender_key, Some(sender));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the tree writes that take `initial` to `accounts`, where `None`
+/// removes the account.
+pub(crate) fn changed_accounts<D: AccountData>(
+    initial: &Accounts<D>,
+    accounts: &Accounts<D>,
+) -> Result<Vec<(KeyHash, Option<Vec<u8>>)>> {
+    let mut changed = Vec::new();
+    for (key, account) in accounts {
+        let value = account.as_ref().map(bincode::serialize).transpose()?;
+        let initial_value = initial
+            .get(key)
+            .and_then(Option::as_ref)
+            .map(bincode::serialize)
+            .transpose()?;
+        if value != initial_value {
+            changed.push((KeyHash(*key), value));
+        }
+    }
+    Ok(changed)
+}
+
+/// Returns the digest [`StfPublicValues::mint_authority`] commits to for
+/// `mint_vk`, all zeros if minting is disabled.
+pub fn mint_authority(mint_vk: Option<&VerifyingKey>) -> Digest {
+    mint_vk.map_or(Digest::new([0; 32]), |vk| Digest::hash(vk.as_bytes()))
+}
+
+/// Checks that `proof`, if it is an STF proof, was executed with `mint_vk`
+/// as the mint authority. Other proofs don't commit to it.
+pub fn check_mint_authority(proof: &EpochProof, mint_vk: Option<&VerifyingKey>) -> Result<()> {
+    if !proof.is_stf() {
+        return Ok(());
+    }
+    let values = StfPublicValues::from_journal(&proof.public_values)?;
+    if values.mint_authority != mint_authority(mint_vk) {
+        return Err(anyhow!(
+            "Epoch {} was proven with another mint authority",
+            proof.epoch
+        ));
+    }
+    Ok(())
+}
+
+/// The public values committed by the guests in STF mode: the epoch's roots
+/// and the [`tx_root`] of its transactions, so verifiers can check which
+/// transactions were executed, and the mint authority they were executed
+/// with, so verifiers can check it is the chain's.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StfPublicValues {
+    pub prev_root: Digest,
+    pub new_root: Digest,
+    pub batch_hash: Digest,
+    /// See [`mint_authority`]
+    pub mint_authority: Digest,
+}
+
+impl StfPublicValues {
+    /// Encodes the values as committed by the guests, see
+    /// [`Journal::to_bytes`].
+    pub fn to_journal(&self) -> Vec<u8> {
+        Journal::Stf(*self).to_bytes()
+    }
+
+    /// Decodes the journal of an STF proof, of any supported version.
+    pub fn from_journal(bytes: &[u8]) -> Result<Self> {
+        match Journal::from_bytes(bytes)? {
+            Journal::Stf(values) => Ok(values),
+            _ => Err(anyhow!("Not the journal of an STF proof")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prism_common::keys::SigningKey;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        keys,
+        state::{NoncePolicy, State},
+        storage::InMemoryStore,
+        testkit::generate_key,
+        tx::TransactionBuilder,
+    };
+
+    /// Executes a mint of `minter` on a state whose mint authority is
+    /// `minter`, and returns the witness of that epoch.
+    fn mint_witness(minter: &SigningKey, minter_vk: &VerifyingKey) -> StfWitness {
+        let mut state: State<InMemoryStore> =
+            State::new(Arc::new(InMemoryStore::default()), NoncePolicy::default())
+                .unwrap()
+                .with_mint_vk(Some(minter_vk.clone()));
+        let mint = TransactionBuilder::new(TransactionType::Mint { amount: 100 })
+            .sign(minter)
+            .unwrap();
+        state.process_tx(mint.clone(), 1).unwrap();
+        state.stf_witness(0, vec![mint]).unwrap()
+    }
+
+    #[test]
+    fn mint_by_authority_verifies() {
+        let authority = generate_key();
+        let authority_vk = keys::verifying_key(&authority);
+        let values = mint_witness(&authority, &authority_vk).verify().unwrap();
+        assert_eq!(values.mint_authority, mint_authority(Some(&authority_vk)));
+    }
+
+    #[test]
+    fn mint_by_non_authority_fails_verification() {
+        let authority_vk = keys::verifying_key(&generate_key());
+        let attacker = generate_key();
+        let attacker_vk = keys::verifying_key(&attacker);
+        let mut witness = mint_witness(&attacker, &attacker_vk);
+
+        witness.mint_vk = Some(authority_vk);
+        assert!(witness.verify().is_err());
+
+        let mut witness = mint_witness(&attacker, &attacker_vk);
+        witness.mint_vk = None;
+        assert!(witness.verify().is_err());
+    }
+}
@@ -0,0 +1,1240 @@
+use anyhow::{anyhow, Context, Result};
+use jmt::{
+    storage::{LeafNode, Node, NodeBatch, NodeKey, TreeReader, TreeWriter},
+    KeyHash, OwnedValue, Version,
+};
+use lru::LruCache;
+use rocksdb::{Direction, IteratorMode, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    num::NonZeroUsize,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+use utoipa::ToSchema;
+
+const NODE_PREFIX: &[u8] = b"node:";
+const VALUE_PREFIX: &[u8] = b"value:";
+const METADATA_PREFIX: &[u8] = b"meta:";
+
+/// Metadata key under which the latest committed tree epoch is stored.
+const EPOCH_KEY: &str = "epoch";
+/// Metadata key under which the last fully processed DA height is stored.
+const DA_HEIGHT_KEY: &str = "da_height";
+
+/// Backing store for the [`KeyDirectoryTree`](crate::tree::KeyDirectoryTree).
+/// Besides the JMT nodes and values, a store also keeps small pieces of node
+/// metadata (e.g. the latest committed epoch) so state can be resumed after a
+/// restart.
+pub trait NodeStore: TreeReader + TreeWriter + Send + Sync + 'static {
+    fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    fn put_metadata(&self, key: &str, value: &[u8]) -> Result<()>;
+    fn delete_metadata(&self, key: &str) -> Result<()>;
+
+    /// Returns all metadata entries whose key starts with `prefix`.
+    fn iter_metadata(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>>;
+
+    /// Returns up to `limit` metadata entries whose key starts with
+    /// `prefix`, ordered by key, from the first key after `prefix` followed
+    /// by `after` on. Stores that can't seek to a key read every entry.
+    fn iter_metadata_range(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let after = after.map(|after| format!("{}{}", prefix, after));
+        let mut entries = self.iter_metadata(prefix)?;
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(entries
+            .into_iter()
+            .skip_while(|(key, _)| after.as_ref().is_some_and(|after| key <= after))
+            .take(limit)
+            .collect())
+    }
+
+    /// Returns the latest value of every key present in the tree at
+    /// `max_version`.
+    fn iter_values(&self, max_version: Version) -> Result<Vec<(KeyHash, OwnedValue)>>;
+
+    /// Deletes all tree nodes and values written after `max_version`, e.g.
+    /// to roll back epochs built on reorged DA blocks.
+    fn truncate_versions(&self, max_version: Version) -> Result<()>;
+
+    /// Deletes tree nodes, e.g. ones that became stale before the oldest
+    /// retained version.
+    fn delete_nodes(&self, node_keys: &[NodeKey]) -> Result<()>;
+
+    /// Deletes the values of `key_hash` that no version from `version` on
+    /// can read, given one was written at `version`: all older ones, and
+    /// that one too if it marks the key as removed. Returns the number of
+    /// deleted values.
+    fn prune_values(&self, key_hash: KeyHash, version: Version) -> Result<usize>;
+
+    /// Buffers all writes from now on, including those of other callers,
+    /// until [`NodeStore::commit_writes`] persists them at once, so a crash
+    /// never leaves only some of them persisted. Reads see the buffered
+    /// writes. Calling it with writes already buffered keeps buffering them.
+    /// Stores that don't persist anything write directly.
+    ///
+    /// Writers running concurrently with the buffering one, like the pruning
+    /// task or the persisted mempool, join its batch: their writes are only
+    /// persisted with its commit, and a crash before it loses them too. Both
+    /// tolerate that, pruning runs again and the mempool is best effort.
+    fn begin_writes(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Atomically persists the writes buffered since
+    /// [`NodeStore::begin_writes`] and stops buffering. If persisting fails,
+    /// the writes stay buffered for the next commit.
+    fn commit_writes(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the usage of the store's node cache, if it has one.
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+
+    fn get_epoch(&self) -> Result<Option<u64>> {
+        match self.get_metadata(EPOCH_KEY)? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_epoch(&self, epoch: u64) -> Result<()> {
+        self.put_metadata(EPOCH_KEY, &bincode::serialize(&epoch)?)
+    }
+
+    fn get_da_height(&self) -> Result<Option<u64>> {
+        match self.get_metadata(DA_HEIGHT_KEY)? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Records that all blocks up to `da_height` have been processed, leaving
+    /// the tree at `epoch`.
+    fn set_da_height(&self, da_height: u64, epoch: u64) -> Result<()> {
+        self.put_metadata(
+            &format!("epoch_da_height:{}", epoch),
+            &bincode::serialize(&da_height)?,
+        )?;
+        self.put_metadata(
+            &format!("da_height_epoch:{}", da_height),
+            &bincode::serialize(&epoch)?,
+        )?;
+        self.put_metadata(DA_HEIGHT_KEY, &bincode::serialize(&da_height)?)
+    }
+
+    /// Returns the epoch the tree was at after processing `da_height`.
+    fn get_da_height_epoch(&self, da_height: u64) -> Result<Option<u64>> {
+        match self.get_metadata(&format!("da_height_epoch:{}", da_height))? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the DA height after which the tree was at `epoch`, if `epoch`
+    /// ended on a DA block boundary.
+    fn get_epoch_da_height(&self, epoch: u64) -> Result<Option<u64>> {
+        match self.get_metadata(&format!("epoch_da_height:{}", epoch))? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Opens a [`RocksDBStore`] at `db_path` if given, caching up to
+/// `node_cache_size` tree nodes in front of it, otherwise falls back to an
+/// [`InMemoryStore`] whose contents are lost on shutdown.
+pub fn open_store(db_path: Option<&Path>, node_cache_size: usize) -> Result<Box<dyn NodeStore>> {
+    match db_path {
+        Some(path) => {
+            let store = RocksDBStore::open(path)?;
+            match NonZeroUsize::new(node_cache_size) {
+                Some(capacity) => Ok(Box::new(CachedStore::new(store, capacity))),
+                None => Ok(Box::new(store)),
+            }
+        }
+        None => Ok(Box::new(InMemoryStore::default())),
+    }
+}
+
+impl TreeReader for Box<dyn NodeStore> {
+    fn get_node_option(&self, node_key: &NodeKey) -> Result<Option<Node>> {
+        self.as_ref().get_node_option(node_key)
+    }
+
+    fn get_rightmost_leaf(&self) -> Result<Option<(NodeKey, LeafNode)>> {
+        self.as_ref().get_rightmost_leaf()
+    }
+
+    fn get_value_option(
+        &self,
+        max_version: Version,
+        key_hash: KeyHash,
+    ) -> Result<Option<OwnedValue>> {
+        self.as_ref().get_value_option(max_version, key_hash)
+    }
+}
+
+impl TreeWriter for Box<dyn NodeStore> {
+    fn write_node_batch(&self, node_batch: &NodeBatch) -> Result<()> {
+        self.as_ref().write_node_batch(node_batch)
+    }
+}
+
+impl NodeStore for Box<dyn NodeStore> {
+    fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.as_ref().get_metadata(key)
+    }
+
+    fn put_metadata(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.as_ref().put_metadata(key, value)
+    }
+
+    fn delete_metadata(&self, key: &str) -> Result<()> {
+        self.as_ref().delete_metadata(key)
+    }
+
+    fn iter_metadata(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        self.as_ref().iter_metadata(prefix)
+    }
+
+    fn iter_metadata_range(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        self.as_ref().iter_metadata_range(prefix, after, limit)
+    }
+
+    fn iter_values(&self, max_version: Version) -> Result<Vec<(KeyHash, OwnedValue)>> {
+        self.as_ref().iter_values(max_version)
+    }
+
+    fn truncate_versions(&self, max_version: Version) -> Result<()> {
+        self.as_ref().truncate_versions(max_version)
+    }
+
+    fn delete_nodes(&self, node_keys: &[NodeKey]) -> Result<()> {
+        self.as_ref().delete_nodes(node_keys)
+    }
+
+    fn prune_values(&self, key_hash: KeyHash, version: Version) -> Result<usize> {
+        self.as_ref().prune_values(key_hash, version)
+    }
+
+    fn begin_writes(&self) -> Result<()> {
+        self.as_ref().begin_writes()
+    }
+
+    fn commit_writes(&self) -> Result<()> {
+        self.as_ref().commit_writes()
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        self.as_ref().cache_stats()
+    }
+}
+
+/// A non-persistent store, useful for local development and tests.
+#[derive(Default)]
+pub struct InMemoryStore {
+    nodes: RwLock<HashMap<NodeKey, Node>>,
+    values: RwLock<BTreeMap<(KeyHash, Version), Option<OwnedValue>>>,
+    metadata: RwLock<HashMap<String, Vec<u8>>>,
+}
+
+impl InMemoryStore {
+    /// Returns the latest value of `key_hash` at or before `max_version`:
+    /// `None` if none was written, `Some(None)` if the key was removed.
+    fn latest_value(
+        &self,
+        max_version: Version,
+        key_hash: KeyHash,
+    ) -> Result<Option<Option<OwnedValue>>> {
+        let values = self.values.read().map_err(|e| anyhow!("{}", e))?;
+        Ok(values
+            .range((key_hash, 0)..=(key_hash, max_version))
+            .next_back()
+            .map(|(_, value)| value.clone()))
+    }
+
+    /// Returns the latest value at or before `max_version` of every key
+    /// written, `None` for removed keys.
+    fn latest_values(&self, max_version: Version) -> Result<BTreeMap<KeyHash, Option<OwnedValue>>> {
+        let values = self.values.read().map_err(|e| anyhow!("{}", e))?;
+        let mut latest = BTreeMap::new();
+        for ((key_hash, version), value) in values.iter() {
+            if *version <= max_version {
+                latest.insert(*key_hash, value.clone());
+            }
+        }
+        Ok(latest)
+    }
+}
+
+impl TreeReader for InMemoryStore {
+    fn get_node_option(&self, node_key: &NodeKey) -> Result<Option<Node>> {
+        let nodes = self.nodes.read().map_err(|e| anyhow!("{}", e))?;
+        Ok(nodes.get(node_key).cloned())
+    }
+
+    fn get_rightmost_leaf(&self) -> Result<Option<(NodeKey, LeafNode)>> {
+        Err(anyhow!("JMT restoration from snapshot is unimplemented"))
+    }
+
+    fn get_value_option(
+        &self,
+        max_version: Version,
+        key_hash: KeyHash,
+    ) -> Result<Option<OwnedValue>> {
+        Ok(self.latest_value(max_version, key_hash)?.flatten())
+    }
+}
+
+impl TreeWriter for InMemoryStore {
+    fn write_node_batch(&self, node_batch: &NodeBatch) -> Result<()> {
+        let mut nodes = self.nodes.write().map_err(|e| anyhow!("{}", e))?;
+        for (node_key, node) in node_batch.nodes() {
+            nodes.insert(node_key.clone(), node.clone());
+        }
+
+        let mut values = self.values.write().map_err(|e| anyhow!("{}", e))?;
+        for ((version, key_hash), value) in node_batch.values() {
+            values.insert((*key_hash, *version), value.clone());
+        }
+        Ok(())
+    }
+}
+
+impl NodeStore for InMemoryStore {
+    fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let metadata = self.metadata.read().map_err(|e| anyhow!("{}", e))?;
+        Ok(metadata.get(key).cloned())
+    }
+
+    fn put_metadata(&self, key: &str, value: &[u8]) -> Result<()> {
+        let mut metadata = self.metadata.write().map_err(|e| anyhow!("{}", e))?;
+        metadata.insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete_metadata(&self, key: &str) -> Result<()> {
+        let mut metadata = self.metadata.write().map_err(|e| anyhow!("{}", e))?;
+        metadata.remove(key);
+        Ok(())
+    }
+
+    fn iter_metadata(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        let metadata = self.metadata.read().map_err(|e| anyhow!("{}", e))?;
+        Ok(metadata
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    fn iter_values(&self, max_version: Version) -> Result<Vec<(KeyHash, OwnedValue)>> {
+        Ok(self
+            .latest_values(max_version)?
+            .into_iter()
+            .filter_map(|(key_hash, value)| value.map(|value| (key_hash, value)))
+            .collect())
+    }
+
+    fn truncate_versions(&self, max_version: Version) -> Result<()> {
+        let mut nodes = self.nodes.write().map_err(|e| anyhow!("{}", e))?;
+        nodes.retain(|node_key, _| node_key.version() <= max_version);
+        let mut values = self.values.write().map_err(|e| anyhow!("{}", e))?;
+        values.retain(|(_, version), _| *version <= max_version);
+        Ok(())
+    }
+
+    fn delete_nodes(&self, node_keys: &[NodeKey]) -> Result<()> {
+        let mut nodes = self.nodes.write().map_err(|e| anyhow!("{}", e))?;
+        for node_key in node_keys {
+            nodes.remove(node_key);
+        }
+        Ok(())
+    }
+
+    fn prune_values(&self, key_hash: KeyHash, version: Version) -> Result<usize> {
+        let mut values = self.values.write().map_err(|e| anyhow!("{}", e))?;
+        let prunable = prunable_values(
+            values
+                .range((key_hash, 0)..=(key_hash, version))
+                .map(|((key_hash, version), value)| (*key_hash, *version, value.is_none())),
+            version,
+        );
+        for key in &prunable {
+            values.remove(key);
+        }
+        Ok(prunable.len())
+    }
+}
+
+/// Returns the values of `values`, ordered by key hash and version, that
+/// no version from `min_version` on can read, see
+/// [`NodeStore::prune_values`]. Each value is given as its key hash, its
+/// version and whether it marks the key as removed.
+fn prunable_values(
+    values: impl Iterator<Item = (KeyHash, Version, bool)>,
+    min_version: Version,
+) -> Vec<(KeyHash, Version)> {
+    let mut prunable = Vec::new();
+    // the latest value of the current key at or before min_version
+    let mut latest: Option<(KeyHash, Version)> = None;
+    for (key_hash, version, removed) in values {
+        if version > min_version {
+            continue;
+        }
+        if let Some((latest_key, latest_version)) = latest {
+            if latest_key == key_hash {
+                prunable.push((latest_key, latest_version));
+            }
+        }
+        latest = Some((key_hash, version));
+        // all older values of the key are pruned, so reading it without the
+        // removal marker returns nothing as well
+        if removed {
+            prunable.push((key_hash, version));
+            latest = None;
+        }
+    }
+    prunable
+}
+
+/// Writes buffered by a [`RocksDBStore`] by their raw key, where `None`
+/// deletes the key.
+type PendingWrites = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// A persistent store backed by RocksDB.
+pub struct RocksDBStore {
+    db: DB,
+    /// The writes buffered since [`NodeStore::begin_writes`], if called,
+    /// from every caller and not just the one that began buffering
+    pending: Mutex<Option<PendingWrites>>,
+}
+
+impl RocksDBStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let db = DB::open_default(path)
+            .with_context(|| format!("Failed to open RocksDB at {}", path.display()))?;
+        Ok(RocksDBStore {
+            db,
+            pending: Mutex::new(None),
+        })
+    }
+
+    fn node_key(node_key: &NodeKey) -> Result<Vec<u8>> {
+        Ok([NODE_PREFIX, &bincode::serialize(node_key)?].concat())
+    }
+
+    fn value_key(key_hash: &KeyHash, version: Version) -> Vec<u8> {
+        [VALUE_PREFIX, &key_hash.0, &version.to_be_bytes()].concat()
+    }
+
+    fn metadata_key(key: &str) -> Vec<u8> {
+        [METADATA_PREFIX, key.as_bytes()].concat()
+    }
+
+    /// Applies `writes` atomically, or buffers them if writes are being
+    /// buffered.
+    fn write(&self, writes: impl IntoIterator<Item = (Vec<u8>, Option<Vec<u8>>)>) -> Result<()> {
+        let mut pending = self.pending.lock().map_err(|e| anyhow!("{}", e))?;
+        if let Some(pending) = pending.as_mut() {
+            pending.extend(writes);
+            return Ok(());
+        }
+        let mut batch = WriteBatch::default();
+        for (key, value) in writes {
+            match value {
+                Some(value) => batch.put(key, value),
+                None => batch.delete(key),
+            }
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        {
+            let pending = self.pending.lock().map_err(|e| anyhow!("{}", e))?;
+            if let Some(value) = pending.as_ref().and_then(|pending| pending.get(key)) {
+                return Ok(value.clone());
+            }
+        }
+        Ok(self.db.get(key)?)
+    }
+
+    /// Returns the buffered writes to keys starting with `prefix`, from the
+    /// key `from` on.
+    fn buffered(&self, prefix: &[u8], from: &[u8]) -> Result<PendingWrites> {
+        let pending = self.pending.lock().map_err(|e| anyhow!("{}", e))?;
+        Ok(pending
+            .iter()
+            .flat_map(|pending| pending.range(from.to_vec()..))
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    /// Calls `f` with every entry whose key starts with `prefix`, ordered by
+    /// key, including buffered writes.
+    fn for_each_prefixed(
+        &self,
+        prefix: &[u8],
+        mut f: impl FnMut(&[u8], &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        self.for_each_prefixed_from(prefix, prefix, |key, value| f(key, value).map(|()| true))
+    }
+
+    /// Like [`RocksDBStore::for_each_prefixed`], but starts at the key
+    /// `from` and stops as soon as `f` returns false.
+    fn for_each_prefixed_from(
+        &self,
+        prefix: &[u8],
+        from: &[u8],
+        mut f: impl FnMut(&[u8], &[u8]) -> Result<bool>,
+    ) -> Result<()> {
+        // taken before iterating, so writes committed meanwhile are read
+        // from one of both
+        let mut buffered = self.buffered(prefix, from)?.into_iter().peekable();
+        for entry in self
+            .db
+            .iterator(IteratorMode::From(from, Direction::Forward))
+        {
+            let (key, value) = entry?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            let mut overwritten = false;
+            while let Some((buffered_key, buffered_value)) =
+                buffered.next_if(|(buffered_key, _)| **buffered_key <= *key)
+            {
+                overwritten = *buffered_key == *key;
+                if let Some(buffered_value) = buffered_value {
+                    if !f(&buffered_key, &buffered_value)? {
+                        return Ok(());
+                    }
+                }
+            }
+            if !overwritten && !f(&key, &value)? {
+                return Ok(());
+            }
+        }
+        for (key, value) in buffered {
+            if let Some(value) = value {
+                if !f(&key, &value)? {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TreeReader for RocksDBStore {
+    fn get_node_option(&self, node_key: &NodeKey) -> Result<Option<Node>> {
+        match self.get(&Self::node_key(node_key)?)? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_rightmost_leaf(&self) -> Result<Option<(NodeKey, LeafNode)>> {
+        Err(anyhow!("JMT restoration from snapshot is unimplemented"))
+    }
+
+    fn get_value_option(
+        &self,
+        max_version: Version,
+        key_hash: KeyHash,
+    ) -> Result<Option<OwnedValue>> {
+        let value_prefix = [VALUE_PREFIX, &key_hash.0].concat();
+        let start = Self::value_key(&key_hash, max_version);
+        let buffered = self.buffered(&value_prefix, &value_prefix)?;
+
+        let mut latest = None;
+        let iter = self
+            .db
+            .iterator(IteratorMode::From(&start, Direction::Reverse));
+        for entry in iter {
+            let (key, value) = entry?;
+            if !key.starts_with(&value_prefix) {
+                break;
+            }
+            // skips values deleted by buffered writes
+            if buffered.get(&*key).is_some_and(Option::is_none) {
+                continue;
+            }
+            latest = Some((key.to_vec(), value.to_vec()));
+            break;
+        }
+        let buffered_latest = buffered
+            .into_iter()
+            .rev()
+            .filter(|(key, _)| *key <= start)
+            .find_map(|(key, value)| value.map(|value| (key, value)));
+        // versions are big endian, so the larger key is the later version
+        match latest.into_iter().chain(buffered_latest).max() {
+            Some((_, value)) => Ok(bincode::deserialize(&value)?),
+            None => Ok(None),
+        }
+    }
+}
+
+impl TreeWriter for RocksDBStore {
+    fn write_node_batch(&self, node_batch: &NodeBatch) -> Result<()> {
+        let mut writes = Vec::new();
+
+        for (node_key, node) in node_batch.nodes() {
+            writes.push((Self::node_key(node_key)?, Some(bincode::serialize(node)?)));
+        }
+
+        for ((version, key_hash), value) in node_batch.values() {
+            writes.push((
+                Self::value_key(key_hash, *version),
+                Some(bincode::serialize(value)?),
+            ));
+        }
+
+        self.write(writes)
+    }
+}
+
+impl NodeStore for RocksDBStore {
+    fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.get(&Self::metadata_key(key))
+    }
+
+    fn put_metadata(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.write([(Self::metadata_key(key), Some(value.to_vec()))])
+    }
+
+    fn delete_metadata(&self, key: &str) -> Result<()> {
+        self.write([(Self::metadata_key(key), None)])
+    }
+
+    fn iter_metadata(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut entries = Vec::new();
+        self.for_each_prefixed(&Self::metadata_key(prefix), |key, value| {
+            let key = String::from_utf8(key[METADATA_PREFIX.len()..].to_vec())?;
+            entries.push((key, value.to_vec()));
+            Ok(())
+        })?;
+        Ok(entries)
+    }
+
+    fn iter_metadata_range(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let prefix_key = Self::metadata_key(prefix);
+        let after_key = after.map(|after| Self::metadata_key(&format!("{}{}", prefix, after)));
+        let from = after_key.as_deref().unwrap_or(&prefix_key);
+        let mut entries = Vec::new();
+        if limit == 0 {
+            return Ok(entries);
+        }
+        self.for_each_prefixed_from(&prefix_key, from, |key, value| {
+            if after_key.as_deref() == Some(key) {
+                return Ok(true);
+            }
+            let key = String::from_utf8(key[METADATA_PREFIX.len()..].to_vec())?;
+            entries.push((key, value.to_vec()));
+            Ok(entries.len() < limit)
+        })?;
+        Ok(entries)
+    }
+
+    fn iter_values(&self, max_version: Version) -> Result<Vec<(KeyHash, OwnedValue)>> {
+        // keys are ordered by (key hash, version), so the last entry per key
+        // hash with a version <= max_version is its latest value
+        let mut latest: BTreeMap<KeyHash, Option<OwnedValue>> = BTreeMap::new();
+        self.for_each_prefixed(VALUE_PREFIX, |key, value| {
+            let key = &key[VALUE_PREFIX.len()..];
+            if key.len() != 40 {
+                return Err(anyhow!("Invalid value key length: {}", key.len()));
+            }
+
+            let mut key_hash = [0u8; 32];
+            key_hash.copy_from_slice(&key[..32]);
+            let mut version = [0u8; 8];
+            version.copy_from_slice(&key[32..]);
+            if Version::from_be_bytes(version) <= max_version {
+                latest.insert(KeyHash(key_hash), bincode::deserialize(value)?);
+            }
+            Ok(())
+        })?;
+        Ok(latest
+            .into_iter()
+            .filter_map(|(key_hash, value)| value.map(|value| (key_hash, value)))
+            .collect())
+    }
+
+    fn truncate_versions(&self, max_version: Version) -> Result<()> {
+        let mut deletes = Vec::new();
+        self.for_each_prefixed(NODE_PREFIX, |key, _| {
+            let node_key: NodeKey = bincode::deserialize(&key[NODE_PREFIX.len()..])?;
+            if node_key.version() > max_version {
+                deletes.push((key.to_vec(), None));
+            }
+            Ok(())
+        })?;
+        self.for_each_prefixed(VALUE_PREFIX, |key, _| {
+            let mut version = [0u8; 8];
+            version.copy_from_slice(&key[key.len() - 8..]);
+            if Version::from_be_bytes(version) > max_version {
+                deletes.push((key.to_vec(), None));
+            }
+            Ok(())
+        })?;
+        self.write(deletes)
+    }
+
+    fn delete_nodes(&self, node_keys: &[NodeKey]) -> Result<()> {
+        let mut deletes = Vec::new();
+        for node_key in node_keys {
+            deletes.push((Self::node_key(node_key)?, None));
+        }
+        self.write(deletes)
+    }
+
+    fn prune_values(&self, key_hash: KeyHash, version: Version) -> Result<usize> {
+        // only the values of `key_hash` are read, not all of them
+        let value_prefix = [VALUE_PREFIX, &key_hash.0].concat();
+        let mut values = Vec::new();
+        self.for_each_prefixed(&value_prefix, |key, value| {
+            let key = &key[value_prefix.len()..];
+            if key.len() != 8 {
+                return Err(anyhow!("Invalid value key version length: {}", key.len()));
+            }
+            let mut value_version = [0u8; 8];
+            value_version.copy_from_slice(key);
+            let value: Option<OwnedValue> = bincode::deserialize(value)?;
+            values.push((
+                key_hash,
+                Version::from_be_bytes(value_version),
+                value.is_none(),
+            ));
+            Ok(())
+        })?;
+
+        let prunable = prunable_values(values.into_iter(), version);
+        self.write(
+            prunable
+                .iter()
+                .map(|(key_hash, version)| (Self::value_key(key_hash, *version), None)),
+        )?;
+        Ok(prunable.len())
+    }
+
+    fn begin_writes(&self) -> Result<()> {
+        let mut pending = self.pending.lock().map_err(|e| anyhow!("{}", e))?;
+        pending.get_or_insert_with(BTreeMap::new);
+        Ok(())
+    }
+
+    fn commit_writes(&self) -> Result<()> {
+        // held while writing, so reads never miss writes in between the
+        // buffer and the database
+        let mut pending = self.pending.lock().map_err(|e| anyhow!("{}", e))?;
+        let Some(writes) = pending.as_ref() else {
+            return Ok(());
+        };
+        let mut batch = WriteBatch::default();
+        for (key, value) in writes {
+            match value {
+                Some(value) => batch.put(key, value),
+                None => batch.delete(key),
+            }
+        }
+        self.db.write(batch)?;
+        *pending = None;
+        Ok(())
+    }
+}
+
+/// Layers in-memory writes over a read-only base store, e.g. to execute
+/// transactions speculatively without touching the canonical state. Reads
+/// of the base are pinned to the version the overlay was created at, so the
+/// base can keep advancing underneath it.
+pub struct OverlayStore<S: NodeStore> {
+    base: Arc<S>,
+    base_version: Version,
+    overlay: InMemoryStore,
+    /// Metadata keys deleted in the overlay, which must not be read from the
+    /// base anymore
+    deleted_metadata: RwLock<HashSet<String>>,
+}
+
+impl<S: NodeStore> OverlayStore<S> {
+    pub fn new(base: Arc<S>) -> Result<Self> {
+        let base_version = base.get_epoch()?.unwrap_or(0);
+        Ok(OverlayStore {
+            base,
+            base_version,
+            overlay: InMemoryStore::default(),
+            deleted_metadata: RwLock::new(HashSet::new()),
+        })
+    }
+}
+
+impl<S: NodeStore> TreeReader for OverlayStore<S> {
+    fn get_node_option(&self, node_key: &NodeKey) -> Result<Option<Node>> {
+        if let Some(node) = self.overlay.get_node_option(node_key)? {
+            return Ok(Some(node));
+        }
+        if node_key.version() > self.base_version {
+            return Ok(None);
+        }
+        self.base.get_node_option(node_key)
+    }
+
+    fn get_rightmost_leaf(&self) -> Result<Option<(NodeKey, LeafNode)>> {
+        Err(anyhow!("JMT restoration from snapshot is unimplemented"))
+    }
+
+    fn get_value_option(
+        &self,
+        max_version: Version,
+        key_hash: KeyHash,
+    ) -> Result<Option<OwnedValue>> {
+        // a key removed in the overlay must not read as its base value
+        if let Some(value) = self.overlay.latest_value(max_version, key_hash)? {
+            return Ok(value);
+        }
+        self.base
+            .get_value_option(max_version.min(self.base_version), key_hash)
+    }
+}
+
+impl<S: NodeStore> TreeWriter for OverlayStore<S> {
+    fn write_node_batch(&self, node_batch: &NodeBatch) -> Result<()> {
+        self.overlay.write_node_batch(node_batch)
+    }
+}
+
+impl<S: NodeStore> NodeStore for OverlayStore<S> {
+    fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.overlay.get_metadata(key)? {
+            return Ok(Some(value));
+        }
+        let deleted = self.deleted_metadata.read().map_err(|e| anyhow!("{}", e))?;
+        if deleted.contains(key) {
+            return Ok(None);
+        }
+        self.base.get_metadata(key)
+    }
+
+    fn put_metadata(&self, key: &str, value: &[u8]) -> Result<()> {
+        let mut deleted = self
+            .deleted_metadata
+            .write()
+            .map_err(|e| anyhow!("{}", e))?;
+        deleted.remove(key);
+        self.overlay.put_metadata(key, value)
+    }
+
+    /// The base is never written to, the key is hidden from its reads
+    /// instead.
+    fn delete_metadata(&self, key: &str) -> Result<()> {
+        let mut deleted = self
+            .deleted_metadata
+            .write()
+            .map_err(|e| anyhow!("{}", e))?;
+        deleted.insert(key.to_string());
+        self.overlay.delete_metadata(key)
+    }
+
+    fn iter_metadata(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        let deleted = self.deleted_metadata.read().map_err(|e| anyhow!("{}", e))?;
+        let mut entries: BTreeMap<String, Vec<u8>> = self
+            .base
+            .iter_metadata(prefix)?
+            .into_iter()
+            .filter(|(key, _)| !deleted.contains(key))
+            .collect();
+        entries.extend(self.overlay.iter_metadata(prefix)?);
+        Ok(entries.into_iter().collect())
+    }
+
+    fn iter_values(&self, max_version: Version) -> Result<Vec<(KeyHash, OwnedValue)>> {
+        let mut values: BTreeMap<KeyHash, OwnedValue> = self
+            .base
+            .iter_values(max_version.min(self.base_version))?
+            .into_iter()
+            .collect();
+        for (key_hash, value) in self.overlay.latest_values(max_version)? {
+            match value {
+                Some(value) => values.insert(key_hash, value),
+                None => values.remove(&key_hash),
+            };
+        }
+        Ok(values.into_iter().collect())
+    }
+
+    fn truncate_versions(&self, max_version: Version) -> Result<()> {
+        self.overlay.truncate_versions(max_version)
+    }
+
+    /// Only deletes overlay nodes, the base is never written to.
+    fn delete_nodes(&self, node_keys: &[NodeKey]) -> Result<()> {
+        self.overlay.delete_nodes(node_keys)
+    }
+
+    fn prune_values(&self, key_hash: KeyHash, version: Version) -> Result<usize> {
+        self.overlay.prune_values(key_hash, version)
+    }
+}
+
+/// The usage of a [`CachedStore`] since the node started.
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema, Default, Debug)]
+pub struct CacheStats {
+    /// The maximum number of cached nodes, 0 if nodes aren't cached
+    pub capacity: usize,
+    pub cached_nodes: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Keeps the most recently used tree nodes of a store in memory, as proofs
+/// and reads walk the same upper nodes over and over. Written nodes are
+/// cached as well, so recent leaves are read without a round trip. Nodes
+/// never change once written, so only deleting them invalidates the cache.
+pub struct CachedStore<S: NodeStore> {
+    inner: S,
+    nodes: Mutex<LruCache<NodeKey, Node>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<S: NodeStore> CachedStore<S> {
+    pub fn new(inner: S, capacity: NonZeroUsize) -> Self {
+        CachedStore {
+            inner,
+            nodes: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<S: NodeStore> TreeReader for CachedStore<S> {
+    fn get_node_option(&self, node_key: &NodeKey) -> Result<Option<Node>> {
+        {
+            let mut nodes = self.nodes.lock().map_err(|e| anyhow!("{}", e))?;
+            if let Some(node) = nodes.get(node_key) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(Some(node.clone()));
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let node = self.inner.get_node_option(node_key)?;
+        if let Some(node) = &node {
+            let mut nodes = self.nodes.lock().map_err(|e| anyhow!("{}", e))?;
+            nodes.put(node_key.clone(), node.clone());
+        }
+        Ok(node)
+    }
+
+    fn get_rightmost_leaf(&self) -> Result<Option<(NodeKey, LeafNode)>> {
+        self.inner.get_rightmost_leaf()
+    }
+
+    fn get_value_option(
+        &self,
+        max_version: Version,
+        key_hash: KeyHash,
+    ) -> Result<Option<OwnedValue>> {
+        self.inner.get_value_option(max_version, key_hash)
+    }
+}
+
+impl<S: NodeStore> TreeWriter for CachedStore<S> {
+    fn write_node_batch(&self, node_batch: &NodeBatch) -> Result<()> {
+        self.inner.write_node_batch(node_batch)?;
+        let mut nodes = self.nodes.lock().map_err(|e| anyhow!("{}", e))?;
+        for (node_key, node) in node_batch.nodes() {
+            nodes.put(node_key.clone(), node.clone());
+        }
+        Ok(())
+    }
+}
+
+impl<S: NodeStore> NodeStore for CachedStore<S> {
+    fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.inner.get_metadata(key)
+    }
+
+    fn put_metadata(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.inner.put_metadata(key, value)
+    }
+
+    fn delete_metadata(&self, key: &str) -> Result<()> {
+        self.inner.delete_metadata(key)
+    }
+
+    fn iter_metadata(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        self.inner.iter_metadata(prefix)
+    }
+
+    fn iter_metadata_range(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        self.inner.iter_metadata_range(prefix, after, limit)
+    }
+
+    fn iter_values(&self, max_version: Version) -> Result<Vec<(KeyHash, OwnedValue)>> {
+        self.inner.iter_values(max_version)
+    }
+
+    /// Empties the cache, as the truncated versions are written anew.
+    fn truncate_versions(&self, max_version: Version) -> Result<()> {
+        self.inner.truncate_versions(max_version)?;
+        self.nodes.lock().map_err(|e| anyhow!("{}", e))?.clear();
+        Ok(())
+    }
+
+    fn delete_nodes(&self, node_keys: &[NodeKey]) -> Result<()> {
+        self.inner.delete_nodes(node_keys)?;
+        let mut nodes = self.nodes.lock().map_err(|e| anyhow!("{}", e))?;
+        for node_key in node_keys {
+            nodes.pop(node_key);
+        }
+        Ok(())
+    }
+
+    fn prune_values(&self, key_hash: KeyHash, version: Version) -> Result<usize> {
+        self.inner.prune_values(key_hash, version)
+    }
+
+    fn begin_writes(&self) -> Result<()> {
+        self.inner.begin_writes()
+    }
+
+    fn commit_writes(&self) -> Result<()> {
+        self.inner.commit_writes()
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        let nodes = self.nodes.lock().ok()?;
+        Some(CacheStats {
+            capacity: nodes.cap().get(),
+            cached_nodes: nodes.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A directory that is removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("shard-storage-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Writes `value` of `key_hash` at `version`, where `None` marks the key
+    /// as removed.
+    fn put_value(store: &RocksDBStore, key_hash: KeyHash, version: Version, value: Option<&[u8]>) {
+        let value = bincode::serialize(&value.map(<[u8]>::to_vec)).unwrap();
+        store
+            .write([(RocksDBStore::value_key(&key_hash, version), Some(value))])
+            .unwrap();
+    }
+
+    fn get_value(store: &RocksDBStore, key_hash: KeyHash, version: Version) -> Option<OwnedValue> {
+        store.get_value_option(version, key_hash).unwrap()
+    }
+
+    #[test]
+    fn prunable_values_keep_the_latest_value_at_min_version() {
+        let (a, b) = (KeyHash([1; 32]), KeyHash([2; 32]));
+        let values = [
+            (a, 1, false),
+            (a, 2, false),
+            (a, 4, false),
+            (b, 1, false),
+            (b, 3, true),
+            (b, 5, false),
+        ];
+        // a's value at 2 is read from 3 on, b's removal at 3 reads as
+        // nothing without the older values too
+        assert_eq!(
+            prunable_values(values.into_iter(), 3),
+            vec![(a, 1), (b, 1), (b, 3)]
+        );
+        assert_eq!(prunable_values(values.into_iter(), 0), vec![]);
+    }
+
+    #[test]
+    fn buffered_metadata_is_read_before_commit() {
+        let dir = TempDir::new("metadata");
+        let store = RocksDBStore::open(&dir.0).unwrap();
+        store.put_metadata("a", b"1").unwrap();
+
+        store.begin_writes().unwrap();
+        store.put_metadata("b", b"2").unwrap();
+        store.delete_metadata("a").unwrap();
+        assert_eq!(store.get_metadata("a").unwrap(), None);
+        assert_eq!(store.get_metadata("b").unwrap(), Some(b"2".to_vec()));
+        // nothing is persisted before the commit
+        assert!(store
+            .db
+            .get(RocksDBStore::metadata_key("a"))
+            .unwrap()
+            .is_some());
+        assert!(store
+            .db
+            .get(RocksDBStore::metadata_key("b"))
+            .unwrap()
+            .is_none());
+
+        store.commit_writes().unwrap();
+        assert!(store
+            .db
+            .get(RocksDBStore::metadata_key("a"))
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            store.db.get(RocksDBStore::metadata_key("b")).unwrap(),
+            Some(b"2".to_vec())
+        );
+        // writes go straight to the database again
+        store.put_metadata("c", b"3").unwrap();
+        assert!(store
+            .db
+            .get(RocksDBStore::metadata_key("c"))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn buffered_values_shadow_persisted_ones() {
+        let dir = TempDir::new("values");
+        let store = RocksDBStore::open(&dir.0).unwrap();
+        let key_hash = KeyHash([1; 32]);
+        put_value(&store, key_hash, 1, Some(b"v1"));
+        put_value(&store, key_hash, 2, Some(b"v2"));
+
+        store.begin_writes().unwrap();
+        // a buffered delete skips to the persisted version before it
+        store
+            .write([(RocksDBStore::value_key(&key_hash, 2), None)])
+            .unwrap();
+        assert_eq!(get_value(&store, key_hash, 2), Some(b"v1".to_vec()));
+        // a buffered removal marker hides the persisted value
+        put_value(&store, key_hash, 3, None);
+        assert_eq!(get_value(&store, key_hash, 3), None);
+        assert_eq!(get_value(&store, key_hash, 2), Some(b"v1".to_vec()));
+        // the buffered latest version overrides the older persisted one
+        put_value(&store, key_hash, 4, Some(b"v4"));
+        assert_eq!(get_value(&store, key_hash, 5), Some(b"v4".to_vec()));
+
+        store.commit_writes().unwrap();
+        assert_eq!(get_value(&store, key_hash, 2), Some(b"v1".to_vec()));
+        assert_eq!(get_value(&store, key_hash, 3), None);
+        assert_eq!(get_value(&store, key_hash, 5), Some(b"v4".to_vec()));
+        assert_eq!(
+            store.iter_values(5).unwrap(),
+            vec![(key_hash, b"v4".to_vec())]
+        );
+    }
+
+    #[test]
+    fn prefix_iteration_merges_buffered_writes() {
+        let dir = TempDir::new("prefix");
+        let store = RocksDBStore::open(&dir.0).unwrap();
+        for key in ["p:1", "p:3", "q:1"] {
+            store.put_metadata(key, b"old").unwrap();
+        }
+
+        store.begin_writes().unwrap();
+        store.delete_metadata("p:1").unwrap();
+        store.put_metadata("p:2", b"new").unwrap();
+        store.put_metadata("p:3", b"new").unwrap();
+        store.put_metadata("p:4", b"new").unwrap();
+        store.put_metadata("q:2", b"new").unwrap();
+        let expected = vec![
+            ("p:2".to_string(), b"new".to_vec()),
+            ("p:3".to_string(), b"new".to_vec()),
+            ("p:4".to_string(), b"new".to_vec()),
+        ];
+        assert_eq!(store.iter_metadata("p:").unwrap(), expected);
+
+        store.commit_writes().unwrap();
+        assert_eq!(store.iter_metadata("p:").unwrap(), expected);
+    }
+
+    #[test]
+    fn overlay_deletions_hide_base_entries() {
+        let (a, b) = (KeyHash([1; 32]), KeyHash([2; 32]));
+        let base = Arc::new(InMemoryStore::default());
+        base.write_node_batch(&NodeBatch::new(
+            BTreeMap::new(),
+            BTreeMap::from([((1, a), Some(b"a".to_vec())), ((1, b), Some(b"b".to_vec()))]),
+        ))
+        .unwrap();
+        base.set_epoch(1).unwrap();
+        base.put_metadata("p:1", b"base").unwrap();
+        base.put_metadata("p:2", b"base").unwrap();
+
+        let overlay = OverlayStore::new(base).unwrap();
+        overlay
+            .write_node_batch(&NodeBatch::new(
+                BTreeMap::new(),
+                BTreeMap::from([((2, a), None)]),
+            ))
+            .unwrap();
+        overlay.delete_metadata("p:1").unwrap();
+
+        assert_eq!(overlay.get_value_option(2, a).unwrap(), None);
+        assert_eq!(overlay.get_value_option(1, a).unwrap(), Some(b"a".to_vec()));
+        assert_eq!(overlay.iter_values(2).unwrap(), vec![(b, b"b".to_vec())]);
+        assert_eq!(overlay.get_metadata("p:1").unwrap(), None);
+        assert_eq!(
+            overlay.iter_metadata("p:").unwrap(),
+            vec![("p:2".to_string(), b"base".to_vec())]
+        );
+
+        overlay.put_metadata("p:1", b"overlay").unwrap();
+        assert_eq!(
+            overlay.get_metadata("p:1").unwrap(),
+            Some(b"overlay".to_vec())
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::{anyhow, Context, Result};
+use std::sync::OnceLock;
+use tracing_subscriber::{
+    layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+/// The service name spans are exported under.
+#[cfg(feature = "otlp")]
+const SERVICE_NAME: &str = "zk-shard";
+
+/// Swaps the filter of the installed subscriber, see [`set_log_filter`].
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Installs the global tracing subscriber, printing spans and events
+/// filtered by `RUST_LOG` until [`set_log_filter`] replaces it. Log records
+/// of dependencies using the `log` crate are forwarded to it. If
+/// `otlp_endpoint` is set, spans are also exported to that OpenTelemetry
+/// collector (requires the `otlp` feature).
+pub fn init(otlp_endpoint: Option<&str>) -> Result<()> {
+    let (filter, handle) = reload::Layer::new(EnvFilter::from_default_env());
+    let _ = LOG_FILTER.set(handle);
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer());
+
+    match otlp_endpoint {
+        None => registry.try_init()?,
+        #[cfg(feature = "otlp")]
+        Some(endpoint) => registry.with(otlp_layer(endpoint)?).try_init()?,
+        #[cfg(not(feature = "otlp"))]
+        Some(endpoint) => {
+            return Err(anyhow!(
+                "Exporting to {} requires building with the `otlp` feature",
+                endpoint
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the log filter at runtime. `filter` uses the `RUST_LOG` syntax;
+/// bare targets like `node=debug` also match the node's own modules, so
+/// they don't need the crate prefix. Returns the filter now in effect.
+pub fn set_log_filter(filter: &str) -> Result<String> {
+    let handle = LOG_FILTER
+        .get()
+        .ok_or_else(|| anyhow!("Logging has not been initialized"))?;
+    let filter = expand_log_filter(filter);
+    let env_filter = EnvFilter::builder()
+        .parse(&filter)
+        .context("Invalid log filter")?;
+    handle
+        .reload(env_filter)
+        .map_err(|e| anyhow!("Failed to replace log filter: {}", e))?;
+    Ok(filter)
+}
+
+/// Adds a directive for the node's module of the same name to every
+/// directive with a bare target, e.g. `node=debug` to
+/// `node=debug,shard_common::node=debug`.
+fn expand_log_filter(filter: &str) -> String {
+    let mut directives = Vec::new();
+    for directive in filter.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        directives.push(directive.to_string());
+        if let Some((target, _)) = directive.split_once('=') {
+            if !target.contains("::") && !target.contains('[') {
+                directives.push(format!("{}::{}", env!("CARGO_CRATE_NAME"), directive));
+            }
+        }
+    }
+    directives.join(",")
+}
+
+#[cfg(feature = "otlp")]
+fn otlp_layer<S>(endpoint: &str) -> Result<impl tracing_subscriber::Layer<S>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::{trace::TracerProvider as _, KeyValue};
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{runtime, trace, Resource};
+
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(trace::Config::default().with_resource(Resource::new(vec![
+            KeyValue::new("service.name", SERVICE_NAME),
+        ])))
+        .install_batch(runtime::Tokio)
+        .map_err(|e| anyhow!("Failed to set up OTLP exporter: {}", e))?;
+    let tracer = provider.tracer(SERVICE_NAME);
+    opentelemetry::global::set_tracer_provider(provider);
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Flushes spans that haven't been exported yet.
+pub fn shutdown() {
+    #[cfg(feature = "otlp")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
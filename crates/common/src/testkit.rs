@@ -0,0 +1,329 @@
+//! Helpers for end-to-end tests of a sequencer running in-process on a
+//! [`MockDA`], whose blocks are only produced when the test asks for them.
+//!
+//! ```ignore
+//! let shard = TestShard::spawn().await?;
+//! let tx_hash = shard.submit(tx).await?;
+//! shard.advance_da_block().await?;
+//! assert!(matches!(
+//!     shard.node().get_tx_status(&tx_hash)?,
+//!     Some(TxStatus::Executed { .. })
+//! ));
+//! ```
+
+use anyhow::{anyhow, Context, Result};
+use prism_common::keys::VerifyingKey;
+use std::{net::TcpListener, sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+
+use crate::{
+    da::{DaKind, MockDA},
+    events::Event,
+    node::{Config, Node},
+    status::TxStatus,
+    tree::Digest,
+    tx::Transaction,
+};
+
+/// How long helpers wait for the node before failing the test.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often helpers check whether the node caught up.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Batches are posted this often, so tests don't wait for the default
+/// interval.
+const TEST_BATCH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A running sequencer on an in-process DA layer with in-memory storage.
+/// Shuts the node down when dropped.
+pub struct TestShard {
+    node: Arc<Node>,
+    da: Arc<MockDA>,
+    url: String,
+    /// Transactions submitted through [`TestShard::submit`] that may not
+    /// have been posted yet
+    unposted: Mutex<Vec<Digest>>,
+}
+
+impl TestShard {
+    /// Starts a sequencer with the default config.
+    pub async fn spawn() -> Result<Self> {
+        Self::spawn_with(Config::default()).await
+    }
+
+    /// Starts a node with `cfg`. The DA layer, storage, webserver address
+    /// and batch interval are overridden, so shards of parallel tests don't
+    /// interfere.
+    pub async fn spawn_with(mut cfg: Config) -> Result<Self> {
+        cfg.da = DaKind::Mock;
+        cfg.db_path = None;
+        cfg.listen_addr = free_local_addr()?;
+        cfg.batch_interval = TEST_BATCH_INTERVAL;
+        let url = format!("http://{}", cfg.listen_addr);
+
+        let da = Arc::new(MockDA::new());
+        let node = Arc::new(Node::with_da(cfg, da.clone()).await?);
+        tokio::spawn(node.clone().start());
+        Ok(TestShard {
+            node,
+            da,
+            url,
+            unposted: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn node(&self) -> &Arc<Node> {
+        &self.node
+    }
+
+    pub fn da(&self) -> &Arc<MockDA> {
+        &self.da
+    }
+
+    /// The URL of the node's webserver, e.g. for testing a
+    /// `shard_client::Client` against it.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Queues `tx` on the sequencer and returns its hash. It is executed by
+    /// the next [`TestShard::advance_da_block`].
+    pub async fn submit(&self, tx: Transaction) -> Result<Digest> {
+        let submitted = self.node.queue_transaction(tx).await?;
+        if submitted.already_known {
+            return Ok(submitted.tx_hash);
+        }
+        let tx_hash = submitted.tx_hash;
+        self.unposted.lock().await.push(tx_hash);
+        Ok(tx_hash)
+    }
+
+    /// Waits until the transactions submitted so far are posted, produces a
+    /// DA block including them and waits until the node processed it.
+    /// Returns the block's height.
+    pub async fn advance_da_block(&self) -> Result<u64> {
+        let mut unposted = self.unposted.lock().await;
+        for tx_hash in unposted.drain(..) {
+            self.wait_until_posted(&tx_hash).await?;
+        }
+
+        let mut events = self.node.subscribe_events();
+        let height = self.da.produce_block()?;
+        tokio::time::timeout(WAIT_TIMEOUT, async {
+            while self.node.da_height() < height {
+                match events.recv().await {
+                    Ok(Event::DaHeightProcessed { height: processed }) if processed >= height => {
+                        break
+                    }
+                    Ok(_) => {}
+                    Err(e) => return Err(anyhow!("Event stream failed: {}", e)),
+                }
+            }
+            Ok(())
+        })
+        .await
+        .with_context(|| format!("Node didn't process celestia height {}", height))??;
+        Ok(height)
+    }
+
+    /// Returns the root of the state as of the last processed block.
+    pub async fn state_root(&self) -> Result<Digest> {
+        Ok(self.node.get_root().await?.0)
+    }
+
+    /// Returns the account nonce the next transaction of `vk` must use.
+    pub async fn next_nonce(&self, vk: &VerifyingKey) -> Result<u64> {
+        Ok(self
+            .node
+            .get_account(vk)
+            .await?
+            .map_or(0, |account| account.nonce()))
+    }
+
+    async fn wait_until_posted(&self, tx_hash: &Digest) -> Result<()> {
+        tokio::time::timeout(WAIT_TIMEOUT, async {
+            loop {
+                match self.node.get_tx_status(tx_hash)? {
+                    Some(TxStatus::Queued | TxStatus::Batched | TxStatus::SoftConfirmed) => {}
+                    // posted, or dropped from the mempool before it was
+                    _ => return Ok(()),
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        })
+        .await
+        .with_context(|| format!("Transaction {} wasn't posted", hex::encode(tx_hash.0)))?
+    }
+}
+
+impl Drop for TestShard {
+    fn drop(&mut self) {
+        self.node.shutdown();
+    }
+}
+
+/// Returns a local address with a port that was free a moment ago.
+fn free_local_addr() -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.to_string())
+}
+
+/// Generates a random signing key for tests.
+#[cfg(test)]
+pub(crate) fn generate_key() -> prism_common::keys::SigningKey {
+    prism_common::keys::SigningKey::Ed25519(Box::new(keystore_rs::create_signing_key()))
+}
+
+#[cfg(test)]
+mod tests {
+    use celestia_types::Blob;
+    use prism_common::keys::SigningKey;
+
+    use super::*;
+    use crate::{
+        block::DIRECT_BATCH_HEIGHT,
+        da::DataAvailability,
+        encoding::encode_blob,
+        error::{ExecutionError, TxError},
+        fraud::OptimisticProver,
+        keys,
+        node::{BatchAuth, NodeRole},
+        tx::{Batch, TransactionBuilder, TransactionType},
+    };
+
+    fn noop_tx(key: &SigningKey, nonce: u64) -> Transaction {
+        TransactionBuilder::new(TransactionType::Noop)
+            .nonce(nonce)
+            .chain_id(Config::default().chain_id)
+            .sign(key)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn submitted_tx_is_executed() {
+        let shard = TestShard::spawn().await.unwrap();
+        let key = generate_key();
+        let vk = keys::verifying_key(&key);
+        let root = shard.state_root().await.unwrap();
+
+        let tx_hash = shard.submit(noop_tx(&key, 0)).await.unwrap();
+        let height = shard.advance_da_block().await.unwrap();
+        assert_eq!(
+            shard.node().get_tx_status(&tx_hash).unwrap(),
+            Some(TxStatus::Executed { da_height: height })
+        );
+        assert_eq!(shard.next_nonce(&vk).await.unwrap(), 1);
+        assert_ne!(shard.state_root().await.unwrap(), root);
+
+        // the next nonce continues from the executed one
+        let tx_hash = shard.submit(noop_tx(&key, 1)).await.unwrap();
+        let height = shard.advance_da_block().await.unwrap();
+        assert_eq!(
+            shard.node().get_tx_status(&tx_hash).unwrap(),
+            Some(TxStatus::Executed { da_height: height })
+        );
+        assert_eq!(shard.next_nonce(&vk).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn reorg_reverts_executed_txs() {
+        let shard = TestShard::spawn().await.unwrap();
+        let key = generate_key();
+        let vk = keys::verifying_key(&key);
+
+        shard.submit(noop_tx(&key, 0)).await.unwrap();
+        shard.advance_da_block().await.unwrap();
+        assert_eq!(shard.next_nonce(&vk).await.unwrap(), 1);
+
+        // the block including the transaction is replaced by an empty one,
+        // which the node notices once the next block builds on it
+        shard.da().reorg(1).unwrap();
+        shard.advance_da_block().await.unwrap();
+        assert!(shard.node().get_account(&vk).await.unwrap().is_none());
+        assert_eq!(shard.next_nonce(&vk).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn forced_scheduled_tx_is_rejected() {
+        let cfg = Config {
+            role: NodeRole::Full,
+            sequencer_url: Some("http://127.0.0.1:1".to_string()),
+            batch_auth: BatchAuth::Signed,
+            sequencer_vk: Some(keys::verifying_key(&generate_key())),
+            forced_inclusion_delay: 1,
+            ..Config::default()
+        };
+        let (namespace, chain_id) = (cfg.namespace, cfg.chain_id);
+        let shard = TestShard::spawn_with(cfg).await.unwrap();
+
+        let tx = TransactionBuilder::new(TransactionType::Noop)
+            .chain_id(chain_id)
+            .execute_at(Some(10))
+            .sign(&generate_key())
+            .unwrap();
+        let tx_hash = tx.hash();
+        let batch = Batch::with_header(DIRECT_BATCH_HEIGHT, 0, vec![tx]);
+        let blob = Blob::new(namespace, encode_blob(&batch)).unwrap();
+        shard.da().submit(&[blob]).await.unwrap();
+
+        // queued for forced inclusion, then executed a block later
+        shard.advance_da_block().await.unwrap();
+        assert_eq!(shard.node().get_tx_status(&tx_hash).unwrap(), None);
+        let height = shard.advance_da_block().await.unwrap();
+        assert_eq!(
+            shard.node().get_tx_status(&tx_hash).unwrap(),
+            Some(TxStatus::Failed {
+                da_height: height,
+                error: ExecutionError::Rejected {
+                    error: TxError::SchedulingUnsupported
+                },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn sealed_batch_claims_executed_root() {
+        let cfg = Config {
+            scheduled_txs: true,
+            ..Config::default()
+        };
+        let chain_id = cfg.chain_id;
+        let shard = TestShard::spawn_with(cfg).await.unwrap();
+
+        // executed at the next height, before the batch posted for it
+        let due_height = shard.node().da_height() + 2;
+        let scheduled = TransactionBuilder::new(TransactionType::Noop)
+            .chain_id(chain_id)
+            .execute_at(Some(due_height))
+            .sign(&generate_key())
+            .unwrap();
+        shard.submit(scheduled).await.unwrap();
+        shard.advance_da_block().await.unwrap();
+        shard.submit(noop_tx(&generate_key(), 0)).await.unwrap();
+        assert_eq!(shard.advance_da_block().await.unwrap(), due_height);
+
+        for height in 1..=2 {
+            let block = shard.node().get_block(height).unwrap().unwrap();
+            assert_eq!(
+                shard.node().get_claimed_root(height).unwrap(),
+                Some(block.new_root)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn proving_sequencer_refuses_scheduled_txs() {
+        let cfg = Config {
+            da: DaKind::Mock,
+            scheduled_txs: true,
+            ..Config::default()
+        };
+        let node = Node::with_da(cfg, Arc::new(MockDA::new()))
+            .await
+            .unwrap()
+            .with_prover(Arc::new(OptimisticProver));
+        let started = tokio::time::timeout(WAIT_TIMEOUT, Arc::new(node).start())
+            .await
+            .unwrap();
+        assert!(started.is_err());
+    }
+}
@@ -2,47 +2,106 @@ use anyhow::{anyhow, Result};
 use jmt::SimpleHasher;
 use jmt::{
     self,
-    storage::{NodeBatch, TreeReader, TreeUpdateBatch, TreeWriter},
-    JellyfishMerkleTree, KeyHash, RootHash,
+    proof::{SparseMerkleProof, UpdateMerkleProof},
+    storage::{Node, NodeBatch, NodeKey, StaleNodeIndex, TreeUpdateBatch},
+    JellyfishMerkleTree, KeyHash, RootHash, Version,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 
+use crate::storage::NodeStore;
+
 pub const SPARSE_MERKLE_PLACEHOLDER_HASH: Digest =
     Digest::new(*b"SPARSE_MERKLE_PLACEHOLDER_HASH__");
 
+/// Metadata key under which the name of the hasher a store's tree was built
+/// with is stored.
+const HASHER_KEY: &str = "tree_hasher";
+/// Metadata key prefix under which the keys of the nodes that became stale
+/// in an epoch are stored, so they can be pruned once the epoch is no
+/// longer retained.
+const STALE_NODES_PREFIX: &str = "stale_nodes:";
+/// Metadata key prefix under which the keys written in an epoch are stored,
+/// since their older values became stale then and can be pruned once the
+/// epoch is the oldest retained one.
+const STALE_VALUES_PREFIX: &str = "stale_values:";
+/// Metadata key under which the oldest epoch that wasn't pruned is stored.
+const PRUNED_FLOOR_KEY: &str = "pruned_floor";
+
+/// The hasher of the tree, transaction hashes and digests, selected at
+/// compile time. SHA-256 unless the `blake3` feature is enabled.
+///
+/// It determines every state root, so all nodes of a rollup and the proving
+/// guests must be built with the same one.
+#[cfg(not(feature = "blake3"))]
+pub type Hasher = Sha256Hasher;
+#[cfg(feature = "blake3")]
+pub type Hasher = Blake3Hasher;
+
+#[cfg(not(feature = "blake3"))]
+pub const HASHER_NAME: &str = "sha256";
+#[cfg(feature = "blake3")]
+pub const HASHER_NAME: &str = "blake3";
+
 #[derive(Debug, Clone, Default)]
-pub struct Hasher(sha2::Sha256);
+pub struct Sha256Hasher(sha2::Sha256);
 
-impl Hasher {
-    pub fn new() -> Self {
+impl SimpleHasher for Sha256Hasher {
+    fn new() -> Self {
         Self(sha2::Sha256::new())
     }
 
-    pub fn update(&mut self, data: &[u8]) {
+    fn update(&mut self, data: &[u8]) {
         self.0.update(data);
     }
 
-    pub fn finalize(self) -> [u8; 32] {
+    fn finalize(self) -> [u8; 32] {
         self.0.finalize()
     }
 }
 
-impl SimpleHasher for Hasher {
+/// BLAKE3, considerably faster than SHA-256 outside of zkVMs with a SHA-256
+/// precompile.
+#[cfg(feature = "blake3")]
+#[derive(Debug, Clone, Default)]
+pub struct Blake3Hasher(blake3::Hasher);
+
+#[cfg(feature = "blake3")]
+impl SimpleHasher for Blake3Hasher {
     fn new() -> Self {
-        Self::new()
+        Self(blake3::Hasher::new())
     }
 
     fn update(&mut self, data: &[u8]) {
-        self.update(data);
+        self.0.update(data);
     }
 
     fn finalize(self) -> [u8; 32] {
-        self.finalize()
+        self.0.finalize().into()
+    }
+}
+
+/// Records the hasher a store's tree is built with, and refuses stores built
+/// with a different one, whose roots would never match.
+pub fn check_store_hasher<S: NodeStore + ?Sized>(store: &S) -> Result<()> {
+    let stored = match store.get_metadata(HASHER_KEY)? {
+        Some(bytes) => String::from_utf8(bytes)?,
+        // stores from before the hasher was configurable
+        None if store.get_epoch()?.is_some() => "sha256".to_string(),
+        None => HASHER_NAME.to_string(),
+    };
+    if stored != HASHER_NAME {
+        return Err(anyhow!(
+            "Store was built with the {} hasher, but this node uses {}",
+            stored,
+            HASHER_NAME
+        ));
     }
+    store.put_metadata(HASHER_KEY, HASHER_NAME.as_bytes())
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Copy)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Copy)]
 pub struct Digest(pub [u8; 32]);
 
 impl Digest {
@@ -51,9 +110,7 @@ impl Digest {
     }
 
     pub fn hash(data: impl AsRef<[u8]>) -> Self {
-        let mut hasher = Hasher::new();
-        hasher.update(data.as_ref());
-        Self(hasher.finalize())
+        Self::hash_with::<Hasher>(data)
     }
 
     pub fn hash_items(items: &[impl AsRef<[u8]>]) -> Self {
@@ -64,6 +121,11 @@ impl Digest {
         Self(hasher.finalize())
     }
 
+    /// Hashes `data` with a hasher other than the configured [`Hasher`].
+    pub fn hash_with<H: SimpleHasher>(data: impl AsRef<[u8]>) -> Self {
+        Self(H::hash(data))
+    }
+
     pub const fn zero() -> Self {
         Self([0u8; 32])
     }
@@ -83,25 +145,40 @@ impl From<RootHash> for Digest {
 
 /// Wraps a [`JellyfishMerkleTree`] to provide a key-value store for [`Hashchain`]s with batched insertions.
 /// This is prism's primary data structure for storing and retrieving [`Hashchain`]s.
-pub struct KeyDirectoryTree<S>
+///
+/// Generic over its hasher, which defaults to the configured [`Hasher`].
+pub struct KeyDirectoryTree<S, H = Hasher>
 where
-    S: TreeReader + TreeWriter,
+    S: NodeStore,
+    H: SimpleHasher,
 {
-    pub(crate) jmt: JellyfishMerkleTree<Arc<S>, Hasher>,
+    pub(crate) jmt: JellyfishMerkleTree<Arc<S>, H>,
     pub(crate) epoch: u64,
     pending_batch: Option<NodeBatch>,
+    /// Nodes replaced by the pending batch
+    pending_stale: Vec<StaleNodeIndex>,
+    /// The latest value written to every key since recording started, see
+    /// [`KeyDirectoryTree::record_writes`]
+    writes: Option<BTreeMap<[u8; 32], Option<Vec<u8>>>>,
+    /// The keys written since recording started, see
+    /// [`KeyDirectoryTree::take_written_keys`]
+    written_keys: Option<BTreeSet<[u8; 32]>>,
     db: Arc<S>,
 }
 
-impl<S> KeyDirectoryTree<S>
+impl<S, H> KeyDirectoryTree<S, H>
 where
-    S: TreeReader + TreeWriter,
+    S: NodeStore,
+    H: SimpleHasher,
 {
     pub fn new(store: Arc<S>) -> Self {
         let tree = Self {
             db: store.clone(),
-            jmt: JellyfishMerkleTree::<Arc<S>, Hasher>::new(store),
+            jmt: JellyfishMerkleTree::<Arc<S>, H>::new(store),
             pending_batch: None,
+            pending_stale: Vec::new(),
+            writes: None,
+            written_keys: None,
             epoch: 0,
         };
         let (_, batch) = tree
@@ -114,22 +191,54 @@ where
 
     pub fn load(store: Arc<S>, epoch: u64) -> Self {
         if epoch == 0 {
-            return KeyDirectoryTree::new(store);
+            return Self::new(store);
         }
         Self {
             db: store.clone(),
-            jmt: JellyfishMerkleTree::<Arc<S>, Hasher>::new(store),
+            jmt: JellyfishMerkleTree::<Arc<S>, H>::new(store),
             pending_batch: None,
+            pending_stale: Vec::new(),
+            writes: None,
+            written_keys: None,
             epoch,
         }
     }
 
+    /// Rebuilds the tree at `epoch` from a full set of key-values, e.g. from a
+    /// snapshot. The store must not contain a tree yet.
+    pub fn restore(store: Arc<S>, epoch: u64, values: Vec<(KeyHash, Vec<u8>)>) -> Result<Self> {
+        if epoch == 0 {
+            return Err(anyhow!("Cannot restore the genesis epoch"));
+        }
+
+        // the JMT builds on the root of the previous version, so start from an
+        // empty root right before the restored epoch
+        let empty_root = NodeBatch::new(
+            BTreeMap::from([(NodeKey::new_empty_path(epoch - 1), Node::Null)]),
+            BTreeMap::new(),
+        );
+        store.write_node_batch(&empty_root)?;
+
+        let mut tree = Self {
+            db: store.clone(),
+            jmt: JellyfishMerkleTree::<Arc<S>, H>::new(store),
+            pending_batch: None,
+            pending_stale: Vec::new(),
+            writes: None,
+            written_keys: None,
+            epoch: epoch - 1,
+        };
+        tree.put(values)?;
+        Ok(tree)
+    }
+
     pub fn get_commitment(&self) -> Result<Digest> {
         let root = self.get_current_root()?;
         Ok(Digest::new(root.0))
     }
 
     pub(crate) fn queue_batch(&mut self, batch: TreeUpdateBatch) {
+        self.pending_stale.extend(batch.stale_node_index_batch);
         match self.pending_batch {
             Some(ref mut pending_batch) => pending_batch.merge(batch.node_batch),
             None => self.pending_batch = Some(batch.node_batch),
@@ -139,14 +248,387 @@ where
     pub(crate) fn write_batch(&mut self) -> Result<()> {
         if let Some(batch) = self.pending_batch.take() {
             self.db.write_node_batch(&batch)?;
+            record_stale_nodes(self.db.as_ref(), std::mem::take(&mut self.pending_stale))?;
+            record_stale_values(self.db.as_ref(), &batch)?;
             self.epoch += 1;
+            self.db.set_epoch(self.epoch)?;
         }
         Ok(())
     }
 
+    /// Discards all epochs after `epoch`, making it the current one.
+    pub(crate) fn rollback(&mut self, epoch: u64) -> Result<()> {
+        if epoch > self.epoch {
+            return Err(anyhow!(
+                "Cannot roll back to epoch {} ahead of current epoch {}",
+                epoch,
+                self.epoch
+            ));
+        }
+        check_not_pruned(self.db.as_ref(), epoch)?;
+        self.pending_batch = None;
+        self.pending_stale.clear();
+        self.db.truncate_versions(epoch)?;
+        // nodes and values only stale in the discarded epochs are live again
+        for (key, _) in self.db.iter_metadata(STALE_NODES_PREFIX)? {
+            if stale_since(&key, STALE_NODES_PREFIX)? > epoch {
+                self.db.delete_metadata(&key)?;
+            }
+        }
+        for (key, _) in self.db.iter_metadata(STALE_VALUES_PREFIX)? {
+            if stale_since(&key, STALE_VALUES_PREFIX)? > epoch {
+                self.db.delete_metadata(&key)?;
+            }
+        }
+        self.epoch = epoch;
+        self.db.set_epoch(epoch)
+    }
+
+    /// Returns the value stored under `key` at the current epoch.
+    pub fn get(&self, key: KeyHash) -> Result<Option<Vec<u8>>> {
+        self.jmt
+            .get(key, self.epoch)
+            .map_err(|e| anyhow!("Failed to get value: {}", e))
+    }
+
+    /// Returns the value stored under `key` at the current epoch, along with
+    /// a proof of its inclusion (or exclusion) against the current root.
+    pub fn get_with_proof(&self, key: KeyHash) -> Result<(Option<Vec<u8>>, SparseMerkleProof<H>)> {
+        self.jmt
+            .get_with_proof(key, self.epoch)
+            .map_err(|e| anyhow!("Failed to get value with proof: {}", e))
+    }
+
+    /// Writes `values` to the tree as a new epoch.
+    pub(crate) fn put(&mut self, values: Vec<(KeyHash, Vec<u8>)>) -> Result<()> {
+        self.put_value_set(
+            values
+                .into_iter()
+                .map(|(key, value)| (key, Some(value)))
+                .collect(),
+        )
+    }
+
+    /// Removes `keys` from the tree as a new epoch.
+    pub(crate) fn remove(&mut self, keys: Vec<KeyHash>) -> Result<()> {
+        self.put_value_set(keys.into_iter().map(|key| (key, None)).collect())
+    }
+
+    /// Writes a set of values, where `None` removes the key, as a new epoch.
+    pub(crate) fn put_value_set(
+        &mut self,
+        value_set: Vec<(KeyHash, Option<Vec<u8>>)>,
+    ) -> Result<()> {
+        if let Some(writes) = &mut self.writes {
+            writes.extend(value_set.iter().map(|(key, value)| (key.0, value.clone())));
+        }
+        if let Some(written_keys) = &mut self.written_keys {
+            written_keys.extend(value_set.iter().map(|(key, _)| key.0));
+        }
+        let (_, batch) = self
+            .jmt
+            .put_value_set(value_set, self.epoch + 1)
+            .map_err(|e| anyhow!("Failed to put values: {}", e))?;
+        self.queue_batch(batch);
+        self.write_batch()
+    }
+
+    /// Starts recording the values written from now on, see
+    /// [`KeyDirectoryTree::take_writes`].
+    pub(crate) fn record_writes(&mut self) {
+        self.writes.get_or_insert_with(BTreeMap::new);
+    }
+
+    /// Returns the latest value written to every key since the last call,
+    /// `None` for removed keys, ordered by key.
+    pub(crate) fn take_writes(&mut self) -> Vec<(KeyHash, Option<Vec<u8>>)> {
+        self.writes
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| (KeyHash(key), value))
+            .collect()
+    }
+
+    /// Starts recording the keys written from now on, see
+    /// [`KeyDirectoryTree::take_written_keys`].
+    pub(crate) fn record_written_keys(&mut self) {
+        self.written_keys.get_or_insert_with(BTreeSet::new);
+    }
+
+    /// Returns the keys written since the last call, ordered by key.
+    pub(crate) fn take_written_keys(&mut self) -> BTreeSet<[u8; 32]> {
+        self.written_keys
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn store(&self) -> &Arc<S> {
+        &self.db
+    }
+
+    /// Returns a read-only view of the tree at the current epoch that does
+    /// not borrow the tree.
+    pub fn view(&self) -> TreeView<S, H> {
+        TreeView {
+            jmt: JellyfishMerkleTree::new(self.db.clone()),
+            db: self.db.clone(),
+            epoch: self.epoch,
+        }
+    }
+
+    /// Returns a read-only view of the tree at a past `epoch`, which must
+    /// not have been pruned.
+    pub fn view_at(&self, epoch: u64) -> Result<TreeView<S, H>> {
+        if epoch > self.epoch {
+            return Err(anyhow!(
+                "Epoch {} is ahead of current epoch {}",
+                epoch,
+                self.epoch
+            ));
+        }
+        check_not_pruned(self.db.as_ref(), epoch)?;
+        Ok(TreeView {
+            jmt: JellyfishMerkleTree::new(self.db.clone()),
+            db: self.db.clone(),
+            epoch,
+        })
+    }
+
     pub fn get_current_root(&self) -> Result<RootHash> {
         self.jmt
             .get_root_hash(self.epoch)
             .map_err(|e| anyhow!("Failed to get root hash: {}", e))
     }
 }
+
+fn stale_nodes_key(stale_since: Version) -> String {
+    format!("{}{:020}", STALE_NODES_PREFIX, stale_since)
+}
+
+fn stale_values_key(stale_since: Version) -> String {
+    format!("{}{:020}", STALE_VALUES_PREFIX, stale_since)
+}
+
+/// Parses the version of a stale nodes or values key with `prefix`.
+fn stale_since(key: &str, prefix: &str) -> Result<Version> {
+    key.strip_prefix(prefix)
+        .and_then(|version| version.parse().ok())
+        .ok_or_else(|| anyhow!("Invalid stale key {}", key))
+}
+
+fn record_stale_nodes<S: NodeStore + ?Sized>(store: &S, stale: Vec<StaleNodeIndex>) -> Result<()> {
+    let mut by_version: BTreeMap<Version, Vec<NodeKey>> = BTreeMap::new();
+    for index in stale {
+        by_version
+            .entry(index.stale_since_version)
+            .or_default()
+            .push(index.node_key);
+    }
+    for (version, node_keys) in by_version {
+        store.put_metadata(&stale_nodes_key(version), &bincode::serialize(&node_keys)?)?;
+    }
+    Ok(())
+}
+
+/// Records the keys `batch` writes values of, by version.
+fn record_stale_values<S: NodeStore + ?Sized>(store: &S, batch: &NodeBatch) -> Result<()> {
+    let mut by_version: BTreeMap<Version, Vec<[u8; 32]>> = BTreeMap::new();
+    for (version, key_hash) in batch.values().keys() {
+        by_version.entry(*version).or_default().push(key_hash.0);
+    }
+    for (version, key_hashes) in by_version {
+        store.put_metadata(
+            &stale_values_key(version),
+            &bincode::serialize(&key_hashes)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the oldest epoch whose tree version can still be read, 0 if
+/// nothing was pruned.
+pub fn pruned_floor<S: NodeStore + ?Sized>(store: &S) -> Result<u64> {
+    match store.get_metadata(PRUNED_FLOOR_KEY)? {
+        Some(bytes) => Ok(bincode::deserialize(&bytes)?),
+        None => Ok(0),
+    }
+}
+
+/// Fails if `epoch` is older than the [`pruned_floor`], as reading its
+/// tree version would run into missing nodes.
+pub(crate) fn check_not_pruned<S: NodeStore + ?Sized>(store: &S, epoch: u64) -> Result<()> {
+    let floor = pruned_floor(store)?;
+    if epoch < floor {
+        return Err(anyhow!(
+            "Epoch {} has been pruned, the oldest retained epoch is {}",
+            epoch,
+            floor
+        ));
+    }
+    Ok(())
+}
+
+/// Deletes the tree nodes and values only needed by epochs before
+/// `min_epoch`, which can't be read, rolled back to or snapshotted
+/// afterwards. Returns the number of deleted nodes and values.
+pub fn prune<S: NodeStore + ?Sized>(store: &S, min_epoch: u64) -> Result<usize> {
+    // raised before deleting anything, so readers are turned away from
+    // versions that are about to lose their nodes
+    if min_epoch > pruned_floor(store)? {
+        store.put_metadata(PRUNED_FLOOR_KEY, &bincode::serialize(&min_epoch)?)?;
+    }
+    let mut pruned = 0;
+    for (key, value) in store.iter_metadata(STALE_NODES_PREFIX)? {
+        if stale_since(&key, STALE_NODES_PREFIX)? > min_epoch {
+            continue;
+        }
+        let node_keys: Vec<NodeKey> = bincode::deserialize(&value)?;
+        store.delete_nodes(&node_keys)?;
+        store.delete_metadata(&key)?;
+        pruned += node_keys.len();
+    }
+    for (key, value) in store.iter_metadata(STALE_VALUES_PREFIX)? {
+        let version = stale_since(&key, STALE_VALUES_PREFIX)?;
+        if version > min_epoch {
+            continue;
+        }
+        let key_hashes: Vec<[u8; 32]> = bincode::deserialize(&value)?;
+        for key_hash in key_hashes {
+            pruned += store.prune_values(KeyHash(key_hash), version)?;
+        }
+        store.delete_metadata(&key)?;
+    }
+    Ok(pruned)
+}
+
+/// A read-only view of a [`KeyDirectoryTree`] at a fixed epoch.
+pub struct TreeView<S, H = Hasher>
+where
+    S: NodeStore,
+    H: SimpleHasher,
+{
+    jmt: JellyfishMerkleTree<Arc<S>, H>,
+    db: Arc<S>,
+    epoch: u64,
+}
+
+impl<S, H> TreeView<S, H>
+where
+    S: NodeStore,
+    H: SimpleHasher,
+{
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns a view of the same tree at an earlier `epoch`, which must
+    /// not have been pruned.
+    pub fn at(&self, epoch: u64) -> Result<Self> {
+        if epoch > self.epoch {
+            return Err(anyhow!(
+                "Epoch {} is ahead of viewed epoch {}",
+                epoch,
+                self.epoch
+            ));
+        }
+        check_not_pruned(self.db.as_ref(), epoch)?;
+        Ok(TreeView {
+            jmt: JellyfishMerkleTree::new(self.db.clone()),
+            db: self.db.clone(),
+            epoch,
+        })
+    }
+
+    pub fn get(&self, key: KeyHash) -> Result<Option<Vec<u8>>> {
+        self.jmt
+            .get(key, self.epoch)
+            .map_err(|e| anyhow!("Failed to get value: {}", e))
+    }
+
+    pub fn get_with_proof(&self, key: KeyHash) -> Result<(Option<Vec<u8>>, SparseMerkleProof<H>)> {
+        self.jmt
+            .get_with_proof(key, self.epoch)
+            .map_err(|e| anyhow!("Failed to get value with proof: {}", e))
+    }
+
+    pub fn get_commitment(&self) -> Result<Digest> {
+        let root = self
+            .jmt
+            .get_root_hash(self.epoch)
+            .map_err(|e| anyhow!("Failed to get root hash: {}", e))?;
+        Ok(Digest::new(root.0))
+    }
+
+    /// Computes the root after writing `value_set`, where `None` removes the
+    /// key, on top of this epoch, with a proof of the update. Nothing is
+    /// written to the store.
+    pub fn prove_update(
+        &self,
+        value_set: Vec<(KeyHash, Option<Vec<u8>>)>,
+    ) -> Result<(Digest, UpdateMerkleProof<H>)> {
+        let (root, proof, _) = self
+            .jmt
+            .put_value_set_with_proof(value_set, self.epoch + 1)
+            .map_err(|e| anyhow!("Failed to prove update: {}", e))?;
+        Ok((Digest::new(root.0), proof))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jmt::storage::TreeReader;
+
+    use super::*;
+    use crate::storage::InMemoryStore;
+
+    #[test]
+    fn prune_deletes_only_stale_values() {
+        let store = Arc::new(InMemoryStore::default());
+        let mut tree = KeyDirectoryTree::<InMemoryStore>::new(store.clone());
+        let (key, other) = (KeyHash([1; 32]), KeyHash([2; 32]));
+        tree.put(vec![(key, b"v1".to_vec()), (other, b"other".to_vec())])
+            .unwrap();
+        tree.put(vec![(key, b"v2".to_vec())]).unwrap();
+        tree.remove(vec![key]).unwrap();
+
+        assert!(prune(store.as_ref(), 2).unwrap() > 0);
+        assert_eq!(store.get_value_option(1, key).unwrap(), None);
+        assert_eq!(
+            store.get_value_option(2, key).unwrap(),
+            Some(b"v2".to_vec())
+        );
+        assert_eq!(
+            store.get_value_option(2, other).unwrap(),
+            Some(b"other".to_vec())
+        );
+
+        // the removal is the latest, so it goes with the value before it
+        prune(store.as_ref(), 3).unwrap();
+        assert_eq!(store.get_value_option(2, key).unwrap(), None);
+        assert_eq!(tree.get(key).unwrap(), None);
+        assert_eq!(tree.get(other).unwrap(), Some(b"other".to_vec()));
+        assert!(store.iter_metadata(STALE_VALUES_PREFIX).unwrap().is_empty());
+    }
+
+    #[test]
+    fn pruned_epochs_cant_be_read_or_rolled_back() {
+        let store = Arc::new(InMemoryStore::default());
+        let mut tree = KeyDirectoryTree::<InMemoryStore>::new(store.clone());
+        for value in [b"v1", b"v2", b"v3"] {
+            tree.put(vec![(KeyHash([1; 32]), value.to_vec())]).unwrap();
+        }
+
+        prune(store.as_ref(), 2).unwrap();
+        // a lower floor never lowers the persisted one
+        prune(store.as_ref(), 1).unwrap();
+        assert_eq!(pruned_floor(store.as_ref()).unwrap(), 2);
+        assert!(tree.view_at(1).is_err());
+        assert!(tree.view().at(1).is_err());
+        assert!(tree.view_at(2).unwrap().at(2).is_ok());
+        assert!(tree.rollback(1).is_err());
+        tree.rollback(2).unwrap();
+    }
+}
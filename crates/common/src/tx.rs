@@ -4,23 +4,305 @@ use clap::Subcommand;
 use prism_common::keys::{Signature, SigningKey, VerifyingKey};
 use serde::{Deserialize, Serialize};
 
-/// If true, the system will verify signatures on transactions. If false,
-/// signatures will be ignored.
-pub const SIGNATURE_VERIFICATION_ENABLED: bool = false;
+use crate::{
+    block::{tx_root, tx_root_of_hashes, BatchHeader},
+    encoding::{decode_legacy_blob, open_blob, Decode, Decoder, Encode, Encoder},
+    error::TxError,
+    keys::{signature_matches, verifying_key, KeyScheme},
+    params::LiveParams,
+    state::MAX_ACCOUNT_KEYS,
+    tree::Digest,
+};
+
+/// Gas charged for every transaction, covering signature verification and
+/// its share of the blob.
+pub const BASE_GAS: u64 = 1_000;
+/// Additional gas charged per account a transaction writes besides the
+/// sender's.
+pub const ACCOUNT_WRITE_GAS: u64 = 500;
+/// Gas charged per byte of deployed contract code.
+pub const CODE_BYTE_GAS: u64 = 10;
+/// Gas charged for a contract call, covering its metered execution.
+pub const CONTRACT_CALL_GAS: u64 = 20_000;
+
+/// The maximum size of deployed contract code.
+pub const MAX_CONTRACT_CODE_SIZE: usize = 256 * 1024;
+/// The maximum size of the input of a contract call.
+pub const MAX_CALL_INPUT_SIZE: usize = 64 * 1024;
+
+/// Prepended to the signing payload so transaction signatures can't be
+/// replayed as signatures over other messages, followed by the
+/// [`KeyScheme::domain`] of the signing key. Version 2 added the account key
+/// to the payload, version 3 the chain id, version 4 the expiry, version 5
+/// the execution height.
+const SIGNING_DOMAIN: &[u8] = b"zk-shard/tx/v5";
+
+/// The chain id of transactions from blobs before version 5, which didn't
+/// carry one. Rollups with history from before chain ids must keep it.
+pub const LEGACY_CHAIN_ID: u64 = 0;
+/// Prepended to the payload of sequencer signatures over batch headers.
+const BATCH_SIGNING_DOMAIN: &[u8] = b"zk-shard/batch/v1";
 
 /// Represents the full set of transaction types supported by the system.
 #[derive(Subcommand, Clone, Serialize, Deserialize, Debug)]
 pub enum TransactionType {
     Noop,
+    /// Moves `amount` from the sender's balance to the account of `to`.
+    Transfer {
+        #[arg(value_parser = parse_verifying_key)]
+        to: VerifyingKey,
+        amount: u64,
+    },
+    /// Credits `amount` to the sender's balance. Only the mint authority,
+    /// see [`crate::node::Config::mint_vk`], can mint.
+    Mint {
+        amount: u64,
+    },
+    /// Removes `amount` from the sender's balance.
+    Burn {
+        amount: u64,
+    },
+    /// Deploys a WASM contract at the address given by
+    /// [`contract_address`] for the sender and nonce.
+    Deploy {
+        /// Path of the WASM file to deploy
+        #[arg(value_parser = read_code_file)]
+        code: ::std::vec::Vec<u8>,
+    },
+    /// Calls the contract at `contract` with `input`.
+    Call {
+        #[arg(value_parser = parse_digest)]
+        contract: Digest,
+        /// Hex encoded input passed to the contract
+        #[arg(long, value_parser = parse_hex, default_value = "")]
+        input: ::std::vec::Vec<u8>,
+    },
+    /// Authorizes `key` to sign transactions for the sender's account.
+    AddKey {
+        #[arg(value_parser = parse_verifying_key)]
+        key: VerifyingKey,
+    },
+    /// Revokes `key`, e.g. after it was compromised. The last authorized key
+    /// of an account can't be revoked.
+    RevokeKey {
+        #[arg(value_parser = parse_verifying_key)]
+        key: VerifyingKey,
+    },
+    /// Removes the sender's account from the state. Its balance must be zero
+    /// after paying the fee.
+    ///
+    /// The account's nonce is removed with it, so transactions it signed
+    /// before can be replayed once it is closed. Only close accounts whose
+    /// keys are no longer used.
+    CloseAccount,
+    /// Turns the sender's account into a multisig account: `keys` replace
+    /// its authorized keys, and `threshold` of them must sign each of its
+    /// transactions, see [`Transaction::cosignatures`].
+    SetMultisig {
+        #[arg(long = "key", required = true, value_delimiter = ',', value_parser = parse_verifying_key)]
+        keys: Vec<VerifyingKey>,
+        #[arg(long)]
+        threshold: u32,
+    },
+    /// Credits `amount` to the account of `to` for the deposit `id` made on
+    /// the bridged chain. Only the bridge key may send deposits, and each
+    /// `id` is only credited once.
+    Deposit {
+        /// Hex encoded id of the deposit on the bridged chain
+        #[arg(value_parser = parse_digest)]
+        id: Digest,
+        #[arg(value_parser = parse_verifying_key)]
+        to: VerifyingKey,
+        amount: u64,
+    },
+    /// Schedules new values of the live chain parameters, taking effect at
+    /// the Celestia height `activation_height`, which must be in the future.
+    /// Only the governance account may send it, see [`crate::params`].
+    ParamChange {
+        #[arg(long)]
+        activation_height: u64,
+        #[command(flatten)]
+        params: LiveParams,
+    },
+}
+
+impl TransactionType {
+    /// Returns the gas used by executing this transaction type.
+    pub fn gas(&self) -> u64 {
+        match self {
+            TransactionType::Noop
+            | TransactionType::Mint { .. }
+            | TransactionType::Burn { .. }
+            | TransactionType::AddKey { .. }
+            | TransactionType::RevokeKey { .. }
+            | TransactionType::CloseAccount
+            | TransactionType::SetMultisig { .. }
+            | TransactionType::ParamChange { .. } => BASE_GAS,
+            TransactionType::Transfer { .. } | TransactionType::Deposit { .. } => {
+                BASE_GAS + ACCOUNT_WRITE_GAS
+            }
+            TransactionType::Deploy { code } => BASE_GAS + code.len() as u64 * CODE_BYTE_GAS,
+            TransactionType::Call { .. } => BASE_GAS + CONTRACT_CALL_GAS,
+        }
+    }
+}
+
+pub(crate) fn parse_verifying_key(s: &str) -> Result<VerifyingKey> {
+    VerifyingKey::try_from(s.to_string()).context("Invalid verifying key")
+}
+
+fn parse_digest(s: &str) -> Result<Digest> {
+    let bytes: [u8; 32] = hex::decode(s)?
+        .try_into()
+        .map_err(|_| anyhow!("Expected 32 bytes"))?;
+    Ok(Digest::new(bytes))
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>> {
+    hex::decode(s).context("Invalid hex")
+}
+
+fn read_code_file(path: &str) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Failed to read contract code from {}", path))
+}
+
+/// Returns the address a contract deployed by `deployer` with `nonce` is
+/// stored at.
+pub fn contract_address(deployer: &VerifyingKey, nonce: u64) -> Digest {
+    Digest::hash_items(&[deployer.as_bytes().as_slice(), &nonce.to_be_bytes()])
+}
+
+// Tags are part of the signing payload and blob format: never reuse or
+// reorder them, only append new ones.
+const TAG_NOOP: u8 = 0;
+const TAG_TRANSFER: u8 = 1;
+const TAG_MINT: u8 = 2;
+const TAG_BURN: u8 = 3;
+const TAG_DEPLOY: u8 = 4;
+const TAG_CALL: u8 = 5;
+const TAG_ADD_KEY: u8 = 6;
+const TAG_REVOKE_KEY: u8 = 7;
+const TAG_CLOSE_ACCOUNT: u8 = 8;
+const TAG_SET_MULTISIG: u8 = 9;
+const TAG_DEPOSIT: u8 = 10;
+const TAG_PARAM_CHANGE: u8 = 11;
+
+impl Encode for TransactionType {
+    fn encode(&self, enc: &mut Encoder) {
+        match self {
+            TransactionType::Noop => enc.put_u8(TAG_NOOP),
+            TransactionType::Transfer { to, amount } => {
+                enc.put_u8(TAG_TRANSFER);
+                to.encode(enc);
+                enc.put_u64(*amount);
+            }
+            TransactionType::Mint { amount } => {
+                enc.put_u8(TAG_MINT);
+                enc.put_u64(*amount);
+            }
+            TransactionType::Burn { amount } => {
+                enc.put_u8(TAG_BURN);
+                enc.put_u64(*amount);
+            }
+            TransactionType::Deploy { code } => {
+                enc.put_u8(TAG_DEPLOY);
+                enc.put_bytes(code);
+            }
+            TransactionType::Call { contract, input } => {
+                enc.put_u8(TAG_CALL);
+                contract.encode(enc);
+                enc.put_bytes(input);
+            }
+            TransactionType::AddKey { key } => {
+                enc.put_u8(TAG_ADD_KEY);
+                key.encode(enc);
+            }
+            TransactionType::RevokeKey { key } => {
+                enc.put_u8(TAG_REVOKE_KEY);
+                key.encode(enc);
+            }
+            TransactionType::CloseAccount => enc.put_u8(TAG_CLOSE_ACCOUNT),
+            TransactionType::SetMultisig { keys, threshold } => {
+                enc.put_u8(TAG_SET_MULTISIG);
+                keys.encode(enc);
+                enc.put_u32(*threshold);
+            }
+            TransactionType::Deposit { id, to, amount } => {
+                enc.put_u8(TAG_DEPOSIT);
+                id.encode(enc);
+                to.encode(enc);
+                enc.put_u64(*amount);
+            }
+            TransactionType::ParamChange {
+                activation_height,
+                params,
+            } => {
+                enc.put_u8(TAG_PARAM_CHANGE);
+                enc.put_u64(*activation_height);
+                params.encode(enc);
+            }
+        }
+    }
+}
+
+impl Decode for TransactionType {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        match dec.u8()? {
+            TAG_NOOP => Ok(TransactionType::Noop),
+            TAG_TRANSFER => Ok(TransactionType::Transfer {
+                to: VerifyingKey::decode(dec)?,
+                amount: dec.u64()?,
+            }),
+            TAG_MINT => Ok(TransactionType::Mint { amount: dec.u64()? }),
+            TAG_BURN => Ok(TransactionType::Burn { amount: dec.u64()? }),
+            TAG_DEPLOY => Ok(TransactionType::Deploy {
+                code: dec.bytes()?.to_vec(),
+            }),
+            TAG_CALL => Ok(TransactionType::Call {
+                contract: Digest::decode(dec)?,
+                input: dec.bytes()?.to_vec(),
+            }),
+            TAG_ADD_KEY => Ok(TransactionType::AddKey {
+                key: VerifyingKey::decode(dec)?,
+            }),
+            TAG_REVOKE_KEY => Ok(TransactionType::RevokeKey {
+                key: VerifyingKey::decode(dec)?,
+            }),
+            TAG_CLOSE_ACCOUNT => Ok(TransactionType::CloseAccount),
+            TAG_SET_MULTISIG => Ok(TransactionType::SetMultisig {
+                keys: Vec::decode(dec)?,
+                threshold: dec.u32()?,
+            }),
+            TAG_DEPOSIT => Ok(TransactionType::Deposit {
+                id: Digest::decode(dec)?,
+                to: VerifyingKey::decode(dec)?,
+                amount: dec.u64()?,
+            }),
+            TAG_PARAM_CHANGE => Ok(TransactionType::ParamChange {
+                activation_height: dec.u64()?,
+                params: LiveParams::decode(dec)?,
+            }),
+            tag => Err(anyhow!("Unknown transaction type tag {}", tag)),
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Transaction {
-    /// Signature of bincode::serialize(&(tx_type, nonce))
-    /// For toy rollups or experimentation, use [`Signature::Placeholder`]
+    /// Signature over [`Transaction::signature_msg`] by one of the keys
+    /// authorized on the account. For toy rollups or experimentation, use
+    /// [`Signature::Placeholder`].
     pub signature: Signature,
 
-    /// Account key of user.
+    /// Signatures over the same payload by further keys of a multisig
+    /// account, counted towards its threshold together with `signature`. See
+    /// [`Transaction::cosign`].
+    #[serde(default)]
+    pub cosignatures: Vec<Signature>,
+
+    /// Key identifying the sender's account. It is only authorized to sign
+    /// for the account until it is revoked, see
+    /// [`Account::authorized_keys`](crate::state::Account::authorized_keys).
     pub vk: VerifyingKey,
 
     /// Nonce of the account.
@@ -28,61 +310,703 @@ pub struct Transaction {
     /// nonces are strictly increasing in your [`State`].
     pub nonce: u64,
 
+    /// The fee the sender pays for inclusion, deducted from its balance on
+    /// execution. Divided by [`TransactionType::gas`], it gives the gas
+    /// price checked against the sequencer's minimum.
+    #[serde(default)]
+    pub fee: u64,
+
+    /// The chain the transaction is meant for, see
+    /// [`Config::chain_id`](crate::node::Config::chain_id).
+    #[serde(default)]
+    pub chain_id: u64,
+
+    /// The last Celestia height the transaction may be executed at. Once it
+    /// has passed, the transaction is rejected by the mempool and by
+    /// execution, so a wallet can safely re-sign it after that height
+    /// instead of risking a stale copy executing later. Never expires if
+    /// unset.
+    #[serde(default)]
+    pub valid_until_da_height: Option<u64>,
+
+    /// The Celestia height the transaction is executed at, e.g. for vesting
+    /// or timelocks. Included at an earlier height, it only uses its nonce
+    /// and pays its fee, and waits in the state until then, see
+    /// [`State::take_scheduled_txs`](crate::state::State::take_scheduled_txs).
+    /// Its signature isn't checked again, so revoking the key that signed it
+    /// doesn't cancel it. Executed right away if unset or already reached.
+    #[serde(default)]
+    pub execute_at_da_height: Option<u64>,
+
     /// Transaction variant.
     pub tx_type: TransactionType,
 }
 
 impl Transaction {
+    /// Checks the parts of the transaction that don't depend on state. The
+    /// signature is checked against the account's keys by
+    /// [`Transaction::verify_signature`].
     pub fn verify(&self) -> Result<()> {
-        if SIGNATURE_VERIFICATION_ENABLED {
-            self.vk
-                .verify_signature(&self.signature_msg()?, &self.signature)?;
+        if self.cosignatures.len() >= MAX_ACCOUNT_KEYS {
+            return Err(TxError::TooManySignatures {
+                max: MAX_ACCOUNT_KEYS,
+            }
+            .into());
+        }
+        if let (Some(execute_at_da_height), Some(valid_until_da_height)) =
+            (self.execute_at_da_height, self.valid_until_da_height)
+        {
+            if execute_at_da_height > valid_until_da_height {
+                return Err(anyhow!(
+                    "Transaction expires at celestia height {} before it is executed at {}",
+                    valid_until_da_height,
+                    execute_at_da_height
+                ));
+            }
         }
+        match &self.tx_type {
+            TransactionType::Noop
+            | TransactionType::AddKey { .. }
+            | TransactionType::RevokeKey { .. }
+            | TransactionType::CloseAccount => Ok(()),
+            TransactionType::Transfer { amount, .. }
+            | TransactionType::Mint { amount }
+            | TransactionType::Burn { amount }
+            | TransactionType::Deposit { amount, .. } => {
+                if *amount == 0 {
+                    return Err(TxError::ZeroAmount.into());
+                }
+                Ok(())
+            }
+            TransactionType::Deploy { code } => check_size(code, MAX_CONTRACT_CODE_SIZE),
+            TransactionType::Call { input, .. } => check_size(input, MAX_CALL_INPUT_SIZE),
+            TransactionType::SetMultisig { keys, threshold } => check_multisig(keys, *threshold),
+            TransactionType::ParamChange { params, .. } => {
+                if params.is_empty() {
+                    return Err(anyhow!("Parameter change sets no parameter"));
+                }
+                Ok(())
+            }
+        }
+    }
 
-        match self.tx_type {
-            TransactionType::Noop => Ok(()),
+    /// Checks that the transaction is signed by at least `threshold` of
+    /// `keys`, the keys authorized on the sender's account. Each key counts
+    /// once, however many of the signatures it made.
+    pub fn verify_signature(&self, keys: &[VerifyingKey], threshold: usize) -> Result<()> {
+        let mut reason = "Not signed by a key authorized on the account".to_string();
+        let mut signed = vec![false; keys.len()];
+        for signature in self.signatures() {
+            for (i, key) in keys.iter().enumerate() {
+                let scheme = KeyScheme::of(key);
+                if signed[i] || !signature_matches(signature, scheme) {
+                    continue;
+                }
+                match key.verify_signature(&self.signature_msg(scheme)?, signature) {
+                    Ok(()) => {
+                        signed[i] = true;
+                        break;
+                    }
+                    Err(e) => reason = e.to_string(),
+                }
+            }
         }
+        let signers = signed.iter().filter(|signed| **signed).count();
+        match signers {
+            0 => Err(TxError::InvalidSignature { reason }.into()),
+            signers if signers < threshold => {
+                Err(TxError::ThresholdNotMet { signers, threshold }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the signature and the cosignatures.
+    pub fn signatures(&self) -> impl Iterator<Item = &Signature> {
+        std::iter::once(&self.signature).chain(&self.cosignatures)
     }
 
+    /// Signs the transaction with `key`, which must be authorized on the
+    /// account of [`Transaction::vk`].
     pub fn sign(&mut self, key: &SigningKey) -> Result<()> {
-        if SIGNATURE_VERIFICATION_ENABLED {
-            let msg = self.signature_msg()?;
-            self.signature = key.sign(&msg);
-            return Ok(());
+        let msg = self.signature_msg(KeyScheme::of_signing_key(key))?;
+        self.signature = key.sign(&msg);
+        Ok(())
+    }
+
+    /// Adds a signature by `key`, another key of the sender's multisig
+    /// account. The transaction must be complete and signed with
+    /// [`Transaction::sign`] first, since cosigning doesn't change the
+    /// payload.
+    pub fn cosign(&mut self, key: &SigningKey) -> Result<()> {
+        let msg = self.signature_msg(KeyScheme::of_signing_key(key))?;
+        self.cosignatures.push(key.sign(&msg));
+        Ok(())
+    }
+
+    /// Checks that the transaction may still be executed at `da_height`.
+    pub fn check_expiry(&self, da_height: u64) -> Result<()> {
+        match self.valid_until_da_height {
+            Some(valid_until_da_height) if da_height > valid_until_da_height => {
+                Err(TxError::Expired {
+                    valid_until_da_height,
+                    da_height,
+                }
+                .into())
+            }
+            _ => Ok(()),
         }
-        Err(anyhow!("Signature verification is disabled"))
     }
 
-    fn signature_msg(&self) -> Result<Vec<u8>> {
-        bincode::serialize(&(self.tx_type.clone(), self.nonce)).map_err(|e| anyhow!(e))
+    /// Returns the price paid per unit of gas.
+    pub fn gas_price(&self) -> u64 {
+        self.fee / self.tx_type.gas()
+    }
+
+    /// Returns the hash identifying this transaction, computed over its
+    /// canonical encoding.
+    pub fn hash(&self) -> Digest {
+        Digest::hash(self.to_canonical_bytes())
     }
+
+    /// The payload signed with a key of `scheme`: [`SIGNING_DOMAIN`] and the
+    /// scheme's domain, then the canonical encoding of the account key, the
+    /// chain id, the expiry, the execution height, the transaction type, the
+    /// nonce and the fee. The account key is included because a key may be
+    /// authorized on several accounts.
+    pub fn signature_msg(&self, scheme: KeyScheme) -> Result<Vec<u8>> {
+        let mut enc = Encoder::default();
+        enc.put_raw(SIGNING_DOMAIN);
+        enc.put_raw(scheme.domain());
+        self.vk.encode(&mut enc);
+        enc.put_u64(self.chain_id);
+        encode_da_height(self.valid_until_da_height, &mut enc);
+        encode_da_height(self.execute_at_da_height, &mut enc);
+        self.tx_type.encode(&mut enc);
+        enc.put_u64(self.nonce);
+        enc.put_u64(self.fee);
+        Ok(enc.finish())
+    }
+}
+
+/// Builds a signed [`Transaction`], e.g.
+/// `TransactionBuilder::new(tx_type).nonce(n).chain_id(id).sign(&key)`.
+/// The fee defaults to 0, the chain id to [`LEGACY_CHAIN_ID`] and the
+/// transaction never expires.
+#[derive(Clone, Debug)]
+pub struct TransactionBuilder {
+    tx_type: TransactionType,
+    nonce: u64,
+    fee: u64,
+    chain_id: u64,
+    valid_until_da_height: Option<u64>,
+    execute_at_da_height: Option<u64>,
+}
+
+impl TransactionBuilder {
+    pub fn new(tx_type: TransactionType) -> Self {
+        TransactionBuilder {
+            tx_type,
+            nonce: 0,
+            fee: 0,
+            chain_id: LEGACY_CHAIN_ID,
+            valid_until_da_height: None,
+            execute_at_da_height: None,
+        }
+    }
+
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    pub fn chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    /// Sets [`Transaction::valid_until_da_height`].
+    pub fn valid_until(mut self, da_height: Option<u64>) -> Self {
+        self.valid_until_da_height = da_height;
+        self
+    }
+
+    /// Sets [`Transaction::execute_at_da_height`].
+    pub fn execute_at(mut self, da_height: Option<u64>) -> Self {
+        self.execute_at_da_height = da_height;
+        self
+    }
+
+    /// Returns the transaction of the account of `key`, signed by it.
+    pub fn sign(self, key: &SigningKey) -> Result<Transaction> {
+        self.sign_with_cosigners(key, &[])
+    }
+
+    /// Returns the transaction of the account of `key`, signed by it and
+    /// cosigned by `cosigners`, other keys of the multisig account.
+    pub fn sign_with_cosigners(
+        self,
+        key: &SigningKey,
+        cosigners: &[SigningKey],
+    ) -> Result<Transaction> {
+        let mut tx = self.unsigned(verifying_key(key));
+        tx.sign(key)?;
+        for cosigner in cosigners {
+            tx.cosign(cosigner)?;
+        }
+        Ok(tx)
+    }
+
+    /// Returns the transaction of the account of `vk` without a signature,
+    /// which only nodes running with
+    /// [`Config::insecure_signatures`](crate::node::Config::insecure_signatures)
+    /// accept.
+    pub fn unsigned(self, vk: VerifyingKey) -> Transaction {
+        Transaction {
+            signature: Signature::default(),
+            cosignatures: Vec::new(),
+            vk,
+            nonce: self.nonce,
+            fee: self.fee,
+            chain_id: self.chain_id,
+            valid_until_da_height: self.valid_until_da_height,
+            execute_at_da_height: self.execute_at_da_height,
+            tx_type: self.tx_type,
+        }
+    }
+}
+
+fn encode_da_height(da_height: Option<u64>, enc: &mut Encoder) {
+    match da_height {
+        Some(height) => {
+            enc.put_u8(1);
+            enc.put_u64(height);
+        }
+        None => enc.put_u8(0),
+    }
+}
+
+fn decode_da_height(dec: &mut Decoder) -> Result<Option<u64>> {
+    match dec.u8()? {
+        0 => Ok(None),
+        1 => Ok(Some(dec.u64()?)),
+        tag => Err(anyhow!("Invalid celestia height tag {}", tag)),
+    }
+}
+
+/// Checks the keys and threshold of a multisig account: at most
+/// [`MAX_ACCOUNT_KEYS`] distinct keys, at least `threshold` of them.
+pub(crate) fn check_multisig(keys: &[VerifyingKey], threshold: u32) -> Result<()> {
+    if keys.len() > MAX_ACCOUNT_KEYS {
+        return Err(TxError::TooManyKeys {
+            max: MAX_ACCOUNT_KEYS,
+        }
+        .into());
+    }
+    if threshold == 0 || threshold as usize > keys.len() {
+        return Err(TxError::InvalidThreshold {
+            threshold,
+            keys: keys.len(),
+        }
+        .into());
+    }
+    for (i, key) in keys.iter().enumerate() {
+        if keys[..i].contains(key) {
+            return Err(TxError::KeyAlreadyAuthorized.into());
+        }
+    }
+    Ok(())
+}
+
+fn check_size(payload: &[u8], max: usize) -> Result<()> {
+    if payload.len() > max {
+        return Err(TxError::PayloadTooLarge {
+            size: payload.len(),
+            max,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+impl Encode for Transaction {
+    fn encode(&self, enc: &mut Encoder) {
+        self.vk.encode(enc);
+        enc.put_u64(self.nonce);
+        enc.put_u64(self.fee);
+        enc.put_u64(self.chain_id);
+        encode_da_height(self.valid_until_da_height, enc);
+        encode_da_height(self.execute_at_da_height, enc);
+        self.tx_type.encode(enc);
+        self.signature.encode(enc);
+        self.cosignatures.encode(enc);
+    }
+}
+
+impl Decode for Transaction {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        Ok(Transaction {
+            vk: VerifyingKey::decode(dec)?,
+            nonce: dec.u64()?,
+            fee: dec.u64()?,
+            // blobs before version 5 carry no chain id
+            chain_id: if dec.version() >= 5 {
+                dec.u64()?
+            } else {
+                LEGACY_CHAIN_ID
+            },
+            // blobs before version 6 carry no expiry
+            valid_until_da_height: if dec.version() >= 6 {
+                decode_da_height(dec)?
+            } else {
+                None
+            },
+            // blobs before version 10 carry no execution height
+            execute_at_da_height: if dec.version() >= 10 {
+                decode_da_height(dec)?
+            } else {
+                None
+            },
+            tx_type: TransactionType::decode(dec)?,
+            signature: Signature::decode(dec)?,
+            // blobs before version 8 carry no cosignatures
+            cosignatures: if dec.version() >= 8 {
+                Vec::decode(dec)?
+            } else {
+                Vec::new()
+            },
+        })
+    }
+}
+
+/// A sequencer's signature over a [`BatchHeader`], which commits to the
+/// batch's transactions through its tx root.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BatchSignature {
+    pub vk: VerifyingKey,
+    pub signature: Signature,
 }
 
 #[derive(Serialize, Deserialize)]
-pub struct Batch(Vec<Transaction>);
+pub struct Batch {
+    /// Missing for batches posted before block headers were introduced.
+    header: Option<BatchHeader>,
+    txs: Vec<Transaction>,
+    signature: Option<BatchSignature>,
+    /// The transactions of a decoded blob that failed to decode, by their
+    /// position among all of its transactions and the hash of their bytes,
+    /// so the tx root can still be checked. They aren't executed.
+    #[serde(skip)]
+    skipped: Vec<(usize, Digest)>,
+}
 
 impl Batch {
     pub fn new(txs: Vec<Transaction>) -> Self {
-        Batch(txs.clone())
+        Batch {
+            header: None,
+            txs,
+            signature: None,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Creates a batch committing to `txs` as the block at `height`.
+    pub fn with_header(height: u64, timestamp: u64, txs: Vec<Transaction>) -> Self {
+        let header = BatchHeader {
+            height,
+            tx_root: tx_root(&txs),
+            timestamp,
+            state_root: None,
+            protocol_version: 0,
+        };
+        Batch {
+            header: Some(header),
+            txs,
+            signature: None,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Claims `state_root` as the root after executing the batch, see
+    /// [`BatchHeader::state_root`]. Must be set before signing.
+    pub fn with_state_root(mut self, state_root: Digest) -> Self {
+        if let Some(header) = &mut self.header {
+            header.state_root = Some(state_root);
+        }
+        self
+    }
+
+    /// Marks the batch as executed under protocol `version`, see
+    /// [`crate::protocol`].
+    pub fn with_protocol_version(mut self, version: u32) -> Self {
+        if let Some(header) = &mut self.header {
+            header.protocol_version = version;
+        }
+        self
+    }
+
+    /// Signs the batch header as the sequencer owning `key`.
+    pub fn sign(&mut self, key: &SigningKey, vk: VerifyingKey) -> Result<()> {
+        let signature = key.sign(&self.signature_msg()?);
+        self.signature = Some(BatchSignature { vk, signature });
+        Ok(())
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.signature.is_some()
+    }
+
+    /// Checks that the batch was signed by the sequencer with key `vk`.
+    pub fn verify_signature(&self, vk: &VerifyingKey) -> Result<()> {
+        let batch_signature = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| anyhow!("Batch is not signed"))?;
+        if batch_signature.vk != *vk {
+            return Err(anyhow!("Batch is signed by an unknown sequencer"));
+        }
+        vk.verify_signature(&self.signature_msg()?, &batch_signature.signature)
+            .map_err(|e| anyhow!("Invalid batch signature: {}", e))
+    }
+
+    fn signature_msg(&self) -> Result<Vec<u8>> {
+        let header = self
+            .header
+            .as_ref()
+            .ok_or_else(|| anyhow!("Batches without a header can't be signed"))?;
+        Ok(header.signature_msg(BATCH_SIGNING_DOMAIN))
+    }
+
+    pub fn header(&self) -> Option<&BatchHeader> {
+        self.header.as_ref()
     }
 
     pub fn get_transactions(&self) -> Vec<Transaction> {
-        self.0.clone()
+        self.txs.clone()
     }
+
+    /// Returns the number of transactions that failed to decode.
+    pub fn skipped_txs(&self) -> usize {
+        self.skipped.len()
+    }
+
+    /// Computes the [`tx_root`] over all transactions of the batch, including
+    /// the ones that failed to decode, to check against its header.
+    pub fn tx_root(&self) -> Result<Digest> {
+        let mut hashes: Vec<_> = self.txs.iter().map(Transaction::hash).collect();
+        for (position, hash) in &self.skipped {
+            if *position > hashes.len() {
+                return Err(anyhow!(
+                    "Skipped transaction position {} out of range",
+                    position
+                ));
+            }
+            hashes.insert(*position, *hash);
+        }
+        Ok(tx_root_of_hashes(hashes))
+    }
+}
+
+/// Only encodes batches with a header; headerless batches only exist in
+/// version 1 blobs. Since version 9 every transaction is length-prefixed, so
+/// one that fails to decode, e.g. of a type the node doesn't know yet, can
+/// be skipped without discarding the rest of the batch.
+impl Encode for Batch {
+    fn encode(&self, enc: &mut Encoder) {
+        if let Some(header) = &self.header {
+            header.encode(enc);
+        }
+        enc.put_u32(self.txs.len() as u32);
+        for tx in &self.txs {
+            enc.put_bytes(&tx.to_canonical_bytes());
+        }
+        match &self.signature {
+            Some(batch_signature) => {
+                enc.put_u8(1);
+                batch_signature.vk.encode(enc);
+                batch_signature.signature.encode(enc);
+            }
+            None => enc.put_u8(0),
+        }
+    }
+}
+
+impl Decode for Batch {
+    fn decode(dec: &mut Decoder) -> Result<Self> {
+        let header = Some(BatchHeader::decode(dec)?);
+        let (txs, skipped) = if dec.version() >= 9 {
+            decode_prefixed_txs(dec)?
+        } else {
+            (Vec::decode(dec)?, Vec::new())
+        };
+        let signature = match dec.u8()? {
+            0 => None,
+            1 => Some(BatchSignature {
+                vk: VerifyingKey::decode(dec)?,
+                signature: Signature::decode(dec)?,
+            }),
+            tag => return Err(anyhow!("Invalid batch signature tag {}", tag)),
+        };
+        Ok(Batch {
+            header,
+            txs,
+            signature,
+            skipped,
+        })
+    }
+}
+
+/// Decodes length-prefixed transactions, collecting the ones that fail to
+/// decode instead of failing.
+fn decode_prefixed_txs(dec: &mut Decoder) -> Result<(Vec<Transaction>, Vec<(usize, Digest)>)> {
+    let len = dec.u32()?;
+    let mut txs = Vec::new();
+    let mut skipped = Vec::new();
+    for position in 0..len as usize {
+        let bytes = dec.bytes()?;
+        let mut tx_dec = Decoder::with_version(bytes, dec.version());
+        let decoded = Transaction::decode(&mut tx_dec).and_then(|tx| {
+            tx_dec.finish()?;
+            Ok(tx)
+        });
+        match decoded {
+            Ok(tx) => txs.push(tx),
+            Err(e) => {
+                debug!("skipping undecodable transaction {}: {}", position, e);
+                skipped.push((position, Digest::hash(bytes)));
+            }
+        }
+    }
+    Ok((txs, skipped))
 }
 
 impl TryFrom<&Blob> for Batch {
     type Error = anyhow::Error;
 
     fn try_from(value: &Blob) -> Result<Self, Self::Error> {
-        match bincode::deserialize(&value.data) {
-            Ok(batch) => Ok(batch),
+        match open_blob(&value.data)? {
+            Some((1, body)) => {
+                let mut dec = Decoder::with_version(&body, 1);
+                let txs = Vec::decode(&mut dec)?;
+                dec.finish()?;
+                return Ok(Batch::new(txs));
+            }
+            Some((2, body)) => {
+                let mut dec = Decoder::with_version(&body, 2);
+                let header = BatchHeader::decode(&mut dec)?;
+                let txs = Vec::decode(&mut dec)?;
+                dec.finish()?;
+                return Ok(Batch {
+                    header: Some(header),
+                    txs,
+                    signature: None,
+                    skipped: Vec::new(),
+                });
+            }
+            Some((version, body)) => {
+                let mut dec = Decoder::with_version(&body, version);
+                let batch = Batch::decode(&mut dec)?;
+                dec.finish()?;
+                return Ok(batch);
+            }
+            None => {}
+        }
+
+        // blobs posted before the versioned format was introduced
+        match decode_legacy_blob(&value.data) {
+            Ok(txs) => Ok(Batch::new(txs)),
             Err(_) => {
-                let transaction: Transaction = bincode::deserialize(&value.data)
+                let transaction: Transaction = decode_legacy_blob(&value.data)
                     .context(format!("Failed to decode blob into Transaction: {value:?}"))?;
 
-                Ok(Batch(vec![transaction]))
+                Ok(Batch::new(vec![transaction]))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encoding::BLOB_VERSION, testkit::generate_key};
+
+    /// Encodes `tx` in the layout of blob `version`, leaving out the fields
+    /// that version doesn't carry.
+    fn encode_in_version(tx: &Transaction, version: u8) -> Vec<u8> {
+        let mut enc = Encoder::default();
+        tx.vk.encode(&mut enc);
+        enc.put_u64(tx.nonce);
+        enc.put_u64(tx.fee);
+        if version >= 5 {
+            enc.put_u64(tx.chain_id);
+        }
+        if version >= 6 {
+            encode_da_height(tx.valid_until_da_height, &mut enc);
+        }
+        if version >= 10 {
+            encode_da_height(tx.execute_at_da_height, &mut enc);
+        }
+        tx.tx_type.encode(&mut enc);
+        tx.signature.encode(&mut enc);
+        if version >= 8 {
+            enc.put_u32(tx.cosignatures.len() as u32);
+            for cosignature in &tx.cosignatures {
+                cosignature.encode(&mut enc);
+            }
+        }
+        enc.finish()
+    }
+
+    fn cosigned_tx() -> Transaction {
+        TransactionBuilder::new(TransactionType::Noop)
+            .nonce(3)
+            .fee(5)
+            .chain_id(7)
+            .valid_until(Some(100))
+            .execute_at(Some(50))
+            .sign_with_cosigners(&generate_key(), &[generate_key()])
+            .unwrap()
+    }
+
+    #[test]
+    fn transactions_round_trip() {
+        let tx = cosigned_tx();
+        let decoded = Transaction::from_canonical_bytes(&tx.to_canonical_bytes()).unwrap();
+        assert_eq!(decoded.hash(), tx.hash());
+        assert_eq!(
+            tx.to_canonical_bytes(),
+            encode_in_version(&tx, BLOB_VERSION)
+        );
+    }
+
+    #[test]
+    fn earlier_versions_default_missing_fields() {
+        let tx = cosigned_tx();
+        for version in 4..=BLOB_VERSION {
+            let bytes = encode_in_version(&tx, version);
+            let mut dec = Decoder::with_version(&bytes, version);
+            let decoded = Transaction::decode(&mut dec).unwrap();
+            dec.finish().unwrap();
+
+            let mut expected = tx.clone();
+            if version < 5 {
+                expected.chain_id = LEGACY_CHAIN_ID;
+            }
+            if version < 6 {
+                expected.valid_until_da_height = None;
+            }
+            if version < 8 {
+                expected.cosignatures = Vec::new();
+            }
+            if version < 10 {
+                expected.execute_at_da_height = None;
             }
+            assert_eq!(decoded.hash(), expected.hash(), "version {}", version);
         }
     }
 }
@@ -0,0 +1,187 @@
+//! A live view of a running node for demos and debugging: follows the
+//! node's `/ws` event stream and redraws the Celestia heights, batch
+//! postings, blocks and recent transactions it reports, along with the
+//! mempool depth polled from `/mempool`.
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use futures::StreamExt;
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    io::{self, Write as _},
+    time::Duration,
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+use crate::{events::Event, webserver::MempoolResponse};
+
+/// How often the mempool depth is polled and the view redrawn without
+/// events.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// The number of batch postings and transactions shown.
+const RECENT_ENTRIES: usize = 10;
+/// Clears the terminal and moves the cursor to its top left.
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// What the node reported since the view started.
+#[derive(Default)]
+struct View {
+    da_height: Option<u64>,
+    /// The state root and its epoch
+    root: Option<(String, u64)>,
+    /// The height and Celestia height of the latest block
+    block: Option<(u64, u64)>,
+    /// The transaction count and Celestia height of recent batch postings,
+    /// latest first
+    batches: VecDeque<(usize, u64)>,
+    /// The sender, nonce and success of recently executed transactions,
+    /// latest first
+    txs: VecDeque<(String, u64, bool)>,
+    mempool: Option<MempoolResponse>,
+    events: u64,
+}
+
+impl View {
+    fn apply(&mut self, event: Event) {
+        self.events += 1;
+        match event {
+            Event::BatchPosted {
+                tx_count,
+                da_height,
+            } => push_recent(&mut self.batches, (tx_count, da_height)),
+            Event::TxIncluded {
+                vk, nonce, success, ..
+            } => push_recent(
+                &mut self.txs,
+                (BASE64.encode(vk.as_bytes()), nonce, success),
+            ),
+            Event::BlockProduced {
+                height, da_height, ..
+            } => self.block = Some((height, da_height)),
+            Event::StateRoot { root, epoch } => self.root = Some((root, epoch)),
+            Event::DaHeightProcessed { height } => self.da_height = Some(height),
+        }
+    }
+
+    fn render(&self, node_url: &str) -> Result<String> {
+        let mut out = String::from(CLEAR_SCREEN);
+        writeln!(
+            out,
+            "\x1b[1m{}\x1b[0m  ({} events, ctrl-c to quit)",
+            node_url, self.events
+        )?;
+        writeln!(out)?;
+        writeln!(out, "celestia height  {}", display_or_dash(self.da_height))?;
+        match &self.root {
+            Some((root, epoch)) => writeln!(out, "state root       {} (epoch {})", root, epoch)?,
+            None => writeln!(out, "state root       -")?,
+        }
+        match self.block {
+            Some((height, da_height)) => writeln!(
+                out,
+                "latest block     {} (celestia height {})",
+                height, da_height
+            )?,
+            None => writeln!(out, "latest block     -")?,
+        }
+        match &self.mempool {
+            Some(mempool) => writeln!(
+                out,
+                "mempool          {} queued, {} pending submissions",
+                mempool.queued_txs, mempool.pending_submissions
+            )?,
+            None => writeln!(out, "mempool          -")?,
+        }
+
+        writeln!(out)?;
+        writeln!(out, "\x1b[1mbatches posted\x1b[0m")?;
+        for (tx_count, da_height) in &self.batches {
+            writeln!(out, "  {} txs at celestia height {}", tx_count, da_height)?;
+        }
+        writeln!(out)?;
+        writeln!(out, "\x1b[1mrecent transactions\x1b[0m")?;
+        for (vk, nonce, success) in &self.txs {
+            let outcome = if *success {
+                "\x1b[32mexecuted\x1b[0m"
+            } else {
+                "\x1b[31mfailed\x1b[0m"
+            };
+            writeln!(out, "  {} nonce {:<6} {}", shorten(vk), nonce, outcome)?;
+        }
+        Ok(out)
+    }
+}
+
+fn push_recent<T>(entries: &mut VecDeque<T>, entry: T) {
+    entries.push_front(entry);
+    entries.truncate(RECENT_ENTRIES);
+}
+
+fn display_or_dash(value: Option<u64>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| value.to_string())
+}
+
+/// Keeps the start of long keys, so lines don't wrap.
+fn shorten(vk: &str) -> String {
+    match vk.char_indices().nth(16) {
+        Some((end, _)) => format!("{}...", &vk[..end]),
+        None => vk.to_string(),
+    }
+}
+
+/// Returns the URL of the event stream of the node at `node_url`.
+fn events_url(node_url: &str) -> Result<String> {
+    let node_url = node_url.trim_end_matches('/');
+    if let Some(rest) = node_url.strip_prefix("http://") {
+        Ok(format!("ws://{}/ws", rest))
+    } else if let Some(rest) = node_url.strip_prefix("https://") {
+        Ok(format!("wss://{}/ws", rest))
+    } else {
+        Err(anyhow!(
+            "Expected an http:// or https:// node URL, got {}",
+            node_url
+        ))
+    }
+}
+
+async fn fetch_mempool(client: &reqwest::Client, node_url: &str) -> Result<MempoolResponse> {
+    let response = client
+        .get(format!("{}/mempool", node_url.trim_end_matches('/')))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.json().await?)
+}
+
+/// Renders the activity of the node at `node_url` until ctrl-c or the node
+/// closes the stream.
+pub async fn run(node_url: &str) -> Result<()> {
+    let (mut events, _) = connect_async(events_url(node_url)?)
+        .await
+        .with_context(|| format!("Failed to connect to the event stream of {}", node_url))?;
+    let client = reqwest::Client::new();
+    let mut view = View::default();
+    let mut refresh = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        tokio::select! {
+            msg = events.next() => match msg {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(event) => view.apply(event),
+                    // e.g. an error response, which only answers subscriptions
+                    Err(_) => continue,
+                },
+                Some(Ok(Message::Close(_))) | None => {
+                    return Err(anyhow!("{} closed the event stream", node_url));
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e).context("Reading the event stream"),
+            },
+            _ = refresh.tick() => view.mempool = fetch_mempool(&client, node_url).await.ok(),
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+        let mut stdout = io::stdout();
+        stdout.write_all(view.render(node_url)?.as_bytes())?;
+        stdout.flush()?;
+    }
+}
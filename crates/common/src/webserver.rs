@@ -1,13 +1,1160 @@
-use crate::node::Node;
+use crate::block::{Block, DaInclusion};
+use crate::error::{ExecutionError, TxError};
+use crate::events::{EventFilter, SubscriptionRequest};
+use crate::fees::FeeEstimate;
+use crate::finality::Finality;
+use crate::fraud::FraudProof;
+use crate::history::{AccountTxsPage, HistoryEntry, DEFAULT_HISTORY_LIMIT, MAX_HISTORY_LIMIT};
+use crate::latency::{LatencyReport, StageLatency, TxTimings};
+use crate::node::{AccountProof, AccountProofBundle, BatchInclusionProof, Node};
+use crate::params::{LiveParams, ParamSchedule, ScheduledChange};
+use crate::proofs::EpochProof;
+use crate::receipt::{Receipt, TxEvent};
+use crate::simulate::{AccountDiff, SimulatedTx, Simulation, MAX_SIMULATED_TXS};
+use crate::state::Account;
+use crate::status::TxStatus;
+use crate::storage::CacheStats;
+use crate::telemetry;
+use crate::tree::Digest;
 use crate::tx::Transaction;
-use axum::{extract::State as AxumState, http::StatusCode, Json};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State as AxumState,
+    },
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use prism_common::keys::VerifyingKey;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
+/// The OpenAPI description of the HTTP API, served at `/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "zk-shard node API"),
+    paths(
+        submit_tx,
+        estimate_fee,
+        simulate,
+        get_tx,
+        get_receipt,
+        get_account,
+        get_account_history,
+        get_account_txs,
+        get_accounts,
+        get_events,
+        get_proof,
+        get_proof_bundle,
+        get_root,
+        get_block,
+        get_block_da,
+        get_inclusion_proof,
+        verify_root,
+        verify_fraud_proof,
+        get_height,
+        get_rejected_data,
+        get_latency_stats,
+        get_cache_stats,
+        get_mempool,
+        get_params,
+        get_snapshot,
+        set_log_level
+    ),
+    components(schemas(
+        Account,
+        AccountResponse,
+        Finality,
+        TxStatus,
+        Receipt,
+        TxEvent,
+        HistoryEntry,
+        AccountTxsPage,
+        AccountEntry,
+        AccountsPage,
+        FeeEstimate,
+        Simulation,
+        SimulatedTx,
+        AccountDiff,
+        TxError,
+        ExecutionError,
+        ApiError,
+        ErrorResponse,
+        RootResponse,
+        BlockResponse,
+        BlockDaResponse,
+        VerifyRootResponse,
+        VerifyFraudProofResponse,
+        HeightResponse,
+        RejectedDataResponse,
+        MempoolResponse,
+        TxResponse,
+        TxTimings,
+        LatencyReport,
+        StageLatency,
+        CacheStats,
+        ParamSchedule,
+        ScheduledChange,
+        LiveParams,
+        SubmitTxResponse,
+        ProofResponse,
+        ProofBundleResponse,
+        LogLevel
+    ))
+)]
+pub struct ApiDoc;
+
+/// The error returned by API endpoints.
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+#[serde(tag = "kind", content = "details", rename_all = "snake_case")]
+pub enum ApiError {
+    /// The transaction was rejected, see [`TxError`].
+    Rejected(TxError),
+    /// The request is malformed, e.g. an invalid key or hash.
+    BadRequest(String),
+    NotFound(String),
+    /// Missing or wrong admin token.
+    Unauthorized,
+    /// Too many requests from this client, retry later.
+    RateLimited,
+    Internal(String),
+}
+
+/// The JSON body of every error response.
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct ErrorResponse {
+    pub error: ApiError,
+    /// A human readable description of the error
+    pub message: String,
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Rejected(TxError::MempoolFull) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Rejected(TxError::MempoolBusy) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Rejected(_) | ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            ApiError::Rejected(e) => e.to_string(),
+            ApiError::BadRequest(msg) | ApiError::NotFound(msg) | ApiError::Internal(msg) => {
+                msg.clone()
+            }
+            ApiError::Unauthorized => "Missing or invalid admin token".to_string(),
+            ApiError::RateLimited => "Too many requests".to_string(),
+        }
+    }
+}
+
+/// Surfaces [`TxError`]s as rejections, everything else as internal errors.
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast_ref::<TxError>() {
+            Some(tx_error) => ApiError::Rejected(tx_error.clone()),
+            None => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let message = self.message();
+        (
+            status,
+            Json(ErrorResponse {
+                error: self,
+                message,
+            }),
+        )
+            .into_response()
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RootResponse {
+    /// The hex encoded state root
+    pub root: String,
+    pub epoch: u64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BlockResponse {
+    pub height: u64,
+    /// The hex encoded state root before the block
+    pub prev_root: String,
+    /// The hex encoded state root after the block
+    pub new_root: String,
+    /// The hex encoded Merkle root of the block's transaction hashes
+    pub tx_root: String,
+    /// The Celestia height the block's batch was included at
+    pub da_height: u64,
+    pub timestamp: u64,
+    #[serde(default = "da_included")]
+    pub finality: Finality,
+}
+
+fn da_included() -> Finality {
+    Finality::DaIncluded
+}
+
+impl From<Block> for BlockResponse {
+    fn from(block: Block) -> Self {
+        BlockResponse {
+            height: block.height,
+            prev_root: hex::encode(block.prev_root.0),
+            new_root: hex::encode(block.new_root.0),
+            tx_root: hex::encode(block.tx_root.0),
+            da_height: block.da_height,
+            timestamp: block.timestamp,
+            // blocks are only stored once executed from Celestia
+            finality: Finality::DaIncluded,
+        }
+    }
+}
+
+/// Where the batch of a block was posted to Celestia.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BlockDaResponse {
+    pub height: u64,
+    /// The Celestia height the batch was included at
+    pub da_height: u64,
+    /// The hex encoded commitment of the batch blob
+    pub commitment: String,
+    /// The hash of the PayForBlobs transaction, unknown for the mock DA layer
+    pub tx_hash: Option<String>,
+}
+
+impl BlockDaResponse {
+    fn new(height: u64, inclusion: DaInclusion) -> Self {
+        BlockDaResponse {
+            height,
+            da_height: inclusion.da_height,
+            commitment: hex::encode(inclusion.commitment.0),
+            tx_hash: inclusion.tx_hash,
+        }
+    }
+}
+
+impl TryFrom<BlockResponse> for Block {
+    type Error = anyhow::Error;
+
+    fn try_from(response: BlockResponse) -> anyhow::Result<Self> {
+        let digest = |hash: &str| parse_digest(hash).map_err(|e| anyhow::anyhow!(e));
+        Ok(Block {
+            height: response.height,
+            prev_root: digest(&response.prev_root)?,
+            new_root: digest(&response.new_root)?,
+            tx_root: digest(&response.tx_root)?,
+            da_height: response.da_height,
+            timestamp: response.timestamp,
+        })
+    }
+}
+
+/// An account with its verifying key.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AccountEntry {
+    /// The base64 encoded verifying key of the account
+    #[schema(value_type = String)]
+    pub vk: VerifyingKey,
+    pub account: Account,
+}
+
+/// A page of the accounts known to the node, ordered by tree key.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AccountsPage {
+    pub accounts: Vec<AccountEntry>,
+    pub limit: usize,
+    /// The cursor to pass as `after` for the next page, none on the last
+    /// page
+    pub next: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RejectedDataResponse {
+    /// The number of blobs in the app namespace that failed to decode since
+    /// the node started
+    pub rejected_blobs: u64,
+    /// The number of transactions that failed to decode in batches that
+    /// were otherwise executed, since the node started
+    pub skipped_txs: u64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TxResponse {
+    #[serde(flatten)]
+    pub status: TxStatus,
+    /// When this node observed the transaction reach each stage
+    #[serde(default)]
+    pub timings: TxTimings,
+    /// How final the transaction's execution is, unset until it is executed
+    #[serde(default)]
+    pub finality: Option<Finality>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AccountResponse {
+    #[serde(flatten)]
+    pub account: Account,
+    /// How final the state the account was read from is
+    pub finality: Finality,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MempoolResponse {
+    /// The number of transactions waiting to be posted
+    pub queued_txs: usize,
+    /// The number of submissions waiting to be checked against the
+    /// mempool. Once too many are waiting, further submissions are turned
+    /// away with 429
+    pub pending_submissions: usize,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct HeightResponse {
+    /// The last Celestia height processed by the node
+    pub da_height: u64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SubmitTxResponse {
+    /// The hex encoded hash of the transaction, used to query its status
+    pub tx_hash: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ProofResponse {
+    /// The account, if it exists
+    pub account: Option<Account>,
+    /// The hex encoded raw value the proof commits to, absent for
+    /// non-existent accounts
+    pub value: Option<String>,
+    /// The hex encoded, bincode serialized `SparseMerkleProof`
+    pub proof: String,
+    /// The hex encoded state root the proof verifies against
+    pub root: String,
+    pub epoch: u64,
+}
+
+impl TryFrom<AccountProof> for ProofResponse {
+    type Error = ApiError;
+
+    fn try_from(account_proof: AccountProof) -> Result<Self, ApiError> {
+        let internal_error = |e: bincode::Error| ApiError::Internal(e.to_string());
+        let account = match &account_proof.value {
+            Some(value) => Some(bincode::deserialize(value).map_err(internal_error)?),
+            None => None,
+        };
+        let proof = bincode::serialize(&account_proof.proof).map_err(internal_error)?;
+        Ok(ProofResponse {
+            account,
+            value: account_proof.value.map(hex::encode),
+            proof: hex::encode(proof),
+            root: hex::encode(account_proof.root.0),
+            epoch: account_proof.epoch,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ProofBundleResponse {
+    /// The account proof against the root of the latest proven epoch
+    pub account_proof: ProofResponse,
+    /// The Celestia height the epoch's proof was posted at
+    pub da_height: u64,
+    /// The hex encoded commitment of the proof blob
+    pub commitment: String,
+}
+
+impl TryFrom<AccountProofBundle> for ProofBundleResponse {
+    type Error = ApiError;
+
+    fn try_from(bundle: AccountProofBundle) -> Result<Self, ApiError> {
+        Ok(ProofBundleResponse {
+            account_proof: bundle.account_proof.try_into()?,
+            da_height: bundle.proof_pointer.da_height,
+            commitment: hex::encode(bundle.proof_pointer.commitment.0),
+        })
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AccountQuery {
+    /// Read the account from the sequencer's soft state, including the
+    /// effects of transactions not yet read back from Celestia. Such
+    /// responses carry the `x-soft-confirmed: true` header.
+    #[serde(default)]
+    pub soft: bool,
+    /// Reads the account from the freshest state of at least this
+    /// finality, e.g. `proof_finalized` reads it as of the latest proven
+    /// epoch
+    pub min_finality: Option<Finality>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FinalityQuery {
+    /// Withholds the result, answering 404, until it reaches this finality
+    pub min_finality: Option<Finality>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct VerifyRootQuery {
+    /// The block height to compare the roots of
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct VerifyRootResponse {
+    pub height: u64,
+    /// The hex encoded state root this node computed by executing the block
+    pub executed_root: String,
+    /// The hex encoded state root the sequencer claimed in the batch header,
+    /// if it claimed one
+    pub claimed_root: Option<String>,
+    /// Whether the roots match, unknown if the sequencer claimed no root
+    pub matches: Option<bool>,
+}
+
+/// A fraud proof and the root claim it challenges.
+#[derive(Serialize, Deserialize)]
+pub struct VerifyFraudProofRequest {
+    pub claim: EpochProof,
+    pub fraud_proof: FraudProof,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct VerifyFraudProofResponse {
+    /// Whether the fraud proof shows the claim is wrong
+    pub valid: bool,
+    /// Why the fraud proof is invalid
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    /// The first Celestia height to return entries of, defaults to the
+    /// oldest
+    pub from: Option<u64>,
+    /// The last Celestia height to return entries of, defaults to the latest
+    pub to: Option<u64>,
+    /// The maximum number of entries to return, at most 1000 (default: 100)
+    pub limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    fn range(&self) -> (u64, u64, usize) {
+        (
+            self.from.unwrap_or(0),
+            self.to.unwrap_or(u64::MAX),
+            self.limit
+                .unwrap_or(DEFAULT_HISTORY_LIMIT)
+                .min(MAX_HISTORY_LIMIT),
+        )
+    }
+}
+
+/// Selects a page of the accounts, see [`AccountsPage`].
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AccountsQuery {
+    /// The `next` cursor of the previous page, starts at the first account
+    /// if unset
+    pub after: Option<String>,
+    /// The number of accounts per page, at most 1000 (default: 100)
+    pub limit: Option<usize>,
+}
+
+/// Selects a page of a paginated endpoint.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// The `next` cursor of the previous page, starts at the oldest entry if
+    /// unset
+    pub after: Option<String>,
+    /// The number of entries per page, at most 1000 (default: 100)
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SnapshotQuery {
+    /// The epoch to export, defaults to the latest epoch
+    pub epoch: Option<u64>,
+}
+
+/// A log filter in the `RUST_LOG` syntax, e.g. `node=debug,sync=trace`.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct LogLevel {
+    pub filter: String,
+}
+
+/// Queues a transaction, or forwards it to the sequencer. Submissions are
+/// idempotent: resubmitting a known transaction returns its hash with 208
+/// instead of queueing it again, so timed out requests can be retried.
+#[utoipa::path(
+    post,
+    path = "/submit_tx",
+    request_body(content = Object, description = "A JSON encoded `Transaction`"),
+    responses(
+        (status = 200, description = "The transaction was queued", body = SubmitTxResponse),
+        (status = 208, description = "The transaction was already known", body = SubmitTxResponse),
+        (status = 400, description = "The transaction was rejected", body = ErrorResponse),
+        (status = 429, description = "The client is rate limited or the mempool is busy", body = ErrorResponse),
+        (status = 503, description = "The mempool is full", body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
 pub(crate) async fn submit_tx(
     AxumState(node): AxumState<Arc<Node>>,
     Json(tx): Json<Transaction>,
-) -> Result<(), (StatusCode, String)> {
-    node.queue_transaction(tx)
+) -> Result<(StatusCode, Json<SubmitTxResponse>), ApiError> {
+    let submitted = node.queue_transaction(tx).await?;
+    let status = if submitted.already_known {
+        StatusCode::ALREADY_REPORTED
+    } else {
+        StatusCode::OK
+    };
+    Ok((
+        status,
+        Json(SubmitTxResponse {
+            tx_hash: hex::encode(submitted.tx_hash.0),
+        }),
+    ))
+}
+
+/// Estimates the fees of a transaction before it is submitted: the gas it
+/// uses, the minimum fee it must pay and its expected share of the cost of
+/// posting its batch to Celestia. The transaction doesn't need to be signed.
+#[utoipa::path(
+    post,
+    path = "/estimate_fee",
+    request_body(content = Object, description = "A JSON encoded `Transaction`"),
+    responses((status = 200, body = FeeEstimate))
+)]
+pub(crate) async fn estimate_fee(
+    AxumState(node): AxumState<Arc<Node>>,
+    Json(tx): Json<Transaction>,
+) -> Json<FeeEstimate> {
+    Json(node.estimate_fee(&tx).await)
+}
+
+/// Transactions to simulate: a single one or an ordered list.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum SimulateRequest {
+    One(Box<Transaction>),
+    Many(Vec<Transaction>),
+}
+
+/// Dry runs transactions on a fork of the current state, returning their
+/// would-be receipts and the accounts they change. Nothing is queued. The
+/// transactions must be signed.
+#[utoipa::path(
+    post,
+    path = "/simulate",
+    request_body(
+        content = Object,
+        description = "A JSON encoded `Transaction` or a list of them, executed in order"
+    ),
+    responses(
+        (status = 200, body = Simulation),
+        (status = 400, description = "Too many transactions", body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn simulate(
+    AxumState(node): AxumState<Arc<Node>>,
+    Json(request): Json<SimulateRequest>,
+) -> Result<Json<Simulation>, ApiError> {
+    let txs = match request {
+        SimulateRequest::One(tx) => vec![*tx],
+        SimulateRequest::Many(txs) => txs,
+    };
+    if txs.len() > MAX_SIMULATED_TXS {
+        return Err(ApiError::BadRequest(format!(
+            "At most {} transactions can be simulated at once",
+            MAX_SIMULATED_TXS
+        )));
+    }
+    Ok(Json(node.simulate(txs).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/tx/{hash}",
+    params(
+        ("hash" = String, Path, description = "The hex encoded transaction hash"),
+        FinalityQuery
+    ),
+    responses(
+        (status = 200, body = TxResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn get_tx(
+    AxumState(node): AxumState<Arc<Node>>,
+    Path(hash): Path<String>,
+    Query(query): Query<FinalityQuery>,
+) -> Result<Json<TxResponse>, ApiError> {
+    let tx_hash = parse_digest(&hash).map_err(ApiError::BadRequest)?;
+    let Some(status) = node.get_tx_status(&tx_hash)? else {
+        return Err(ApiError::NotFound("Transaction not found".to_string()));
+    };
+    let finality = node.tx_finality(&status)?;
+    if let Some(min_finality) = query
+        .min_finality
+        .filter(|min| finality.map_or(true, |finality| finality < *min))
+    {
+        return Err(ApiError::NotFound(format!(
+            "Transaction is not {} yet",
+            min_finality
+        )));
+    }
+    Ok(Json(TxResponse {
+        status,
+        timings: node.get_tx_timings(&tx_hash)?.unwrap_or_default(),
+        finality,
+    }))
+}
+
+/// Returns the receipt of an executed transaction.
+#[utoipa::path(
+    get,
+    path = "/receipt/{tx_hash}",
+    params(("tx_hash" = String, Path, description = "The hex encoded transaction hash")),
+    responses(
+        (status = 200, body = Receipt),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn get_receipt(
+    AxumState(node): AxumState<Arc<Node>>,
+    Path(hash): Path<String>,
+) -> Result<Json<Receipt>, ApiError> {
+    let tx_hash = parse_digest(&hash).map_err(ApiError::BadRequest)?;
+    match node.get_receipt(&tx_hash).await? {
+        Some(receipt) => Ok(Json(receipt)),
+        None => Err(ApiError::NotFound("Receipt not found".to_string())),
+    }
+}
+
+fn parse_digest(hash: &str) -> Result<Digest, String> {
+    let bytes = hex::decode(hash).map_err(|e| format!("Invalid hash: {}", e))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "Hash must be 32 bytes".to_string())?;
+    Ok(Digest::new(bytes))
+}
+
+fn parse_vk(vk: String) -> Result<VerifyingKey, ApiError> {
+    VerifyingKey::try_from(vk).map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/account/{vk}",
+    params(
+        ("vk" = String, Path, description = "The base64 encoded verifying key"),
+        AccountQuery
+    ),
+    responses(
+        (status = 200, body = AccountResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn get_account(
+    AxumState(node): AxumState<Arc<Node>>,
+    Path(vk): Path<String>,
+    Query(query): Query<AccountQuery>,
+) -> Result<Response, ApiError> {
+    let vk = parse_vk(vk)?;
+    let requested = if query.soft {
+        Finality::SoftConfirmed
+    } else {
+        Finality::DaIncluded
+    };
+    let min_finality = query
+        .min_finality
+        .map_or(requested, |min| min.max(requested));
+    let (account, finality) = match node.get_account_with_finality(&vk, min_finality).await? {
+        Some(read) => read,
+        None if min_finality == Finality::SoftConfirmed => {
+            return Err(ApiError::BadRequest(
+                "Soft confirmations are disabled".to_string(),
+            ))
+        }
+        None => return Err(ApiError::NotFound("No epoch proven yet".to_string())),
+    };
+    let Some(account) = account else {
+        return Err(ApiError::NotFound("Account not found".to_string()));
+    };
+    let response = Json(AccountResponse { account, finality });
+    if finality == Finality::SoftConfirmed {
+        return Ok(([("x-soft-confirmed", "true")], response).into_response());
+    }
+    Ok(response.into_response())
+}
+
+/// Returns the transactions that touched the account, oldest first. Only
+/// served by archive nodes.
+#[utoipa::path(
+    get,
+    path = "/account/{vk}/history",
+    params(
+        ("vk" = String, Path, description = "The base64 encoded verifying key"),
+        HistoryQuery
+    ),
+    responses((status = 200, body = Vec<HistoryEntry>), (status = 400, body = ErrorResponse))
+)]
+pub(crate) async fn get_account_history(
+    AxumState(node): AxumState<Arc<Node>>,
+    Path(vk): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<HistoryEntry>>, ApiError> {
+    let vk = parse_vk(vk)?;
+    let (from, to, limit) = query.range();
+    Ok(Json(node.get_account_history(&vk, from, to, limit)?))
+}
+
+/// Returns a page of the transactions that touched the account, oldest
+/// first. Only covers transactions executed by this node, so nodes started
+/// from a snapshot lack earlier ones.
+#[utoipa::path(
+    get,
+    path = "/account/{vk}/txs",
+    params(
+        ("vk" = String, Path, description = "The base64 encoded verifying key"),
+        PageQuery
+    ),
+    responses((status = 200, body = AccountTxsPage), (status = 400, body = ErrorResponse))
+)]
+pub(crate) async fn get_account_txs(
+    AxumState(node): AxumState<Arc<Node>>,
+    Path(vk): Path<String>,
+    Query(query): Query<PageQuery>,
+) -> Result<Json<AccountTxsPage>, ApiError> {
+    let vk = parse_vk(vk)?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .clamp(1, MAX_HISTORY_LIMIT);
+    Ok(Json(node.get_account_txs(
+        &vk,
+        query.after.as_deref(),
+        limit,
+    )?))
+}
+
+/// Returns a page of the existing accounts, for explorers and exports.
+/// Accounts are stored under hashes of their keys, so only accounts that
+/// transactions executed by this node touched are listed.
+#[utoipa::path(
+    get,
+    path = "/accounts",
+    params(AccountsQuery),
+    responses((status = 200, body = AccountsPage))
+)]
+pub(crate) async fn get_accounts(
+    AxumState(node): AxumState<Arc<Node>>,
+    Query(query): Query<AccountsQuery>,
+) -> Result<Json<AccountsPage>, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .clamp(1, MAX_HISTORY_LIMIT);
+    let (accounts, next) = node.get_accounts(query.after.as_deref(), limit)?;
+    Ok(Json(AccountsPage {
+        accounts: accounts
+            .into_iter()
+            .map(|(vk, account)| AccountEntry { vk, account })
+            .collect(),
+        limit,
+        next,
+    }))
+}
+
+/// Returns the transactions executed in a range of Celestia heights with
+/// their events, oldest first. Only served by archive nodes.
+#[utoipa::path(
+    get,
+    path = "/events",
+    params(HistoryQuery),
+    responses((status = 200, body = Vec<HistoryEntry>))
+)]
+pub(crate) async fn get_events(
+    AxumState(node): AxumState<Arc<Node>>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<HistoryEntry>>, ApiError> {
+    let (from, to, limit) = query.range();
+    Ok(Json(node.get_events(from, to, limit)?))
+}
+
+/// Returns a proof of the account's inclusion, or exclusion if it doesn't
+/// exist, so clients can verify it against the root without trusting the
+/// node.
+#[utoipa::path(
+    get,
+    path = "/proof/{vk}",
+    params(("vk" = String, Path, description = "The base64 encoded verifying key")),
+    responses((status = 200, body = ProofResponse), (status = 400, body = ErrorResponse))
+)]
+pub(crate) async fn get_proof(
+    AxumState(node): AxumState<Arc<Node>>,
+    Path(vk): Path<String>,
+) -> Result<Json<ProofResponse>, ApiError> {
+    let vk = parse_vk(vk)?;
+    Ok(Json(node.get_account_proof(&vk).await?.try_into()?))
+}
+
+/// Returns a proof of the account against the root of the latest proven
+/// epoch, along with where that epoch's validity proof was posted, so light
+/// clients can verify both in one round trip.
+#[utoipa::path(
+    get,
+    path = "/account/{vk}/proof_bundle",
+    params(("vk" = String, Path, description = "The base64 encoded verifying key")),
+    responses(
+        (status = 200, body = ProofBundleResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn get_proof_bundle(
+    AxumState(node): AxumState<Arc<Node>>,
+    Path(vk): Path<String>,
+) -> Result<Json<ProofBundleResponse>, ApiError> {
+    let vk = parse_vk(vk)?;
+    match node.get_account_proof_bundle(&vk).await? {
+        Some(bundle) => Ok(Json(bundle.try_into()?)),
+        None => Err(ApiError::NotFound("No epoch proven yet".to_string())),
+    }
+}
+
+#[utoipa::path(get, path = "/root", responses((status = 200, body = RootResponse)))]
+pub(crate) async fn get_root(
+    AxumState(node): AxumState<Arc<Node>>,
+) -> Result<Json<RootResponse>, ApiError> {
+    let (root, epoch) = node.get_root().await?;
+    Ok(Json(RootResponse {
+        root: hex::encode(root.0),
+        epoch,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/block/{height}",
+    params(("height" = u64, Path), FinalityQuery),
+    responses((status = 200, body = BlockResponse), (status = 404, body = ErrorResponse))
+)]
+pub(crate) async fn get_block(
+    AxumState(node): AxumState<Arc<Node>>,
+    Path(height): Path<u64>,
+    Query(query): Query<FinalityQuery>,
+) -> Result<Json<BlockResponse>, ApiError> {
+    let Some(block) = node.get_block(height)? else {
+        return Err(ApiError::NotFound("Block not found".to_string()));
+    };
+    let finality = node.da_height_finality(block.da_height)?;
+    if let Some(min_finality) = query.min_finality.filter(|min| finality < *min) {
+        return Err(ApiError::NotFound(format!(
+            "Block is not {} yet",
+            min_finality
+        )));
+    }
+    let mut response = BlockResponse::from(block);
+    response.finality = finality;
+    Ok(Json(response))
+}
+
+/// Returns the Celestia height, blob commitment and PayForBlobs transaction
+/// of the block's batch. Only the sequencer that posted the batch records
+/// them.
+#[utoipa::path(
+    get,
+    path = "/block/{height}/da",
+    params(("height" = u64, Path)),
+    responses((status = 200, body = BlockDaResponse), (status = 404, body = ErrorResponse))
+)]
+pub(crate) async fn get_block_da(
+    AxumState(node): AxumState<Arc<Node>>,
+    Path(height): Path<u64>,
+) -> Result<Json<BlockDaResponse>, ApiError> {
+    match node.get_da_inclusion(height)? {
+        Some(inclusion) => Ok(Json(BlockDaResponse::new(height, inclusion))),
+        None => Err(ApiError::NotFound(
+            "No DA inclusion recorded for block".to_string(),
+        )),
+    }
+}
+
+/// Compares the state root this node computed for a block with the one the
+/// sequencer claimed when posting it, to diagnose state divergence.
+#[utoipa::path(
+    get,
+    path = "/verify_root",
+    params(VerifyRootQuery),
+    responses((status = 200, body = VerifyRootResponse), (status = 404, body = ErrorResponse))
+)]
+pub(crate) async fn verify_root(
+    AxumState(node): AxumState<Arc<Node>>,
+    Query(query): Query<VerifyRootQuery>,
+) -> Result<Json<VerifyRootResponse>, ApiError> {
+    let Some(block) = node.get_block(query.height)? else {
+        return Err(ApiError::NotFound("Block not found".to_string()));
+    };
+    let claimed_root = node.get_claimed_root(query.height)?;
+    Ok(Json(VerifyRootResponse {
+        height: block.height,
+        executed_root: hex::encode(block.new_root.0),
+        claimed_root: claimed_root.map(|root| hex::encode(root.0)),
+        matches: claimed_root.map(|root| root == block.new_root),
+    }))
+}
+
+/// Checks a fraud proof against the optimistic root claim it challenges.
+/// Verification only depends on the request, so verifiers can run it on any
+/// node or locally with [`FraudProof::verify`].
+#[utoipa::path(
+    post,
+    path = "/verify_fraud_proof",
+    request_body(content = Object, description = "A JSON encoded `VerifyFraudProofRequest`"),
+    responses((status = 200, body = VerifyFraudProofResponse))
+)]
+pub(crate) async fn verify_fraud_proof(
+    Json(request): Json<VerifyFraudProofRequest>,
+) -> Json<VerifyFraudProofResponse> {
+    let result = request.fraud_proof.verify(&request.claim);
+    Json(VerifyFraudProofResponse {
+        valid: result.is_ok(),
+        reason: result.err().map(|e| format!("{:#}", e)),
+    })
+}
+
+/// Returns the Celestia NMT proofs of the block's batch blob with the batch
+/// hash, so clients can verify the batch was published without running a
+/// Celestia node.
+#[utoipa::path(
+    get,
+    path = "/block/{height}/inclusion_proof",
+    params(("height" = u64, Path)),
+    responses(
+        (status = 200, body = Object, description = "A JSON encoded `BatchInclusionProof`"),
+        (status = 404, body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn get_inclusion_proof(
+    AxumState(node): AxumState<Arc<Node>>,
+    Path(height): Path<u64>,
+) -> Result<Json<BatchInclusionProof>, ApiError> {
+    match node.get_inclusion_proof(height).await? {
+        Some(proof) => Ok(Json(proof)),
+        None => Err(ApiError::NotFound("Block not found".to_string())),
+    }
+}
+
+#[utoipa::path(get, path = "/height", responses((status = 200, body = HeightResponse)))]
+pub(crate) async fn get_height(AxumState(node): AxumState<Arc<Node>>) -> Json<HeightResponse> {
+    Json(HeightResponse {
+        da_height: node.da_height(),
+    })
+}
+
+/// Returns how much DA data the node couldn't decode, e.g. to alert on a
+/// sequencer posting transactions of a type the node doesn't know yet.
+#[utoipa::path(
+    get,
+    path = "/rejected_data",
+    responses((status = 200, body = RejectedDataResponse))
+)]
+pub(crate) async fn get_rejected_data(
+    AxumState(node): AxumState<Arc<Node>>,
+) -> Json<RejectedDataResponse> {
+    let (rejected_blobs, skipped_txs) = node.rejected_da_data();
+    Json(RejectedDataResponse {
+        rejected_blobs,
+        skipped_txs,
+    })
+}
+
+/// Returns how long transactions took between the stages of their
+/// lifecycle since the node started, to find where time goes between
+/// submission and execution.
+#[utoipa::path(
+    get,
+    path = "/stats/latency",
+    responses((status = 200, body = LatencyReport))
+)]
+pub(crate) async fn get_latency_stats(
+    AxumState(node): AxumState<Arc<Node>>,
+) -> Json<LatencyReport> {
+    Json(node.latency_stats())
+}
+
+/// Returns how often tree reads were served from the node cache since the
+/// node started, to size [`crate::node::Config::node_cache_size`].
+#[utoipa::path(get, path = "/stats/cache", responses((status = 200, body = CacheStats)))]
+pub(crate) async fn get_cache_stats(AxumState(node): AxumState<Arc<Node>>) -> Json<CacheStats> {
+    Json(node.cache_stats())
+}
+
+/// Returns the depth of the sequencer's mempool and of the queue of
+/// submissions waiting for it, e.g. to alert on a sequencer falling behind.
+#[utoipa::path(get, path = "/mempool", responses((status = 200, body = MempoolResponse)))]
+pub(crate) async fn get_mempool(AxumState(node): AxumState<Arc<Node>>) -> Json<MempoolResponse> {
+    let (queued_txs, pending_submissions) = node.mempool_depth().await;
+    Json(MempoolResponse {
+        queued_txs,
+        pending_submissions,
+    })
+}
+
+/// Returns the chain parameters governance has set and the changes still to
+/// come. Parameters it never set are the ones the node is configured with.
+#[utoipa::path(
+    get,
+    path = "/params",
+    responses(
+        (status = 200, body = ParamSchedule),
+        (status = 500, body = ErrorResponse)
+    )
+)]
+pub(crate) async fn get_params(
+    AxumState(node): AxumState<Arc<Node>>,
+) -> Result<Json<ParamSchedule>, ApiError> {
+    Ok(Json(node.get_params()?))
+}
+
+/// Returns the bincode encoded [`Snapshot`](crate::snapshot::Snapshot) of
+/// the requested epoch.
+#[utoipa::path(
+    get,
+    path = "/snapshot",
+    params(SnapshotQuery),
+    responses(
+        (status = 200, content_type = "application/octet-stream", body = Vec<u8>),
+        (status = 400, body = ErrorResponse),
+        (status = 401, description = "An admin token is configured but was not provided", body = ErrorResponse)
+    )
+)]
+pub(crate) async fn get_snapshot(
+    AxumState(node): AxumState<Arc<Node>>,
+    Query(query): Query<SnapshotQuery>,
+) -> Result<Response, ApiError> {
+    let snapshot = node
+        .export_snapshot(query.epoch)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let bytes = bincode::serialize(&snapshot).map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], bytes).into_response())
+}
+
+/// Replaces the log filter without restarting the node. Bare targets like
+/// `node` match the node's module of that name. Returns the filter now in
+/// effect.
+#[utoipa::path(
+    put,
+    path = "/admin/log_level",
+    request_body = LogLevel,
+    responses(
+        (status = 200, body = LogLevel),
+        (status = 400, body = ErrorResponse),
+        (status = 401, description = "An admin token is configured but was not provided", body = ErrorResponse)
+    )
+)]
+pub(crate) async fn set_log_level(
+    Json(request): Json<LogLevel>,
+) -> Result<Json<LogLevel>, ApiError> {
+    let filter = telemetry::set_log_filter(&request.filter)
+        .map_err(|e| ApiError::BadRequest(format!("{:#}", e)))?;
+    info!("log filter set to {}", filter);
+    Ok(Json(LogLevel { filter }))
+}
+
+pub(crate) async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+pub(crate) async fn ws_handler(
+    ws: WebSocketUpgrade,
+    AxumState(node): AxumState<Arc<Node>>,
+) -> Response {
+    ws.on_upgrade(move |socket| stream_events(socket, node))
+}
+
+/// Forwards node events to the websocket as JSON text messages until the
+/// client disconnects. Clients narrow the events down by sending
+/// [`SubscriptionRequest`]s, invalid ones are answered with an
+/// [`ErrorResponse`].
+async fn stream_events(mut socket: WebSocket, node: Arc<Node>) {
+    let mut events = node.subscribe_events();
+    let mut filter = EventFilter::default();
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("websocket subscriber lagged, skipped {} events", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    let Err(e) = serde_json::from_str::<SubscriptionRequest>(&text)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|request| filter.apply(request))
+                    else {
+                        continue;
+                    };
+                    let error = ApiError::BadRequest(format!("Invalid subscription: {}", e));
+                    let response = ErrorResponse {
+                        message: error.message(),
+                        error,
+                    };
+                    if let Ok(msg) = serde_json::to_string(&response) {
+                        if socket.send(Message::Text(msg)).await.is_err() {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(_)) | None => break,
+            },
+        };
+        if !filter.matches(&event) {
+            continue;
+        }
+
+        let msg = match serde_json::to_string(&event) {
+            Ok(msg) => msg,
+            Err(e) => {
+                error!("serializing event: {}", e);
+                continue;
+            }
+        };
+        if socket.send(Message::Text(msg)).await.is_err() {
+            break;
+        }
+    }
 }
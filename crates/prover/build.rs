@@ -0,0 +1,30 @@
+/// Guest features the prover is built with, so the guests hash the state the
+/// same way as the node.
+#[allow(dead_code)]
+fn guest_features() -> Vec<String> {
+    let mut features = Vec::new();
+    if cfg!(feature = "blake3") {
+        features.push("blake3".to_string());
+    }
+    features
+}
+
+fn main() {
+    #[cfg(feature = "sp1")]
+    sp1_build::build_program_with_args(
+        "../sp1",
+        sp1_build::BuildArgs {
+            features: guest_features(),
+            ..Default::default()
+        },
+    );
+
+    #[cfg(feature = "risc0")]
+    risc0_build::embed_methods_with_options(std::collections::HashMap::from([(
+        "shard-risc0",
+        risc0_build::GuestOptions {
+            features: guest_features(),
+            ..Default::default()
+        },
+    )]));
+}
@@ -0,0 +1,8 @@
+//! Prints the verification key of the SP1 guest program, as expected by
+//! `export-verifier` and the settlement contract.
+
+use shard_prover::Sp1Prover;
+
+fn main() {
+    println!("{}", Sp1Prover::new().program_vkey());
+}
@@ -0,0 +1,49 @@
+use anyhow::{anyhow, Context, Result};
+use shard_common::{
+    journal::PublicValues,
+    proofs::{Batch, EpochProof, ProverBackend},
+};
+
+use crate::public_roots;
+
+/// A [`ProverBackend`] for local development that skips the zkVM: the proof
+/// bytes are the [`Batch`] itself, which verifiers re-check the way the
+/// guest programs would. Proofs are as large as the batch and verifying
+/// them is as expensive as executing it, so it only suits devnets.
+pub struct DevProver;
+
+impl ProverBackend for DevProver {
+    fn prove(&self, epoch: u64, batch: &Batch) -> Result<EpochProof> {
+        batch.verify()?;
+        Ok(EpochProof {
+            epoch,
+            prev_root: batch.prev_root,
+            new_root: batch.new_root,
+            proof: bincode::serialize(batch)?,
+            public_values: PublicValues::from_batch(batch).to_journal(),
+        })
+    }
+
+    fn prove_recursive(
+        &self,
+        _epoch: u64,
+        _batch: &Batch,
+        _prev: Option<&EpochProof>,
+    ) -> Result<EpochProof> {
+        Err(anyhow!("Recursive proofs require a zkVM backend"))
+    }
+
+    fn verify(&self, proof: &EpochProof) -> Result<bool> {
+        let batch: Batch =
+            bincode::deserialize(&proof.proof).context("Proof is not a dev mode batch")?;
+        let (prev_root, new_root) = public_roots(&proof.public_values)?;
+        if batch.prev_root != proof.prev_root
+            || batch.new_root != proof.new_root
+            || prev_root != proof.prev_root
+            || new_root != proof.new_root
+        {
+            return Ok(false);
+        }
+        Ok(batch.verify().is_ok())
+    }
+}
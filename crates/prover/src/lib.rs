@@ -0,0 +1,58 @@
+//! [`ProverBackend`] implementations for the supported zkVMs. Each backend is
+//! behind a feature flag of the same name, `sp1` is enabled by default.
+//! [`DevProver`] needs no zkVM and is always available.
+
+use anyhow::{anyhow, Result};
+use shard_common::{
+    journal::{Journal, PublicValues},
+    proofs::EpochProof,
+    tree::Digest,
+};
+
+pub use shard_common::{fraud::OptimisticProver, proofs::ProverBackend};
+
+mod dev;
+#[cfg(feature = "risc0")]
+mod risc0;
+#[cfg(feature = "sp1")]
+mod sp1;
+
+pub use dev::DevProver;
+#[cfg(feature = "risc0")]
+pub use risc0::Risc0Prover;
+#[cfg(feature = "sp1")]
+pub use sp1::Sp1Prover;
+
+/// Decodes the (prev_root, new_root) pair from the [`PublicValues`]
+/// journal committed by the guest programs, of any supported version.
+fn public_roots(public_values: &[u8]) -> Result<(Digest, Digest)> {
+    let values = PublicValues::from_journal(public_values)
+        .map_err(|e| anyhow!("Invalid public values: {}", e))?;
+    Ok((values.prev_root, values.new_root))
+}
+
+/// Checks that the verified `public_values` attest to the roots claimed by
+/// `proof`. Recursive proofs must additionally be for `program_id`, since
+/// the guest only checks that every proof in the chain is for the same
+/// program. STF proofs attest to a batch hash and a mint authority as well,
+/// which are left to callers to check against the epoch's transactions and
+/// the chain's mint authority, see [`shard_common::stf::check_mint_authority`].
+fn check_public_values(
+    proof: &EpochProof,
+    public_values: &[u8],
+    program_id: [u32; 8],
+) -> Result<bool> {
+    Ok(match Journal::from_bytes(public_values)? {
+        Journal::Epoch(values) => {
+            values.prev_root == proof.prev_root && values.new_root == proof.new_root
+        }
+        Journal::Recursive(values) => {
+            values.program_id == program_id
+                && values.genesis_root == proof.prev_root
+                && values.root == proof.new_root
+        }
+        Journal::Stf(values) => {
+            values.prev_root == proof.prev_root && values.new_root == proof.new_root
+        }
+    })
+}
@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use risc0_zkvm::{default_prover, ExecutorEnv, ProverOpts, Receipt};
+use shard_common::{
+    proofs::{Batch, EpochProof, GuestMode, ProverBackend, Recursion, RecursivePublicValues},
+    stf::{StfPublicValues, StfWitness},
+};
+
+use crate::{check_public_values, public_roots};
+
+// Generated by risc0-build from `crates/risc0`, defines `SHARD_RISC0_ELF`
+// and `SHARD_RISC0_ID`.
+include!(concat!(env!("OUT_DIR"), "/methods.rs"));
+
+/// Generates and verifies validity proofs for [`Batch`]es using RISC Zero.
+#[derive(Default)]
+pub struct Risc0Prover;
+
+impl Risc0Prover {
+    pub fn new() -> Self {
+        Risc0Prover
+    }
+}
+
+impl ProverBackend for Risc0Prover {
+    fn prove(&self, epoch: u64, batch: &Batch) -> Result<EpochProof> {
+        let env = ExecutorEnv::builder()
+            .write(&GuestMode::Batch)
+            .and_then(|builder| builder.write(&None::<Recursion>))
+            .and_then(|builder| builder.write(batch))
+            .map_err(|e| anyhow!("Failed to write batch to executor: {}", e))?
+            .build()
+            .map_err(|e| anyhow!("Failed to build executor env: {}", e))?;
+
+        let receipt = default_prover()
+            .prove(env, SHARD_RISC0_ELF)
+            .map_err(|e| anyhow!("Failed to generate proof: {}", e))?
+            .receipt;
+
+        let (prev_root, new_root) = public_roots(&receipt.journal.bytes)?;
+        if prev_root != batch.prev_root || new_root != batch.new_root {
+            return Err(anyhow!("Public values do not match batch roots"));
+        }
+
+        Ok(EpochProof {
+            epoch,
+            prev_root,
+            new_root,
+            proof: bincode::serialize(&receipt)?,
+            public_values: receipt.journal.bytes.clone(),
+        })
+    }
+
+    fn prove_recursive(
+        &self,
+        epoch: u64,
+        batch: &Batch,
+        prev: Option<&EpochProof>,
+    ) -> Result<EpochProof> {
+        let mut builder = ExecutorEnv::builder();
+        let prev_values = match prev {
+            Some(prev) => {
+                let prev_receipt: Receipt = bincode::deserialize(&prev.proof)?;
+                builder.add_assumption(prev_receipt);
+                Some(RecursivePublicValues::from_journal(&prev.public_values)?)
+            }
+            None => None,
+        };
+        let recursion = Some(Recursion {
+            program_id: SHARD_RISC0_ID,
+            prev: prev_values,
+        });
+        let env = builder
+            .write(&GuestMode::Batch)
+            .and_then(|builder| builder.write(&recursion))
+            .and_then(|builder| builder.write(batch))
+            .map_err(|e| anyhow!("Failed to write batch to executor: {}", e))?
+            .build()
+            .map_err(|e| anyhow!("Failed to build executor env: {}", e))?;
+
+        // succinct receipts resolve the assumption, so the proof stays the
+        // same size however long the chain gets
+        let receipt = default_prover()
+            .prove_with_opts(env, SHARD_RISC0_ELF, &ProverOpts::succinct())
+            .map_err(|e| anyhow!("Failed to generate proof: {}", e))?
+            .receipt;
+
+        let values = RecursivePublicValues::from_journal(&receipt.journal.bytes)?;
+        if values.root != batch.new_root {
+            return Err(anyhow!("Public values do not match batch root"));
+        }
+
+        Ok(EpochProof {
+            epoch,
+            prev_root: values.genesis_root,
+            new_root: values.root,
+            proof: bincode::serialize(&receipt)?,
+            public_values: receipt.journal.bytes.clone(),
+        })
+    }
+
+    fn prove_stf(&self, epoch: u64, witness: &StfWitness) -> Result<EpochProof> {
+        let env = ExecutorEnv::builder()
+            .write(&GuestMode::Stf)
+            .and_then(|builder| builder.write(witness))
+            .map_err(|e| anyhow!("Failed to write witness to executor: {}", e))?
+            .build()
+            .map_err(|e| anyhow!("Failed to build executor env: {}", e))?;
+
+        let receipt = default_prover()
+            .prove(env, SHARD_RISC0_ELF)
+            .map_err(|e| anyhow!("Failed to generate proof: {}", e))?
+            .receipt;
+
+        let values = StfPublicValues::from_journal(&receipt.journal.bytes)?;
+        if values.prev_root != witness.prev_root || values.new_root != witness.new_root {
+            return Err(anyhow!("Public values do not match witness roots"));
+        }
+
+        Ok(EpochProof {
+            epoch,
+            prev_root: values.prev_root,
+            new_root: values.new_root,
+            proof: bincode::serialize(&receipt)?,
+            public_values: receipt.journal.bytes.clone(),
+        })
+    }
+
+    fn verify(&self, proof: &EpochProof) -> Result<bool> {
+        let receipt: Receipt = bincode::deserialize(&proof.proof)?;
+        if receipt.verify(SHARD_RISC0_ID).is_err() {
+            return Ok(false);
+        }
+
+        check_public_values(proof, &receipt.journal.bytes, SHARD_RISC0_ID)
+    }
+}
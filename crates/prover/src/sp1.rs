@@ -0,0 +1,183 @@
+use anyhow::{anyhow, Result};
+use shard_common::{
+    proofs::{Batch, EpochProof, GuestMode, ProverBackend, Recursion, RecursivePublicValues},
+    stf::{StfPublicValues, StfWitness},
+};
+use sp1_sdk::{
+    include_elf, HashableKey, ProverClient, SP1Proof, SP1ProofWithPublicValues, SP1ProvingKey,
+    SP1Stdin, SP1VerifyingKey,
+};
+
+use crate::{check_public_values, public_roots};
+
+/// The ELF of the guest program in `crates/sp1`.
+pub const SHARD_SP1_ELF: &[u8] = include_elf!("shard-sp1");
+
+/// Generates and verifies validity proofs for [`Batch`]es using SP1.
+pub struct Sp1Prover {
+    client: ProverClient,
+    pk: SP1ProvingKey,
+    vk: SP1VerifyingKey,
+}
+
+impl Default for Sp1Prover {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sp1Prover {
+    /// Creates a new prover. Whether proofs are generated locally, by the
+    /// prover network or mocked is determined by the `SP1_PROVER` env var.
+    pub fn new() -> Self {
+        let client = ProverClient::new();
+        let (pk, vk) = client.setup(SHARD_SP1_ELF);
+        Sp1Prover { client, pk, vk }
+    }
+
+    pub fn verifying_key(&self) -> &SP1VerifyingKey {
+        &self.vk
+    }
+
+    /// Returns the 0x prefixed verification key the settlement contract
+    /// checks proofs against.
+    pub fn program_vkey(&self) -> String {
+        self.vk.bytes32()
+    }
+
+    /// Generates a Groth16 wrapped proof for settlement on Ethereum. Unlike
+    /// [`ProverBackend::prove`], the proof bytes are the on-chain encoding
+    /// expected by the SP1 verifier gateway, so they can't be verified with
+    /// [`ProverBackend::verify`].
+    pub fn prove_groth16(&self, epoch: u64, batch: &Batch) -> Result<EpochProof> {
+        let mut stdin = SP1Stdin::new();
+        stdin.write(&GuestMode::Batch);
+        stdin.write(&None::<Recursion>);
+        stdin.write(batch);
+
+        let proof = self
+            .client
+            .prove(&self.pk, stdin)
+            .groth16()
+            .run()
+            .map_err(|e| anyhow!("Failed to generate proof: {}", e))?;
+        let (prev_root, new_root) = public_roots(proof.public_values.as_slice())?;
+        if prev_root != batch.prev_root || new_root != batch.new_root {
+            return Err(anyhow!("Public values do not match batch roots"));
+        }
+
+        Ok(EpochProof {
+            epoch,
+            prev_root,
+            new_root,
+            proof: proof.bytes(),
+            public_values: proof.public_values.to_vec(),
+        })
+    }
+
+    /// Proves the guest with `stdin`, returning the proof and its public
+    /// values. Proofs are compressed, so they can be verified recursively.
+    fn run(&self, stdin: SP1Stdin) -> Result<SP1ProofWithPublicValues> {
+        self.client
+            .prove(&self.pk, stdin)
+            .compressed()
+            .run()
+            .map_err(|e| anyhow!("Failed to generate proof: {}", e))
+    }
+}
+
+impl ProverBackend for Sp1Prover {
+    fn prove(&self, epoch: u64, batch: &Batch) -> Result<EpochProof> {
+        let mut stdin = SP1Stdin::new();
+        stdin.write(&GuestMode::Batch);
+        stdin.write(&None::<Recursion>);
+        stdin.write(batch);
+
+        let proof = self.run(stdin)?;
+        let (prev_root, new_root) = public_roots(proof.public_values.as_slice())?;
+        if prev_root != batch.prev_root || new_root != batch.new_root {
+            return Err(anyhow!("Public values do not match batch roots"));
+        }
+
+        Ok(EpochProof {
+            epoch,
+            prev_root,
+            new_root,
+            proof: bincode::serialize(&proof)?,
+            public_values: proof.public_values.to_vec(),
+        })
+    }
+
+    fn prove_recursive(
+        &self,
+        epoch: u64,
+        batch: &Batch,
+        prev: Option<&EpochProof>,
+    ) -> Result<EpochProof> {
+        let mut stdin = SP1Stdin::new();
+        let prev_values = match prev {
+            Some(prev) => {
+                let prev_proof: SP1ProofWithPublicValues = bincode::deserialize(&prev.proof)?;
+                let SP1Proof::Compressed(reduce_proof) = prev_proof.proof else {
+                    return Err(anyhow!("Previous proof is not compressed"));
+                };
+                stdin.write_proof(*reduce_proof, self.vk.vk.clone());
+                Some(RecursivePublicValues::from_journal(&prev.public_values)?)
+            }
+            None => None,
+        };
+        stdin.write(&GuestMode::Batch);
+        stdin.write(&Some(Recursion {
+            program_id: self.vk.hash_u32(),
+            prev: prev_values,
+        }));
+        stdin.write(batch);
+
+        let proof = self.run(stdin)?;
+        let values = RecursivePublicValues::from_journal(proof.public_values.as_slice())?;
+        if values.root != batch.new_root {
+            return Err(anyhow!("Public values do not match batch root"));
+        }
+
+        Ok(EpochProof {
+            epoch,
+            prev_root: values.genesis_root,
+            new_root: values.root,
+            proof: bincode::serialize(&proof)?,
+            public_values: proof.public_values.to_vec(),
+        })
+    }
+
+    fn prove_stf(&self, epoch: u64, witness: &StfWitness) -> Result<EpochProof> {
+        let mut stdin = SP1Stdin::new();
+        stdin.write(&GuestMode::Stf);
+        stdin.write(witness);
+
+        let proof = self.run(stdin)?;
+        let values = StfPublicValues::from_journal(proof.public_values.as_slice())?;
+        if values.prev_root != witness.prev_root || values.new_root != witness.new_root {
+            return Err(anyhow!("Public values do not match witness roots"));
+        }
+
+        Ok(EpochProof {
+            epoch,
+            prev_root: values.prev_root,
+            new_root: values.new_root,
+            proof: bincode::serialize(&proof)?,
+            public_values: proof.public_values.to_vec(),
+        })
+    }
+
+    fn verify(&self, proof: &EpochProof) -> Result<bool> {
+        let sp1_proof: SP1ProofWithPublicValues = bincode::deserialize(&proof.proof)?;
+        if self.client.verify(&sp1_proof, &self.vk).is_err() {
+            return Ok(false);
+        }
+
+        check_public_values(
+            proof,
+            sp1_proof.public_values.as_slice(),
+            self.vk.hash_u32(),
+        )
+    }
+}
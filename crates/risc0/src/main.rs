@@ -0,0 +1,35 @@
+#![no_main]
+risc0_zkvm::guest::entry!(main);
+
+use risc0_zkvm::guest::env;
+use shard_common::{
+    journal::PublicValues,
+    proofs::{Batch, GuestMode, Recursion},
+    stf::StfWitness,
+};
+
+pub fn main() {
+    let mode: GuestMode = env::read();
+    if mode == GuestMode::Stf {
+        let witness: StfWitness = env::read();
+        let public_values = witness.verify().expect("invalid epoch");
+        env::commit_slice(&public_values.to_journal());
+        return;
+    }
+
+    let recursion: Option<Recursion> = env::read();
+    let batch: Batch = env::read();
+
+    let Some(recursion) = recursion else {
+        batch.verify().expect("invalid batch");
+        env::commit_slice(&PublicValues::from_batch(&batch).to_journal());
+        return;
+    };
+
+    if let Some(prev) = &recursion.prev {
+        // resolved against the receipt added as an assumption by the prover
+        env::verify(recursion.program_id, &prev.to_journal()).expect("invalid previous proof");
+    }
+    let public_values = recursion.next(&batch).expect("invalid batch");
+    env::commit_slice(&public_values.to_journal());
+}
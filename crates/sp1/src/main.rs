@@ -1,29 +1,36 @@
 #![no_main]
 sp1_zkvm::entrypoint!(main);
 
+use sha2::{Digest, Sha256};
 use shard_common::{
-    digest::Digest,
-    tree::{Batch, Proof},
+    journal::PublicValues,
+    proofs::{Batch, GuestMode, Recursion},
+    stf::StfWitness,
 };
 
 pub fn main() {
+    let mode = sp1_zkvm::io::read::<GuestMode>();
+    if mode == GuestMode::Stf {
+        let witness = sp1_zkvm::io::read::<StfWitness>();
+        let public_values = witness.verify().expect("invalid epoch");
+        sp1_zkvm::io::commit_slice(&public_values.to_journal());
+        return;
+    }
+
+    let recursion = sp1_zkvm::io::read::<Option<Recursion>>();
     let batch = sp1_zkvm::io::read::<Batch>();
-    let mut current = batch.prev_root;
-    sp1_zkvm::io::commit_slice(&current.0);
 
-    for proof in batch.proofs.iter() {
-        match proof {
-            Proof::Update(p) => {
-                assert_eq!(current, Digest::new(p.old_root.into()));
-                assert!(p.verify().is_ok());
-                current = Digest::new(p.new_root.into());
-            }
-            Proof::Insert(p) => {
-                assert_eq!(current, p.non_membership_proof.root);
-                assert!(p.verify().is_ok());
-                current = p.new_root;
-            }
-        }
+    let Some(recursion) = recursion else {
+        batch.verify().expect("invalid batch");
+        sp1_zkvm::io::commit_slice(&PublicValues::from_batch(&batch).to_journal());
+        return;
+    };
+
+    if let Some(prev) = &recursion.prev {
+        // the proof itself is passed to the prover via `SP1Stdin::write_proof`
+        let digest: [u8; 32] = Sha256::digest(prev.to_journal()).into();
+        sp1_zkvm::lib::verify::verify_sp1_proof(&recursion.program_id, &digest);
     }
-    sp1_zkvm::io::commit_slice(&current.0);
+    let public_values = recursion.next(&batch).expect("invalid batch");
+    sp1_zkvm::io::commit_slice(&public_values.to_journal());
 }
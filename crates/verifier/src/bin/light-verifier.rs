@@ -0,0 +1,90 @@
+//! Follows a shard's state root through the epoch proofs posted to its proof
+//! namespace, without executing transactions, and serves it along with
+//! account proof verification.
+
+use anyhow::{anyhow, Context, Result};
+use celestia_types::nmt::Namespace;
+use clap::Parser;
+use shard_common::{
+    da::{CelestiaDA, CelestiaTxOptions},
+    tree::Digest,
+};
+use shard_verifier::{light::LightVerifier, Backend};
+use std::sync::Arc;
+use tracing::info;
+
+#[derive(Parser, Debug)]
+#[command(about = "Track a shard's state root from its epoch proofs only")]
+struct Args {
+    /// The proof system the sequencer proves with
+    #[arg(long, value_enum, default_value_t)]
+    backend: Backend,
+
+    /// The hex encoded root the first epoch starts at, usually the genesis
+    /// root. If unset, the first valid proof is trusted to start at the
+    /// right root
+    #[arg(long)]
+    trusted_root: Option<String>,
+
+    /// The Celestia height to start verifying proofs from
+    #[arg(long, default_value_t = 1)]
+    start_height: u64,
+
+    /// The namespace epoch proofs are posted to (hex encoded)
+    #[arg(long, default_value = "2a2a2a2b")]
+    proof_namespace: String,
+
+    /// Comma separated URLs of Celestia nodes to fail over between
+    #[arg(long, default_value = "ws://0.0.0.0:26658", value_delimiter = ',')]
+    celestia_url: Vec<String>,
+
+    /// The auth token to use when connecting to Celestia
+    #[arg(long)]
+    auth_token: Option<String>,
+
+    /// The address to serve `/root` and `/verify_account_proof` on
+    #[arg(long, default_value = "0.0.0.0:3000")]
+    listen_addr: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+
+    let proof_namespace = Namespace::new_v0(
+        &hex::decode(&args.proof_namespace).context("Invalid proof namespace hex")?,
+    )
+    .context("Failed to create proof namespace")?;
+    let trusted_root = args
+        .trusted_root
+        .map(|root| -> Result<Digest> {
+            let bytes = hex::decode(root).context("Invalid trusted root hex")?;
+            Ok(Digest::new(
+                bytes
+                    .try_into()
+                    .map_err(|_| anyhow!("Trusted root must be 32 bytes"))?,
+            ))
+        })
+        .transpose()?;
+    let da = CelestiaDA::new(
+        &args.celestia_url,
+        args.auth_token.as_deref(),
+        &CelestiaTxOptions::default(),
+    )
+    .await?;
+
+    let verifier = Arc::new(LightVerifier::new(args.backend.prover(), trusted_root));
+    let listen_addr = args
+        .listen_addr
+        .parse()
+        .with_context(|| format!("Invalid listen address {}", args.listen_addr))?;
+    let server =
+        axum::Server::bind(&listen_addr).serve(verifier.clone().router().into_make_service());
+    info!("light verifier listening on {}", listen_addr);
+
+    tokio::select! {
+        result = server => result.context("Failed to start server"),
+        result = verifier.run(&da, proof_namespace, args.start_height) => result,
+    }
+}
@@ -0,0 +1,93 @@
+//! Downloads the epoch proofs posted to the proof namespace at a Celestia
+//! height and verifies them against the roots they claim. Exits with an
+//! error if any proof is invalid.
+
+use anyhow::{anyhow, Context, Result};
+use celestia_types::nmt::Namespace;
+use clap::Parser;
+use shard_common::da::{CelestiaDA, CelestiaTxOptions};
+use shard_verifier::{fetch_epoch_proofs, verify, Backend};
+
+#[derive(Parser, Debug)]
+#[command(about = "Verify the epoch proofs posted at a Celestia height")]
+struct Args {
+    /// The Celestia height the epoch proof was posted at
+    #[arg(long)]
+    height: u64,
+
+    /// Only verify the proof of this epoch, if several were posted at the
+    /// height
+    #[arg(long)]
+    epoch: Option<u64>,
+
+    /// The proof system the sequencer proves with. `dev` re-executes the
+    /// raw batch instead of verifying a zk proof
+    #[arg(long, value_enum, default_value_t)]
+    backend: Backend,
+
+    /// The namespace epoch proofs are posted to (hex encoded)
+    #[arg(long, default_value = "2a2a2a2b")]
+    proof_namespace: String,
+
+    /// Comma separated URLs of Celestia nodes to fail over between
+    #[arg(long, default_value = "ws://0.0.0.0:26658", value_delimiter = ',')]
+    celestia_url: Vec<String>,
+
+    /// The auth token to use when connecting to Celestia
+    #[arg(long)]
+    auth_token: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+
+    let proof_namespace = Namespace::new_v0(
+        &hex::decode(&args.proof_namespace).context("Invalid proof namespace hex")?,
+    )
+    .context("Failed to create proof namespace")?;
+    let da = CelestiaDA::new(
+        &args.celestia_url,
+        args.auth_token.as_deref(),
+        &CelestiaTxOptions::default(),
+    )
+    .await?;
+
+    let proofs: Vec<_> = fetch_epoch_proofs(&da, proof_namespace, args.height)
+        .await?
+        .into_iter()
+        .filter(|proof| args.epoch.map_or(true, |epoch| proof.epoch == epoch))
+        .collect();
+    if proofs.is_empty() {
+        return Err(anyhow!("No epoch proof found at height {}", args.height));
+    }
+
+    let prover = args.backend.prover();
+    let mut invalid = 0;
+    for proof in &proofs {
+        let verification = verify(prover.as_ref(), proof)?;
+        println!(
+            "epoch {}: {} -> {}: {}",
+            verification.epoch,
+            hex::encode(verification.prev_root.0),
+            hex::encode(verification.new_root.0),
+            if verification.valid {
+                "valid"
+            } else {
+                "INVALID"
+            }
+        );
+        if !verification.valid {
+            invalid += 1;
+        }
+    }
+    if invalid > 0 {
+        return Err(anyhow!(
+            "{} of {} proofs are invalid",
+            invalid,
+            proofs.len()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Verifies the epoch proofs a shard posts to its proof namespace, so
+//! services can check its state roots without running a node. The zkVM
+//! backends are behind feature flags of the same name as in `shard-prover`,
+//! `sp1` is enabled by default.
+//!
+//! [`light::LightVerifier`] follows the state root through the proofs alone
+//! and checks account proofs against it, see the `light-verifier` binary.
+//!
+//! ```ignore
+//! let proofs = shard_verifier::fetch_epoch_proofs(&da, proof_namespace, height).await?;
+//! let prover = shard_verifier::Backend::Sp1.prover();
+//! for proof in &proofs {
+//!     assert!(shard_verifier::verify(prover.as_ref(), proof)?.valid);
+//! }
+//! ```
+
+use anyhow::{anyhow, Result};
+use celestia_types::nmt::Namespace;
+use clap::ValueEnum;
+use shard_common::{
+    da::DataAvailability,
+    proofs::{EpochProof, ProverBackend},
+    tree::Digest,
+};
+use shard_prover::DevProver;
+use tracing::debug;
+
+pub mod light;
+
+/// The proof system epoch proofs are verified with. Must match the backend
+/// the sequencer proves with.
+#[derive(ValueEnum, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Proofs are the raw [`shard_common::proofs::Batch`], re-executed
+    /// instead of verified, see [`DevProver`].
+    #[cfg_attr(not(feature = "sp1"), default)]
+    Dev,
+    #[cfg(feature = "sp1")]
+    #[default]
+    Sp1,
+    #[cfg(feature = "risc0")]
+    Risc0,
+}
+
+impl Backend {
+    /// Returns the prover whose verification this backend uses. Setting up
+    /// a zkVM prover can take a while, so reuse it for multiple proofs.
+    pub fn prover(self) -> Box<dyn ProverBackend> {
+        match self {
+            Backend::Dev => Box::new(DevProver),
+            #[cfg(feature = "sp1")]
+            Backend::Sp1 => Box::new(shard_prover::Sp1Prover::new()),
+            #[cfg(feature = "risc0")]
+            Backend::Risc0 => Box::new(shard_prover::Risc0Prover::new()),
+        }
+    }
+}
+
+/// The outcome of verifying an epoch proof.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Verification {
+    pub epoch: u64,
+    /// The root the proof claims the epoch started at, the genesis root for
+    /// recursive proofs
+    pub prev_root: Digest,
+    /// The root the proof claims the epoch ended at
+    pub new_root: Digest,
+    /// Whether the proof is valid and attests to the claimed roots
+    pub valid: bool,
+}
+
+/// Verifies `proof` with `prover` against the roots it claims. Fails if the
+/// proof can't be checked at all, e.g. because it is an optimistic root
+/// claim without proof bytes.
+pub fn verify(prover: &dyn ProverBackend, proof: &EpochProof) -> Result<Verification> {
+    if proof.is_optimistic() {
+        return Err(anyhow!(
+            "Epoch {} is an optimistic root claim without a proof",
+            proof.epoch
+        ));
+    }
+    Ok(Verification {
+        epoch: proof.epoch,
+        prev_root: proof.prev_root,
+        new_root: proof.new_root,
+        valid: prover.verify(proof)?,
+    })
+}
+
+/// Returns the epoch proofs posted to `proof_namespace` at `height`. Blobs
+/// that aren't epoch proofs are skipped.
+pub async fn fetch_epoch_proofs(
+    da: &dyn DataAvailability,
+    proof_namespace: Namespace,
+    height: u64,
+) -> Result<Vec<EpochProof>> {
+    let proofs = da
+        .get_blobs(height, proof_namespace)
+        .await?
+        .iter()
+        .filter_map(|blob| match EpochProof::try_from(blob) {
+            Ok(proof) => Some(proof),
+            Err(e) => {
+                debug!("skipping proof namespace blob: {}", e);
+                None
+            }
+        })
+        .collect();
+    Ok(proofs)
+}
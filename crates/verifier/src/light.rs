@@ -0,0 +1,264 @@
+//! A light verifier that follows a shard's state root through its epoch
+//! proofs only, without executing transactions, and checks account proofs
+//! from untrusted full nodes against it.
+
+use anyhow::{anyhow, Context, Result};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use celestia_types::nmt::Namespace;
+use futures::StreamExt;
+use prism_common::keys::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use shard_common::{
+    da::DataAvailability,
+    proofs::{EpochProof, ProverBackend},
+    state::verify_account_proof,
+    tree::Digest,
+};
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+use crate::{fetch_epoch_proofs, verify};
+
+/// The latest state root attested to by a valid epoch proof.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VerifiedRoot {
+    pub root: Digest,
+    /// The epoch as labeled by the proof's poster. Unlike the root, it
+    /// isn't proven, so it's only informational.
+    pub epoch: u64,
+    /// The Celestia height the proof was posted at
+    pub da_height: u64,
+    /// The last Celestia height the verified epochs' batches were read
+    /// from, if their proofs commit to it
+    pub last_batch_height: Option<u64>,
+}
+
+/// Tracks the state root from the epoch proofs posted to the proof
+/// namespace. A proof only advances the root if it is valid and continues
+/// the verified chain: plain and STF proofs must start at the latest
+/// verified root, recursive proofs at the anchor root. Since the epoch
+/// number isn't proven, proofs are ordered by the Celestia heights their
+/// journals commit to instead, so a recursive proof of an older state can't
+/// roll the root back.
+pub struct LightVerifier {
+    prover: Arc<dyn ProverBackend>,
+    /// The root the chain of proofs starts at. Without a trusted root, the
+    /// first valid proof is trusted to start at the right one.
+    anchor: RwLock<Option<Digest>>,
+    latest: RwLock<Option<VerifiedRoot>>,
+}
+
+impl LightVerifier {
+    pub fn new(prover: Box<dyn ProverBackend>, trusted_root: Option<Digest>) -> Self {
+        LightVerifier {
+            prover: prover.into(),
+            anchor: RwLock::new(trusted_root),
+            latest: RwLock::new(None),
+        }
+    }
+
+    pub fn latest(&self) -> Option<VerifiedRoot> {
+        *self.latest.read().unwrap()
+    }
+
+    /// Verifies `proof`, posted at `da_height`, and advances the latest
+    /// root if it continues the verified chain. Returns whether it did.
+    pub fn apply(&self, proof: &EpochProof, da_height: u64) -> Result<bool> {
+        let latest = self.latest();
+        let da_height_range = proof.da_height_range();
+        let ordered = match (latest, da_height_range) {
+            (None, _) => true,
+            (Some(latest), Some((first, _))) => latest
+                .last_batch_height
+                .map_or(!proof.is_recursive(), |last| first > last),
+            // only root continuity orders plain and STF proofs without a
+            // committed range, recursive ones can't be ordered at all
+            (Some(_), None) => !proof.is_recursive(),
+        };
+        if !ordered {
+            warn!(
+                "epoch {} proof doesn't read batches past the verified ones, skipping",
+                proof.epoch
+            );
+            return Ok(false);
+        }
+        let anchor = *self.anchor.read().unwrap();
+        let expected_prev_root = match latest {
+            Some(latest) if !proof.is_recursive() => Some(latest.root),
+            _ => anchor,
+        };
+        if expected_prev_root.is_some_and(|root| root != proof.prev_root) {
+            warn!(
+                "epoch {} proof doesn't continue the verified roots, skipping",
+                proof.epoch
+            );
+            return Ok(false);
+        }
+
+        let verification = verify(self.prover.as_ref(), proof)?;
+        if !verification.valid {
+            warn!("epoch {} proof is invalid, skipping", proof.epoch);
+            return Ok(false);
+        }
+        if anchor.is_none() {
+            warn!(
+                "no trusted root configured, trusting epoch {} to start at {}",
+                proof.epoch,
+                hex::encode(proof.prev_root.0)
+            );
+            *self.anchor.write().unwrap() = Some(proof.prev_root);
+        }
+        *self.latest.write().unwrap() = Some(VerifiedRoot {
+            root: proof.new_root,
+            epoch: proof.epoch,
+            da_height,
+            last_batch_height: da_height_range
+                .map(|(_, last)| last)
+                .or(latest.and_then(|latest| latest.last_batch_height)),
+        });
+        info!(
+            "verified epoch {}, root {}",
+            proof.epoch,
+            hex::encode(proof.new_root.0)
+        );
+        Ok(true)
+    }
+
+    /// Verifies the proofs posted from `start_height` on, then follows new
+    /// ones as they are posted. Runs until the subscription ends.
+    pub async fn run(
+        self: Arc<Self>,
+        da: &dyn DataAvailability,
+        proof_namespace: Namespace,
+        start_height: u64,
+    ) -> Result<()> {
+        // subscribe first, so no height is missed while catching up
+        let mut blobs = da.subscribe(proof_namespace).await?;
+        let network_height = da.network_height().await?;
+        for height in start_height..=network_height {
+            let proofs = fetch_epoch_proofs(da, proof_namespace, height).await?;
+            self.clone().apply_all(proofs, height).await;
+        }
+
+        while let Some(result) = blobs.next().await {
+            let (height, blobs) = result?;
+            if height <= network_height {
+                continue;
+            }
+            let proofs = blobs
+                .iter()
+                .filter_map(|blob| EpochProof::try_from(blob).ok())
+                .collect();
+            self.clone().apply_all(proofs, height).await;
+        }
+        Err(anyhow!("Proof namespace subscription ended"))
+    }
+
+    /// Applies `proofs` off the async runtime, since verifying zk proofs is
+    /// CPU bound.
+    async fn apply_all(self: Arc<Self>, mut proofs: Vec<EpochProof>, da_height: u64) {
+        proofs.sort_by_key(|proof| proof.da_height_range());
+        let result = tokio::task::spawn_blocking(move || {
+            for proof in &proofs {
+                if let Err(e) = self.apply(proof, da_height) {
+                    warn!("verifying epoch {}: {}", proof.epoch, e);
+                }
+            }
+        })
+        .await;
+        if let Err(e) = result {
+            warn!("verification task panicked: {}", e);
+        }
+    }
+
+    /// The light verifier's API: `/root` and `/verify_account_proof`.
+    pub fn router(self: Arc<Self>) -> Router {
+        Router::new()
+            .route("/root", get(get_root))
+            .route("/verify_account_proof", post(verify_account_proof_handler))
+            .with_state(self)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RootResponse {
+    /// The hex encoded latest verified state root
+    pub root: String,
+    pub epoch: u64,
+    /// The Celestia height the epoch proof was posted at
+    pub da_height: u64,
+}
+
+/// An account proof as returned by a full node's `/proof/<vk>`, along with
+/// the account's key.
+#[derive(Serialize, Deserialize)]
+pub struct VerifyAccountProofRequest {
+    /// The base64 encoded verifying key of the account
+    pub vk: String,
+    /// The hex encoded raw account value, absent for non-existent accounts
+    pub value: Option<String>,
+    /// The hex encoded, bincode serialized `SparseMerkleProof`
+    pub proof: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VerifyAccountProofResponse {
+    /// Whether the proof holds against the latest verified root
+    pub valid: bool,
+    /// Why the proof doesn't hold, if it doesn't
+    pub error: Option<String>,
+    /// The hex encoded root the proof was checked against
+    pub root: String,
+    pub epoch: u64,
+}
+
+type ApiResult<T> = Result<Json<T>, (StatusCode, String)>;
+
+fn no_verified_root() -> (StatusCode, String) {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        "No epoch proof verified yet".to_string(),
+    )
+}
+
+async fn get_root(State(verifier): State<Arc<LightVerifier>>) -> ApiResult<RootResponse> {
+    let latest = verifier.latest().ok_or_else(no_verified_root)?;
+    Ok(Json(RootResponse {
+        root: hex::encode(latest.root.0),
+        epoch: latest.epoch,
+        da_height: latest.da_height,
+    }))
+}
+
+async fn verify_account_proof_handler(
+    State(verifier): State<Arc<LightVerifier>>,
+    Json(request): Json<VerifyAccountProofRequest>,
+) -> ApiResult<VerifyAccountProofResponse> {
+    let latest = verifier.latest().ok_or_else(no_verified_root)?;
+    let vk =
+        VerifyingKey::try_from(request.vk).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let bad_request = |e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string());
+    let value = request
+        .value
+        .map(hex::decode)
+        .transpose()
+        .context("Invalid value hex")
+        .map_err(bad_request)?;
+    let proof = hex::decode(&request.proof)
+        .context("Invalid proof hex")
+        .and_then(|bytes| bincode::deserialize(&bytes).context("Invalid proof"))
+        .map_err(bad_request)?;
+
+    let result = verify_account_proof(latest.root, &vk, value, &proof);
+    Ok(Json(VerifyAccountProofResponse {
+        valid: result.is_ok(),
+        error: result.err().map(|e| e.to_string()),
+        root: hex::encode(latest.root.0),
+        epoch: latest.epoch,
+    }))
+}